@@ -0,0 +1,149 @@
+use crate::auth::{AuthError, Principal};
+use crate::models::{ListMember, Scope, ShareLevel, TodoItem, TodoItemDTO, TodoList, TodoListDTO, ValidationError};
+use crate::repository::sqlite::todo_list_repository::TodoListRepository;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, AuthError>;
+
+/// The todo list operations available to an authenticated principal.
+///
+/// Each operation needs a minimum [`ShareLevel`] on the list: viewers can
+/// read it, editors can also add and move todos, and owners can also rename,
+/// delete and share it. Admins can do everything. Falling short fails with
+/// `AuthError::Forbidden`. Reads need the `todos:read` scope and writes
+/// `todos:write`.
+pub struct UserLists<'a> {
+    lists: &'a TodoListRepository,
+    todos: &'a TodoRepository,
+    principal: &'a Principal,
+}
+
+impl<'a> UserLists<'a> {
+    pub fn new(lists: &'a TodoListRepository, todos: &'a TodoRepository, principal: &'a Principal) -> UserLists<'a> {
+        UserLists { lists, todos, principal }
+    }
+
+    /// The lists the principal owns or has accepted a share of.
+    pub fn list(&self) -> Result<Vec<TodoList>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.lists.get_user_lists(&self.principal.user_id)?)
+    }
+
+    /// Create a list owned by the principal.
+    pub fn create(&self, name: &str) -> Result<i64> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        Ok(self.lists.save_new_item(&TodoListDTO { owner_id: self.principal.user_id, name: name.to_string() })?)
+    }
+
+    pub fn get(&self, list_id: &i64) -> Result<TodoList> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        self.require_level(list_id, ShareLevel::Viewer)
+    }
+
+    pub fn rename(&self, list_id: &i64, name: &str) -> Result<usize> {
+        let list = self.writable(list_id, ShareLevel::Owner)?;
+        Ok(self.lists.update_item(list_id, &TodoListDTO { owner_id: list.owner_id, name: name.to_string() })?)
+    }
+
+    /// Delete a list. Its todos stay with their owners.
+    pub fn delete(&self, list_id: &i64) -> Result<usize> {
+        self.writable(list_id, ShareLevel::Owner)?;
+        Ok(self.lists.delete_item_by_id(list_id)?)
+    }
+
+    /// Invite a user to the list at `level`, or change the level of an existing member.
+    pub fn share(&self, list_id: &i64, user_id: &i64, level: ShareLevel) -> Result<()> {
+        let list = self.writable(list_id, ShareLevel::Owner)?;
+        if list.owner_id == *user_id {
+            return Err(ValidationError::field("user_id", "already owns the list").into());
+        }
+        if !self.lists.user_exists(user_id)? {
+            return Err(ValidationError::field("user_id", "is not a user").into());
+        }
+        self.lists.invite(list_id, user_id, level, &self.principal.user_id)?;
+        Ok(())
+    }
+
+    /// Remove someone from a list. Owners can remove anyone, and anyone can leave.
+    pub fn unshare(&self, list_id: &i64, user_id: &i64) -> Result<()> {
+        if *user_id == self.principal.user_id {
+            self.principal.require_scope(Scope::WriteTodos)?;
+        } else {
+            self.writable(list_id, ShareLevel::Owner)?;
+        }
+        if self.lists.remove_member(list_id, user_id)? == 0 {
+            return Err(RepositoryError::NotFound.into());
+        }
+        Ok(())
+    }
+
+    /// Everyone the list is shared with, including pending invitations.
+    pub fn members(&self, list_id: &i64) -> Result<Vec<ListMember>> {
+        self.get(list_id)?;
+        Ok(self.lists.get_members(list_id)?)
+    }
+
+    /// The principal's pending invitations.
+    pub fn invitations(&self) -> Result<Vec<ListMember>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.lists.get_pending_invitations(&self.principal.user_id)?)
+    }
+
+    /// Accept an invitation to a list; fails with `RepositoryError::NotFound`
+    /// if there is no pending invitation.
+    pub fn accept(&self, list_id: &i64) -> Result<()> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        if self.lists.accept_invitation(list_id, &self.principal.user_id)? == 0 {
+            return Err(RepositoryError::NotFound.into());
+        }
+        Ok(())
+    }
+
+    /// Decline an invitation, or leave a list.
+    pub fn decline(&self, list_id: &i64) -> Result<()> {
+        self.unshare(list_id, &self.principal.user_id)
+    }
+
+    /// The todos in a list.
+    pub fn todos(&self, list_id: &i64) -> Result<Vec<TodoItem>> {
+        self.get(list_id)?;
+        Ok(self.lists.get_list_todos(list_id)?)
+    }
+
+    /// Create a todo owned by the principal, in a list they can edit.
+    pub fn add_todo(&self, list_id: &i64, task: &str) -> Result<i64> {
+        self.writable(list_id, ShareLevel::Editor)?;
+        let todo_dto = TodoItemDTO { user_id: self.principal.user_id, task: task.to_string() };
+        Ok(self.todos.save_new_item_in_list(&todo_dto, list_id).map_err(RepositoryError::from)?)
+    }
+
+    /// Move a todo the principal can change into a list they can edit, or out
+    /// of its list with `None`.
+    pub fn move_todo(&self, todo_id: &i64, list_id: Option<i64>) -> Result<usize> {
+        self.principal.todos(self.todos).with_lists(self.lists).writable(todo_id)?;
+        if let Some(list_id) = list_id {
+            self.writable(&list_id, ShareLevel::Editor)?;
+        }
+        Ok(self.todos.set_todo_list(todo_id, list_id).map_err(RepositoryError::from)?)
+    }
+
+    /// Every todo the principal can see, in their own lists or shared ones.
+    pub fn visible_todos(&self) -> Result<Vec<TodoItem>> {
+        self.principal.todos(self.todos).with_lists(self.lists).visible()
+    }
+
+    fn writable(&self, list_id: &i64, level: ShareLevel) -> Result<TodoList> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        self.require_level(list_id, level)
+    }
+
+    /// Load a list, checking the principal has at least `level` on it.
+    fn require_level(&self, list_id: &i64, level: ShareLevel) -> Result<TodoList> {
+        let list = self.lists.select_item_by_id(list_id)?;
+        if self.principal.is_admin() || self.lists.access_level(list_id, &self.principal.user_id)? >= Some(level) {
+            return Ok(list);
+        }
+        Err(AuthError::Forbidden)
+    }
+}
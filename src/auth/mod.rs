@@ -0,0 +1,415 @@
+//! Password authentication, login sessions and personal API tokens.
+//!
+//! [`AuthService`] registers users with an argon2 password hash, exchanges an
+//! email and password for a session token, mints scoped API tokens, and turns
+//! either kind of token back into a [`Principal`]. Todo access for a principal
+//! goes through [`UserTodos`], which checks ownership, roles and scopes
+//! before touching the todo repository, shared lists go through
+//! [`UserLists`], and organizations through [`UserOrganizations`].
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::{ApiToken, Role, Scope, UserDTO, ValidationError};
+use crate::repository::sqlite::auth_repository::AuthRepository;
+use crate::repository::sqlite::organization_repository::OrganizationRepository;
+use crate::repository::sqlite::todo_list_repository::TodoListRepository;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::sqlite::user_repository::UserRepository;
+use crate::repository::{Repository, RepositoryError};
+
+pub use password::{generate_token, hash_password, hash_token, verify_password};
+#[cfg(any(feature = "webhooks", feature = "encryption"))]
+pub(crate) use password::to_hex;
+pub use lists::UserLists;
+pub use organizations::{MemberTodos, UserOrganizations};
+pub use todos::UserTodos;
+
+mod lists;
+mod organizations;
+mod password;
+mod todos;
+
+/// Passwords shorter than this are rejected.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// How long a session lasts unless configured otherwise.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::hours(24);
+
+/// Every API token starts with this, which is how [`AuthService::verify_bearer`]
+/// tells them apart from session tokens.
+pub const API_TOKEN_PREFIX: &str = "tdt_";
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// Unknown email, wrong password, or a user without a password.
+    InvalidCredentials,
+    /// The token is unknown, expired or revoked.
+    InvalidToken,
+    /// The token is valid but was not granted this scope.
+    MissingScope(Scope),
+    /// The principal may not act on someone else's data.
+    Forbidden,
+    Repository(RepositoryError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::InvalidCredentials => write!(f, "invalid email or password"),
+            AuthError::InvalidToken => write!(f, "invalid, expired or revoked token"),
+            AuthError::MissingScope(scope) => write!(f, "token is missing the {} scope", scope),
+            AuthError::Forbidden => write!(f, "not allowed to access another user's data"),
+            AuthError::Repository(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for AuthError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AuthError::Repository(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<RepositoryError> for AuthError {
+    fn from(e: RepositoryError) -> Self {
+        AuthError::Repository(e)
+    }
+}
+
+impl From<rusqlite::Error> for AuthError {
+    fn from(e: rusqlite::Error) -> Self {
+        AuthError::Repository(e.into())
+    }
+}
+
+impl From<ValidationError> for AuthError {
+    fn from(e: ValidationError) -> Self {
+        AuthError::Repository(e.into())
+    }
+}
+
+impl From<argon2::password_hash::Error> for AuthError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        AuthError::Repository(ValidationError::field("password", &e.to_string()).into())
+    }
+}
+
+/// How a principal proved who they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// A password login. Sessions may do anything the user can.
+    Session { id: i64 },
+    /// A personal API token, limited to its scopes.
+    ApiToken { id: i64, scopes: Vec<Scope> },
+}
+
+/// The authenticated user a request is acting as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    pub user_id: i64,
+    pub role: Role,
+    pub credential: Credential,
+}
+
+impl Principal {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Fail with `AuthError::Forbidden` unless the principal is `user_id` or an admin.
+    pub fn require_user(&self, user_id: &i64) -> Result<(), AuthError> {
+        if self.user_id != *user_id && !self.is_admin() {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(())
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match &self.credential {
+            Credential::Session { .. } => true,
+            Credential::ApiToken { scopes, .. } => scopes.contains(&scope),
+        }
+    }
+
+    /// Fail with `AuthError::MissingScope` unless the principal has `scope`.
+    pub fn require_scope(&self, scope: Scope) -> Result<(), AuthError> {
+        if !self.has_scope(scope) {
+            return Err(AuthError::MissingScope(scope));
+        }
+        Ok(())
+    }
+
+    /// The principal's view of the todos in `repo`.
+    pub fn todos<'a>(&'a self, repo: &'a TodoRepository) -> UserTodos<'a> {
+        UserTodos::new(repo, self)
+    }
+
+    /// The principal's view of the lists in `lists`, whose todos live in `todos`.
+    pub fn lists<'a>(&'a self, lists: &'a TodoListRepository, todos: &'a TodoRepository) -> UserLists<'a> {
+        UserLists::new(lists, todos, self)
+    }
+
+    /// The principal's view of the organizations in `repo`.
+    pub fn organizations<'a>(&'a self, repo: &'a OrganizationRepository) -> UserOrganizations<'a> {
+        UserOrganizations::new(repo, self)
+    }
+}
+
+/// A freshly issued session. The token is only available here; the database keeps its hash.
+#[derive(Debug, Clone)]
+pub struct IssuedSession {
+    pub token: String,
+    pub principal: Principal,
+    pub expires_datetime: DateTime<Utc>,
+}
+
+/// A freshly minted API token. As with sessions, the token is only available here.
+#[derive(Debug, Clone)]
+pub struct MintedApiToken {
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+pub struct AuthService {
+    users: UserRepository,
+    auth: AuthRepository,
+    session_ttl: Duration,
+}
+
+fn validate_password(password: &str) -> Result<(), ValidationError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ValidationError::field(
+            "password",
+            &format!("must be at least {} characters", MIN_PASSWORD_LENGTH),
+        ));
+    }
+    Ok(())
+}
+
+/// A hash of a random password, made once, to verify against when there is
+/// no real hash to check.
+fn dummy_password_hash() -> Result<&'static str, AuthError> {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    if let Some(hash) = DUMMY.get() {
+        return Ok(hash);
+    }
+    let hash = hash_password(&generate_token())?;
+    Ok(DUMMY.get_or_init(|| hash))
+}
+
+impl AuthService {
+    /// Open the user and auth repositories on the same database file, or in memory
+    /// if no connection string is provided.
+    ///
+    /// The in-memory database is a named, shared-cache one, so users and
+    /// their credentials live in the same database; each call gets a fresh one.
+    pub fn new(connection_string: Option<&str>) -> Result<AuthService, RepositoryError> {
+        static IN_MEMORY_COUNT: AtomicUsize = AtomicUsize::new(0);
+        let in_memory;
+        let connection_string = match connection_string {
+            Some(connection_string) => connection_string,
+            None => {
+                let n = IN_MEMORY_COUNT.fetch_add(1, Ordering::Relaxed);
+                in_memory = format!("file:to_dont_auth_{}?mode=memory&cache=shared", n);
+                &in_memory
+            }
+        };
+        Ok(AuthService::from_repositories(
+            UserRepository::new(Some(connection_string))?,
+            AuthRepository::new(Some(connection_string))?,
+        ))
+    }
+
+    pub fn from_repositories(users: UserRepository, auth: AuthRepository) -> AuthService {
+        AuthService { users, auth, session_ttl: DEFAULT_SESSION_TTL }
+    }
+
+    /// Change how long newly issued sessions last.
+    pub fn with_session_ttl(mut self, session_ttl: Duration) -> AuthService {
+        self.session_ttl = session_ttl;
+        self
+    }
+
+    pub fn users(&self) -> &UserRepository {
+        &self.users
+    }
+
+    /// Create a user with a password, returning the new user's id.
+    pub fn register(&self, user: &UserDTO, password: &str) -> Result<i64, AuthError> {
+        validate_password(password)?;
+        let password_hash = hash_password(password)?;
+        let user_id = self.users.save_new_item(user)?;
+        if let Err(e) = self.auth.set_password_hash(&user_id, &password_hash) {
+            // don't leave behind a user who can never log in
+            self.users.delete_item_by_id(&user_id)?;
+            return Err(e.into());
+        }
+        Ok(user_id)
+    }
+
+    /// Register the first admin, for setting up a new database: admins are
+    /// otherwise only made by other admins through [`set_role`](AuthService::set_role).
+    ///
+    /// Fails with `AuthError::Forbidden` once any admin exists.
+    pub fn create_first_admin(&self, user: &UserDTO, password: &str) -> Result<i64, AuthError> {
+        if self.users.get_users()?.iter().any(|user| user.role == Role::Admin) {
+            return Err(AuthError::Forbidden);
+        }
+        let user_id = self.register(user, password)?;
+        self.users.set_role(&user_id, Role::Admin)?;
+        Ok(user_id)
+    }
+
+    /// Set or change a user's password. Existing sessions are revoked.
+    pub fn set_password(&self, user_id: &i64, password: &str) -> Result<(), AuthError> {
+        validate_password(password)?;
+        self.users.select_item_by_id(user_id)?;
+        self.auth.set_password_hash(user_id, &hash_password(password)?)?;
+        self.auth.revoke_user_sessions(user_id)?;
+        Ok(())
+    }
+
+    /// Change a user's role. Only admins may do this.
+    pub fn set_role(&self, principal: &Principal, user_id: &i64, role: Role) -> Result<(), AuthError> {
+        principal.require_scope(Scope::ManageUsers)?;
+        if !principal.is_admin() {
+            return Err(AuthError::Forbidden);
+        }
+        if self.users.set_role(user_id, role)? == 0 {
+            return Err(RepositoryError::NotFound.into());
+        }
+        Ok(())
+    }
+
+    /// Check an email and password and issue a new session.
+    ///
+    /// Unknown emails and users without a password are checked against a
+    /// dummy hash, so they take as long to refuse as a wrong password and
+    /// the response time doesn't tell which emails are registered.
+    pub fn authenticate(&self, email: &str, password: &str) -> Result<IssuedSession, AuthError> {
+        let user = self.users.find_by_email(email)?;
+        let password_hash = match &user {
+            Some(user) => self.auth.get_password_hash(&user.id)?,
+            None => None,
+        };
+        let (Some(user), Some(password_hash)) = (user, password_hash) else {
+            verify_password(password, dummy_password_hash()?);
+            return Err(AuthError::InvalidCredentials);
+        };
+        if !verify_password(password, &password_hash) {
+            return Err(AuthError::InvalidCredentials);
+        }
+        let token = generate_token();
+        let expires_datetime = Utc::now() + self.session_ttl;
+        let session_id = self.auth.save_session(&user.id, &hash_token(&token), &expires_datetime)?;
+        Ok(IssuedSession {
+            token,
+            principal: Principal { user_id: user.id, role: user.role, credential: Credential::Session { id: session_id } },
+            expires_datetime,
+        })
+    }
+
+    /// Resolve a session token to the principal it was issued to.
+    pub fn verify_session(&self, token: &str) -> Result<Principal, AuthError> {
+        let session = self.auth.find_session(&hash_token(token))?.ok_or(AuthError::InvalidToken)?;
+        if !session.is_active(Utc::now()) {
+            return Err(AuthError::InvalidToken);
+        }
+        Ok(Principal {
+            user_id: session.user_id,
+            role: self.role_of(&session.user_id)?,
+            credential: Credential::Session { id: session.id },
+        })
+    }
+
+    /// Log out the session a token belongs to.
+    pub fn revoke_session(&self, token: &str) -> Result<(), AuthError> {
+        let session = self.auth.find_session(&hash_token(token))?.ok_or(AuthError::InvalidToken)?;
+        self.auth.revoke_session(&session.id)?;
+        Ok(())
+    }
+
+    /// Log out every session of a user, returning how many were active.
+    pub fn revoke_all_sessions(&self, user_id: &i64) -> Result<usize, AuthError> {
+        Ok(self.auth.revoke_user_sessions(user_id)?)
+    }
+
+    /// Create an API token for a user with the given scopes.
+    pub fn mint_api_token(&self, user_id: &i64, name: &str, scopes: &[Scope]) -> Result<MintedApiToken, AuthError> {
+        let mut error = ValidationError::default();
+        if name.trim().is_empty() {
+            error.add("name", "must not be empty");
+        }
+        if scopes.is_empty() {
+            error.add("scopes", "must include at least one scope");
+        }
+        if !error.is_empty() {
+            return Err(error.into());
+        }
+        self.users.select_item_by_id(user_id)?;
+        let token = format!("{}{}", API_TOKEN_PREFIX, generate_token());
+        let id = self.auth.save_api_token(user_id, name.trim(), &hash_token(&token), scopes)?;
+        Ok(MintedApiToken { token, api_token: self.auth.select_api_token_by_id(&id)? })
+    }
+
+    /// All of a user's API tokens, including revoked ones.
+    pub fn list_api_tokens(&self, user_id: &i64) -> Result<Vec<ApiToken>, AuthError> {
+        Ok(self.auth.get_user_api_tokens(user_id)?)
+    }
+
+    /// Revoke one of a user's API tokens by id.
+    pub fn revoke_api_token(&self, user_id: &i64, id: &i64) -> Result<(), AuthError> {
+        if self.auth.revoke_api_token(user_id, id)? == 0 {
+            return Err(RepositoryError::NotFound.into());
+        }
+        Ok(())
+    }
+
+    /// Resolve an API token to its principal, checking it was granted `scope`
+    /// and recording that it was used.
+    pub fn verify_api_token(&self, token: &str, scope: Scope) -> Result<Principal, AuthError> {
+        let principal = self.verify_api_token_unscoped(token)?;
+        principal.require_scope(scope)?;
+        Ok(principal)
+    }
+
+    /// Resolve either a session token or an API token. Scopes are left to the
+    /// caller, through [`Principal::require_scope`].
+    pub fn verify_bearer(&self, token: &str) -> Result<Principal, AuthError> {
+        if token.starts_with(API_TOKEN_PREFIX) {
+            self.verify_api_token_unscoped(token)
+        } else {
+            self.verify_session(token)
+        }
+    }
+
+    /// The current role of a token's user; tokens of deleted users are invalid.
+    fn role_of(&self, user_id: &i64) -> Result<Role, AuthError> {
+        match self.users.select_item_by_id(user_id) {
+            Ok(user) => Ok(user.role),
+            Err(RepositoryError::NotFound) => Err(AuthError::InvalidToken),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn verify_api_token_unscoped(&self, token: &str) -> Result<Principal, AuthError> {
+        let api_token = self.auth.find_api_token(&hash_token(token))?.ok_or(AuthError::InvalidToken)?;
+        if api_token.revoked_datetime.is_some() {
+            return Err(AuthError::InvalidToken);
+        }
+        self.auth.touch_api_token(&api_token.id)?;
+        Ok(Principal {
+            user_id: api_token.user_id,
+            role: self.role_of(&api_token.user_id)?,
+            credential: Credential::ApiToken { id: api_token.id, scopes: api_token.scopes },
+        })
+    }
+}
@@ -0,0 +1,202 @@
+use crate::auth::{AuthError, Principal};
+use crate::models::{Membership, Organization, OrganizationDTO, OrganizationStats, OrgRole, Scope, TodoItem, TodoItemDTO};
+use crate::repository::sqlite::organization_repository::{OrganizationRepository, TenantTodos};
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, AuthError>;
+
+/// The organization operations available to an authenticated principal.
+///
+/// Each operation needs a minimum [`OrgRole`] in the organization: members
+/// can see it, its members, statistics and todos, admins can also add and
+/// remove members, and owners can also rename and delete it, and manage other
+/// owners. Site admins can do everything. Falling short fails with
+/// `AuthError::Forbidden`. Reads need the `todos:read` scope and changes
+/// `users:manage`; the organization's todos are worked on through
+/// [`MemberTodos`].
+pub struct UserOrganizations<'a> {
+    repo: &'a OrganizationRepository,
+    principal: &'a Principal,
+}
+
+impl<'a> UserOrganizations<'a> {
+    pub fn new(repo: &'a OrganizationRepository, principal: &'a Principal) -> UserOrganizations<'a> {
+        UserOrganizations { repo, principal }
+    }
+
+    /// The organizations the principal belongs to.
+    pub fn list(&self) -> Result<Vec<Organization>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_user_organizations(&self.principal.user_id)?)
+    }
+
+    /// Create an organization with the principal as its owner.
+    pub fn create(&self, name: &str) -> Result<i64> {
+        self.principal.require_scope(Scope::ManageUsers)?;
+        let id = self.repo.save_new_item(&OrganizationDTO { name: name.to_string() })?;
+        if let Err(e) = self.repo.add_member(&id, &self.principal.user_id, OrgRole::Owner) {
+            // don't leave behind an organization nobody can manage
+            self.repo.delete_item_by_id(&id)?;
+            return Err(e.into());
+        }
+        Ok(id)
+    }
+
+    pub fn get(&self, organization_id: &i64) -> Result<Organization> {
+        self.readable(organization_id)?;
+        Ok(self.repo.select_item_by_id(organization_id)?)
+    }
+
+    pub fn rename(&self, organization_id: &i64, name: &str) -> Result<usize> {
+        self.manageable(organization_id, OrgRole::Owner)?;
+        Ok(self.repo.update_item(organization_id, &OrganizationDTO { name: name.to_string() })?)
+    }
+
+    /// Delete an organization and every todo in it.
+    pub fn delete(&self, organization_id: &i64) -> Result<usize> {
+        self.manageable(organization_id, OrgRole::Owner)?;
+        Ok(self.repo.delete_item_by_id(organization_id)?)
+    }
+
+    /// Add a user to the organization, or change their role. Only owners can
+    /// make other owners or change an owner's role, and the last owner can't
+    /// be demoted.
+    pub fn add_member(&self, organization_id: &i64, user_id: &i64, role: OrgRole) -> Result<()> {
+        let current = self.repo.get_membership(organization_id, user_id)?.map(|m| m.role);
+        self.manageable(organization_id, OrgRole::Admin.max(role).max(current.unwrap_or(OrgRole::Member)))?;
+        self.repo.add_member(organization_id, user_id, role)?;
+        Ok(())
+    }
+
+    /// Remove a member. Admins can remove members, owners can remove anyone,
+    /// and anyone but the last owner can leave.
+    pub fn remove_member(&self, organization_id: &i64, user_id: &i64) -> Result<()> {
+        if *user_id == self.principal.user_id {
+            self.principal.require_scope(Scope::ManageUsers)?;
+        } else {
+            let role = self.repo.get_membership(organization_id, user_id)?.map(|m| m.role).unwrap_or(OrgRole::Member);
+            self.manageable(organization_id, if role == OrgRole::Member { OrgRole::Admin } else { OrgRole::Owner })?;
+        }
+        if self.repo.remove_member(organization_id, user_id)? == 0 {
+            return Err(RepositoryError::NotFound.into());
+        }
+        Ok(())
+    }
+
+    pub fn members(&self, organization_id: &i64) -> Result<Vec<Membership>> {
+        self.readable(organization_id)?;
+        Ok(self.repo.get_members(organization_id)?)
+    }
+
+    pub fn stats(&self, organization_id: &i64) -> Result<OrganizationStats> {
+        self.readable(organization_id)?;
+        Ok(self.repo.get_stats(organization_id)?)
+    }
+
+    /// The organization's todos, for its members.
+    pub fn todos(&self, organization_id: &i64) -> Result<MemberTodos<'a>> {
+        self.readable(organization_id)?;
+        let role = self.repo.get_membership(organization_id, &self.principal.user_id)?.map(|m| m.role);
+        let manager = self.principal.is_admin() || role >= Some(OrgRole::Admin);
+        Ok(MemberTodos { todos: self.repo.todos(organization_id), principal: self.principal, manager })
+    }
+
+    fn readable(&self, organization_id: &i64) -> Result<()> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        self.require_role(organization_id, OrgRole::Member)
+    }
+
+    fn manageable(&self, organization_id: &i64, role: OrgRole) -> Result<()> {
+        self.principal.require_scope(Scope::ManageUsers)?;
+        self.require_role(organization_id, role)
+    }
+
+    /// Check the organization exists and the principal has at least `role` in it.
+    fn require_role(&self, organization_id: &i64, role: OrgRole) -> Result<()> {
+        self.repo.select_item_by_id(organization_id)?;
+        if self.principal.is_admin() {
+            return Ok(());
+        }
+        match self.repo.get_membership(organization_id, &self.principal.user_id)? {
+            Some(membership) if membership.role >= role => Ok(()),
+            _ => Err(AuthError::Forbidden),
+        }
+    }
+}
+
+/// An organization's todos as seen by one of its members, from
+/// [`UserOrganizations::todos`].
+///
+/// Every member can read them. Changing one needs the `todos:write` scope
+/// and either owning it or being an admin of the organization; only admins
+/// can create todos for other members.
+pub struct MemberTodos<'a> {
+    todos: TenantTodos<'a>,
+    principal: &'a Principal,
+    manager: bool,
+}
+
+impl MemberTodos<'_> {
+    pub fn organization_id(&self) -> i64 {
+        self.todos.organization_id()
+    }
+
+    /// Every todo in the organization.
+    pub fn list(&self) -> Result<Vec<TodoItem>> {
+        Ok(self.todos.get_todos()?)
+    }
+
+    /// A member's todos within the organization.
+    pub fn list_for(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        Ok(self.todos.get_user_todos(user_id)?)
+    }
+
+    pub fn get(&self, id: &i64) -> Result<TodoItem> {
+        Ok(self.todos.select_item_by_id(id)?)
+    }
+
+    /// Create a todo owned by the principal.
+    pub fn create(&self, task: &str) -> Result<i64> {
+        self.create_for(&self.principal.user_id, task)
+    }
+
+    /// Create a todo owned by another member, if the principal manages the organization.
+    pub fn create_for(&self, user_id: &i64, task: &str) -> Result<i64> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        if *user_id != self.principal.user_id && !self.manager {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(self.todos.save_new_item(&TodoItemDTO { user_id: *user_id, task: task.to_string() })?)
+    }
+
+    /// Change a todo's task, keeping its owner.
+    pub fn update(&self, id: &i64, task: &str) -> Result<usize> {
+        let todo = self.writable(id)?;
+        Ok(self.todos.update_item(id, &TodoItemDTO { user_id: todo.user_id, task: task.to_string() })?)
+    }
+
+    pub fn complete(&self, id: &i64) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.todos.complete_todo_item(id)?)
+    }
+
+    pub fn uncomplete(&self, id: &i64) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.todos.uncomplete_todo_item(id)?)
+    }
+
+    pub fn delete(&self, id: &i64) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.todos.delete_item_by_id(id)?)
+    }
+
+    /// Load a todo, checking the principal owns it or manages the organization.
+    fn writable(&self, id: &i64) -> Result<TodoItem> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        let todo = self.todos.select_item_by_id(id)?;
+        if todo.user_id != self.principal.user_id && !self.manager {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(todo)
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, Utc};
+
+use crate::auth::{AuthError, Principal};
+use crate::models::{
+    Delegation, Excuse, ExcuseCount, Refusal, RefusalCount, RefusalReason, Scope, ShareLevel, TodoItem, TodoItemDTO,
+};
+use crate::repository::sqlite::todo_list_repository::TodoListRepository;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::{EventKind, Repository, RepositoryError};
+use crate::undo::{HistoryEntry, TodoHistory};
+
+type Result<T> = std::result::Result<T, AuthError>;
+
+/// The todo operations available to an authenticated principal.
+///
+/// Regular users can work with their own todos, and, once [`with_lists`] is
+/// given the list repository, with todos in lists shared with them: viewers
+/// can read them and editors can also change them. Todos assigned to them can
+/// be read, completed, uncompleted and passed on, but not otherwise changed.
+/// Admins can work with anyone's. Touching any other todo fails with `AuthError::Forbidden`,
+/// and a missing id with `RepositoryError::NotFound`. Reads need the
+/// `todos:read` scope and writes `todos:write`.
+///
+/// Creating, updating, completing, uncompleting and deleting todos is logged
+/// in the principal's undo history (see [`crate::undo`]), so it can be
+/// [`undo`](UserTodos::undo)ne and [`redo`](UserTodos::redo)ne.
+///
+/// [`with_lists`]: UserTodos::with_lists
+pub struct UserTodos<'a> {
+    repo: &'a TodoRepository,
+    lists: Option<&'a TodoListRepository>,
+    principal: &'a Principal,
+}
+
+impl<'a> UserTodos<'a> {
+    pub fn new(repo: &'a TodoRepository, principal: &'a Principal) -> UserTodos<'a> {
+        UserTodos { repo, lists: None, principal }
+    }
+
+    /// Also allow access through shared lists.
+    pub fn with_lists(mut self, lists: &'a TodoListRepository) -> UserTodos<'a> {
+        self.lists = Some(lists);
+        self
+    }
+
+    /// The principal's own todos.
+    pub fn list(&self) -> Result<Vec<TodoItem>> {
+        self.list_for(&self.principal.user_id)
+    }
+
+    /// Another user's todos, if the principal may see them.
+    pub fn list_for(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        self.check_list_for(user_id)?;
+        Ok(self.repo.get_user_todos(user_id)?)
+    }
+
+    /// Check the principal may list a user's todos, for callers that load
+    /// them some other way, such as in a batch for several users.
+    pub fn check_list_for(&self, user_id: &i64) -> Result<()> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        self.principal.require_user(user_id)
+    }
+
+    /// The principal's own todos, those assigned to them, and those in lists
+    /// shared with them.
+    pub fn visible(&self) -> Result<Vec<TodoItem>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        if let Some(lists) = self.lists {
+            return Ok(lists.get_visible_todos(&self.principal.user_id)?);
+        }
+        let mut todos = self.repo.get_user_todos(&self.principal.user_id)?;
+        todos.extend(self.repo.get_assigned_todos(&self.principal.user_id)?);
+        todos.sort_by_key(|todo| todo.id);
+        todos.dedup_by_key(|todo| todo.id);
+        Ok(todos)
+    }
+
+    /// The todos assigned to the principal, whoever created them.
+    pub fn assigned_to_me(&self) -> Result<Vec<TodoItem>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_assigned_todos(&self.principal.user_id)?)
+    }
+
+    pub fn get(&self, id: &i64) -> Result<TodoItem> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        self.authorized(id, ShareLevel::Viewer, true)
+    }
+
+    /// Create a todo owned by the principal.
+    pub fn create(&self, task: &str) -> Result<i64> {
+        self.create_for(&self.principal.user_id, task)
+    }
+
+    /// Create a todo owned by another user, if the principal may.
+    pub fn create_for(&self, user_id: &i64, task: &str) -> Result<i64> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        self.principal.require_user(user_id)?;
+        Ok(self.history().save_new_item(&TodoItemDTO { user_id: *user_id, task: task.to_string() })?)
+    }
+
+    /// Change a todo's task, keeping its owner.
+    pub fn update(&self, id: &i64, task: &str) -> Result<usize> {
+        let todo = self.writable(id)?;
+        Ok(self.history().update_item(id, &TodoItemDTO { user_id: todo.user_id, task: task.to_string() })?)
+    }
+
+    /// Change a todo's task like [`update`](UserTodos::update), failing with
+    /// `RepositoryError::Conflict` if it is no longer at `expected_version`.
+    pub fn update_if_version(&self, id: &i64, task: &str, expected_version: i64) -> Result<usize> {
+        let todo = self.writable(id)?;
+        let todo_dto = TodoItemDTO { user_id: todo.user_id, task: task.to_string() };
+        Ok(self.history().update_item_if_version(id, &todo_dto, expected_version)?)
+    }
+
+    pub fn complete(&self, id: &i64) -> Result<usize> {
+        self.workable(id)?;
+        Ok(self.history().complete_todo_item(id)?)
+    }
+
+    pub fn uncomplete(&self, id: &i64) -> Result<usize> {
+        self.workable(id)?;
+        Ok(self.history().uncomplete_todo_item(id)?)
+    }
+
+    /// Assign a todo to someone, reassign it, or unassign it with `None`.
+    /// Its current assignee may pass it on as well.
+    pub fn assign(&self, id: &i64, assignee_id: Option<i64>) -> Result<usize> {
+        self.workable(id)?;
+        Ok(self.repo.assign_todo_item(id, assignee_id, &self.principal.user_id)?)
+    }
+
+    /// Check the principal may assign a todo, for callers that look up the
+    /// new assignee first.
+    pub fn check_assign(&self, id: &i64) -> Result<()> {
+        self.workable(id).map(|_| ())
+    }
+
+    /// Who passed a todo to whom, oldest first.
+    pub fn delegations(&self, id: &i64) -> Result<Vec<Delegation>> {
+        self.get(id)?;
+        Ok(self.repo.get_delegations(id)?)
+    }
+
+    /// Hide a todo until `until`, logging the excuse.
+    pub fn snooze(&self, id: &i64, until: &DateTime<Utc>, excuse: &str) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.repo.snooze(id, until, excuse)?)
+    }
+
+    pub fn unsnooze(&self, id: &i64) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.repo.unsnooze(id)?)
+    }
+
+    /// The principal's todos that are snoozed right now.
+    pub fn snoozed(&self) -> Result<Vec<TodoItem>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_snoozed_todos(&self.principal.user_id)?)
+    }
+
+    /// The principal's most snoozed todos, up to `limit`.
+    pub fn most_deferred(&self, limit: u32) -> Result<Vec<TodoItem>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_most_deferred(&self.principal.user_id, limit)?)
+    }
+
+    /// The excuses given for snoozing a todo, oldest first.
+    pub fn excuses(&self, id: &i64) -> Result<Vec<Excuse>> {
+        self.get(id)?;
+        Ok(self.repo.get_excuses(id)?)
+    }
+
+    /// How often the principal has used each excuse.
+    pub fn excuse_report(&self) -> Result<Vec<ExcuseCount>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_excuse_report(&self.principal.user_id)?)
+    }
+
+    /// Declare that a todo won't be done.
+    pub fn refuse(&self, id: &i64, reason: RefusalReason) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.repo.refuse(id, reason)?)
+    }
+
+    pub fn reconsider(&self, id: &i64) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.repo.reconsider(id)?)
+    }
+
+    /// The principal's currently refused todos.
+    pub fn refused(&self) -> Result<Vec<TodoItem>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_refused_todos(&self.principal.user_id)?)
+    }
+
+    /// Every time a todo was refused, oldest first.
+    pub fn refusals(&self, id: &i64) -> Result<Vec<Refusal>> {
+        self.get(id)?;
+        Ok(self.repo.get_refusals(id)?)
+    }
+
+    /// How often the principal has refused todos for each reason.
+    pub fn refusal_report(&self) -> Result<Vec<RefusalCount>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.repo.get_refusal_report(&self.principal.user_id)?)
+    }
+
+    pub fn delete(&self, id: &i64) -> Result<usize> {
+        self.writable(id)?;
+        Ok(self.history().delete_item_by_id(id)?)
+    }
+
+    /// Revert the principal's latest write that isn't undone yet, returning
+    /// it, or `None` if there is nothing to undo. The principal must still be
+    /// allowed to make that write, so losing access to a todo also loses the
+    /// undo.
+    pub fn undo(&self) -> Result<Option<HistoryEntry>> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        self.history().undo(|entry, todo| self.check_step(entry, todo))
+    }
+
+    /// Repeat the principal's most recently undone write, returning it, or
+    /// `None` if there is nothing to redo. The principal must still be
+    /// allowed to make that write.
+    pub fn redo(&self) -> Result<Option<HistoryEntry>> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        self.history().redo(|entry, todo| self.check_step(entry, todo))
+    }
+
+    /// Forget the write [`undo`](UserTodos::undo) would revert next,
+    /// returning it, for when undoing it fails because the todo has changed,
+    /// gone or is out of reach, so the writes before it can still be undone.
+    pub fn discard_undo(&self) -> Result<Option<HistoryEntry>> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        Ok(self.history().discard()?)
+    }
+
+    /// The principal's logged writes, newest first.
+    pub fn history_entries(&self) -> Result<Vec<HistoryEntry>> {
+        self.principal.require_scope(Scope::ReadTodos)?;
+        Ok(self.history().entries()?)
+    }
+
+    fn history(&self) -> TodoHistory<'a> {
+        self.repo.history(self.principal.user_id)
+    }
+
+    /// Load a todo, checking the principal may change it.
+    pub(crate) fn writable(&self, id: &i64) -> Result<TodoItem> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        self.authorized(id, ShareLevel::Editor, false)
+    }
+
+    /// Load a todo, checking the principal may complete, uncomplete or
+    /// reassign it: anyone who may change it, and its assignee.
+    fn workable(&self, id: &i64) -> Result<TodoItem> {
+        self.principal.require_scope(Scope::WriteTodos)?;
+        self.authorized(id, ShareLevel::Editor, true)
+    }
+
+    /// Check the principal may still make a logged write to `todo`, with
+    /// the same check as the write itself.
+    fn check_step(&self, entry: &HistoryEntry, todo: &TodoItem) -> Result<()> {
+        let assignee_may = matches!(entry.operation, EventKind::Completed | EventKind::Uncompleted);
+        self.check(todo, ShareLevel::Editor, assignee_may)
+    }
+
+    /// Load a todo, checking the principal may access it as for [`check`](UserTodos::check).
+    fn authorized(&self, id: &i64, level: ShareLevel, assignee_may: bool) -> Result<TodoItem> {
+        let todo = self.repo.select_item_by_id(id).map_err(RepositoryError::from)?;
+        self.check(&todo, level, assignee_may)?;
+        Ok(todo)
+    }
+
+    /// Check the principal owns a todo, is an admin, has at least `level` on
+    /// its list, or, if `assignee_may`, is its assignee.
+    fn check(&self, todo: &TodoItem, level: ShareLevel, assignee_may: bool) -> Result<()> {
+        let assigned = assignee_may && todo.assignee_id == Some(self.principal.user_id);
+        if assigned || self.principal.require_user(&todo.user_id).is_ok() {
+            return Ok(());
+        }
+        if let (Some(lists), Some(list_id)) = (self.lists, todo.list_id) {
+            if lists.access_level(&list_id, &self.principal.user_id)? >= Some(level) {
+                return Ok(());
+            }
+        }
+        Err(AuthError::Forbidden)
+    }
+}
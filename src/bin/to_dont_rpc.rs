@@ -0,0 +1,19 @@
+use std::env;
+use std::error::Error;
+use std::io;
+use std::process;
+
+use to_dont::auth::AuthService;
+use to_dont::jsonrpc::Server;
+use to_dont::repository::sqlite::todo_repository::TodoRepository;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        eprintln!("usage: {} <database file>", args[0]);
+        process::exit(2);
+    }
+    let mut server = Server::new(AuthService::new(Some(&args[1]))?, TodoRepository::new(Some(&args[1]))?);
+    server.serve(io::stdin().lock(), io::stdout().lock())?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::process;
+use std::sync::Arc;
+
+use tokio::net::TcpListener;
+
+use to_dont::auth::AuthService;
+use to_dont::http::{self, ApiState};
+use to_dont::models::UserDTO;
+
+fn usage(program: &str) -> ! {
+    eprintln!("usage: {} <database file> [listen address]", program);
+    eprintln!("       {} <database file> --create-admin <first name> <last name> <email>", program);
+    eprintln!("  --create-admin reads the password from stdin and only works while there is no admin");
+    process::exit(2);
+}
+
+/// Register the first admin of a new database, reading their password from stdin.
+fn create_admin(database: &str, first_name: &str, last_name: &str, email: &str) -> Result<(), Box<dyn Error>> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let user = UserDTO { first_name: first_name.to_string(), last_name: last_name.to_string(), email: email.to_string() };
+    let user_id = AuthService::new(Some(database))?.create_first_admin(&user, password.trim_end_matches(['\r', '\n']))?;
+    println!("created admin {} with id {}", email, user_id);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.get(2).map(String::as_str) == Some("--create-admin") {
+        let [_, database, _, first_name, last_name, email] = args.as_slice() else {
+            usage(&args[0]);
+        };
+        return create_admin(database, first_name, last_name, email);
+    }
+    if args.len() < 2 || args.len() > 3 {
+        usage(&args[0]);
+    }
+    let database = args[1].as_str();
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:3000");
+    let state = Arc::new(ApiState::open(Some(database))?);
+    let app = http::router(state.clone());
+    #[cfg(feature = "graphql")]
+    let app = {
+        use to_dont::repository::sqlite::todo_repository::TodoRepository;
+        use to_dont::repository::sqlite::user_repository::UserRepository;
+
+        let schema = to_dont::graphql::build_schema(UserRepository::new(Some(database))?, TodoRepository::new(Some(database))?);
+        app.merge(http::graphql_router(state, schema))
+    };
+    let listener = TcpListener::bind(address).await?;
+    println!("listening on http://{}", listener.local_addr()?);
+    http::serve(listener, app).await?;
+    Ok(())
+}
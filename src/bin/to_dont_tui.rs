@@ -0,0 +1,22 @@
+use std::env;
+use std::error::Error;
+use std::process;
+
+use to_dont::repository::sqlite::todo_repository::TodoRepository;
+use to_dont::tui::{self, App};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <database file> <user id>", args[0]);
+        process::exit(2);
+    }
+    let user_id: i64 = args[2].parse()?;
+    let repo = TodoRepository::new(Some(&args[1]))?;
+    let mut app = App::new(repo, user_id)?;
+
+    let mut terminal = ratatui::init();
+    let result = tui::run(&mut terminal, &mut app);
+    ratatui::restore();
+    Ok(result?)
+}
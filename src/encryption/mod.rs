@@ -0,0 +1,309 @@
+//! Encryption at rest for todo tasks, with a key supplied by the user.
+//!
+//! Turn a repository into an [`EncryptedTodos`] with [`TodoRepository::encrypted`].
+//! Tasks are encrypted with XChaCha20-Poly1305 before they reach the `todos`
+//! table, under a key derived from the user's with Argon2 and a random salt
+//! kept in the database, and decrypted on the way out. The first time a
+//! database is encrypted, the tasks already in it, in its undo history and in
+//! queued webhook payloads are encrypted too.
+//!
+//! From then on the database only opens with its key, through
+//! [`EncryptedTodos::open`]; [`TodoRepository::new`] refuses it, and triggers
+//! reject tasks written in plain text by anything else still connected.
+//!
+//! Only tasks are encrypted: ids, owners and dates stay readable so queries
+//! keep working. Anything that reads tasks straight from the database, such
+//! as webhooks and shared lists, sees the encrypted form.
+//!
+//! An encrypted database can't be synced, since other databases would only
+//! get the encrypted tasks: encrypting it drops its sync log, and
+//! [`TodoRepository::sync`] refuses it.
+//!
+//! [`TodoRepository::encrypted`]: crate::repository::sqlite::todo_repository::TodoRepository::encrypted
+//! [`TodoRepository::new`]: crate::repository::sqlite::todo_repository::TodoRepository::new
+//! [`TodoRepository::sync`]: crate::repository::sqlite::todo_repository::TodoRepository::sync
+
+use std::io;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand_core::RngCore;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::auth::to_hex;
+use crate::models::{TodoItem, TodoItemDTO, ValidationError};
+use crate::repository::sqlite::table_exists;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::{Repository, RepositoryError, TodoStore};
+use crate::sync::drop_sync_tables;
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Marks an encrypted task, and the format it is in.
+const PREFIX: &str = "enc1:";
+
+/// Encrypted with the key and stored, so a wrong key can be told apart from
+/// damaged data.
+const KEY_CHECK: &str = "to_dont key check";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn invalid_data(message: String) -> RepositoryError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+/// Encrypts and decrypts tasks with one key.
+struct TaskCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl TaskCipher {
+    /// Derive the key for `key` and `salt`.
+    fn new(key: &str, salt: &[u8]) -> Result<TaskCipher> {
+        if key.is_empty() {
+            return Err(ValidationError::field("key", "can't be empty").into());
+        }
+        let mut derived = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(key.as_bytes(), salt, &mut derived)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(TaskCipher { cipher: XChaCha20Poly1305::new(Key::from_slice(&derived)) })
+    }
+
+    /// `task` encrypted under a fresh random nonce, as text.
+    fn encrypt(&self, task: &str) -> Result<String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, task.as_bytes()).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(format!("{}{}{}", PREFIX, to_hex(&nonce), to_hex(&ciphertext)))
+    }
+
+    /// The task `encrypted` holds, or `None` if it wasn't encrypted with this
+    /// key or has been tampered with.
+    fn decrypt(&self, encrypted: &str) -> Option<String> {
+        let bytes = from_hex(encrypted.strip_prefix(PREFIX)?)?;
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(XNonce::from_slice(nonce), ciphertext).ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+/// A random salt, as text.
+fn new_salt() -> String {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    to_hex(&salt)
+}
+
+/// Replace every task, in `todos`, the undo history's snapshots and queued
+/// webhook payloads, with `rewrite(task)`, without counting it as a change to
+/// the todo.
+///
+/// The triggers on `todos` are dropped while the tasks are rewritten and put
+/// back afterwards, so the rewrite queues no webhooks. Call
+/// it inside a transaction.
+fn rewrite_tasks(conn: &Connection, rewrite: impl Fn(&str) -> Result<String>) -> Result<()> {
+    let triggers: Vec<(String, String)> = conn
+        .prepare("SELECT name, sql FROM sqlite_master WHERE type = 'trigger' AND tbl_name = 'todos'")?
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (name, _) in &triggers {
+        conn.execute(&format!("DROP TRIGGER \"{}\"", name), ())?;
+    }
+    let tasks: Vec<(i64, String)> =
+        conn.prepare("SELECT id, task FROM todos")?.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+    for (id, task) in tasks {
+        conn.execute("UPDATE todos SET task = ?1 WHERE id = ?2", params![rewrite(&task)?, id])?;
+    }
+    for (_, sql) in &triggers {
+        conn.execute(sql, ())?;
+    }
+    let snapshots: Vec<(i64, Option<String>, Option<String>)> = conn
+        .prepare("SELECT id, json_extract(before_state, '$.task'), json_extract(after_state, '$.task') FROM todo_history")?
+        .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, before, after) in snapshots {
+        conn.execute(
+            "UPDATE todo_history SET before_state = json_set(before_state, '$.task', ?1), \
+after_state = json_set(after_state, '$.task', ?2) WHERE id = ?3",
+            params![before.as_deref().map(&rewrite).transpose()?, after.as_deref().map(&rewrite).transpose()?, id],
+        )?;
+    }
+    if table_exists(conn, "webhook_outbox")? {
+        let payloads: Vec<(i64, String)> = conn
+            .prepare(
+                "SELECT id, json_extract(payload, '$.todo.task') FROM webhook_outbox \
+WHERE json_extract(payload, '$.todo.task') IS NOT NULL",
+            )?
+            .query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (id, task) in payloads {
+            conn.execute(
+                "UPDATE webhook_outbox SET payload = json_set(payload, '$.todo.task', ?1) WHERE id = ?2",
+                params![rewrite(&task)?, id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Triggers rejecting tasks that aren't encrypted, so connections opened
+/// before the database was encrypted can't write plain text into it.
+fn create_guard_triggers(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TRIGGER IF NOT EXISTS todos_encrypted_insert BEFORE INSERT ON todos \
+WHEN NEW.task NOT LIKE '{prefix}%' BEGIN SELECT RAISE(ABORT, 'todo tasks are encrypted'); END;
+CREATE TRIGGER IF NOT EXISTS todos_encrypted_update BEFORE UPDATE OF task ON todos \
+WHEN NEW.task NOT LIKE '{prefix}%' BEGIN SELECT RAISE(ABORT, 'todo tasks are encrypted'); END;",
+        prefix = PREFIX,
+    ))?;
+    Ok(())
+}
+
+/// Create the table holding the salt and key check, if needed.
+fn create_encryption_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS todo_encryption (\
+id INTEGER PRIMARY KEY CHECK (id = 1),\
+salt TEXT NOT NULL,\
+key_check TEXT NOT NULL\
+)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Todo operations with tasks encrypted at rest, from [`EncryptedTodos::open`]
+/// or [`TodoRepository::encrypted`].
+///
+/// [`TodoRepository::encrypted`]: crate::repository::sqlite::todo_repository::TodoRepository::encrypted
+pub struct EncryptedTodos {
+    repo: TodoRepository,
+    cipher: TaskCipher,
+}
+
+impl EncryptedTodos {
+    /// Open the todos at `connection_string`, or an in-memory db if there is
+    /// none, with `key`, setting the database up for encryption with `key`
+    /// if this is the first time.
+    ///
+    /// Fails with `RepositoryError::WrongKey` if the database was encrypted
+    /// with a different key.
+    pub fn open(connection_string: Option<&str>, key: &str) -> Result<EncryptedTodos> {
+        EncryptedTodos::new(TodoRepository::open(connection_string)?, key)
+    }
+
+    /// Unlock `repo`'s database with `key`, or set it up for encryption with
+    /// `key` if this is the first time.
+    pub(crate) fn new(repo: TodoRepository, key: &str) -> Result<EncryptedTodos> {
+        let conn = repo.connection();
+        create_encryption_table(conn)?;
+        let stored: Option<(String, String)> = conn
+            .query_row("SELECT salt, key_check FROM todo_encryption WHERE id = 1", (), |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        let cipher = match stored {
+            Some((salt, key_check)) => {
+                let salt = from_hex(&salt).ok_or_else(|| invalid_data("the encryption salt is damaged".to_string()))?;
+                let cipher = TaskCipher::new(key, &salt)?;
+                if cipher.decrypt(&key_check).as_deref() != Some(KEY_CHECK) {
+                    return Err(RepositoryError::WrongKey);
+                }
+                cipher
+            }
+            None => {
+                let salt = new_salt();
+                let cipher = TaskCipher::new(key, &from_hex(&salt).unwrap_or_default())?;
+                let tx = conn.unchecked_transaction()?;
+                drop_sync_tables(&tx)?;
+                rewrite_tasks(&tx, |task| cipher.encrypt(task))?;
+                create_guard_triggers(&tx)?;
+                tx.execute(
+                    "INSERT INTO todo_encryption (id, salt, key_check) VALUES (1, ?1, ?2)",
+                    params![salt, cipher.encrypt(KEY_CHECK)?],
+                )?;
+                tx.commit()?;
+                cipher
+            }
+        };
+        Ok(EncryptedTodos { repo, cipher })
+    }
+
+    /// Re-encrypt every task under `new_key`, all at once, and use it from
+    /// now on. The old key stops working.
+    pub fn rotate_key(&mut self, new_key: &str) -> Result<()> {
+        let salt = new_salt();
+        let new_cipher = TaskCipher::new(new_key, &from_hex(&salt).unwrap_or_default())?;
+        let tx = self.repo.connection().unchecked_transaction()?;
+        rewrite_tasks(&tx, |task| new_cipher.encrypt(&self.decrypt_task(task)?))?;
+        tx.execute(
+            "UPDATE todo_encryption SET salt = ?1, key_check = ?2 WHERE id = 1",
+            params![salt, new_cipher.encrypt(KEY_CHECK)?],
+        )?;
+        tx.commit()?;
+        self.cipher = new_cipher;
+        Ok(())
+    }
+
+    fn decrypt_task(&self, task: &str) -> Result<String> {
+        self.cipher.decrypt(task).ok_or_else(|| invalid_data("a todo's task can't be decrypted".to_string()))
+    }
+
+    fn decrypt(&self, mut todo: TodoItem) -> Result<TodoItem> {
+        todo.task = self
+            .cipher
+            .decrypt(&todo.task)
+            .ok_or_else(|| invalid_data(format!("the task of todo {} can't be decrypted", todo.id)))?;
+        Ok(todo)
+    }
+
+    fn encrypt(&self, todo_dto: &TodoItemDTO) -> Result<TodoItemDTO> {
+        Ok(TodoItemDTO { user_id: todo_dto.user_id, task: self.cipher.encrypt(&todo_dto.task)? })
+    }
+}
+
+impl Repository<Connection, TodoItem, RepositoryError> for EncryptedTodos {
+    fn connect_to_db(connection_string: &str) -> Result<Connection> {
+        Ok(TodoRepository::connect_to_db(connection_string)?)
+    }
+
+    fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        Ok(self.repo.save_new_item(&self.encrypt(todo_dto)?)?)
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
+        self.decrypt(self.repo.select_item_by_id(id)?)
+    }
+
+    fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        Ok(self.repo.update_item(id, &self.encrypt(todo_dto)?)?)
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        Ok(self.repo.delete_item_by_id(id)?)
+    }
+}
+
+impl TodoStore<Connection, RepositoryError> for EncryptedTodos {
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        self.repo.get_user_todos(user_id)?.into_iter().map(|todo| self.decrypt(todo)).collect()
+    }
+
+    fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        Ok(self.repo.complete_todo_item(id)?)
+    }
+
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        Ok(self.repo.uncomplete_todo_item(id)?)
+    }
+}
@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_graphql::dataloader::Loader;
+
+use crate::models::TodoItem;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+
+/// Batches todo lookups by user id, so resolving `todos` on a list of users
+/// costs one query instead of one per user.
+pub struct TodoLoader {
+    todos: Arc<Mutex<TodoRepository>>,
+}
+
+impl TodoLoader {
+    pub fn new(todos: Arc<Mutex<TodoRepository>>) -> TodoLoader {
+        TodoLoader { todos }
+    }
+}
+
+impl Loader<i64> for TodoLoader {
+    type Value = Vec<TodoItem>;
+    type Error = String;
+
+    async fn load(&self, user_ids: &[i64]) -> Result<HashMap<i64, Vec<TodoItem>>, String> {
+        let todos = self.todos.lock().map_err(|_| "todo repository lock poisoned".to_string())?;
+        todos.get_todos_for_users(user_ids).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,154 @@
+//! GraphQL schema over the SQLite repositories, built on async-graphql.
+//!
+//! Exposes `User` and `TodoItem`, with `User.todos` as a cursor connection.
+//! Todos are fetched through a [`TodoLoader`] so that resolving todos for many
+//! users is batched into a single query.
+//!
+//! Every request acts as the [`Principal`] in its data, which the `/graphql`
+//! route resolves from the bearer token; requests without one are refused.
+//! Todos are reached through [`UserTodos`](crate::auth::UserTodos), and users
+//! follow the same rules as `/users`: admins may list everyone, and anyone
+//! may read their own account, with a token holding `users:manage`.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{Context, EmptySubscription, Error, Object, Result, Schema};
+
+use crate::auth::{AuthError, Principal};
+use crate::models::{Scope, TodoItem, User};
+use crate::repository::{Repository, RepositoryError};
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::sqlite::user_repository::UserRepository;
+
+pub use loader::TodoLoader;
+pub use types::TodoFilter;
+
+mod loader;
+mod types;
+
+pub type TodoSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// Build the schema around the given repositories.
+///
+/// Must be executed inside a tokio runtime, which the data loader uses to run its batches.
+pub fn build_schema(users: UserRepository, todos: TodoRepository) -> TodoSchema {
+    let todos = Arc::new(Mutex::new(todos));
+    Schema::build(Query, Mutation, EmptySubscription)
+        .data(Arc::new(Mutex::new(users)))
+        .data(DataLoader::new(TodoLoader::new(todos.clone()), tokio::spawn))
+        .data(todos)
+        .finish()
+}
+
+fn users<'a>(ctx: &'a Context<'_>) -> Result<MutexGuard<'a, UserRepository>> {
+    ctx.data::<Arc<Mutex<UserRepository>>>()?
+        .lock()
+        .map_err(|_| Error::new("user repository lock poisoned"))
+}
+
+fn todos<'a>(ctx: &'a Context<'_>) -> Result<MutexGuard<'a, TodoRepository>> {
+    ctx.data::<Arc<Mutex<TodoRepository>>>()?
+        .lock()
+        .map_err(|_| Error::new("todo repository lock poisoned"))
+}
+
+/// The principal a request acts as, added to its data by whoever authenticated it.
+fn principal<'a>(ctx: &'a Context<'_>) -> Result<&'a Principal> {
+    ctx.data::<Principal>().map_err(|_| Error::new("not authenticated"))
+}
+
+/// Turn "not found" into `None` so missing ids resolve to null instead of an error.
+fn optional<T>(result: std::result::Result<T, AuthError>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(AuthError::Repository(RepositoryError::NotFound)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn not_found(id: i64) -> Error {
+    Error::new(format!("todo {} not found", id))
+}
+
+/// Like `?`, but with the todo id in the message when it doesn't exist.
+fn existing<T>(id: i64, result: std::result::Result<T, AuthError>) -> Result<T> {
+    optional(result)?.ok_or_else(|| not_found(id))
+}
+
+fn validate_task(task: &str) -> Result<()> {
+    if task.trim().is_empty() {
+        return Err(Error::new("task must not be empty"));
+    }
+    Ok(())
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn user(&self, ctx: &Context<'_>, id: i64) -> Result<Option<User>> {
+        let principal = principal(ctx)?;
+        principal.require_scope(Scope::ManageUsers)?;
+        principal.require_user(&id)?;
+        optional(users(ctx)?.select_item_by_id(&id).map_err(AuthError::from))
+    }
+
+    /// Every user for admins, or just the principal for everyone else.
+    async fn users(&self, ctx: &Context<'_>) -> Result<Vec<User>> {
+        let principal = principal(ctx)?;
+        principal.require_scope(Scope::ManageUsers)?;
+        let users = users(ctx)?;
+        if principal.is_admin() {
+            return Ok(users.get_users()?);
+        }
+        Ok(vec![users.select_item_by_id(&principal.user_id)?])
+    }
+
+    async fn todo(&self, ctx: &Context<'_>, id: i64) -> Result<Option<TodoItem>> {
+        let todos = todos(ctx)?;
+        optional(principal(ctx)?.todos(&todos).get(&id))
+    }
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn create_todo(&self, ctx: &Context<'_>, user_id: i64, task: String) -> Result<TodoItem> {
+        validate_task(&task)?;
+        let todos = todos(ctx)?;
+        let id = principal(ctx)?.todos(&todos).create_for(&user_id, &task)?;
+        Ok(todos.select_item_by_id(&id)?)
+    }
+
+    /// With `version`, fails instead of updating if the todo is no longer at that version.
+    async fn update_todo(&self, ctx: &Context<'_>, id: i64, task: String, version: Option<i64>) -> Result<TodoItem> {
+        validate_task(&task)?;
+        let todos = todos(ctx)?;
+        let user_todos = principal(ctx)?.todos(&todos);
+        existing(id, match version {
+            Some(version) => user_todos.update_if_version(&id, &task, version),
+            None => user_todos.update(&id, &task),
+        })?;
+        Ok(todos.select_item_by_id(&id)?)
+    }
+
+    async fn complete_todo(&self, ctx: &Context<'_>, id: i64) -> Result<TodoItem> {
+        let todos = todos(ctx)?;
+        existing(id, principal(ctx)?.todos(&todos).complete(&id))?;
+        Ok(todos.select_item_by_id(&id)?)
+    }
+
+    async fn uncomplete_todo(&self, ctx: &Context<'_>, id: i64) -> Result<TodoItem> {
+        let todos = todos(ctx)?;
+        existing(id, principal(ctx)?.todos(&todos).uncomplete(&id))?;
+        Ok(todos.select_item_by_id(&id)?)
+    }
+
+    /// Returns whether a todo was deleted.
+    async fn delete_todo(&self, ctx: &Context<'_>, id: i64) -> Result<bool> {
+        let todos = todos(ctx)?;
+        Ok(optional(principal(ctx)?.todos(&todos).delete(&id))?.is_some_and(|deleted| deleted > 0))
+    }
+}
@@ -0,0 +1,114 @@
+use async_graphql::connection::{query, Connection, Edge};
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{Context, InputObject, Object, Result};
+use chrono::{DateTime, Utc};
+
+use crate::graphql::loader::TodoLoader;
+use crate::graphql::{principal, todos};
+use crate::models::{TodoItem, User};
+
+/// Narrows down the todos returned by `User.todos`.
+#[derive(Debug, Default, InputObject)]
+pub struct TodoFilter {
+    /// Only todos with this completion state.
+    pub completed: Option<bool>,
+    /// Only todos whose task contains this text, ignoring case.
+    pub search: Option<String>,
+}
+
+impl TodoFilter {
+    fn matches(&self, todo: &TodoItem) -> bool {
+        if self.completed.is_some_and(|completed| completed != todo.completed) {
+            return false;
+        }
+        match &self.search {
+            Some(search) => todo.task.to_lowercase().contains(&search.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+#[Object]
+impl User {
+    async fn id(&self) -> i64 {
+        self.id
+    }
+
+    async fn first_name(&self) -> &str {
+        &self.first_name
+    }
+
+    async fn last_name(&self) -> &str {
+        &self.last_name
+    }
+
+    async fn email(&self) -> &str {
+        &self.email
+    }
+
+    async fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// The user's todos in id order, paged with the todo id as the cursor.
+    /// Only the user themselves and admins may see them.
+    async fn todos(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TodoFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<i64, TodoItem>> {
+        principal(ctx)?.todos(&*todos(ctx)?).check_list_for(&self.id)?;
+        let loader = ctx.data::<DataLoader<TodoLoader>>()?;
+        let todos = loader.load_one(self.id).await?.unwrap_or_default();
+        let filter = filter.unwrap_or_default();
+        query(after, None, first, None, |after: Option<i64>, _, first, _| async move {
+            let matching: Vec<TodoItem> = todos.into_iter().filter(|todo| filter.matches(todo)).collect();
+            let start = match after {
+                Some(after) => matching.iter().position(|todo| todo.id > after).unwrap_or(matching.len()),
+                None => 0,
+            };
+            let end = first.map_or(matching.len(), |first| (start + first).min(matching.len()));
+            let mut connection = Connection::new(start > 0, end < matching.len());
+            connection.edges.extend(
+                matching[start..end]
+                    .iter()
+                    .map(|todo| Edge::new(todo.id, todo.clone())),
+            );
+            Ok::<_, async_graphql::Error>(connection)
+        })
+        .await
+    }
+}
+
+#[Object]
+impl TodoItem {
+    async fn id(&self) -> i64 {
+        self.id
+    }
+
+    async fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    async fn task(&self) -> &str {
+        &self.task
+    }
+
+    async fn completed(&self) -> bool {
+        self.completed
+    }
+
+    async fn created_datetime(&self) -> DateTime<Utc> {
+        self.created_datetime
+    }
+
+    async fn completed_datetime(&self) -> Option<DateTime<Utc>> {
+        self.completed_datetime
+    }
+
+    async fn version(&self) -> i64 {
+        self.version
+    }
+}
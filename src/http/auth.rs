@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+
+use crate::auth::Principal;
+use crate::http::{ApiError, ApiState};
+
+/// Pull the token out of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Result<&str, ApiError> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| ApiError::Unauthorized("missing bearer token".to_string()))
+}
+
+/// Handlers that take a `Principal` only run for requests with a valid session
+/// or API token; checking scopes is up to each handler.
+impl FromRequestParts<Arc<ApiState>> for Principal {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<ApiState>) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)?.to_string();
+        state.blocking(move |state| Ok(state.auth()?.verify_bearer(&token)?)).await
+    }
+}
@@ -0,0 +1,87 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+
+use crate::auth::AuthError;
+use crate::repository::RepositoryError;
+
+/// An error returned by an API handler, rendered as `{"error": "..."}` with a matching status.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The requested user or todo does not exist (404).
+    NotFound(String),
+    /// The request has no valid credentials (401).
+    Unauthorized(String),
+    /// The credentials are valid but don't allow this request (403).
+    Forbidden(String),
+    /// The item was changed since the version the client sent in `If-Match` (409).
+    Conflict(String),
+    /// The request body was well-formed but its values are not acceptable (422).
+    Validation(String),
+    /// Anything else, such as a database failure (500).
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::NotFound("not found".to_string()),
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<RepositoryError> for ApiError {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::NotFound => ApiError::NotFound("not found".to_string()),
+            RepositoryError::Validation(e) => ApiError::Validation(e.to_string()),
+            e @ RepositoryError::Conflict { .. } => ApiError::Conflict(e.to_string()),
+            RepositoryError::Sqlite(e) => e.into(),
+            RepositoryError::Io(e) => ApiError::Internal(e.to_string()),
+            #[cfg(feature = "postgres")]
+            RepositoryError::Postgres(e) => ApiError::Internal(e.to_string()),
+            #[cfg(feature = "encryption")]
+            e @ RepositoryError::WrongKey => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Repository(e) => e.into(),
+            AuthError::MissingScope(_) | AuthError::Forbidden => ApiError::Forbidden(e.to_string()),
+            e => ApiError::Unauthorized(e.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let message = match self {
+            ApiError::NotFound(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::Conflict(message)
+            | ApiError::Validation(message)
+            | ApiError::Internal(message) => message,
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
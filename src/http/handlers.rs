@@ -0,0 +1,435 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::auth::{AuthError, Credential, MintedApiToken, Principal};
+use crate::http::auth::bearer_token;
+use crate::http::{ApiError, ApiState};
+use crate::models::{ApiToken, Delegation, Scope, TodoItem, User, UserDTO};
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::{Repository, RepositoryError};
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Request body for creating or updating a todo under `/users/{id}/todos`;
+/// the owner comes from the path.
+#[derive(Debug, Deserialize)]
+pub struct TaskBody {
+    pub task: String,
+}
+
+/// Request body for `POST /auth/register`.
+#[derive(Debug, Deserialize)]
+pub struct RegisterBody {
+    #[serde(flatten)]
+    pub user: UserDTO,
+    pub password: String,
+}
+
+/// Request body for `POST /auth/login`.
+#[derive(Debug, Deserialize)]
+pub struct LoginBody {
+    pub email: String,
+    pub password: String,
+}
+
+/// Response body for `POST /auth/login`.
+#[derive(Debug, Serialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub user_id: i64,
+    pub expires_datetime: DateTime<Utc>,
+}
+
+/// Request body for `POST /todos/{id}/assign`; a `null` assignee unassigns the todo.
+#[derive(Debug, Deserialize)]
+pub struct AssignBody {
+    pub assignee_id: Option<i64>,
+}
+
+/// Request body for `POST /auth/tokens`.
+#[derive(Debug, Deserialize)]
+pub struct NewTokenBody {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+/// Response body for `POST /auth/tokens`; the only time the token itself is shown.
+#[derive(Debug, Serialize)]
+pub struct NewTokenResponse {
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+}
+
+fn validate_task(task: &str) -> ApiResult<()> {
+    if task.trim().is_empty() {
+        return Err(ApiError::Validation("task must not be empty".to_string()));
+    }
+    Ok(())
+}
+
+/// The version in an `If-Match` header, if the client sent one. Quoted
+/// (`"3"`) and bare (`3`) versions are both accepted.
+fn if_match_version(headers: &HeaderMap) -> ApiResult<Option<i64>> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().trim_matches('"').parse().ok())
+        .map(Some)
+        .ok_or_else(|| ApiError::Validation("If-Match must be a version number".to_string()))
+}
+
+fn parse_scopes(scopes: &[String]) -> ApiResult<Vec<Scope>> {
+    scopes
+        .iter()
+        .map(|scope| Scope::parse(scope).ok_or_else(|| ApiError::Validation(format!("unknown scope {:?}", scope))))
+        .collect()
+}
+
+fn user_not_found(id: i64) -> ApiError {
+    ApiError::NotFound(format!("user {} not found", id))
+}
+
+fn todo_not_found(id: i64) -> impl FnOnce(AuthError) -> ApiError {
+    move |e| match e {
+        AuthError::Repository(RepositoryError::NotFound) => ApiError::NotFound(format!("todo {} not found", id)),
+        e => e.into(),
+    }
+}
+
+/// Accounts under `/users/{id}` can only be seen and changed by that user or an admin,
+/// with a token holding `users:manage`.
+fn require_user(principal: &Principal, user_id: i64) -> ApiResult<()> {
+    principal.require_scope(Scope::ManageUsers)?;
+    principal.require_user(&user_id)?;
+    Ok(())
+}
+
+/// Listing and creating users under `/users` is for admins, with a token
+/// holding `users:manage`. Everyone else signs up through `/auth/register`.
+fn require_admin(principal: &Principal) -> ApiResult<()> {
+    principal.require_scope(Scope::ManageUsers)?;
+    if !principal.is_admin() {
+        return Err(ApiError::Forbidden("only admins may list or create users".to_string()));
+    }
+    Ok(())
+}
+
+/// `/users/{id}/todos/{todo_id}` only finds todos that belong to user `id`.
+fn require_todo_of(principal: &Principal, todos: &TodoRepository, user_id: i64, todo_id: i64) -> ApiResult<()> {
+    principal.require_user(&user_id)?;
+    match todos.select_item_by_id(&todo_id) {
+        Ok(todo) if todo.user_id == user_id => Ok(()),
+        Ok(_) | Err(rusqlite::Error::QueryReturnedNoRows) => Err(ApiError::NotFound(format!("todo {} not found", todo_id))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// API tokens can't be used to manage API tokens.
+fn require_session(principal: &Principal) -> ApiResult<()> {
+    match principal.credential {
+        Credential::Session { .. } => Ok(()),
+        Credential::ApiToken { .. } => Err(ApiError::Forbidden("API tokens are managed with a login session".to_string())),
+    }
+}
+
+fn existing_user(state: &ApiState, id: i64) -> ApiResult<User> {
+    state.auth()?.users().select_item_by_id(&id).map_err(|e| match e {
+        RepositoryError::NotFound => user_not_found(id),
+        e => e.into(),
+    })
+}
+
+pub async fn register(
+    State(state): State<Arc<ApiState>>,
+    Json(body): Json<RegisterBody>,
+) -> ApiResult<(StatusCode, Json<User>)> {
+    state
+        .blocking(move |state| {
+            let auth = state.auth()?;
+            let id = auth.register(&body.user, &body.password)?;
+            Ok((StatusCode::CREATED, Json(auth.users().select_item_by_id(&id)?)))
+        })
+        .await
+}
+
+pub async fn login(State(state): State<Arc<ApiState>>, Json(body): Json<LoginBody>) -> ApiResult<Json<LoginResponse>> {
+    let session = state.blocking(move |state| Ok(state.auth()?.authenticate(&body.email, &body.password)?)).await?;
+    Ok(Json(LoginResponse {
+        token: session.token,
+        user_id: session.principal.user_id,
+        expires_datetime: session.expires_datetime,
+    }))
+}
+
+pub async fn logout(State(state): State<Arc<ApiState>>, headers: HeaderMap) -> ApiResult<StatusCode> {
+    let token = bearer_token(&headers)?.to_string();
+    state.blocking(move |state| Ok(state.auth()?.revoke_session(&token)?)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_api_token(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Json(body): Json<NewTokenBody>,
+) -> ApiResult<(StatusCode, Json<NewTokenResponse>)> {
+    require_session(&principal)?;
+    let scopes = parse_scopes(&body.scopes)?;
+    let MintedApiToken { token, api_token } =
+        state.blocking(move |state| Ok(state.auth()?.mint_api_token(&principal.user_id, &body.name, &scopes)?)).await?;
+    Ok((StatusCode::CREATED, Json(NewTokenResponse { token, api_token })))
+}
+
+pub async fn list_api_tokens(State(state): State<Arc<ApiState>>, principal: Principal) -> ApiResult<Json<Vec<ApiToken>>> {
+    require_session(&principal)?;
+    state.blocking(move |state| Ok(Json(state.auth()?.list_api_tokens(&principal.user_id)?))).await
+}
+
+pub async fn revoke_api_token(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+) -> ApiResult<StatusCode> {
+    require_session(&principal)?;
+    state
+        .blocking(move |state| {
+            state.auth()?.revoke_api_token(&principal.user_id, &id).map_err(|e| match e {
+                AuthError::Repository(RepositoryError::NotFound) => ApiError::NotFound(format!("API token {} not found", id)),
+                e => e.into(),
+            })
+        })
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_users(State(state): State<Arc<ApiState>>, principal: Principal) -> ApiResult<Json<Vec<User>>> {
+    require_admin(&principal)?;
+    state.blocking(|state| Ok(Json(state.auth()?.users().get_users()?))).await
+}
+
+pub async fn create_user(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Json(user): Json<UserDTO>,
+) -> ApiResult<(StatusCode, Json<User>)> {
+    require_admin(&principal)?;
+    state
+        .blocking(move |state| {
+            let auth = state.auth()?;
+            let id = auth.users().save_new_item(&user)?;
+            Ok((StatusCode::CREATED, Json(auth.users().select_item_by_id(&id)?)))
+        })
+        .await
+}
+
+pub async fn get_user(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<User>> {
+    require_user(&principal, id)?;
+    state.blocking(move |state| Ok(Json(existing_user(state, id)?))).await
+}
+
+pub async fn update_user(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(user): Json<UserDTO>,
+) -> ApiResult<Json<User>> {
+    require_user(&principal, id)?;
+    let version = if_match_version(&headers)?;
+    state
+        .blocking(move |state| {
+            let auth = state.auth()?;
+            let updated = match version {
+                Some(version) => auth.users().update_item_if_version(&id, &user, version).map_err(|e| match e {
+                    RepositoryError::NotFound => user_not_found(id),
+                    e => e.into(),
+                })?,
+                None => auth.users().update_item(&id, &user)?,
+            };
+            if updated == 0 {
+                return Err(user_not_found(id));
+            }
+            Ok(Json(auth.users().select_item_by_id(&id)?))
+        })
+        .await
+}
+
+pub async fn delete_user(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+) -> ApiResult<StatusCode> {
+    require_user(&principal, id)?;
+    if state.blocking(move |state| Ok(state.auth()?.users().delete_account(&id)?)).await? == 0 {
+        return Err(user_not_found(id));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_user_todos(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(user_id): Path<i64>,
+) -> ApiResult<Json<Vec<TodoItem>>> {
+    state.blocking(move |state| Ok(Json(principal.todos(&*state.todos()?).list_for(&user_id)?))).await
+}
+
+pub async fn create_user_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(user_id): Path<i64>,
+    Json(body): Json<TaskBody>,
+) -> ApiResult<(StatusCode, Json<TodoItem>)> {
+    validate_task(&body.task)?;
+    state
+        .blocking(move |state| {
+            let todos = state.todos()?;
+            let id = principal.todos(&todos).create_for(&user_id, &body.task)?;
+            Ok((StatusCode::CREATED, Json(todos.select_item_by_id(&id)?)))
+        })
+        .await
+}
+
+pub async fn get_user_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path((user_id, todo_id)): Path<(i64, i64)>,
+) -> ApiResult<Json<TodoItem>> {
+    state
+        .blocking(move |state| {
+            let todos = state.todos()?;
+            require_todo_of(&principal, &todos, user_id, todo_id)?;
+            Ok(Json(principal.todos(&todos).get(&todo_id).map_err(todo_not_found(todo_id))?))
+        })
+        .await
+}
+
+pub async fn update_user_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path((user_id, todo_id)): Path<(i64, i64)>,
+    headers: HeaderMap,
+    Json(body): Json<TaskBody>,
+) -> ApiResult<Json<TodoItem>> {
+    validate_task(&body.task)?;
+    let version = if_match_version(&headers)?;
+    state
+        .blocking(move |state| {
+            let todos = state.todos()?;
+            require_todo_of(&principal, &todos, user_id, todo_id)?;
+            let user_todos = principal.todos(&todos);
+            match version {
+                Some(version) => user_todos.update_if_version(&todo_id, &body.task, version),
+                None => user_todos.update(&todo_id, &body.task),
+            }
+            .map_err(todo_not_found(todo_id))?;
+            Ok(Json(todos.select_item_by_id(&todo_id)?))
+        })
+        .await
+}
+
+pub async fn delete_user_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path((user_id, todo_id)): Path<(i64, i64)>,
+) -> ApiResult<StatusCode> {
+    state
+        .blocking(move |state| {
+            let todos = state.todos()?;
+            require_todo_of(&principal, &todos, user_id, todo_id)?;
+            principal.todos(&todos).delete(&todo_id).map_err(todo_not_found(todo_id))
+        })
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn complete_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<TodoItem>> {
+    state
+        .blocking(move |state| {
+            let todos = state.todos()?;
+            principal.todos(&todos).complete(&id).map_err(todo_not_found(id))?;
+            Ok(Json(todos.select_item_by_id(&id)?))
+        })
+        .await
+}
+
+pub async fn uncomplete_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<TodoItem>> {
+    state
+        .blocking(move |state| {
+            let todos = state.todos()?;
+            principal.todos(&todos).uncomplete(&id).map_err(todo_not_found(id))?;
+            Ok(Json(todos.select_item_by_id(&id)?))
+        })
+        .await
+}
+
+pub async fn assign_todo(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+    Json(body): Json<AssignBody>,
+) -> ApiResult<Json<TodoItem>> {
+    state
+        .blocking(move |state| {
+            // authorize before looking up the assignee, so ids can't be probed
+            principal.todos(&*state.todos()?).check_assign(&id).map_err(todo_not_found(id))?;
+            if let Some(assignee_id) = body.assignee_id {
+                existing_user(state, assignee_id)?;
+            }
+            let todos = state.todos()?;
+            principal.todos(&todos).assign(&id, body.assignee_id).map_err(todo_not_found(id))?;
+            Ok(Json(todos.select_item_by_id(&id)?))
+        })
+        .await
+}
+
+pub async fn assigned_todos(State(state): State<Arc<ApiState>>, principal: Principal) -> ApiResult<Json<Vec<TodoItem>>> {
+    state.blocking(move |state| Ok(Json(principal.todos(&*state.todos()?).assigned_to_me()?))).await
+}
+
+pub async fn todo_delegations(
+    State(state): State<Arc<ApiState>>,
+    principal: Principal,
+    Path(id): Path<i64>,
+) -> ApiResult<Json<Vec<Delegation>>> {
+    state
+        .blocking(move |state| Ok(Json(principal.todos(&*state.todos()?).delegations(&id).map_err(todo_not_found(id))?)))
+        .await
+}
+
+pub async fn openapi() -> Json<Value> {
+    Json(crate::http::openapi_document())
+}
+
+/// Run a GraphQL request as the principal of its bearer token.
+#[cfg(feature = "graphql")]
+pub async fn graphql(
+    State((state, schema)): State<(Arc<ApiState>, crate::graphql::TodoSchema)>,
+    headers: HeaderMap,
+    Json(request): Json<async_graphql::Request>,
+) -> ApiResult<Json<async_graphql::Response>> {
+    let token = bearer_token(&headers)?.to_string();
+    let principal = state.blocking(move |state| Ok(state.auth()?.verify_bearer(&token)?)).await?;
+    Ok(Json(schema.execute(request.data(principal)).await))
+}
@@ -0,0 +1,128 @@
+//! REST/JSON API over the SQLite repositories, built on axum.
+//!
+//! Build a router with [`router`] and either serve it with [`serve`] or drive
+//! it directly as a tower service (which is what the integration tests do).
+//! Todo routes act as the principal of the `Authorization: Bearer` token,
+//! either a session from `POST /auth/login` or an API token from
+//! `POST /auth/tokens`, which must carry the scope the route needs.
+//! Repository errors are mapped to status codes by [`ApiError`], and the routes
+//! are described by the document returned from [`openapi_document`], which is
+//! also served at `/openapi.json`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use axum::routing::{delete, get, post};
+use axum::Router;
+use tokio::net::TcpListener;
+
+use crate::auth::AuthService;
+#[cfg(feature = "graphql")]
+use crate::graphql::TodoSchema;
+use crate::repository::RepositoryError;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+
+pub use error::ApiError;
+pub use openapi::openapi_document;
+
+mod auth;
+mod error;
+mod handlers;
+mod openapi;
+
+/// The services shared by every request.
+///
+/// SQLite connections can't be used from several threads at once, so each
+/// one sits behind its own mutex. Users are reached through the auth service.
+pub struct ApiState {
+    auth: Mutex<AuthService>,
+    todos: Mutex<TodoRepository>,
+}
+
+impl ApiState {
+    pub fn new(auth: AuthService, todos: TodoRepository) -> ApiState {
+        ApiState { auth: Mutex::new(auth), todos: Mutex::new(todos) }
+    }
+
+    /// Open everything on the same database file, or in memory if no
+    /// connection string is provided.
+    ///
+    /// The in-memory database is a named, shared-cache one, so users and
+    /// todos live in the same database; each call gets a fresh one.
+    pub fn open(connection_string: Option<&str>) -> Result<ApiState, RepositoryError> {
+        static IN_MEMORY_COUNT: AtomicUsize = AtomicUsize::new(0);
+        let in_memory;
+        let connection_string = match connection_string {
+            Some(connection_string) => connection_string,
+            None => {
+                let n = IN_MEMORY_COUNT.fetch_add(1, Ordering::Relaxed);
+                in_memory = format!("file:to_dont_api_{}?mode=memory&cache=shared", n);
+                &in_memory
+            }
+        };
+        Ok(ApiState::new(AuthService::new(Some(connection_string))?, TodoRepository::new(Some(connection_string))?))
+    }
+
+    /// Run `work` on tokio's blocking threads. Password hashing and waiting
+    /// for a connection's mutex would otherwise hold up every other request
+    /// on the same worker thread.
+    async fn blocking<T: Send + 'static>(
+        self: &Arc<Self>,
+        work: impl FnOnce(&ApiState) -> Result<T, ApiError> + Send + 'static,
+    ) -> Result<T, ApiError> {
+        let state = Arc::clone(self);
+        tokio::task::spawn_blocking(move || work(&state))
+            .await
+            .map_err(|e| ApiError::Internal(format!("request failed: {}", e)))?
+    }
+
+    fn auth(&self) -> Result<MutexGuard<'_, AuthService>, ApiError> {
+        self.auth.lock().map_err(|_| ApiError::Internal("auth service lock poisoned".to_string()))
+    }
+
+    fn todos(&self) -> Result<MutexGuard<'_, TodoRepository>, ApiError> {
+        self.todos.lock().map_err(|_| ApiError::Internal("todo repository lock poisoned".to_string()))
+    }
+}
+
+/// Build the API router.
+pub fn router(state: Arc<ApiState>) -> Router {
+    Router::new()
+        .route("/auth/register", post(handlers::register))
+        .route("/auth/login", post(handlers::login))
+        .route("/auth/logout", post(handlers::logout))
+        .route("/auth/tokens", get(handlers::list_api_tokens).post(handlers::create_api_token))
+        .route("/auth/tokens/{id}", delete(handlers::revoke_api_token))
+        .route("/users", get(handlers::list_users).post(handlers::create_user))
+        .route(
+            "/users/{id}",
+            get(handlers::get_user).put(handlers::update_user).delete(handlers::delete_user),
+        )
+        .route("/users/{id}/todos", get(handlers::list_user_todos).post(handlers::create_user_todo))
+        .route(
+            "/users/{id}/todos/{todo_id}",
+            get(handlers::get_user_todo)
+                .put(handlers::update_user_todo)
+                .delete(handlers::delete_user_todo),
+        )
+        .route("/todos/{id}/complete", post(handlers::complete_todo))
+        .route("/todos/{id}/uncomplete", post(handlers::uncomplete_todo))
+        .route("/todos/{id}/assign", post(handlers::assign_todo))
+        .route("/todos/{id}/delegations", get(handlers::todo_delegations))
+        .route("/todos/assigned", get(handlers::assigned_todos))
+        .route("/openapi.json", get(handlers::openapi))
+        .with_state(state)
+}
+
+/// Build a router serving the GraphQL schema at `POST /graphql`, to be merged into [`router`].
+///
+/// Requests need a bearer token like the todo routes, verified against `state`.
+#[cfg(feature = "graphql")]
+pub fn graphql_router(state: Arc<ApiState>, schema: TodoSchema) -> Router {
+    Router::new().route("/graphql", post(handlers::graphql)).with_state((state, schema))
+}
+
+/// Serve a router on an already-bound listener until the process is stopped.
+pub async fn serve(listener: TcpListener, app: Router) -> std::io::Result<()> {
+    axum::serve(listener, app).await
+}
@@ -0,0 +1,414 @@
+use serde_json::{json, Value};
+
+fn json_body(schema: Value) -> Value {
+    json!({ "content": { "application/json": { "schema": schema } } })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn array_of(name: &str) -> Value {
+    json!({ "type": "array", "items": schema_ref(name) })
+}
+
+fn response(description: &str, schema: Option<Value>) -> Value {
+    let mut response = json!({ "description": description });
+    if let Some(schema) = schema {
+        response["content"] = json_body(schema)["content"].clone();
+    }
+    response
+}
+
+fn error_response(description: &str) -> Value {
+    response(description, Some(schema_ref("Error")))
+}
+
+fn id_parameter(name: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "schema": { "type": "integer", "format": "int64" } })
+}
+
+/// Mark the `methods` operations under `path` (all of them if empty) as
+/// needing a session or API token.
+fn require_token(document: &mut Value, path: &str, methods: &[&str]) {
+    if let Some(operations) = document["paths"][path].as_object_mut() {
+        for (method, operation) in operations.iter_mut() {
+            if method == "parameters" || !(methods.is_empty() || methods.contains(&method.as_str())) {
+                continue;
+            }
+            operation["security"] = json!([{ "bearerAuth": [] }]);
+            operation["responses"]["401"] = error_response("Missing, expired or revoked token");
+            operation["responses"]["403"] = if path == "/users" {
+                error_response("The caller is not an admin, or the API token lacks the scope")
+            } else if path.starts_with("/users/") {
+                error_response("Belongs to another user and the caller is not an admin, or the API token lacks the scope")
+            } else {
+                error_response("The API token lacks the scope")
+            };
+        }
+    }
+}
+
+/// The OpenAPI 3.0 description of every route served by [`crate::http::router`].
+pub fn openapi_document() -> Value {
+    let user_id = id_parameter("id");
+    let todo_id = id_parameter("todo_id");
+    let if_match = json!({
+        "name": "If-Match",
+        "in": "header",
+        "required": false,
+        "description": "Only update if the item is still at this version",
+        "schema": { "type": "integer", "format": "int64" },
+    });
+    let scope = json!({ "type": "string", "enum": crate::models::Scope::ALL.map(|scope| scope.as_str()) });
+    let mut document = json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "To Don't",
+            "description": "An API for all the things you won't do.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/auth/register": {
+                "post": {
+                    "summary": "Sign up: create a user with a password",
+                    "description": "The only way to create a user without an admin's token.",
+                    "requestBody": json_body(schema_ref("Registration")),
+                    "responses": {
+                        "201": response("The created user", Some(schema_ref("User"))),
+                        "422": error_response("Invalid user or password"),
+                    },
+                },
+            },
+            "/auth/login": {
+                "post": {
+                    "summary": "Exchange an email and password for a session token",
+                    "requestBody": json_body(schema_ref("Login")),
+                    "responses": {
+                        "200": response("A new session", Some(schema_ref("Session"))),
+                        "401": error_response("Wrong email or password"),
+                    },
+                },
+            },
+            "/auth/logout": {
+                "post": {
+                    "summary": "Revoke the current session token",
+                    "security": [{ "bearerAuth": [] }],
+                    "responses": {
+                        "204": response("Logged out", None),
+                        "401": error_response("Missing or unknown session token"),
+                    },
+                },
+            },
+            "/auth/tokens": {
+                "get": {
+                    "summary": "List the current user's API tokens",
+                    "responses": { "200": response("The user's API tokens", Some(array_of("ApiToken"))) },
+                },
+                "post": {
+                    "summary": "Mint an API token for the current user",
+                    "requestBody": json_body(schema_ref("ApiTokenInput")),
+                    "responses": {
+                        "201": response("The new API token, including its secret", Some(schema_ref("NewApiToken"))),
+                        "422": error_response("Invalid name or scopes"),
+                    },
+                },
+            },
+            "/auth/tokens/{id}": {
+                "parameters": [id_parameter("id")],
+                "delete": {
+                    "summary": "Revoke one of the current user's API tokens",
+                    "responses": {
+                        "204": response("Revoked", None),
+                        "404": error_response("No such active API token"),
+                    },
+                },
+            },
+            "/users": {
+                "get": {
+                    "summary": "List users (admins only)",
+                    "responses": { "200": response("All users", Some(array_of("User"))) },
+                },
+                "post": {
+                    "summary": "Create a user without a password (admins only)",
+                    "requestBody": json_body(schema_ref("UserInput")),
+                    "responses": {
+                        "201": response("The created user", Some(schema_ref("User"))),
+                        "422": error_response("Invalid user"),
+                    },
+                },
+            },
+            "/users/{id}": {
+                "parameters": [user_id],
+                "get": {
+                    "summary": "Get a user",
+                    "responses": {
+                        "200": response("The user", Some(schema_ref("User"))),
+                        "404": error_response("No such user"),
+                    },
+                },
+                "put": {
+                    "summary": "Update a user",
+                    "parameters": [if_match],
+                    "requestBody": json_body(schema_ref("UserInput")),
+                    "responses": {
+                        "200": response("The updated user", Some(schema_ref("User"))),
+                        "404": error_response("No such user"),
+                        "409": error_response("The user was changed since the If-Match version"),
+                        "422": error_response("Invalid user"),
+                    },
+                },
+                "delete": {
+                    "summary": "Delete a user",
+                    "responses": {
+                        "204": response("Deleted", None),
+                        "404": error_response("No such user"),
+                    },
+                },
+            },
+            "/users/{id}/todos": {
+                "parameters": [user_id],
+                "get": {
+                    "summary": "List a user's todos",
+                    "responses": {
+                        "200": response("The user's todos", Some(array_of("TodoItem"))),
+                        "404": error_response("No such user"),
+                    },
+                },
+                "post": {
+                    "summary": "Create a todo for a user",
+                    "requestBody": json_body(schema_ref("TaskInput")),
+                    "responses": {
+                        "201": response("The created todo", Some(schema_ref("TodoItem"))),
+                        "404": error_response("No such user"),
+                        "422": error_response("Invalid todo"),
+                    },
+                },
+            },
+            "/users/{id}/todos/{todo_id}": {
+                "parameters": [user_id, todo_id],
+                "get": {
+                    "summary": "Get one of a user's todos",
+                    "responses": {
+                        "200": response("The todo", Some(schema_ref("TodoItem"))),
+                        "404": error_response("No such user or todo"),
+                    },
+                },
+                "put": {
+                    "summary": "Update one of a user's todos",
+                    "parameters": [if_match],
+                    "requestBody": json_body(schema_ref("TaskInput")),
+                    "responses": {
+                        "200": response("The updated todo", Some(schema_ref("TodoItem"))),
+                        "404": error_response("No such user or todo"),
+                        "409": error_response("The todo was changed since the If-Match version"),
+                        "422": error_response("Invalid todo"),
+                    },
+                },
+                "delete": {
+                    "summary": "Delete one of a user's todos",
+                    "responses": {
+                        "204": response("Deleted", None),
+                        "404": error_response("No such user or todo"),
+                    },
+                },
+            },
+            "/todos/{id}/complete": {
+                "parameters": [user_id],
+                "post": {
+                    "summary": "Mark a todo as completed",
+                    "responses": {
+                        "200": response("The completed todo", Some(schema_ref("TodoItem"))),
+                        "404": error_response("No such todo"),
+                    },
+                },
+            },
+            "/todos/{id}/uncomplete": {
+                "parameters": [user_id],
+                "post": {
+                    "summary": "Mark a todo as not completed",
+                    "responses": {
+                        "200": response("The uncompleted todo", Some(schema_ref("TodoItem"))),
+                        "404": error_response("No such todo"),
+                    },
+                },
+            },
+            "/todos/{id}/assign": {
+                "parameters": [user_id],
+                "post": {
+                    "summary": "Assign a todo to a user, or unassign it with a null assignee",
+                    "requestBody": json_body(schema_ref("AssignInput")),
+                    "responses": {
+                        "200": response("The assigned todo", Some(schema_ref("TodoItem"))),
+                        "404": error_response("No such todo or assignee"),
+                    },
+                },
+            },
+            "/todos/{id}/delegations": {
+                "parameters": [user_id],
+                "get": {
+                    "summary": "Who passed a todo to whom, oldest first",
+                    "responses": {
+                        "200": response("The todo's delegation log", Some(array_of("Delegation"))),
+                        "404": error_response("No such todo"),
+                    },
+                },
+            },
+            "/todos/assigned": {
+                "get": {
+                    "summary": "List the todos assigned to the current user",
+                    "responses": { "200": response("The assigned todos", Some(array_of("TodoItem"))) },
+                },
+            },
+        },
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": {
+                "Registration": {
+                    "type": "object",
+                    "required": ["first_name", "last_name", "email", "password"],
+                    "properties": {
+                        "first_name": { "type": "string" },
+                        "last_name": { "type": "string" },
+                        "email": { "type": "string" },
+                        "password": { "type": "string", "minLength": crate::auth::MIN_PASSWORD_LENGTH },
+                    },
+                },
+                "Login": {
+                    "type": "object",
+                    "required": ["email", "password"],
+                    "properties": {
+                        "email": { "type": "string" },
+                        "password": { "type": "string" },
+                    },
+                },
+                "Session": {
+                    "type": "object",
+                    "required": ["token", "user_id", "expires_datetime"],
+                    "properties": {
+                        "token": { "type": "string" },
+                        "user_id": { "type": "integer", "format": "int64" },
+                        "expires_datetime": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "ApiTokenInput": {
+                    "type": "object",
+                    "required": ["name", "scopes"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "scopes": { "type": "array", "items": scope.clone() },
+                    },
+                },
+                "ApiToken": {
+                    "type": "object",
+                    "required": ["id", "user_id", "name", "scopes", "created_datetime"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64" },
+                        "user_id": { "type": "integer", "format": "int64" },
+                        "name": { "type": "string" },
+                        "scopes": { "type": "array", "items": scope.clone() },
+                        "created_datetime": { "type": "string", "format": "date-time" },
+                        "last_used_datetime": { "type": "string", "format": "date-time", "nullable": true },
+                        "revoked_datetime": { "type": "string", "format": "date-time", "nullable": true },
+                    },
+                },
+                "NewApiToken": {
+                    "allOf": [
+                        schema_ref("ApiToken"),
+                        { "type": "object", "required": ["token"], "properties": { "token": { "type": "string" } } },
+                    ],
+                },
+                "User": {
+                    "type": "object",
+                    "required": ["id", "first_name", "last_name", "email", "role", "version"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64" },
+                        "first_name": { "type": "string" },
+                        "last_name": { "type": "string" },
+                        "email": { "type": "string" },
+                        "role": { "type": "string", "enum": crate::models::Role::ALL.map(|role| role.as_str()) },
+                        "version": { "type": "integer", "format": "int64" },
+                    },
+                },
+                "UserInput": {
+                    "type": "object",
+                    "required": ["first_name", "last_name", "email"],
+                    "properties": {
+                        "first_name": { "type": "string" },
+                        "last_name": { "type": "string" },
+                        "email": { "type": "string" },
+                    },
+                },
+                "TodoItem": {
+                    "type": "object",
+                    "required": ["id", "user_id", "task", "completed", "created_datetime", "deferral_count", "version"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64" },
+                        "user_id": { "type": "integer", "format": "int64" },
+                        "task": { "type": "string" },
+                        "completed": { "type": "boolean" },
+                        "created_datetime": { "type": "string", "format": "date-time" },
+                        "completed_datetime": { "type": "string", "format": "date-time", "nullable": true },
+                        "list_id": { "type": "integer", "format": "int64", "nullable": true },
+                        "assignee_id": { "type": "integer", "format": "int64", "nullable": true },
+                        "organization_id": { "type": "integer", "format": "int64", "nullable": true },
+                        "snoozed_until": { "type": "string", "format": "date-time", "nullable": true },
+                        "deferral_count": { "type": "integer", "format": "int64" },
+                        "refused_datetime": { "type": "string", "format": "date-time", "nullable": true },
+                        "refusal_reason": {
+                            "type": "string",
+                            "enum": crate::models::RefusalReason::ALL.map(|reason| reason.as_str()),
+                            "nullable": true,
+                        },
+                        "version": { "type": "integer", "format": "int64" },
+                    },
+                },
+                "AssignInput": {
+                    "type": "object",
+                    "required": ["assignee_id"],
+                    "properties": { "assignee_id": { "type": "integer", "format": "int64", "nullable": true } },
+                },
+                "Delegation": {
+                    "type": "object",
+                    "required": ["id", "todo_id", "delegated_by", "delegated_datetime"],
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64" },
+                        "todo_id": { "type": "integer", "format": "int64" },
+                        "from_user_id": { "type": "integer", "format": "int64", "nullable": true },
+                        "to_user_id": { "type": "integer", "format": "int64", "nullable": true },
+                        "delegated_by": { "type": "integer", "format": "int64" },
+                        "delegated_datetime": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "TaskInput": {
+                    "type": "object",
+                    "required": ["task"],
+                    "properties": { "task": { "type": "string" } },
+                },
+                "Error": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": { "error": { "type": "string" } },
+                },
+            },
+        },
+    });
+    for path in [
+        "/auth/tokens",
+        "/auth/tokens/{id}",
+        "/users",
+        "/users/{id}",
+        "/users/{id}/todos",
+        "/users/{id}/todos/{todo_id}",
+        "/todos/{id}/complete",
+        "/todos/{id}/uncomplete",
+        "/todos/{id}/assign",
+        "/todos/{id}/delegations",
+        "/todos/assigned",
+    ] {
+        require_token(&mut document, path, &[]);
+    }
+    document
+}
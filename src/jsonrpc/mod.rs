@@ -0,0 +1,19 @@
+//! JSON-RPC 2.0 server for editor plugins and scripts, speaking newline-delimited
+//! JSON over stdin/stdout.
+//!
+//! Methods are named after the repository operations they call, e.g.
+//! `users.select_item_by_id` or `todos.complete_todo_item`, and take named
+//! params (`{"id": 1}`, `{"user_id": 1}`, or `{"id": 1, "item": {...}}` for
+//! updates, plus `"expected_version"` for `update_item_if_version`). Every successful todo write is followed by a `todos.changed`
+//! notification carrying the todo id and the kind of change.
+//!
+//! Clients first call `auth.authenticate` with `{"token": ...}`, a session or
+//! API token from the REST API; until then every other method fails with
+//! [`UNAUTHORIZED`](protocol::UNAUTHORIZED). Methods then act as that
+//! principal, with todos reached through [`UserTodos`](crate::auth::UserTodos).
+
+pub use protocol::{Notification, Request, Response, RpcError};
+pub use server::{Server, TODOS_CHANGED};
+
+pub mod protocol;
+mod server;
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+
+use crate::auth::AuthError;
+use crate::repository::RepositoryError;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// Server-defined: the requested user or todo does not exist.
+pub const NOT_FOUND: i64 = -32001;
+/// Server-defined: the item is no longer at the version the client expected.
+pub const CONFLICT: i64 = -32002;
+/// Server-defined: the connection hasn't authenticated, or its token is no
+/// longer valid.
+pub const UNAUTHORIZED: i64 = -32003;
+/// Server-defined: the principal may not do this, or its token lacks the scope.
+pub const FORBIDDEN: i64 = -32004;
+
+/// A request, or a notification if it has no `id`.
+///
+/// An `"id": null` is still a request: it is `Some(Value::Null)` and gets a
+/// response with a null id.
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default, deserialize_with = "present")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Option<Value>,
+}
+
+/// Deserialize a field that is present, keeping a `null` as `Some(Value::Null)`
+/// rather than `None`, which is left for a missing field.
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> RpcError {
+        RpcError { code, message: message.into(), data: None }
+    }
+}
+
+impl From<RepositoryError> for RpcError {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::NotFound => RpcError::new(NOT_FOUND, "not found"),
+            RepositoryError::Validation(e) => RpcError {
+                code: INVALID_PARAMS,
+                message: e.to_string(),
+                data: Some(json!(e.errors)),
+            },
+            RepositoryError::Conflict { expected, actual } => RpcError {
+                code: CONFLICT,
+                message: e.to_string(),
+                data: Some(json!({ "expected": expected, "actual": actual })),
+            },
+            RepositoryError::Sqlite(e) => e.into(),
+            RepositoryError::Io(e) => RpcError::new(INTERNAL_ERROR, e.to_string()),
+            #[cfg(feature = "postgres")]
+            RepositoryError::Postgres(e) => RpcError::new(INTERNAL_ERROR, e.to_string()),
+            #[cfg(feature = "encryption")]
+            RepositoryError::WrongKey => RpcError::new(INTERNAL_ERROR, e.to_string()),
+        }
+    }
+}
+
+impl From<AuthError> for RpcError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidCredentials | AuthError::InvalidToken => RpcError::new(UNAUTHORIZED, e.to_string()),
+            AuthError::MissingScope(_) | AuthError::Forbidden => RpcError::new(FORBIDDEN, e.to_string()),
+            AuthError::Repository(e) => e.into(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for RpcError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => RpcError::new(NOT_FOUND, "not found"),
+            e => RpcError::new(INTERNAL_ERROR, e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RpcError {
+    fn from(e: serde_json::Error) -> Self {
+        RpcError::new(INVALID_PARAMS, e.to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn success(id: Value, result: Value) -> Response {
+        Response { jsonrpc: "2.0".to_string(), id, result: Some(result), error: None }
+    }
+
+    pub fn failure(id: Value, error: RpcError) -> Response {
+        Response { jsonrpc: "2.0".to_string(), id, result: None, error: Some(error) }
+    }
+}
+
+/// A message pushed to the client without a request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Value,
+}
+
+impl Notification {
+    pub fn new(method: &str, params: Value) -> Notification {
+        Notification { jsonrpc: "2.0".to_string(), method: method.to_string(), params }
+    }
+}
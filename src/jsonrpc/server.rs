@@ -0,0 +1,245 @@
+use std::io::{self, BufRead, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::{AuthService, Principal};
+use crate::jsonrpc::protocol::{
+    Notification, Request, Response, RpcError, FORBIDDEN, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND,
+    PARSE_ERROR, UNAUTHORIZED,
+};
+use crate::models::{Scope, TodoItemDTO, UserDTO};
+use crate::repository::Repository;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+
+/// The notification method sent whenever a todo is written.
+pub const TODOS_CHANGED: &str = "todos.changed";
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenParams {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct UserIdParams {
+    user_id: i64,
+}
+
+#[derive(Deserialize)]
+struct UpdateParams<T> {
+    id: i64,
+    item: T,
+}
+
+#[derive(Deserialize)]
+struct VersionedUpdateParams<T> {
+    id: i64,
+    item: T,
+    expected_version: i64,
+}
+
+fn parse_params<T: DeserializeOwned>(params: Option<Value>) -> Result<T, RpcError> {
+    Ok(serde_json::from_value(params.unwrap_or(Value::Null))?)
+}
+
+/// Serves repository operations as JSON-RPC 2.0, one message per line.
+///
+/// A connection starts unauthenticated; `auth.authenticate` with a session or
+/// API token makes every later call act as that token's principal, checked
+/// again on each call so revoked tokens stop working.
+pub struct Server {
+    auth: AuthService,
+    todos: TodoRepository,
+    token: Option<String>,
+}
+
+impl Server {
+    pub fn new(auth: AuthService, todos: TodoRepository) -> Server {
+        Server { auth, todos, token: None }
+    }
+
+    /// The principal of the connection's token.
+    fn principal(&self) -> Result<Principal, RpcError> {
+        let token = self.token.as_deref().ok_or_else(|| RpcError::new(UNAUTHORIZED, "call auth.authenticate first"))?;
+        Ok(self.auth.verify_bearer(token)?)
+    }
+
+    /// Read messages from `input` until it is closed, writing responses and
+    /// notifications to `output` as they are produced.
+    pub fn serve<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            for message in self.handle_line(&line) {
+                writeln!(output, "{}", message)?;
+            }
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Handle one line of input (a request, a notification or a batch), returning
+    /// the messages to send back in order: the response(s), then any notifications.
+    pub fn handle_line(&mut self, line: &str) -> Vec<Value> {
+        let mut notifications = Vec::new();
+        let responses = match serde_json::from_str::<Value>(line) {
+            Err(e) => vec![Some(Response::failure(Value::Null, RpcError::new(PARSE_ERROR, e.to_string())))],
+            Ok(Value::Array(batch)) if batch.is_empty() => {
+                vec![Some(Response::failure(Value::Null, RpcError::new(INVALID_REQUEST, "empty batch")))]
+            }
+            Ok(Value::Array(batch)) => batch
+                .into_iter()
+                .map(|message| self.handle_message(message, &mut notifications))
+                .collect(),
+            Ok(message) => vec![self.handle_message(message, &mut notifications)],
+        };
+        let mut responses: Vec<Value> = responses.into_iter().flatten().map(|response| json!(response)).collect();
+        let batched = line.trim_start().starts_with('[');
+        if batched && !responses.is_empty() {
+            responses = vec![Value::Array(responses)];
+        }
+        responses.extend(notifications.into_iter().map(|notification| json!(notification)));
+        responses
+    }
+
+    fn handle_message(&mut self, message: Value, notifications: &mut Vec<Notification>) -> Option<Response> {
+        let request: Request = match serde_json::from_value(message) {
+            Ok(request) => request,
+            Err(e) => return Some(Response::failure(Value::Null, RpcError::new(INVALID_REQUEST, e.to_string()))),
+        };
+        if request.jsonrpc != "2.0" {
+            let id = request.id.unwrap_or(Value::Null);
+            return Some(Response::failure(id, RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")));
+        }
+        let result = self.dispatch(&request.method, request.params, notifications);
+        // requests without an id are notifications and get no response
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => Response::success(id, result),
+            Err(error) => Response::failure(id, error),
+        })
+    }
+
+    fn dispatch(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+        notifications: &mut Vec<Notification>,
+    ) -> Result<Value, RpcError> {
+        if method == "auth.authenticate" {
+            let TokenParams { token } = parse_params(params)?;
+            let principal = self.auth.verify_bearer(&token)?;
+            self.token = Some(token);
+            return Ok(json!({ "user_id": principal.user_id }));
+        }
+        let principal = self.principal()?;
+        let users = self.auth.users();
+        let todos = principal.todos(&self.todos);
+        match method {
+            "users.save_new_item" => {
+                principal.require_scope(Scope::ManageUsers)?;
+                if !principal.is_admin() {
+                    return Err(RpcError::new(FORBIDDEN, "only admins may create users"));
+                }
+                let user: UserDTO = parse_params(params)?;
+                Ok(json!(users.save_new_item(&user)?))
+            }
+            "users.select_item_by_id" => {
+                let IdParams { id } = parse_params(params)?;
+                require_user(&principal, id)?;
+                Ok(json!(users.select_item_by_id(&id)?))
+            }
+            "users.update_item" => {
+                let UpdateParams::<UserDTO> { id, item } = parse_params(params)?;
+                require_user(&principal, id)?;
+                Ok(json!(users.update_item(&id, &item)?))
+            }
+            "users.update_item_if_version" => {
+                let VersionedUpdateParams::<UserDTO> { id, item, expected_version } = parse_params(params)?;
+                require_user(&principal, id)?;
+                Ok(json!(users.update_item_if_version(&id, &item, expected_version)?))
+            }
+            "users.delete_item_by_id" => {
+                let IdParams { id } = parse_params(params)?;
+                require_user(&principal, id)?;
+                Ok(json!(users.delete_account(&id)?))
+            }
+            "todos.save_new_item" => {
+                let todo: TodoItemDTO = parse_params(params)?;
+                if todo.task.trim().is_empty() {
+                    return Err(RpcError::new(INVALID_PARAMS, "task must not be empty"));
+                }
+                let id = todos.create_for(&todo.user_id, &todo.task)?;
+                notifications.push(todo_changed(id, "created"));
+                Ok(json!(id))
+            }
+            "todos.select_item_by_id" => {
+                let IdParams { id } = parse_params(params)?;
+                Ok(json!(todos.get(&id)?))
+            }
+            "todos.update_item" => {
+                let UpdateParams::<TodoItemDTO> { id, item } = parse_params(params)?;
+                let updated = todos.update(&id, &item.task)?;
+                if updated > 0 {
+                    notifications.push(todo_changed(id, "updated"));
+                }
+                Ok(json!(updated))
+            }
+            "todos.update_item_if_version" => {
+                let VersionedUpdateParams::<TodoItemDTO> { id, item, expected_version } = parse_params(params)?;
+                let updated = todos.update_if_version(&id, &item.task, expected_version)?;
+                notifications.push(todo_changed(id, "updated"));
+                Ok(json!(updated))
+            }
+            "todos.delete_item_by_id" => {
+                let IdParams { id } = parse_params(params)?;
+                let deleted = todos.delete(&id)?;
+                if deleted > 0 {
+                    notifications.push(todo_changed(id, "deleted"));
+                }
+                Ok(json!(deleted))
+            }
+            "todos.get_user_todos" => {
+                let UserIdParams { user_id } = parse_params(params)?;
+                Ok(json!(todos.list_for(&user_id)?))
+            }
+            "todos.complete_todo_item" => {
+                let IdParams { id } = parse_params(params)?;
+                let updated = todos.complete(&id)?;
+                if updated > 0 {
+                    notifications.push(todo_changed(id, "completed"));
+                }
+                Ok(json!(updated))
+            }
+            "todos.uncomplete_todo_item" => {
+                let IdParams { id } = parse_params(params)?;
+                let updated = todos.uncomplete(&id)?;
+                if updated > 0 {
+                    notifications.push(todo_changed(id, "uncompleted"));
+                }
+                Ok(json!(updated))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+}
+
+/// Users can only be read and changed by themselves or an admin, with a
+/// token holding `users:manage`, as under `/users/{id}` in the REST API.
+fn require_user(principal: &Principal, user_id: i64) -> Result<(), RpcError> {
+    principal.require_scope(Scope::ManageUsers)?;
+    principal.require_user(&user_id)?;
+    Ok(())
+}
+
+fn todo_changed(id: i64, kind: &str) -> Notification {
+    Notification::new(TODOS_CHANGED, json!({ "id": id, "kind": kind }))
+}
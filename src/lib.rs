@@ -1,4 +1,19 @@
+pub mod auth;
+pub mod maintenance;
 pub mod models;
 pub mod repository;
-
-
+pub mod stats;
+pub mod sync;
+pub mod undo;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "jsonrpc")]
+pub mod jsonrpc;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+#[cfg(feature = "encryption")]
+pub mod encryption;
@@ -0,0 +1,129 @@
+//! Operational tooling for a SQLite database file: online backups and
+//! restores, `VACUUM`, `ANALYZE`, integrity checks, and size and row counts.
+//!
+//! [`Maintenance`] opens its own connection to the same file the
+//! repositories use, so it can run alongside them. Backups and restores go
+//! through SQLite's online backup API, a few pages at a time, so other
+//! connections are only locked out briefly and a backup taken while they
+//! write is still consistent.
+
+use std::path::Path;
+use std::time::Duration;
+
+use rusqlite::backup::Backup;
+use rusqlite::{ffi, Connection, OpenFlags, Result};
+
+/// Pages copied per step of a backup or restore.
+const PAGES_PER_STEP: i32 = 128;
+
+/// How long to wait between steps, letting other connections get a word in.
+const STEP_PAUSE: Duration = Duration::from_millis(5);
+
+/// How much space a database takes, from its page counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseSize {
+    pub page_size: i64,
+    pub page_count: i64,
+    /// Pages on the free list: space a [`Maintenance::vacuum`] would give back.
+    pub free_pages: i64,
+}
+
+impl DatabaseSize {
+    /// The size of the database file, in bytes.
+    pub fn bytes(&self) -> i64 {
+        self.page_size * self.page_count
+    }
+
+    /// The unused part of the file, in bytes.
+    pub fn free_bytes(&self) -> i64 {
+        self.page_size * self.free_pages
+    }
+}
+
+/// The number of rows in one table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableRowCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+/// Maintenance operations on one database.
+pub struct Maintenance {
+    conn: Connection,
+}
+
+impl Maintenance {
+    /// Open the database the repositories were given as `connection_string`.
+    pub fn open(connection_string: &str) -> Result<Maintenance> {
+        Ok(Maintenance { conn: Connection::open(connection_string)? })
+    }
+
+    /// Copy the live database to `path`, replacing anything already there.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut backup = Connection::open(path)?;
+        let copy = Backup::new(&self.conn, &mut backup)?;
+        copy.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)
+    }
+
+    /// Replace the whole database with the backup at `path`.
+    ///
+    /// The backup is checked first: this fails, leaving the database as it
+    /// was, if `path` doesn't exist or doesn't pass an integrity check.
+    /// Connections already open see the restored data from their next query.
+    pub fn restore_from(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let backup = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let problems = integrity_problems(&backup)?;
+        if !problems.is_empty() {
+            return Err(rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_CORRUPT), Some(problems.join("; "))));
+        }
+        let copy = Backup::new(&backup, &mut self.conn)?;
+        copy.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)
+    }
+
+    /// Rebuild the database file, giving free pages back to the file system.
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")
+    }
+
+    /// Gather the statistics the query planner uses to pick indexes.
+    pub fn analyze(&self) -> Result<()> {
+        self.conn.execute_batch("ANALYZE")
+    }
+
+    /// Run SQLite's integrity check, returning what it found wrong; empty if
+    /// the database is sound.
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        integrity_problems(&self.conn)
+    }
+
+    /// How big the database is.
+    pub fn size(&self) -> Result<DatabaseSize> {
+        Ok(DatabaseSize {
+            page_size: self.conn.query_row("PRAGMA page_size", (), |row| row.get(0))?,
+            page_count: self.conn.query_row("PRAGMA page_count", (), |row| row.get(0))?,
+            free_pages: self.conn.query_row("PRAGMA freelist_count", (), |row| row.get(0))?,
+        })
+    }
+
+    /// The number of rows in every table, by table name, leaving out
+    /// SQLite's own.
+    pub fn row_counts(&self) -> Result<Vec<TableRowCount>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name FROM sqlite_schema WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let tables = stmt.query_map((), |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>>>()?;
+        let mut counts = Vec::new();
+        for table in tables {
+            let rows = self.conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\"")), (), |row| row.get(0))?;
+            counts.push(TableRowCount { table, rows });
+        }
+        Ok(counts)
+    }
+}
+
+/// What `PRAGMA integrity_check` reports, without its "ok" for a sound database.
+fn integrity_problems(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let problems = stmt.query_map((), |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>>>()?;
+    Ok(problems.into_iter().filter(|problem| problem != "ok").collect())
+}
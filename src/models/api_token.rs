@@ -0,0 +1,74 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A permission an API token can be granted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub enum Scope {
+    /// List and read todos.
+    ReadTodos,
+    /// Create, update, complete and delete todos.
+    WriteTodos,
+    /// Change or delete user accounts.
+    ManageUsers,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::ReadTodos, Scope::WriteTodos, Scope::ManageUsers];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadTodos => "todos:read",
+            Scope::WriteTodos => "todos:write",
+            Scope::ManageUsers => "users:manage",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|s| s.as_str() == scope)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+
+    fn try_from(scope: String) -> Result<Self, Self::Error> {
+        Scope::parse(&scope).ok_or_else(|| format!("unknown scope {}", scope))
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.as_str().to_string()
+    }
+}
+
+/// A long-lived personal token for scripts and bots. As with sessions, only
+/// a hash of the token itself is stored.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_datetime: DateTime<Utc>,
+    pub last_used_datetime: Option<DateTime<Utc>>,
+    pub revoked_datetime: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
@@ -1,6 +1,17 @@
+pub use api_token::*;
+pub use organization::*;
+pub use session::*;
 pub use todo::*;
+pub use todo_list::*;
 pub use user::*;
+pub use validation::*;
+pub use webhook::*;
 
+pub mod api_token;
+pub mod organization;
+pub mod session;
 pub mod user;
 pub mod todo;
-
+pub mod todo_list;
+pub mod validation;
+pub mod webhook;
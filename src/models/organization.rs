@@ -0,0 +1,90 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::validation::ValidationError;
+
+/// A user's role within an organization. Roles are ordered, so an `Admin`
+/// can do everything a `Member` can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum OrgRole {
+    /// Works with todos inside the organization.
+    Member,
+    /// Can also add and remove members.
+    Admin,
+    /// Can also rename and delete the organization.
+    Owner,
+}
+
+impl OrgRole {
+    pub const ALL: [OrgRole; 3] = [OrgRole::Member, OrgRole::Admin, OrgRole::Owner];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Member => "member",
+            OrgRole::Admin => "admin",
+            OrgRole::Owner => "owner",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<OrgRole> {
+        OrgRole::ALL.into_iter().find(|r| r.as_str() == role)
+    }
+}
+
+impl fmt::Display for OrgRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A team sharing one deployment. Todos created inside an organization stay
+/// inside it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Organization {
+    pub id: i64,
+    pub name: String,
+    pub created_datetime: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OrganizationDTO {
+    pub name: String,
+}
+
+impl OrganizationDTO {
+    /// Check the DTO and return a copy with the name trimmed.
+    pub fn validate(&self) -> Result<OrganizationDTO, ValidationError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(ValidationError::field("name", "must not be empty"));
+        }
+        Ok(OrganizationDTO { name: name.to_string() })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Membership {
+    pub organization_id: i64,
+    pub user_id: i64,
+    pub role: OrgRole,
+    pub joined_datetime: DateTime<Utc>,
+}
+
+/// Counts for one organization, computed in the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OrganizationStats {
+    pub organization_id: i64,
+    pub member_count: i64,
+    pub todo_count: i64,
+    pub completed_count: i64,
+    pub open_count: i64,
+}
@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+
+/// A login session. Only a hash of its token is stored, so the token itself
+/// is only ever known to the client it was issued to.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: i64,
+    pub user_id: i64,
+    pub created_datetime: DateTime<Utc>,
+    pub expires_datetime: DateTime<Utc>,
+    pub revoked_datetime: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// Whether the session can still be used at `now`.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_datetime.is_none() && self.expires_datetime > now
+    }
+}
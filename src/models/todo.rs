@@ -1,6 +1,46 @@
+use std::fmt;
+
 use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+/// Why a todo was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RefusalReason {
+    NotMyJob,
+    TooBoring,
+    Later,
+    Never,
+}
+
+impl RefusalReason {
+    pub const ALL: [RefusalReason; 4] =
+        [RefusalReason::NotMyJob, RefusalReason::TooBoring, RefusalReason::Later, RefusalReason::Never];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefusalReason::NotMyJob => "not_my_job",
+            RefusalReason::TooBoring => "too_boring",
+            RefusalReason::Later => "later",
+            RefusalReason::Never => "never",
+        }
+    }
+
+    pub fn parse(reason: &str) -> Option<RefusalReason> {
+        RefusalReason::ALL.into_iter().find(|r| r.as_str() == reason)
+    }
+}
+
+impl fmt::Display for RefusalReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TodoItem {
     pub id: i64,
     pub user_id: i64,
@@ -8,6 +48,21 @@ pub struct TodoItem {
     pub completed: bool,
     pub created_datetime: DateTime<Utc>,
     pub completed_datetime: Option<DateTime<Utc>>,
+    /// The [`TodoList`](crate::models::TodoList) the todo belongs to, if any.
+    pub list_id: Option<i64>,
+    /// Who is expected to do the todo, if not its creator.
+    pub assignee_id: Option<i64>,
+    /// The [`Organization`](crate::models::Organization) the todo is scoped to, if any.
+    pub organization_id: Option<i64>,
+    /// The todo is hidden from todo lists until this time, if set.
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// How many times the todo has been snoozed.
+    pub deferral_count: i64,
+    /// When the todo was declared won't-do, if it currently is.
+    pub refused_datetime: Option<DateTime<Utc>>,
+    pub refusal_reason: Option<RefusalReason>,
+    /// Starts at 1 and goes up with every change, for detecting concurrent edits.
+    pub version: i64,
     // Future: Steps of sub-tasks?
     // pub sub_tasks: Vec<TodoItem>,
     // Future: additional notes?
@@ -15,8 +70,67 @@ pub struct TodoItem {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TodoItemDTO {
     pub user_id: i64,
     pub task: String,
 }
 
+/// One hand-off of a todo from one assignee to the next. `None` on either
+/// side means nobody was assigned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Delegation {
+    pub id: i64,
+    pub todo_id: i64,
+    pub from_user_id: Option<i64>,
+    pub to_user_id: Option<i64>,
+    /// The user who made the change, who need not be the previous assignee.
+    pub delegated_by: i64,
+    pub delegated_datetime: DateTime<Utc>,
+}
+
+/// One snooze of a todo and the excuse given for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Excuse {
+    pub id: i64,
+    pub todo_id: i64,
+    /// The todo's owner at the time it was snoozed.
+    pub user_id: i64,
+    pub excuse: String,
+    pub snoozed_until: DateTime<Utc>,
+    pub created_datetime: DateTime<Utc>,
+}
+
+/// How often a user has given the same excuse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExcuseCount {
+    pub excuse: String,
+    pub count: i64,
+    pub last_used_datetime: DateTime<Utc>,
+}
+
+/// One refusal of a todo. Reconsidering it sets `reconsidered_datetime`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Refusal {
+    pub id: i64,
+    pub todo_id: i64,
+    /// The todo's owner at the time it was refused.
+    pub user_id: i64,
+    pub reason: RefusalReason,
+    pub refused_datetime: DateTime<Utc>,
+    pub reconsidered_datetime: Option<DateTime<Utc>>,
+}
+
+/// How often a user has refused todos for one reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RefusalCount {
+    pub reason: RefusalReason,
+    pub refused_count: i64,
+    /// How many of those refusals were later reconsidered.
+    pub reconsidered_count: i64,
+}
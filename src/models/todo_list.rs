@@ -0,0 +1,90 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::validation::ValidationError;
+
+/// How much a user can do with a list shared with them. Levels are ordered,
+/// so `Editor` can do everything a `Viewer` can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ShareLevel {
+    /// Can see the list and its todos.
+    Viewer,
+    /// Can also add, change, complete and remove todos in the list.
+    Editor,
+    /// Can also rename, delete and share the list.
+    Owner,
+}
+
+impl ShareLevel {
+    pub const ALL: [ShareLevel; 3] = [ShareLevel::Viewer, ShareLevel::Editor, ShareLevel::Owner];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareLevel::Viewer => "viewer",
+            ShareLevel::Editor => "editor",
+            ShareLevel::Owner => "owner",
+        }
+    }
+
+    pub fn parse(level: &str) -> Option<ShareLevel> {
+        ShareLevel::ALL.into_iter().find(|l| l.as_str() == level)
+    }
+}
+
+impl fmt::Display for ShareLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A named group of todos, owned by one user and optionally shared with others.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TodoList {
+    pub id: i64,
+    pub owner_id: i64,
+    pub name: String,
+    pub created_datetime: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TodoListDTO {
+    pub owner_id: i64,
+    pub name: String,
+}
+
+impl TodoListDTO {
+    /// Check the DTO and return a copy with the name trimmed.
+    pub fn validate(&self) -> Result<TodoListDTO, ValidationError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(ValidationError::field("name", "must not be empty"));
+        }
+        Ok(TodoListDTO { owner_id: self.owner_id, name: name.to_string() })
+    }
+}
+
+/// A user a list has been shared with. Until the user accepts, the share is
+/// only an invitation and grants nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ListMember {
+    pub list_id: i64,
+    pub user_id: i64,
+    pub level: ShareLevel,
+    pub invited_by: i64,
+    pub invited_datetime: DateTime<Utc>,
+    pub accepted_datetime: Option<DateTime<Utc>>,
+}
+
+impl ListMember {
+    pub fn is_accepted(&self) -> bool {
+        self.accepted_datetime.is_some()
+    }
+}
@@ -1,14 +1,110 @@
-#[derive(Debug)]
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::validation::ValidationError;
+
+/// What a user is allowed to do beyond their own todos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Role {
+    /// Works with their own todos only.
+    #[default]
+    User,
+    /// May read and change every user's todos and accounts.
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 2] = [Role::User, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.as_str() == role)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct User {
     pub id: i64,
     pub first_name: String,
     pub last_name: String,
     pub email: String,
+    pub role: Role,
+    /// Starts at 1 and goes up with every change, for detecting concurrent edits.
+    pub version: i64,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UserDTO {
     pub first_name: String,
     pub last_name: String,
     pub email: String,
 }
+
+impl UserDTO {
+    /// Check the DTO and return a normalized copy of it: names are trimmed and
+    /// the email is trimmed and lower-cased.
+    ///
+    /// All problems are collected into a single `ValidationError`.
+    pub fn validate(&self) -> Result<UserDTO, ValidationError> {
+        let normalized = UserDTO {
+            first_name: self.first_name.trim().to_string(),
+            last_name: self.last_name.trim().to_string(),
+            email: normalize_email(&self.email),
+        };
+        let mut error = ValidationError::default();
+        if normalized.first_name.is_empty() {
+            error.add("first_name", "must not be empty");
+        }
+        if normalized.last_name.is_empty() {
+            error.add("last_name", "must not be empty");
+        }
+        if normalized.email.is_empty() {
+            error.add("email", "must not be empty");
+        } else if !is_valid_email(&normalized.email) {
+            error.add("email", "is not a valid email address");
+        }
+        if error.is_empty() {
+            Ok(normalized)
+        } else {
+            Err(error)
+        }
+    }
+}
+
+/// The form emails are stored and compared in.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// A deliberately simple syntax check: one `@`, a non-empty local part, and a
+/// dotted domain, with no whitespace anywhere.
+fn is_valid_email(email: &str) -> bool {
+    if email.len() > 254 || email.chars().any(char::is_whitespace) {
+        return false;
+    }
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+}
@@ -0,0 +1,51 @@
+use std::error::Error;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A problem with a single field of a DTO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every problem found while validating a DTO, so they can all be reported at once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ValidationError {
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationError {
+    /// A validation error for a single field.
+    pub fn field(field: &str, message: &str) -> ValidationError {
+        let mut error = ValidationError::default();
+        error.add(field, message);
+        error
+    }
+
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.errors.push(FieldError { field: field.to_string(), message: message.to_string() });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Whether there is an error for `field`.
+    pub fn has_field(&self, field: &str) -> bool {
+        self.errors.iter().any(|error| error.field == field)
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self.errors.iter().map(|error| format!("{} {}", error.field, error.message)).collect();
+        write!(f, "{}", errors.join(", "))
+    }
+}
+
+impl Error for ValidationError {}
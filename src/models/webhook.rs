@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::models::validation::ValidationError;
+use crate::repository::EventKind;
+
+/// A URL that is sent the chosen events for a user's todos.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Webhook {
+    pub id: i64,
+    pub user_id: i64,
+    pub url: String,
+    pub events: Vec<EventKind>,
+    /// The key payloads are signed with, so receivers can check they came from us.
+    pub secret: String,
+    pub created_datetime: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WebhookDTO {
+    pub user_id: i64,
+    pub url: String,
+    pub events: Vec<EventKind>,
+}
+
+impl WebhookDTO {
+    /// Check the DTO and return a copy with the URL trimmed and the events
+    /// deduplicated.
+    pub fn validate(&self) -> Result<WebhookDTO, ValidationError> {
+        let url = self.url.trim();
+        let mut events = Vec::new();
+        for event in &self.events {
+            if !events.contains(event) {
+                events.push(*event);
+            }
+        }
+        let mut error = ValidationError::default();
+        if !(url.starts_with("http://") || url.starts_with("https://")) || url.contains(char::is_whitespace) {
+            error.add("url", "must be an http or https URL");
+        }
+        if events.is_empty() {
+            error.add("events", "must not be empty");
+        }
+        if !error.is_empty() {
+            return Err(error);
+        }
+        Ok(WebhookDTO { user_id: self.user_id, url: url.to_string(), events })
+    }
+}
+
+/// One event queued for one webhook, and how delivering it has gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: EventKind,
+    /// The JSON body to send, fixed when the event happened.
+    pub payload: String,
+    pub attempts: i64,
+    pub next_attempt_datetime: DateTime<Utc>,
+    pub delivered_datetime: Option<DateTime<Utc>>,
+    /// Set once the delivery has failed too many times to try again.
+    pub failed_datetime: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_datetime: DateTime<Utc>,
+}
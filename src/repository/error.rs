@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::models::ValidationError;
+
+/// Errors returned by repositories that do more than pass SQLite errors through.
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// No row with the requested id (or other key) exists.
+    NotFound,
+    /// The item was rejected before (or by a constraint during) the write.
+    Validation(ValidationError),
+    /// The item was changed since the caller read it: it is at version
+    /// `actual`, not the `expected` one.
+    Conflict { expected: i64, actual: i64 },
+    /// Any other database error.
+    Sqlite(rusqlite::Error),
+    /// Reading or writing a file-based store failed.
+    Io(std::io::Error),
+    /// Any error from a PostgreSQL database.
+    #[cfg(feature = "postgres")]
+    Postgres(postgres::Error),
+    /// The database is encrypted with a different key than the one given.
+    #[cfg(feature = "encryption")]
+    WrongKey,
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::NotFound => write!(f, "not found"),
+            RepositoryError::Validation(e) => write!(f, "invalid item: {}", e),
+            RepositoryError::Conflict { expected, actual } => {
+                write!(f, "item was changed: expected version {}, found {}", expected, actual)
+            }
+            RepositoryError::Sqlite(e) => write!(f, "database error: {}", e),
+            RepositoryError::Io(e) => write!(f, "storage error: {}", e),
+            #[cfg(feature = "postgres")]
+            RepositoryError::Postgres(e) => write!(f, "database error: {}", e),
+            #[cfg(feature = "encryption")]
+            RepositoryError::WrongKey => write!(f, "wrong encryption key"),
+        }
+    }
+}
+
+impl Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepositoryError::NotFound | RepositoryError::Conflict { .. } => None,
+            #[cfg(feature = "encryption")]
+            RepositoryError::WrongKey => None,
+            RepositoryError::Validation(e) => Some(e),
+            RepositoryError::Sqlite(e) => Some(e),
+            RepositoryError::Io(e) => Some(e),
+            #[cfg(feature = "postgres")]
+            RepositoryError::Postgres(e) => Some(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for RepositoryError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => RepositoryError::NotFound,
+            e => RepositoryError::Sqlite(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for RepositoryError {
+    fn from(e: std::io::Error) -> Self {
+        RepositoryError::Io(e)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<postgres::Error> for RepositoryError {
+    fn from(e: postgres::Error) -> Self {
+        RepositoryError::Postgres(e)
+    }
+}
+
+impl From<ValidationError> for RepositoryError {
+    fn from(e: ValidationError) -> Self {
+        RepositoryError::Validation(e)
+    }
+}
+
+/// Whether `e` is a UNIQUE constraint failure.
+pub(crate) fn is_unique_violation(e: &rusqlite::Error) -> bool {
+    matches!(
+        e,
+        rusqlite::Error::SqliteFailure(failure, _)
+            if failure.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+    )
+}
+
+/// The error for users whose emails are the same once normalized, found while
+/// adding the unique email index to a database written before emails were
+/// validated. `duplicates` pairs each normalized email with the ids using it.
+///
+/// The rows are reported rather than merged, since only a person can tell
+/// which account to keep.
+pub(crate) fn duplicate_emails(duplicates: Vec<(String, String)>) -> RepositoryError {
+    let mut error = ValidationError::default();
+    for (email, ids) in duplicates {
+        error.add("email", &format!("{} is used by users {}; change or merge them before upgrading", email, ids));
+    }
+    error.into()
+}
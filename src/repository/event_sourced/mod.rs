@@ -0,0 +1,529 @@
+//! Storage where every change is an immutable event in an append-only log,
+//! and the current state is a projection of it.
+//!
+//! The log is the `event_log` table; nothing in it is ever updated or
+//! deleted. [`EventSourcedTodoRepository`](todo_repository::EventSourcedTodoRepository)
+//! and [`EventSourcedUserRepository`](user_repository::EventSourcedUserRepository)
+//! keep the projection in memory and catch up with the log on every call, so
+//! several of them, in one process or many, can share a database. Snapshots
+//! of the projection, taken every [`SNAPSHOT_INTERVAL`] events or on request,
+//! save replaying the whole log when a repository is opened. Because nothing
+//! is lost, the state at any past instant can be rebuilt too.
+//!
+//! A new todo or user gets one more than the highest id in the log, deleted
+//! ones included, so ids are never reused; the projection keeps track of it
+//! and snapshots save it. Timestamps work as in the SQLite repositories,
+//! except that events are timed to the millisecond so that time travel can
+//! tell them apart.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params, Row, Transaction, TransactionBehavior};
+
+use crate::models::{Role, TodoItem, User};
+use crate::repository::sqlite::{add_column_if_missing, optional_timestamp, timestamp};
+use crate::repository::RepositoryError;
+
+pub mod todo_repository;
+pub mod user_repository;
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Take a snapshot after this many events since the last one.
+pub const SNAPSHOT_INTERVAL: i64 = 500;
+
+/// A change to a todo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TodoEvent {
+    Created { user_id: i64, task: String },
+    /// Written with the todo's current owner; logs from before updates kept
+    /// the owner may name a new one.
+    Updated { user_id: i64, task: String },
+    Completed,
+    Uncompleted,
+    Deleted,
+}
+
+/// A change to a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserEvent {
+    Created { first_name: String, last_name: String, email: String },
+    Updated { first_name: String, last_name: String, email: String },
+    RoleSet(Role),
+    Deleted,
+}
+
+/// A change to the todo or user with the given id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Todo(i64, TodoEvent),
+    User(i64, UserEvent),
+}
+
+/// An event as stored in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedEvent {
+    /// The event's position in the log, starting at 1.
+    pub seq: i64,
+    pub timestamp: DateTime<Utc>,
+    pub event: Event,
+}
+
+/// Every todo and user as of the event `seq`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Projection {
+    pub(crate) seq: i64,
+    pub(crate) todos: BTreeMap<i64, TodoItem>,
+    pub(crate) users: BTreeMap<i64, User>,
+    /// The highest todo id in the log, deleted todos included, so new ids
+    /// are never reused.
+    pub(crate) last_todo_id: i64,
+    /// The same for users.
+    pub(crate) last_user_id: i64,
+}
+
+/// Drop the milliseconds, as SQLite timestamps are kept to the second.
+fn to_the_second(datetime: DateTime<Utc>) -> DateTime<Utc> {
+    DateTime::from_timestamp(datetime.timestamp(), 0).unwrap_or_default()
+}
+
+impl Projection {
+    fn apply(&mut self, logged: &LoggedEvent) {
+        self.seq = logged.seq;
+        match logged.event {
+            Event::Todo(id, _) => self.last_todo_id = self.last_todo_id.max(id),
+            Event::User(id, _) => self.last_user_id = self.last_user_id.max(id),
+        }
+        let at = to_the_second(logged.timestamp);
+        match &logged.event {
+            Event::Todo(id, TodoEvent::Created { user_id, task }) => {
+                self.todos.insert(
+                    *id,
+                    TodoItem {
+                        id: *id,
+                        user_id: *user_id,
+                        task: task.clone(),
+                        completed: false,
+                        created_datetime: at,
+                        completed_datetime: None,
+                        list_id: None,
+                        assignee_id: None,
+                        organization_id: None,
+                        snoozed_until: None,
+                        deferral_count: 0,
+                        refused_datetime: None,
+                        refusal_reason: None,
+                        version: 1,
+                    },
+                );
+            }
+            Event::Todo(id, TodoEvent::Deleted) => {
+                self.todos.remove(id);
+            }
+            Event::Todo(id, event) => {
+                if let Some(todo) = self.todos.get_mut(id) {
+                    match event {
+                        TodoEvent::Updated { user_id, task } => {
+                            todo.user_id = *user_id;
+                            todo.task = task.clone();
+                        }
+                        TodoEvent::Completed => {
+                            todo.completed = true;
+                            todo.completed_datetime = Some(at);
+                        }
+                        TodoEvent::Uncompleted => {
+                            todo.completed = false;
+                            todo.completed_datetime = None;
+                        }
+                        TodoEvent::Created { .. } | TodoEvent::Deleted => {}
+                    }
+                    todo.version += 1;
+                }
+            }
+            Event::User(id, UserEvent::Created { first_name, last_name, email }) => {
+                self.users.insert(
+                    *id,
+                    User {
+                        id: *id,
+                        first_name: first_name.clone(),
+                        last_name: last_name.clone(),
+                        email: email.clone(),
+                        role: Role::default(),
+                        version: 1,
+                    },
+                );
+            }
+            Event::User(id, UserEvent::Deleted) => {
+                self.users.remove(id);
+            }
+            Event::User(id, event) => {
+                if let Some(user) = self.users.get_mut(id) {
+                    match event {
+                        UserEvent::Updated { first_name, last_name, email } => {
+                            user.first_name = first_name.clone();
+                            user.last_name = last_name.clone();
+                            user.email = email.clone();
+                        }
+                        UserEvent::RoleSet(role) => user.role = *role,
+                        UserEvent::Created { .. } | UserEvent::Deleted => {}
+                    }
+                    user.version += 1;
+                }
+            }
+        }
+    }
+}
+
+const EVENT_COLUMNS: &str = "seq, timestamp, entity, entity_id, kind, user_id, task, first_name, last_name, email, role";
+
+fn unknown(idx: usize, what: &str, value: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, format!("unknown {} {}", what, value).into())
+}
+
+/// Build a `LoggedEvent` from a row selected with [`EVENT_COLUMNS`].
+fn event_from_row(row: &Row) -> rusqlite::Result<LoggedEvent> {
+    let millis: i64 = row.get(1)?;
+    let entity: String = row.get(2)?;
+    let id: i64 = row.get(3)?;
+    let kind: String = row.get(4)?;
+    let event = match (entity.as_str(), kind.as_str()) {
+        ("todo", "created") => Event::Todo(id, TodoEvent::Created { user_id: row.get(5)?, task: row.get(6)? }),
+        ("todo", "updated") => Event::Todo(id, TodoEvent::Updated { user_id: row.get(5)?, task: row.get(6)? }),
+        ("todo", "completed") => Event::Todo(id, TodoEvent::Completed),
+        ("todo", "uncompleted") => Event::Todo(id, TodoEvent::Uncompleted),
+        ("todo", "deleted") => Event::Todo(id, TodoEvent::Deleted),
+        ("user", "created") => {
+            Event::User(id, UserEvent::Created { first_name: row.get(7)?, last_name: row.get(8)?, email: row.get(9)? })
+        }
+        ("user", "updated") => {
+            Event::User(id, UserEvent::Updated { first_name: row.get(7)?, last_name: row.get(8)?, email: row.get(9)? })
+        }
+        ("user", "role_set") => {
+            let role: String = row.get(10)?;
+            Event::User(id, UserEvent::RoleSet(Role::parse(&role).ok_or_else(|| unknown(10, "role", &role))?))
+        }
+        ("user", "deleted") => Event::User(id, UserEvent::Deleted),
+        ("todo" | "user", _) => return Err(unknown(4, "event", &kind)),
+        _ => return Err(unknown(2, "entity", &entity)),
+    };
+    Ok(LoggedEvent {
+        seq: row.get(0)?,
+        timestamp: DateTime::from_timestamp_millis(millis).ok_or(rusqlite::Error::QueryReturnedNoRows)?,
+        event,
+    })
+}
+
+/// The projection and how far behind it the latest snapshot is.
+struct State {
+    projection: Projection,
+    snapshot_seq: i64,
+}
+
+/// The event log in a SQLite database, with a cached projection of it.
+pub(crate) struct EventStore {
+    conn: Connection,
+    state: Mutex<State>,
+}
+
+impl EventStore {
+    /// Create the tables if needed and load the projection from the latest
+    /// snapshot and the events after it.
+    pub(crate) fn new(conn: Connection) -> Result<EventStore> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS event_log (\
+seq INTEGER PRIMARY KEY,\
+timestamp INTEGER NOT NULL,\
+entity TEXT NOT NULL,\
+entity_id INTEGER NOT NULL,\
+kind TEXT NOT NULL,\
+user_id INTEGER,\
+task TEXT,\
+first_name TEXT,\
+last_name TEXT,\
+email TEXT,\
+role TEXT);
+CREATE INDEX IF NOT EXISTS event_log_entity ON event_log (entity, entity_id);
+CREATE TABLE IF NOT EXISTS event_snapshots (\
+seq INTEGER PRIMARY KEY,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+last_todo_id INTEGER,\
+last_user_id INTEGER);
+CREATE TABLE IF NOT EXISTS event_snapshot_todos (\
+snapshot_seq INTEGER NOT NULL,\
+id INTEGER NOT NULL,\
+user_id INTEGER NOT NULL,\
+task TEXT NOT NULL,\
+completed INTEGER NOT NULL,\
+created_datetime INTEGER NOT NULL,\
+completed_datetime INTEGER,\
+version INTEGER NOT NULL,\
+PRIMARY KEY (snapshot_seq, id));
+CREATE TABLE IF NOT EXISTS event_snapshot_users (\
+snapshot_seq INTEGER NOT NULL,\
+id INTEGER NOT NULL,\
+first_name TEXT NOT NULL,\
+last_name TEXT NOT NULL,\
+email TEXT NOT NULL,\
+role TEXT NOT NULL,\
+version INTEGER NOT NULL,\
+PRIMARY KEY (snapshot_seq, id));",
+        )?;
+        // snapshots taken before they kept the highest ids get them from the
+        // log once
+        add_column_if_missing(&conn, "event_snapshots", "last_todo_id", "INTEGER")?;
+        add_column_if_missing(&conn, "event_snapshots", "last_user_id", "INTEGER")?;
+        conn.execute(
+            "UPDATE event_snapshots SET \
+last_todo_id = (SELECT COALESCE(MAX(entity_id), 0) FROM event_log WHERE entity = 'todo' AND seq <= event_snapshots.seq), \
+last_user_id = (SELECT COALESCE(MAX(entity_id), 0) FROM event_log WHERE entity = 'user' AND seq <= event_snapshots.seq) \
+WHERE last_todo_id IS NULL OR last_user_id IS NULL",
+            (),
+        )?;
+        let projection = EventStore::project(&conn, None)?;
+        let snapshot_seq = EventStore::snapshot_before(&conn, projection.seq)?;
+        Ok(EventStore { conn, state: Mutex::new(State { projection, snapshot_seq }) })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Run `f` on the projection, brought up to date with the log.
+    pub(crate) fn read<T>(&self, f: impl FnOnce(&Projection) -> T) -> Result<T> {
+        let mut state = self.lock();
+        EventStore::catch_up(&self.conn, &mut state.projection)?;
+        Ok(f(&state.projection))
+    }
+
+    /// Let `decide` look at the up-to-date projection and choose a result
+    /// and, optionally, an event to append. Deciding and appending happen in
+    /// one write transaction, so concurrent writers can't both act on the
+    /// same state.
+    pub(crate) fn write<T>(&self, decide: impl FnOnce(&Projection) -> Result<(T, Option<Event>)>) -> Result<T> {
+        let mut state = self.lock();
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        EventStore::catch_up(&tx, &mut state.projection)?;
+        let (result, event) = decide(&state.projection)?;
+        let Some(event) = event else {
+            return Ok(result);
+        };
+        EventStore::append(&tx, &event)?;
+        tx.commit()?;
+        EventStore::catch_up(&self.conn, &mut state.projection)?;
+        if state.projection.seq - state.snapshot_seq >= SNAPSHOT_INTERVAL {
+            state.snapshot_seq = EventStore::save_snapshot(&self.conn, &state.projection)?;
+        }
+        Ok(result)
+    }
+
+    /// Snapshot the current projection, returning the seq it is as of.
+    pub(crate) fn snapshot(&self) -> Result<i64> {
+        let mut state = self.lock();
+        EventStore::catch_up(&self.conn, &mut state.projection)?;
+        state.snapshot_seq = EventStore::save_snapshot(&self.conn, &state.projection)?;
+        Ok(state.snapshot_seq)
+    }
+
+    /// The projection as it was at `instant`.
+    pub(crate) fn projection_at(&self, instant: &DateTime<Utc>) -> Result<Projection> {
+        EventStore::project(&self.conn, Some(instant))
+    }
+
+    /// Every event about one todo or user, oldest first.
+    pub(crate) fn events(&self, entity: &str, id: &i64) -> Result<Vec<LoggedEvent>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM event_log WHERE entity = ?1 AND entity_id = ?2 ORDER BY seq",
+            EVENT_COLUMNS
+        ))?;
+        let event_iter = stmt.query_map(params![entity, id], event_from_row)?;
+        let mut events = Vec::new();
+        for event in event_iter {
+            events.push(event?);
+        }
+        Ok(events)
+    }
+
+    /// Append an event, timed now, or just after the latest event if the
+    /// clock has gone backwards, so timestamps never decrease along the log.
+    fn append(conn: &Connection, event: &Event) -> Result<()> {
+        let (entity, id, kind) = match event {
+            Event::Todo(id, event) => (
+                "todo",
+                id,
+                match event {
+                    TodoEvent::Created { .. } => "created",
+                    TodoEvent::Updated { .. } => "updated",
+                    TodoEvent::Completed => "completed",
+                    TodoEvent::Uncompleted => "uncompleted",
+                    TodoEvent::Deleted => "deleted",
+                },
+            ),
+            Event::User(id, event) => (
+                "user",
+                id,
+                match event {
+                    UserEvent::Created { .. } => "created",
+                    UserEvent::Updated { .. } => "updated",
+                    UserEvent::RoleSet(_) => "role_set",
+                    UserEvent::Deleted => "deleted",
+                },
+            ),
+        };
+        let (user_id, task) = match event {
+            Event::Todo(_, TodoEvent::Created { user_id, task } | TodoEvent::Updated { user_id, task }) => (Some(user_id), Some(task)),
+            _ => (None, None),
+        };
+        let (first_name, last_name, email) = match event {
+            Event::User(
+                _,
+                UserEvent::Created { first_name, last_name, email } | UserEvent::Updated { first_name, last_name, email },
+            ) => (Some(first_name), Some(last_name), Some(email)),
+            _ => (None, None, None),
+        };
+        let role = match event {
+            Event::User(_, UserEvent::RoleSet(role)) => Some(role.as_str()),
+            _ => None,
+        };
+        conn.execute(
+            "INSERT INTO event_log (timestamp, entity, entity_id, kind, user_id, task, first_name, last_name, email, role) \
+VALUES (MAX(?1, COALESCE((SELECT MAX(timestamp) FROM event_log), 0)), ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![Utc::now().timestamp_millis(), entity, id, kind, user_id, task, first_name, last_name, email, role],
+        )?;
+        Ok(())
+    }
+
+    /// Apply the events `projection` hasn't seen yet.
+    fn catch_up(conn: &Connection, projection: &mut Projection) -> Result<()> {
+        EventStore::replay(conn, projection, None)
+    }
+
+    /// Apply the events after `projection.seq`, up to `until` if given.
+    fn replay(conn: &Connection, projection: &mut Projection, until: Option<&DateTime<Utc>>) -> Result<()> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM event_log WHERE seq > ?1 AND (?2 IS NULL OR timestamp <= ?2) ORDER BY seq",
+            EVENT_COLUMNS
+        ))?;
+        let event_iter = stmt.query_map(params![projection.seq, until.map(DateTime::timestamp_millis)], event_from_row)?;
+        for event in event_iter {
+            projection.apply(&event?);
+        }
+        Ok(())
+    }
+
+    /// Build the projection as of `until`, or now, from the latest snapshot
+    /// before it and the events after that.
+    fn project(conn: &Connection, until: Option<&DateTime<Utc>>) -> Result<Projection> {
+        let last_seq: i64 = conn.query_row(
+            "SELECT COALESCE(MAX(seq), 0) FROM event_log WHERE ?1 IS NULL OR timestamp <= ?1",
+            params![until.map(DateTime::timestamp_millis)],
+            |row| row.get(0),
+        )?;
+        let mut projection = EventStore::load_snapshot(conn, EventStore::snapshot_before(conn, last_seq)?)?;
+        EventStore::replay(conn, &mut projection, until)?;
+        Ok(projection)
+    }
+
+    /// The seq of the latest snapshot at or before `seq`, or 0 if there is none.
+    fn snapshot_before(conn: &Connection, seq: i64) -> Result<i64> {
+        Ok(conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM event_snapshots WHERE seq <= ?1", params![seq], |row| row.get(0))?)
+    }
+
+    /// The projection saved in the snapshot at `seq`; empty for 0.
+    fn load_snapshot(conn: &Connection, seq: i64) -> Result<Projection> {
+        let (last_todo_id, last_user_id) = conn
+            .query_row("SELECT last_todo_id, last_user_id FROM event_snapshots WHERE seq = ?1", params![seq], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?
+            .unwrap_or((0, 0));
+        let mut projection = Projection { seq, last_todo_id, last_user_id, ..Projection::default() };
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, task, completed, created_datetime, completed_datetime, version \
+FROM event_snapshot_todos WHERE snapshot_seq = ?1",
+        )?;
+        let todo_iter = stmt.query_map(params![seq], |row| {
+            Ok(TodoItem {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                task: row.get(2)?,
+                completed: row.get(3)?,
+                created_datetime: timestamp(row, 4)?,
+                completed_datetime: optional_timestamp(row, 5)?,
+                list_id: None,
+                assignee_id: None,
+                organization_id: None,
+                snoozed_until: None,
+                deferral_count: 0,
+                refused_datetime: None,
+                refusal_reason: None,
+                version: row.get(6)?,
+            })
+        })?;
+        for todo in todo_iter {
+            let todo = todo?;
+            projection.todos.insert(todo.id, todo);
+        }
+        let mut stmt =
+            conn.prepare("SELECT id, first_name, last_name, email, role, version FROM event_snapshot_users WHERE snapshot_seq = ?1")?;
+        let user_iter = stmt.query_map(params![seq], |row| {
+            let role: String = row.get(4)?;
+            Ok(User {
+                id: row.get(0)?,
+                first_name: row.get(1)?,
+                last_name: row.get(2)?,
+                email: row.get(3)?,
+                role: Role::parse(&role).ok_or_else(|| unknown(4, "role", &role))?,
+                version: row.get(5)?,
+            })
+        })?;
+        for user in user_iter {
+            let user = user?;
+            projection.users.insert(user.id, user);
+        }
+        Ok(projection)
+    }
+
+    /// Save `projection` as a snapshot, unless there already is one at its
+    /// seq, returning the seq.
+    fn save_snapshot(conn: &Connection, projection: &Projection) -> Result<i64> {
+        let tx = conn.unchecked_transaction()?;
+        let exists = tx
+            .query_row("SELECT seq FROM event_snapshots WHERE seq = ?1", params![projection.seq], |row| row.get::<_, i64>(0))
+            .optional()?
+            .is_some();
+        if !exists {
+            tx.execute(
+                "INSERT INTO event_snapshots (seq, last_todo_id, last_user_id) VALUES (?1, ?2, ?3)",
+                params![projection.seq, projection.last_todo_id, projection.last_user_id],
+            )?;
+            for todo in projection.todos.values() {
+                tx.execute(
+                    "INSERT INTO event_snapshot_todos \
+(snapshot_seq, id, user_id, task, completed, created_datetime, completed_datetime, version) \
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        projection.seq,
+                        todo.id,
+                        todo.user_id,
+                        todo.task,
+                        todo.completed,
+                        todo.created_datetime.timestamp(),
+                        todo.completed_datetime.map(|completed| completed.timestamp()),
+                        todo.version
+                    ],
+                )?;
+            }
+            for user in projection.users.values() {
+                tx.execute(
+                    "INSERT INTO event_snapshot_users (snapshot_seq, id, first_name, last_name, email, role, version) \
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![projection.seq, user.id, user.first_name, user.last_name, user.email, user.role.as_str(), user.version],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(projection.seq)
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+
+use crate::models::{TodoItem, TodoItemDTO};
+use crate::repository::event_sourced::{Event, EventStore, LoggedEvent, Projection, TodoEvent};
+use crate::repository::{Repository, RepositoryError, TodoStore};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// A user's todos in `projection`, in id order.
+fn user_todos(projection: &Projection, user_id: &i64) -> Vec<TodoItem> {
+    projection.todos.values().filter(|todo| todo.user_id == *user_id).cloned().collect()
+}
+
+/// Stores todos as events; see [the module docs](crate::repository::event_sourced).
+///
+/// Behaves like the SQLite [`TodoRepository`](crate::repository::sqlite::todo_repository::TodoRepository)
+/// for everything in [`TodoStore`]. A new todo gets one more than the highest
+/// todo id in the log, so a deleted todo's id is never reused.
+pub struct EventSourcedTodoRepository {
+    store: EventStore,
+}
+
+impl EventSourcedTodoRepository {
+    /// Open the event log in the database at `connection_string`, creating
+    /// its tables if needed.
+    pub fn new(connection_string: &str) -> Result<EventSourcedTodoRepository> {
+        Ok(EventSourcedTodoRepository { store: EventStore::new(EventSourcedTodoRepository::connect_to_db(connection_string)?)? })
+    }
+
+    /// What `get_user_todos` would have returned at `instant`.
+    pub fn get_user_todos_at(&self, user_id: &i64, instant: &DateTime<Utc>) -> Result<Vec<TodoItem>> {
+        Ok(user_todos(&self.store.projection_at(instant)?, user_id))
+    }
+
+    /// Every event about a todo, oldest first; still there after it is deleted.
+    pub fn history(&self, id: &i64) -> Result<Vec<LoggedEvent>> {
+        self.store.events("todo", id)
+    }
+
+    /// Snapshot the current state now rather than waiting for the next
+    /// automatic one, returning the seq of the last event it includes.
+    pub fn snapshot(&self) -> Result<i64> {
+        self.store.snapshot()
+    }
+
+    /// Append `event` for todo `id` if it exists, returning the number of
+    /// todos changed.
+    fn change(&self, id: &i64, event: TodoEvent) -> Result<usize> {
+        self.store.write(|projection| {
+            if projection.todos.contains_key(id) {
+                Ok((1, Some(Event::Todo(*id, event))))
+            } else {
+                Ok((0, None))
+            }
+        })
+    }
+}
+
+impl Repository<Connection, TodoItem, RepositoryError> for EventSourcedTodoRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Connection> {
+        Ok(Connection::open(connection_string)?)
+    }
+
+    fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        self.store.write(|projection| {
+            let id = projection.last_todo_id + 1;
+            Ok((id, Some(Event::Todo(id, TodoEvent::Created { user_id: todo_dto.user_id, task: todo_dto.task.clone() }))))
+        })
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
+        self.store.read(|projection| projection.todos.get(id).cloned())?.ok_or(RepositoryError::NotFound)
+    }
+
+    /// Update a todo's task. Its owner stays the same; `todo_dto.user_id` is ignored.
+    fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        let Some(user_id) = self.store.read(|projection| projection.todos.get(id).map(|todo| todo.user_id))? else {
+            return Ok(0);
+        };
+        self.change(id, TodoEvent::Updated { user_id, task: todo_dto.task.clone() })
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        self.change(id, TodoEvent::Deleted)
+    }
+}
+
+impl TodoStore<Connection, RepositoryError> for EventSourcedTodoRepository {
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        self.store.read(|projection| user_todos(projection, user_id))
+    }
+
+    fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.change(id, TodoEvent::Completed)
+    }
+
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.change(id, TodoEvent::Uncompleted)
+    }
+}
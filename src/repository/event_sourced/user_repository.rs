@@ -0,0 +1,113 @@
+use rusqlite::Connection;
+
+use crate::models::{normalize_email, Role, User, UserDTO, ValidationError};
+use crate::repository::event_sourced::{Event, EventStore, LoggedEvent, Projection, UserEvent};
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Stores users as events; see [the module docs](crate::repository::event_sourced).
+///
+/// Validates users and keeps emails unique like the SQLite
+/// [`UserRepository`](crate::repository::sqlite::user_repository::UserRepository).
+pub struct EventSourcedUserRepository {
+    store: EventStore,
+}
+
+/// Fail if a user other than `id` already has `email`.
+fn check_email_unused(projection: &Projection, email: &str, id: Option<i64>) -> Result<()> {
+    if projection.users.values().any(|user| Some(user.id) != id && normalize_email(&user.email) == email) {
+        return Err(ValidationError::field("email", "is already in use").into());
+    }
+    Ok(())
+}
+
+impl EventSourcedUserRepository {
+    /// Open the event log in the database at `connection_string`, creating
+    /// its tables if needed.
+    pub fn new(connection_string: &str) -> Result<EventSourcedUserRepository> {
+        Ok(EventSourcedUserRepository { store: EventStore::new(EventSourcedUserRepository::connect_to_db(connection_string)?)? })
+    }
+
+    /// Get every user, ordered by id.
+    pub fn get_users(&self) -> Result<Vec<User>> {
+        self.store.read(|projection| projection.users.values().cloned().collect())
+    }
+
+    /// Find the user with the given email, compared after normalization.
+    pub fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        let email = normalize_email(email);
+        self.store.read(|projection| projection.users.values().find(|user| normalize_email(&user.email) == email).cloned())
+    }
+
+    /// Change a user's role, returning the number of users updated.
+    pub fn set_role(&self, id: &i64, role: Role) -> Result<usize> {
+        self.change(id, UserEvent::RoleSet(role))
+    }
+
+    /// Every event about a user, oldest first; still there after it is deleted.
+    pub fn history(&self, id: &i64) -> Result<Vec<LoggedEvent>> {
+        self.store.events("user", id)
+    }
+
+    /// Append `event` for user `id` if it exists, returning the number of
+    /// users changed.
+    fn change(&self, id: &i64, event: UserEvent) -> Result<usize> {
+        self.store.write(|projection| {
+            if projection.users.contains_key(id) {
+                Ok((1, Some(Event::User(*id, event))))
+            } else {
+                Ok((0, None))
+            }
+        })
+    }
+}
+
+impl Repository<Connection, User, RepositoryError> for EventSourcedUserRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Connection> {
+        Ok(Connection::open(connection_string)?)
+    }
+
+    /// Validate and save a new user, returning its id.
+    ///
+    /// Fails with `RepositoryError::Validation` if the DTO is invalid or the
+    /// email is already used by another user.
+    fn save_new_item(&self, user_dto: &UserDTO) -> Result<i64> {
+        let user_dto = user_dto.validate()?;
+        self.store.write(|projection| {
+            check_email_unused(projection, &user_dto.email, None)?;
+            let id = projection.last_user_id + 1;
+            let event = UserEvent::Created {
+                first_name: user_dto.first_name,
+                last_name: user_dto.last_name,
+                email: user_dto.email,
+            };
+            Ok((id, Some(Event::User(id, event))))
+        })
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<User> {
+        self.store.read(|projection| projection.users.get(id).cloned())?.ok_or(RepositoryError::NotFound)
+    }
+
+    /// Validate and update a user, with the same rules as `save_new_item`.
+    fn update_item(&self, id: &i64, user_dto: &UserDTO) -> Result<usize> {
+        let user_dto = user_dto.validate()?;
+        self.store.write(|projection| {
+            check_email_unused(projection, &user_dto.email, Some(*id))?;
+            if !projection.users.contains_key(id) {
+                return Ok((0, None));
+            }
+            let event = UserEvent::Updated {
+                first_name: user_dto.first_name,
+                last_name: user_dto.last_name,
+                email: user_dto.email,
+            };
+            Ok((1, Some(Event::User(*id, event))))
+        })
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        self.change(id, UserEvent::Deleted)
+    }
+}
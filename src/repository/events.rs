@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The kind of item a [`RepositoryEvent`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EntityType {
+    Todo,
+    User,
+}
+
+/// What happened to the item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+    Completed,
+    Uncompleted,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 5] =
+        [EventKind::Created, EventKind::Updated, EventKind::Deleted, EventKind::Completed, EventKind::Uncompleted];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Updated => "updated",
+            EventKind::Deleted => "deleted",
+            EventKind::Completed => "completed",
+            EventKind::Uncompleted => "uncompleted",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<EventKind> {
+        EventKind::ALL.into_iter().find(|k| k.as_str() == kind)
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A write that a repository has just committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RepositoryEvent {
+    pub entity: EntityType,
+    pub id: i64,
+    pub kind: EventKind,
+    pub timestamp: DateTime<Utc>,
+}
+
+type Callback = Box<dyn FnMut(&RepositoryEvent) + Send>;
+
+/// The subscribers of one repository.
+///
+/// Events are delivered synchronously on the thread that made the write, so
+/// callbacks should be quick. No lock is held while they run, so they may
+/// subscribe or cause further events; those are delivered once the current
+/// one has reached everybody.
+#[derive(Default)]
+pub(crate) struct Observers {
+    callbacks: Mutex<Vec<Callback>>,
+    senders: Mutex<Vec<Sender<RepositoryEvent>>>,
+    queue: Mutex<Queue>,
+}
+
+/// Events waiting to be delivered.
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<RepositoryEvent>,
+    /// How many [`Observers::transaction`]s are open; events wait for the
+    /// outermost one to commit.
+    held: usize,
+    delivering: bool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Observers {
+    pub(crate) fn subscribe(&self, callback: impl FnMut(&RepositoryEvent) + Send + 'static) {
+        lock(&self.callbacks).push(Box::new(callback));
+    }
+
+    /// A new channel that receives every event from now on. The sending side
+    /// is dropped once the receiver is.
+    pub(crate) fn channel(&self) -> Receiver<RepositoryEvent> {
+        let (sender, receiver) = mpsc::channel();
+        lock(&self.senders).push(sender);
+        receiver
+    }
+
+    /// Tell every subscriber about a write. Inside a
+    /// [`transaction`](Observers::transaction) the event waits until it
+    /// commits; otherwise call this only once the write has been committed.
+    pub(crate) fn notify(&self, entity: EntityType, id: i64, kind: EventKind) {
+        let event = RepositoryEvent { entity, id, kind, timestamp: Utc::now() };
+        lock(&self.queue).events.push_back(event);
+        self.deliver();
+    }
+
+    /// Run `write` in a transaction on `conn`, committing if it succeeds and
+    /// rolling back if it fails. Events notified meanwhile are delivered
+    /// after the commit, or dropped with the rollback.
+    ///
+    /// If `conn` is already in a transaction, `write` joins it, and its
+    /// events wait for that one instead.
+    pub(crate) fn transaction<T, E: From<rusqlite::Error>>(
+        &self,
+        conn: &Connection,
+        write: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        if !conn.is_autocommit() {
+            return write();
+        }
+        let tx = conn.unchecked_transaction()?;
+        lock(&self.queue).held += 1;
+        let result = write().and_then(|value| Ok(tx.commit().map(|()| value)?));
+        let mut queue = lock(&self.queue);
+        queue.held -= 1;
+        if result.is_err() {
+            queue.events.clear();
+        }
+        drop(queue);
+        self.deliver();
+        result
+    }
+
+    /// Hand queued events to every subscriber, one at a time and in order,
+    /// unless they are being held or delivered further up the stack.
+    fn deliver(&self) {
+        {
+            let mut queue = lock(&self.queue);
+            if queue.held > 0 || queue.delivering {
+                return;
+            }
+            queue.delivering = true;
+        }
+        loop {
+            let event = {
+                let mut queue = lock(&self.queue);
+                let event = queue.events.pop_front();
+                queue.delivering = event.is_some();
+                event
+            };
+            let Some(event) = event else {
+                return;
+            };
+            // take the callbacks out so they can subscribe, keeping any new ones after them
+            let mut callbacks = mem::take(&mut *lock(&self.callbacks));
+            for callback in callbacks.iter_mut() {
+                callback(&event);
+            }
+            let mut current = lock(&self.callbacks);
+            callbacks.append(&mut current);
+            *current = callbacks;
+            drop(current);
+            lock(&self.senders).retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Like [`notify`](Observers::notify), but only if `count` rows were
+    /// written, passing the count through.
+    pub(crate) fn notify_written(&self, count: usize, entity: EntityType, id: i64, kind: EventKind) -> usize {
+        if count > 0 {
+            self.notify(entity, id, kind);
+        }
+        count
+    }
+}
@@ -0,0 +1,110 @@
+//! Storage in a single JSON or TOML file, for scripts and single-user setups
+//! where a database is overkill.
+//!
+//! [`FileTodoRepository`](todo_repository::FileTodoRepository) and
+//! [`FileUserRepository`](user_repository::FileUserRepository) can share one
+//! file. Every call reads the whole file, and every write replaces it through
+//! a rename, holding a lock on `<file>.lock` throughout, so several processes
+//! can use the same file safely. Ids and timestamps work as in the SQLite
+//! repositories: a new item gets one more than the highest id in use, and
+//! times are kept to the second.
+
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{TodoItem, User};
+use crate::repository::files::{invalid_data, write_atomically};
+use crate::repository::RepositoryError;
+
+pub mod todo_repository;
+pub mod user_repository;
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Everything in a data file.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct FileData {
+    /// The highest user id ever given out, so deleted users' ids aren't
+    /// reused. Comes first, as TOML needs plain values before tables.
+    #[serde(default)]
+    pub(crate) last_user_id: i64,
+    #[serde(default)]
+    pub(crate) todos: Vec<TodoItem>,
+    #[serde(default)]
+    pub(crate) users: Vec<User>,
+}
+
+/// How a data file is written, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Toml,
+}
+
+/// A data file and its lock file: what the file repositories "connect" to.
+pub struct DataFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    format: Format,
+}
+
+impl DataFile {
+    /// Use the file at `path`, TOML if it ends in `.toml` and JSON otherwise,
+    /// creating an empty one if it doesn't exist.
+    pub(crate) fn open(path: &str) -> Result<DataFile> {
+        let path = PathBuf::from(path);
+        let format = if path.extension().is_some_and(|extension| extension == "toml") { Format::Toml } else { Format::Json };
+        let mut lock_name = path.as_os_str().to_owned();
+        lock_name.push(".lock");
+        let data_file = DataFile { lock_path: PathBuf::from(lock_name), path, format };
+        let lock = data_file.lock()?;
+        lock.lock()?;
+        if !data_file.path.exists() {
+            data_file.write(&FileData::default())?;
+        }
+        Ok(data_file)
+    }
+
+    /// Read the file under a shared lock.
+    pub(crate) fn read(&self) -> Result<FileData> {
+        let lock = self.lock()?;
+        lock.lock_shared()?;
+        self.load()
+    }
+
+    /// Read the file, let `change` modify the data and write it back, all
+    /// under an exclusive lock. Nothing is written if `change` fails.
+    pub(crate) fn update<T>(&self, change: impl FnOnce(&mut FileData) -> Result<T>) -> Result<T> {
+        let lock = self.lock()?;
+        lock.lock()?;
+        let mut data = self.load()?;
+        let result = change(&mut data)?;
+        self.write(&data)?;
+        Ok(result)
+    }
+
+    /// The lock file, opened but not locked yet. The lock is released when
+    /// it is closed.
+    fn lock(&self) -> Result<File> {
+        Ok(OpenOptions::new().create(true).truncate(false).write(true).open(&self.lock_path)?)
+    }
+
+    fn load(&self) -> Result<FileData> {
+        let contents = std::fs::read_to_string(&self.path)?;
+        let data = match self.format {
+            Format::Json => serde_json::from_str(&contents).map_err(|e| invalid_data(&self.path, e))?,
+            Format::Toml => toml::from_str(&contents).map_err(|e| invalid_data(&self.path, e))?,
+        };
+        Ok(data)
+    }
+
+    fn write(&self, data: &FileData) -> Result<()> {
+        let contents = match self.format {
+            Format::Json => serde_json::to_string_pretty(data).map_err(|e| invalid_data(&self.path, e))?,
+            Format::Toml => toml::to_string_pretty(data).map_err(|e| invalid_data(&self.path, e))?,
+        };
+        Ok(write_atomically(&self.path, &contents)?)
+    }
+}
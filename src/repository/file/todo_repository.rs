@@ -0,0 +1,107 @@
+use crate::models::{TodoItem, TodoItemDTO};
+use crate::repository::file::DataFile;
+use crate::repository::files::{next_id, now};
+use crate::repository::{Repository, RepositoryError, TodoStore};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Stores todos in a JSON or TOML file; see [the module docs](crate::repository::file).
+pub struct FileTodoRepository {
+    file: DataFile,
+}
+
+impl FileTodoRepository {
+    /// Open the data file at `path`, creating it if needed.
+    pub fn new(path: &str) -> Result<FileTodoRepository> {
+        Ok(FileTodoRepository { file: FileTodoRepository::connect_to_db(path)? })
+    }
+
+    /// Apply `change` to todo `id` and bump its version, returning the
+    /// number of todos changed.
+    fn modify(&self, id: &i64, change: impl FnOnce(&mut TodoItem)) -> Result<usize> {
+        self.file.update(|data| {
+            let Some(todo) = data.todos.iter_mut().find(|todo| todo.id == *id) else {
+                return Ok(0);
+            };
+            change(todo);
+            todo.version += 1;
+            Ok(1)
+        })
+    }
+}
+
+impl Repository<DataFile, TodoItem, RepositoryError> for FileTodoRepository {
+    /// Treat the connection string as the data file's path.
+    fn connect_to_db(connection_string: &str) -> Result<DataFile> {
+        DataFile::open(connection_string)
+    }
+
+    fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        self.file.update(|data| {
+            let id = next_id(data.todos.iter().map(|todo| todo.id));
+            data.todos.push(TodoItem {
+                id,
+                user_id: todo_dto.user_id,
+                task: todo_dto.task.clone(),
+                completed: false,
+                created_datetime: now(),
+                completed_datetime: None,
+                list_id: None,
+                assignee_id: None,
+                organization_id: None,
+                snoozed_until: None,
+                deferral_count: 0,
+                refused_datetime: None,
+                refusal_reason: None,
+                version: 1,
+            });
+            Ok(id)
+        })
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
+        self.file.read()?.todos.into_iter().find(|todo| todo.id == *id).ok_or(RepositoryError::NotFound)
+    }
+
+    /// Update a todo's task. Its owner stays the same; `todo_dto.user_id` is ignored.
+    fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        self.modify(id, |todo| todo.task = todo_dto.task.clone())
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        self.file.update(|data| {
+            let count = data.todos.len();
+            data.todos.retain(|todo| todo.id != *id);
+            Ok(count - data.todos.len())
+        })
+    }
+}
+
+impl TodoStore<DataFile, RepositoryError> for FileTodoRepository {
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        let now = now();
+        let mut todos: Vec<TodoItem> = self
+            .file
+            .read()?
+            .todos
+            .into_iter()
+            .filter(|todo| todo.user_id == *user_id && todo.snoozed_until.is_none_or(|until| until <= now))
+            .collect();
+        todos.sort_by_key(|todo| todo.id);
+        Ok(todos)
+    }
+
+    fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.modify(id, |todo| {
+            todo.completed = true;
+            todo.completed_datetime = Some(now());
+        })
+    }
+
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.modify(id, |todo| {
+            todo.completed = false;
+            todo.completed_datetime = None;
+        })
+    }
+}
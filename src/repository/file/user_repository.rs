@@ -0,0 +1,111 @@
+use crate::models::{normalize_email, Role, User, UserDTO, ValidationError};
+use crate::repository::file::{DataFile, FileData};
+use crate::repository::files::next_id;
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Stores users in a JSON or TOML file; see [the module docs](crate::repository::file).
+///
+/// Validates users and keeps emails unique like the SQLite
+/// [`UserRepository`](crate::repository::sqlite::user_repository::UserRepository).
+pub struct FileUserRepository {
+    file: DataFile,
+}
+
+/// Fail if a user other than `id` already has `email`.
+fn check_email_unused(data: &FileData, email: &str, id: Option<i64>) -> Result<()> {
+    if data.users.iter().any(|user| Some(user.id) != id && normalize_email(&user.email) == email) {
+        return Err(ValidationError::field("email", "is already in use").into());
+    }
+    Ok(())
+}
+
+impl FileUserRepository {
+    /// Open the data file at `path`, creating it if needed.
+    pub fn new(path: &str) -> Result<FileUserRepository> {
+        Ok(FileUserRepository { file: FileUserRepository::connect_to_db(path)? })
+    }
+
+    /// Get every user, ordered by id.
+    pub fn get_users(&self) -> Result<Vec<User>> {
+        let mut users = self.file.read()?.users;
+        users.sort_by_key(|user| user.id);
+        Ok(users)
+    }
+
+    /// Find the user with the given email, compared after normalization.
+    pub fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        let email = normalize_email(email);
+        Ok(self.file.read()?.users.into_iter().find(|user| normalize_email(&user.email) == email))
+    }
+
+    /// Change a user's role, returning the number of users updated.
+    pub fn set_role(&self, id: &i64, role: Role) -> Result<usize> {
+        self.file.update(|data| {
+            let Some(user) = data.users.iter_mut().find(|user| user.id == *id) else {
+                return Ok(0);
+            };
+            user.role = role;
+            user.version += 1;
+            Ok(1)
+        })
+    }
+}
+
+impl Repository<DataFile, User, RepositoryError> for FileUserRepository {
+    /// Treat the connection string as the data file's path.
+    fn connect_to_db(connection_string: &str) -> Result<DataFile> {
+        DataFile::open(connection_string)
+    }
+
+    /// Validate and save a new user, returning its id.
+    ///
+    /// Fails with `RepositoryError::Validation` if the DTO is invalid or the
+    /// email is already used by another user.
+    fn save_new_item(&self, user_dto: &UserDTO) -> Result<i64> {
+        let user_dto = user_dto.validate()?;
+        self.file.update(|data| {
+            check_email_unused(data, &user_dto.email, None)?;
+            let id = next_id(data.users.iter().map(|user| user.id).chain([data.last_user_id]));
+            data.last_user_id = id;
+            data.users.push(User {
+                id,
+                first_name: user_dto.first_name,
+                last_name: user_dto.last_name,
+                email: user_dto.email,
+                role: Role::default(),
+                version: 1,
+            });
+            Ok(id)
+        })
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<User> {
+        self.file.read()?.users.into_iter().find(|user| user.id == *id).ok_or(RepositoryError::NotFound)
+    }
+
+    /// Validate and update a user, with the same rules as `save_new_item`.
+    fn update_item(&self, id: &i64, user_dto: &UserDTO) -> Result<usize> {
+        let user_dto = user_dto.validate()?;
+        self.file.update(|data| {
+            check_email_unused(data, &user_dto.email, Some(*id))?;
+            let Some(user) = data.users.iter_mut().find(|user| user.id == *id) else {
+                return Ok(0);
+            };
+            user.first_name = user_dto.first_name;
+            user.last_name = user_dto.last_name;
+            user.email = user_dto.email;
+            user.version += 1;
+            Ok(1)
+        })
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        self.file.update(|data| {
+            let count = data.users.len();
+            data.users.retain(|user| user.id != *id);
+            Ok(count - data.users.len())
+        })
+    }
+}
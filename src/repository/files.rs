@@ -0,0 +1,36 @@
+//! Helpers for the backends that keep their data in plain files.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+/// Replace `path` with `contents` by writing a temporary file next to it and
+/// renaming it over the old one, so readers never see half a file, even if
+/// the process dies mid-write.
+pub(crate) fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = Path::new(&tmp_name);
+    let mut file = File::create(tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
+
+/// An `io::Error` for a file that can't be parsed or written out.
+pub(crate) fn invalid_data(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+}
+
+/// The current time, to the second like the SQLite timestamps.
+pub(crate) fn now() -> DateTime<Utc> {
+    DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap_or_default()
+}
+
+/// One more than the highest id in `ids`, or 1 if there are none, the way
+/// SQLite assigns row ids.
+pub(crate) fn next_id(ids: impl Iterator<Item = i64>) -> i64 {
+    ids.max().unwrap_or(0) + 1
+}
@@ -0,0 +1,81 @@
+//! Storage in plain-text files inside a git repository, one commit per write,
+//! so history, diffs and merges come from git itself.
+//!
+//! Needs the `git` command on the `PATH`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::repository::RepositoryError;
+
+pub mod todo_repository;
+
+/// Run `git` in `dir`, returning its output, or its error output as an
+/// `io::Error` if it fails.
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<String, RepositoryError> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("git {}: {}", args.join(" "), stderr.trim())).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Create `dir` and a git repository in it, unless they already exist.
+///
+/// New repositories get a local committer identity if there is none
+/// configured, so commits work on machines where git was never set up.
+pub(crate) fn init_repository(dir: &Path) -> Result<(), RepositoryError> {
+    fs::create_dir_all(dir)?;
+    if !dir.join(".git").exists() {
+        git(dir, &["init", "--quiet"])?;
+        if git(dir, &["config", "user.email"]).is_err() {
+            git(dir, &["config", "user.name", "to_dont"])?;
+            git(dir, &["config", "user.email", "to_dont@localhost"])?;
+        }
+    }
+    Ok(())
+}
+
+/// The commit `HEAD` points at in `dir`, or an empty string if there are no
+/// commits yet.
+pub(crate) fn head(dir: &Path) -> Result<String, RepositoryError> {
+    let output = Command::new("git").arg("-C").arg(dir).args(["rev-parse", "--verify", "--quiet", "HEAD"]).output()?;
+    if !output.status.success() && !output.stderr.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("git rev-parse HEAD: {}", stderr.trim())).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Stage the file at `path` in `dir`, deletion included, and commit it with
+/// `message`.
+///
+/// If that fails the file is put back as it was at `HEAD`, so the working
+/// tree never holds a write that isn't in the history.
+pub(crate) fn commit(dir: &Path, path: &str, message: &str) -> Result<(), RepositoryError> {
+    let committed = git(dir, &["add", "--all", "--", path])
+        .and_then(|_| git(dir, &["commit", "--quiet", "--no-verify", "--message", message]));
+    if let Err(e) = committed {
+        restore(dir, path)?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Put the file at `path` in `dir` back as it is at `HEAD`, in both the
+/// index and the working tree, removing it if it isn't there.
+fn restore(dir: &Path, path: &str) -> Result<(), RepositoryError> {
+    if git(dir, &["cat-file", "-e", &format!("HEAD:{}", path)]).is_ok() {
+        git(dir, &["checkout", "HEAD", "--", path])?;
+    } else {
+        git(dir, &["rm", "--cached", "--quiet", "--ignore-unmatch", "--", path])?;
+        let file = dir.join(path);
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{TodoItem, TodoItemDTO};
+use crate::repository::files::{invalid_data, next_id, now, write_atomically};
+use crate::repository::git::{commit, head, init_repository};
+use crate::repository::{Repository, RepositoryError, TodoStore};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// The directory, inside the repository, holding one file per user.
+const TODOS_DIR: &str = "todos";
+
+/// The contents of one user's file: a `[[todo]]` table per todo.
+#[derive(Default, Serialize, Deserialize)]
+struct TodoFile {
+    #[serde(default, rename = "todo")]
+    todos: Vec<TodoItem>,
+}
+
+/// Where each todo is, so a write only has to read its owner's file.
+#[derive(Default)]
+struct Index {
+    /// The commit the index was built from, `None` until it is first built.
+    head: Option<String>,
+    /// The owner of each todo, by id.
+    owners: HashMap<i64, i64>,
+    /// The highest id in use.
+    last_id: i64,
+}
+
+/// Stores todos as TOML files in a git repository, `todos/<user id>.toml`
+/// per user, committing every write.
+///
+/// Behaves like the SQLite [`TodoRepository`](crate::repository::sqlite::todo_repository::TodoRepository)
+/// for everything in [`TodoStore`], ids included: a new todo gets one more
+/// than the highest id in use.
+pub struct GitTodoRepository {
+    dir: PathBuf,
+    /// Rebuilt whenever the repository has moved on without us, and held for
+    /// the whole of each write, so two can't interleave their file changes
+    /// and commits.
+    index: Mutex<Index>,
+}
+
+impl GitTodoRepository {
+    /// Open the repository in `dir`, creating the directory and running
+    /// `git init` if needed.
+    pub fn new(dir: &str) -> Result<GitTodoRepository> {
+        Ok(GitTodoRepository { dir: GitTodoRepository::connect_to_db(dir)?, index: Mutex::new(Index::default()) })
+    }
+
+    /// The repository's directory, for running git commands of your own.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    fn file_name(user_id: &i64) -> String {
+        format!("{}/{}.toml", TODOS_DIR, user_id)
+    }
+
+    fn read_file(&self, path: &Path) -> Result<Vec<TodoItem>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(path)?;
+        let file: TodoFile = toml::from_str(&contents).map_err(|e| invalid_data(path, e))?;
+        Ok(file.todos)
+    }
+
+    fn read_user(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        self.read_file(&self.dir.join(GitTodoRepository::file_name(user_id)))
+    }
+
+    /// Every user's todos, in no particular order.
+    fn read_all(&self) -> Result<Vec<TodoItem>> {
+        let dir = self.dir.join(TODOS_DIR);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut todos = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "toml") {
+                todos.extend(self.read_file(&path)?);
+            }
+        }
+        Ok(todos)
+    }
+
+    /// Replace a user's file with `todos`, in id order, or remove it if there
+    /// are none left.
+    fn write_user(&self, user_id: &i64, mut todos: Vec<TodoItem>) -> Result<()> {
+        let path = self.dir.join(GitTodoRepository::file_name(user_id));
+        if todos.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        todos.sort_by_key(|todo| todo.id);
+        fs::create_dir_all(self.dir.join(TODOS_DIR))?;
+        let contents = toml::to_string_pretty(&TodoFile { todos }).map_err(|e| invalid_data(&path, e))?;
+        Ok(write_atomically(&path, &contents)?)
+    }
+
+    /// Lock the index, first rebuilding it from every user's file if
+    /// something else has committed since it was built.
+    fn index(&self) -> Result<MutexGuard<'_, Index>> {
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let head = head(&self.dir)?;
+        if index.head.as_ref() != Some(&head) {
+            let todos = self.read_all()?;
+            *index = Index {
+                head: Some(head),
+                owners: todos.iter().map(|todo| (todo.id, todo.user_id)).collect(),
+                last_id: next_id(todos.iter().map(|todo| todo.id)) - 1,
+            };
+        }
+        Ok(index)
+    }
+
+    /// Todo `id` and the rest of its owner's todos, or `None` if there is no
+    /// such todo.
+    fn find(&self, index: &Index, id: &i64) -> Result<Option<(TodoItem, Vec<TodoItem>)>> {
+        let Some(user_id) = index.owners.get(id) else {
+            return Ok(None);
+        };
+        let (todo, others): (Vec<_>, Vec<_>) = self.read_user(user_id)?.into_iter().partition(|todo| todo.id == *id);
+        Ok(todo.into_iter().next().map(|todo| (todo, others)))
+    }
+
+    /// Commit the change to `user_id`'s file with `message`, keeping the
+    /// index in step with the new commit.
+    fn commit(&self, index: &mut Index, user_id: &i64, message: &str) -> Result<()> {
+        commit(&self.dir, &GitTodoRepository::file_name(user_id), message)?;
+        index.head = Some(head(&self.dir)?);
+        Ok(())
+    }
+
+    /// Apply `change` to todo `id` in its owner's file and commit it with
+    /// `message`, returning the number of todos changed.
+    fn modify(&self, id: &i64, message: &str, change: impl FnOnce(&mut TodoItem)) -> Result<usize> {
+        let mut index = self.index()?;
+        let Some((mut todo, mut todos)) = self.find(&index, id)? else {
+            return Ok(0);
+        };
+        change(&mut todo);
+        todo.version += 1;
+
+        let user_id = todo.user_id;
+        todos.push(todo);
+        self.write_user(&user_id, todos)?;
+        self.commit(&mut index, &user_id, message)?;
+        Ok(1)
+    }
+}
+
+impl Repository<PathBuf, TodoItem, RepositoryError> for GitTodoRepository {
+    /// Treat the connection string as the repository's directory.
+    fn connect_to_db(connection_string: &str) -> Result<PathBuf> {
+        let dir = PathBuf::from(connection_string);
+        init_repository(&dir)?;
+        Ok(dir)
+    }
+
+    fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        let mut index = self.index()?;
+        let id = index.last_id + 1;
+        let mut todos = self.read_user(&todo_dto.user_id)?;
+        todos.push(TodoItem {
+            id,
+            user_id: todo_dto.user_id,
+            task: todo_dto.task.clone(),
+            completed: false,
+            created_datetime: now(),
+            completed_datetime: None,
+            list_id: None,
+            assignee_id: None,
+            organization_id: None,
+            snoozed_until: None,
+            deferral_count: 0,
+            refused_datetime: None,
+            refusal_reason: None,
+            version: 1,
+        });
+        self.write_user(&todo_dto.user_id, todos)?;
+        self.commit(&mut index, &todo_dto.user_id, &format!("Add todo {}: {}", id, todo_dto.task))?;
+        index.owners.insert(id, todo_dto.user_id);
+        index.last_id = id;
+        Ok(id)
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
+        let index = self.index()?;
+        Ok(self.find(&index, id)?.ok_or(RepositoryError::NotFound)?.0)
+    }
+
+    /// Update a todo's task. Its owner stays the same; `todo_dto.user_id` is ignored.
+    fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        self.modify(id, &format!("Update todo {}: {}", id, todo_dto.task), |todo| todo.task = todo_dto.task.clone())
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        let mut index = self.index()?;
+        let Some((todo, todos)) = self.find(&index, id)? else {
+            return Ok(0);
+        };
+        self.write_user(&todo.user_id, todos)?;
+        self.commit(&mut index, &todo.user_id, &format!("Delete todo {}: {}", id, todo.task))?;
+        index.owners.remove(id);
+        index.last_id = next_id(index.owners.keys().copied()) - 1;
+        Ok(1)
+    }
+}
+
+impl TodoStore<PathBuf, RepositoryError> for GitTodoRepository {
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        let now = Utc::now();
+        let mut todos = self.read_user(user_id)?;
+        todos.retain(|todo| todo.snoozed_until.is_none_or(|until| until <= now));
+        todos.sort_by_key(|todo| todo.id);
+        Ok(todos)
+    }
+
+    fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.modify(id, &format!("Complete todo {}", id), |todo| {
+            todo.completed = true;
+            todo.completed_datetime = Some(now());
+        })
+    }
+
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.modify(id, &format!("Uncomplete todo {}", id), |todo| {
+            todo.completed = false;
+            todo.completed_datetime = None;
+        })
+    }
+}
@@ -1,6 +1,21 @@
+use crate::models::TodoItem;
 use crate::repository::entity::Entity;
 
+pub use error::RepositoryError;
+pub use events::{EntityType, EventKind, RepositoryEvent};
+
 mod entity;
+pub mod error;
+pub mod event_sourced;
+pub mod events;
+#[cfg(feature = "flatfile")]
+pub mod file;
+#[cfg(any(feature = "git", feature = "flatfile"))]
+mod files;
+#[cfg(feature = "git")]
+pub mod git;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod sqlite;
 
 /// The `Repository` trait defines a set of common CRUD operations.
@@ -13,3 +28,12 @@ pub trait Repository<C, E: Entity, Err> {
     fn update_item(&self, id: &E::Id, item: &E::ItemDto) -> Result<usize, Err>;
     fn delete_item_by_id(&self, id: &E::Id) -> Result<usize, Err>;
 }
+
+/// The todo operations every storage backend has, on top of CRUD, so code
+/// and tests can be written once for all of them.
+pub trait TodoStore<C, Err>: Repository<C, TodoItem, Err> {
+    /// A user's todos, leaving out snoozed ones, in id order.
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>, Err>;
+    fn complete_todo_item(&self, id: &i64) -> Result<usize, Err>;
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize, Err>;
+}
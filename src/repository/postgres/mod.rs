@@ -0,0 +1,67 @@
+//! Storage in a PostgreSQL database, for when one SQLite file is no longer
+//! enough.
+//!
+//! The tables mirror the SQLite ones, with native booleans and timestamps,
+//! and are created or brought up to date when a repository is opened. Ids
+//! come from identity columns, so like SQLite's AUTOINCREMENT an id is never
+//! given out twice, though a failed insert may skip one. Times are kept to
+//! the second.
+
+use std::io;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use postgres::error::SqlState;
+use postgres::{Client, NoTls};
+
+use crate::repository::RepositoryError;
+
+pub mod todo_repository;
+pub mod user_repository;
+
+/// Connect to the database described by `connection_string`, either
+/// `key=value` pairs (`host=localhost user=postgres dbname=to_dont`) or a
+/// `postgresql://` URL.
+pub(crate) fn connect(connection_string: &str) -> Result<Client, RepositoryError> {
+    Ok(Client::connect(connection_string, NoTls)?)
+}
+
+/// Lock a repository's client for one call; queries need it mutably.
+pub(crate) fn lock(client: &Mutex<Client>) -> MutexGuard<'_, Client> {
+    client.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Make the `id` column of `table` an identity column if it isn't one yet,
+/// starting after the highest id in use. `upgrade` is run in the same
+/// transaction once the column is added, to carry over any other record of
+/// ids given out.
+pub(crate) fn add_identity(client: &mut Client, table: &str, upgrade: &str) -> Result<(), RepositoryError> {
+    let mut tx = client.transaction()?;
+    tx.batch_execute(&format!("LOCK TABLE {} IN ACCESS EXCLUSIVE MODE", table))?;
+    let identity: bool = tx
+        .query_one(
+            "SELECT is_identity = 'YES' FROM information_schema.columns \
+WHERE table_schema = current_schema() AND table_name = $1 AND column_name = 'id'",
+            &[&table],
+        )?
+        .try_get(0)?;
+    if !identity {
+        tx.batch_execute(&format!(
+            "ALTER TABLE {0} ALTER COLUMN id ADD GENERATED ALWAYS AS IDENTITY;
+SELECT setval(pg_get_serial_sequence('{0}', 'id'), MAX(id)) FROM {0} HAVING MAX(id) > 0;
+{1}",
+            table, upgrade
+        ))?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Whether `e` is a UNIQUE constraint failure.
+pub(crate) fn is_unique_violation(e: &postgres::Error) -> bool {
+    e.code() == Some(&SqlState::UNIQUE_VIOLATION)
+}
+
+/// A column holding a value this version doesn't know, such as a role.
+pub(crate) fn unknown(what: &str, value: &str) -> RepositoryError {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unknown {} {}", what, value)).into()
+}
@@ -0,0 +1,146 @@
+use std::sync::Mutex;
+
+use postgres::{Client, Row};
+
+use crate::models::{RefusalReason, TodoItem, TodoItemDTO};
+use crate::repository::postgres::{add_identity, connect, lock, unknown};
+use crate::repository::{Repository, RepositoryError, TodoStore};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// The columns read by [`todo_from_row`], in order.
+const TODO_COLUMNS: &str = "id, user_id, task, completed, created_datetime, completed_datetime, list_id, assignee_id, organization_id, snoozed_until, deferral_count, refused_datetime, refusal_reason, version";
+
+/// Build a `TodoItem` from a row selected with [`TODO_COLUMNS`].
+fn todo_from_row(row: &Row) -> Result<TodoItem> {
+    let refusal_reason = match row.try_get::<_, Option<String>>(12)? {
+        Some(reason) => Some(RefusalReason::parse(&reason).ok_or_else(|| unknown("refusal reason", &reason))?),
+        None => None,
+    };
+    Ok(TodoItem {
+        id: row.try_get(0)?,
+        user_id: row.try_get(1)?,
+        task: row.try_get(2)?,
+        completed: row.try_get(3)?,
+        created_datetime: row.try_get(4)?,
+        completed_datetime: row.try_get(5)?,
+        list_id: row.try_get(6)?,
+        assignee_id: row.try_get(7)?,
+        organization_id: row.try_get(8)?,
+        snoozed_until: row.try_get(9)?,
+        deferral_count: row.try_get(10)?,
+        refused_datetime: row.try_get(11)?,
+        refusal_reason,
+        version: row.try_get(13)?,
+    })
+}
+
+/// Stores todos in PostgreSQL; see [the module docs](crate::repository::postgres).
+///
+/// Behaves like the SQLite [`TodoRepository`](crate::repository::sqlite::todo_repository::TodoRepository)
+/// for everything in [`TodoStore`].
+pub struct PostgresTodoRepository {
+    client: Mutex<Client>,
+}
+
+impl PostgresTodoRepository {
+    /// Connect to the database at `connection_string` and create or update
+    /// the `todos` table.
+    pub fn new(connection_string: &str) -> Result<PostgresTodoRepository> {
+        let todo_repo = PostgresTodoRepository { client: Mutex::new(PostgresTodoRepository::connect_to_db(connection_string)?) };
+        todo_repo.create_db()?;
+        Ok(todo_repo)
+    }
+
+    fn create_db(&self) -> Result<()> {
+        lock(&self.client).batch_execute(
+            "CREATE TABLE IF NOT EXISTS todos (\
+id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,\
+user_id BIGINT NOT NULL,\
+task TEXT NOT NULL,\
+completed BOOLEAN NOT NULL DEFAULT FALSE,\
+created_datetime TIMESTAMPTZ NOT NULL DEFAULT date_trunc('second', now()),\
+completed_datetime TIMESTAMPTZ,\
+list_id BIGINT,\
+assignee_id BIGINT,\
+organization_id BIGINT,\
+snoozed_until TIMESTAMPTZ,\
+deferral_count BIGINT NOT NULL DEFAULT 0,\
+refused_datetime TIMESTAMPTZ,\
+refusal_reason TEXT,\
+version BIGINT NOT NULL DEFAULT 1);
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS list_id BIGINT;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS assignee_id BIGINT;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS organization_id BIGINT;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS snoozed_until TIMESTAMPTZ;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS deferral_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS refused_datetime TIMESTAMPTZ;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS refusal_reason TEXT;
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;",
+        )?;
+        add_identity(&mut lock(&self.client), "todos", "")?;
+        Ok(())
+    }
+}
+
+impl Repository<Client, TodoItem, RepositoryError> for PostgresTodoRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Client> {
+        connect(connection_string)
+    }
+
+    fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        let row = lock(&self.client).query_one(
+            "INSERT INTO todos (user_id, task) VALUES ($1, $2) RETURNING id",
+            &[&todo_dto.user_id, &todo_dto.task],
+        )?;
+        Ok(row.try_get(0)?)
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
+        let row = lock(&self.client).query_opt(&format!("SELECT {} FROM todos WHERE id = $1", TODO_COLUMNS), &[id])?;
+        todo_from_row(&row.ok_or(RepositoryError::NotFound)?)
+    }
+
+    /// Update a todo's task. Its owner stays the same; `todo_dto.user_id` is ignored.
+    fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        let updated = lock(&self.client).execute(
+            "UPDATE todos SET version = version + 1, task = $1 WHERE id = $2",
+            &[&todo_dto.task, id],
+        )?;
+        Ok(updated as usize)
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        let deleted = lock(&self.client).execute("DELETE FROM todos WHERE id = $1", &[id])?;
+        Ok(deleted as usize)
+    }
+}
+
+impl TodoStore<Client, RepositoryError> for PostgresTodoRepository {
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        let rows = lock(&self.client).query(
+            &format!(
+                "SELECT {} FROM todos WHERE user_id = $1 AND (snoozed_until IS NULL OR snoozed_until <= now()) ORDER BY id",
+                TODO_COLUMNS
+            ),
+            &[user_id],
+        )?;
+        rows.iter().map(todo_from_row).collect()
+    }
+
+    fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        let updated = lock(&self.client).execute(
+            "UPDATE todos SET version = version + 1, completed = TRUE, completed_datetime = date_trunc('second', now()) WHERE id = $1",
+            &[id],
+        )?;
+        Ok(updated as usize)
+    }
+
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        let updated = lock(&self.client).execute(
+            "UPDATE todos SET version = version + 1, completed = FALSE, completed_datetime = NULL WHERE id = $1",
+            &[id],
+        )?;
+        Ok(updated as usize)
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::Mutex;
+
+use postgres::{Client, Row};
+
+use crate::models::{normalize_email, Role, User, UserDTO, ValidationError};
+use crate::repository::postgres::{add_identity, connect, is_unique_violation, lock, unknown};
+use crate::repository::error::duplicate_emails;
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// The columns read by [`user_from_row`], in order.
+const USER_COLUMNS: &str = "id, first_name, last_name, email, role, version";
+
+/// Build a `User` from a row selected with [`USER_COLUMNS`].
+fn user_from_row(row: &Row) -> Result<User> {
+    let role: String = row.try_get(4)?;
+    Ok(User {
+        id: row.try_get(0)?,
+        first_name: row.try_get(1)?,
+        last_name: row.try_get(2)?,
+        email: row.try_get(3)?,
+        role: Role::parse(&role).ok_or_else(|| unknown("role", &role))?,
+        version: row.try_get(5)?,
+    })
+}
+
+/// The error for an email another user already has.
+fn email_in_use() -> RepositoryError {
+    ValidationError::field("email", "is already in use").into()
+}
+
+/// Report a duplicate email as a validation error rather than a raw constraint failure.
+fn map_write_error(e: postgres::Error) -> RepositoryError {
+    if is_unique_violation(&e) {
+        email_in_use()
+    } else {
+        e.into()
+    }
+}
+
+/// Stores users in PostgreSQL; see [the module docs](crate::repository::postgres).
+///
+/// Validates users and keeps emails unique like the SQLite
+/// [`UserRepository`](crate::repository::sqlite::user_repository::UserRepository).
+pub struct PostgresUserRepository {
+    client: Mutex<Client>,
+}
+
+impl PostgresUserRepository {
+    /// Connect to the database at `connection_string` and create or update
+    /// the `users` table.
+    pub fn new(connection_string: &str) -> Result<PostgresUserRepository> {
+        let user_repo = PostgresUserRepository { client: Mutex::new(PostgresUserRepository::connect_to_db(connection_string)?) };
+        user_repo.create_db()?;
+        Ok(user_repo)
+    }
+
+    fn create_db(&self) -> Result<()> {
+        lock(&self.client).batch_execute(
+            "CREATE TABLE IF NOT EXISTS users (\
+id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,\
+first_name TEXT NOT NULL,\
+last_name TEXT NOT NULL,\
+email TEXT NOT NULL,\
+role TEXT NOT NULL DEFAULT 'user',\
+version BIGINT NOT NULL DEFAULT 1);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;",
+        )?;
+        let mut client = lock(&self.client);
+        // ids were once counted in an `id_sequence` table, which may be past
+        // the highest id in use if the newest users were deleted
+        add_identity(
+            &mut client,
+            "users",
+            "DO $$ BEGIN
+IF to_regclass('id_sequence') IS NOT NULL THEN
+PERFORM setval(pg_get_serial_sequence('users', 'id'), seq) FROM id_sequence
+WHERE name = 'users' AND seq > (SELECT COALESCE(MAX(id), 0) FROM users);
+DROP TABLE id_sequence;
+END IF;
+END $$;",
+        )?;
+        // rows written before emails were validated may clash once
+        // normalized, which is reported instead of failing on the index
+        let indexed: bool = client.query_one("SELECT to_regclass('users_email_unique') IS NOT NULL", &[])?.try_get(0)?;
+        if !indexed {
+            let duplicates = client.query(
+                "SELECT lower(trim(email)), string_agg(id::text, ', ' ORDER BY id) FROM users \
+GROUP BY lower(trim(email)) HAVING count(*) > 1 ORDER BY min(id)",
+                &[],
+            )?;
+            if !duplicates.is_empty() {
+                let duplicates = duplicates.iter().map(|row| Ok((row.try_get(0)?, row.try_get(1)?))).collect::<Result<_>>()?;
+                return Err(duplicate_emails(duplicates));
+            }
+        }
+        client.batch_execute("CREATE UNIQUE INDEX IF NOT EXISTS users_email_unique ON users (lower(trim(email)));")?;
+        Ok(())
+    }
+
+    /// Get every user, ordered by id.
+    pub fn get_users(&self) -> Result<Vec<User>> {
+        let rows = lock(&self.client).query(&format!("SELECT {} FROM users ORDER BY id", USER_COLUMNS), &[])?;
+        rows.iter().map(user_from_row).collect()
+    }
+
+    /// Find the user with the given email, compared after normalization.
+    ///
+    /// Returns `Ok(None)` if there is no such user.
+    pub fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = lock(&self.client).query_opt(
+            &format!("SELECT {} FROM users WHERE lower(trim(email)) = $1", USER_COLUMNS),
+            &[&normalize_email(email)],
+        )?;
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    /// Change a user's role, returning the number of rows updated.
+    pub fn set_role(&self, id: &i64, role: Role) -> Result<usize> {
+        let updated = lock(&self.client).execute(
+            "UPDATE users SET version = version + 1, role = $1 WHERE id = $2",
+            &[&role.as_str(), id],
+        )?;
+        Ok(updated as usize)
+    }
+}
+
+impl Repository<Client, User, RepositoryError> for PostgresUserRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Client> {
+        connect(connection_string)
+    }
+
+    /// Validate and save a new user, returning its id.
+    ///
+    /// Fails with `RepositoryError::Validation` if the DTO is invalid or the
+    /// email is already used by another user.
+    fn save_new_item(&self, user_dto: &UserDTO) -> Result<i64> {
+        let user_dto = user_dto.validate()?;
+        // a taken email is caught before an id is drawn, so it doesn't use
+        // one up; the index still catches a concurrent insert
+        let row = lock(&self.client)
+            .query_opt(
+                "INSERT INTO users (first_name, last_name, email) SELECT $1, $2, $3 \
+WHERE NOT EXISTS (SELECT 1 FROM users WHERE lower(trim(email)) = $3) RETURNING id",
+                &[&user_dto.first_name, &user_dto.last_name, &user_dto.email],
+            )
+            .map_err(map_write_error)?;
+        Ok(row.ok_or_else(email_in_use)?.try_get(0)?)
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<User> {
+        let row = lock(&self.client).query_opt(&format!("SELECT {} FROM users WHERE id = $1", USER_COLUMNS), &[id])?;
+        user_from_row(&row.ok_or(RepositoryError::NotFound)?)
+    }
+
+    /// Validate and update a user, with the same rules as `save_new_item`.
+    fn update_item(&self, id: &i64, user_dto: &UserDTO) -> Result<usize> {
+        let user_dto = user_dto.validate()?;
+        let updated = lock(&self.client)
+            .execute(
+                "UPDATE users SET version = version + 1, first_name = $1, last_name = $2, email = $3 WHERE id = $4",
+                &[&user_dto.first_name, &user_dto.last_name, &user_dto.email, id],
+            )
+            .map_err(map_write_error)?;
+        Ok(updated as usize)
+    }
+
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        let deleted = lock(&self.client).execute("DELETE FROM users WHERE id = $1", &[id])?;
+        Ok(deleted as usize)
+    }
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params, Result, Row};
+
+use crate::models::{ApiToken, Scope, Session};
+use crate::repository::sqlite::{optional_timestamp, timestamp};
+
+/// Stores password hashes, login sessions and API tokens, in tables separate from `users`.
+/// They are removed along with the user by [`UserRepository::delete_account`].
+///
+/// [`UserRepository::delete_account`]: crate::repository::sqlite::user_repository::UserRepository::delete_account
+pub struct AuthRepository {
+    conn: Connection,
+}
+
+fn session_from_row(row: &Row) -> Result<Session> {
+    let revoked_datetime = optional_timestamp(row, 4)?;
+    Ok(Session {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        created_datetime: timestamp(row, 2)?,
+        expires_datetime: timestamp(row, 3)?,
+        revoked_datetime,
+    })
+}
+
+const API_TOKEN_COLUMNS: &str = "id, user_id, name, scopes, created_datetime, last_used_datetime, revoked_datetime";
+
+/// Build an `ApiToken` from a row selected with [`API_TOKEN_COLUMNS`].
+/// Scopes are stored space-separated; unknown ones are ignored.
+fn api_token_from_row(row: &Row) -> Result<ApiToken> {
+    let scopes: String = row.get(3)?;
+    Ok(ApiToken {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        scopes: scopes.split_whitespace().filter_map(Scope::parse).collect(),
+        created_datetime: timestamp(row, 4)?,
+        last_used_datetime: optional_timestamp(row, 5)?,
+        revoked_datetime: optional_timestamp(row, 6)?,
+    })
+}
+
+impl AuthRepository {
+    /// Generate an instance of the auth repository.
+    /// If no connection string (desired db file name) is provided, returns an in-memory db.
+    pub fn new(connection_string: Option<&str>) -> Result<AuthRepository> {
+        let conn = match connection_string {
+            Some(connection_string) => Connection::open(connection_string)?,
+            None => Connection::open_in_memory()?,
+        };
+        let auth_repo = AuthRepository { conn };
+        auth_repo.create_db()?;
+        Ok(auth_repo)
+    }
+
+    fn create_db(&self) -> Result<()> {
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS credentials (\
+user_id INTEGER PRIMARY KEY,\
+password_hash TEXT NOT NULL,\
+updated_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+            (),
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS sessions (\
+id INTEGER PRIMARY KEY,\
+user_id INTEGER NOT NULL,\
+token_hash TEXT NOT NULL UNIQUE,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+expires_datetime INTEGER NOT NULL,\
+revoked_datetime INTEGER\
+)",
+            (),
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS api_tokens (\
+id INTEGER PRIMARY KEY,\
+user_id INTEGER NOT NULL,\
+name TEXT NOT NULL,\
+token_hash TEXT NOT NULL UNIQUE,\
+scopes TEXT NOT NULL,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+last_used_datetime INTEGER,\
+revoked_datetime INTEGER\
+)",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Store (or replace) the password hash for a user.
+    pub fn set_password_hash(&self, user_id: &i64, password_hash: &str) -> Result<usize> {
+        self.conn.execute(
+            "INSERT INTO credentials (user_id, password_hash) VALUES (?1, ?2) \
+ON CONFLICT (user_id) DO UPDATE SET password_hash = excluded.password_hash, updated_datetime = strftime('%s', 'now')",
+            params![user_id, password_hash],
+        )
+    }
+
+    /// Get the password hash for a user, or `None` if they have no password.
+    pub fn get_password_hash(&self, user_id: &i64) -> Result<Option<String>> {
+        self.conn.query_row(
+            "SELECT password_hash FROM credentials WHERE user_id = ?1",
+            params![user_id],
+            |row| row.get(0),
+        ).optional()
+    }
+
+    /// Save a new session for the hash of its token, returning the session id.
+    pub fn save_session(&self, user_id: &i64, token_hash: &str, expires_datetime: &DateTime<Utc>) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO sessions (user_id, token_hash, expires_datetime) VALUES (?1, ?2, ?3)",
+            params![user_id, token_hash, expires_datetime.timestamp()],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Find a session by the hash of its token, whether or not it is still active.
+    pub fn find_session(&self, token_hash: &str) -> Result<Option<Session>> {
+        self.conn.query_row(
+            "SELECT id, user_id, created_datetime, expires_datetime, revoked_datetime FROM sessions WHERE token_hash = ?1",
+            params![token_hash],
+            session_from_row,
+        ).optional()
+    }
+
+    /// Revoke a session, returning the number of sessions revoked.
+    /// Already-revoked sessions keep their original revocation time.
+    pub fn revoke_session(&self, id: &i64) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE sessions SET revoked_datetime = strftime('%s', 'now') WHERE id = ?1 AND revoked_datetime IS NULL",
+            params![id],
+        )
+    }
+
+    /// Revoke every active session of a user, returning how many were revoked.
+    pub fn revoke_user_sessions(&self, user_id: &i64) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE sessions SET revoked_datetime = strftime('%s', 'now') WHERE user_id = ?1 AND revoked_datetime IS NULL",
+            params![user_id],
+        )
+    }
+
+    /// Save a new API token for the hash of its token, returning the token id.
+    pub fn save_api_token(&self, user_id: &i64, name: &str, token_hash: &str, scopes: &[Scope]) -> Result<i64> {
+        let scopes: Vec<&str> = scopes.iter().map(Scope::as_str).collect();
+        self.conn.execute(
+            "INSERT INTO api_tokens (user_id, name, token_hash, scopes) VALUES (?1, ?2, ?3, ?4)",
+            params![user_id, name, token_hash, scopes.join(" ")],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn select_api_token_by_id(&self, id: &i64) -> Result<ApiToken> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM api_tokens WHERE id = ?1", API_TOKEN_COLUMNS),
+            params![id],
+            api_token_from_row,
+        )
+    }
+
+    /// Find an API token by the hash of its token, whether or not it has been revoked.
+    pub fn find_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM api_tokens WHERE token_hash = ?1", API_TOKEN_COLUMNS),
+            params![token_hash],
+            api_token_from_row,
+        ).optional()
+    }
+
+    /// Get all of a user's API tokens, including revoked ones, ordered by id.
+    pub fn get_user_api_tokens(&self, user_id: &i64) -> Result<Vec<ApiToken>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM api_tokens WHERE user_id = ?1 ORDER BY id",
+            API_TOKEN_COLUMNS
+        ))?;
+        let token_iter = stmt.query_map(params![user_id], api_token_from_row)?;
+        let mut tokens = Vec::new();
+        for token in token_iter {
+            tokens.push(token?);
+        }
+        Ok(tokens)
+    }
+
+    /// Record that an API token was just used.
+    pub fn touch_api_token(&self, id: &i64) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE api_tokens SET last_used_datetime = strftime('%s', 'now') WHERE id = ?1",
+            params![id],
+        )
+    }
+
+    /// Revoke one of a user's API tokens, returning the number of tokens revoked.
+    pub fn revoke_api_token(&self, user_id: &i64, id: &i64) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE api_tokens SET revoked_datetime = strftime('%s', 'now') \
+WHERE id = ?1 AND user_id = ?2 AND revoked_datetime IS NULL",
+            params![id, user_id],
+        )
+    }
+}
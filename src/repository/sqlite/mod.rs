@@ -1,2 +1,48 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Row};
+
+pub mod auth_repository;
+pub mod organization_repository;
 pub mod user_repository;
+pub mod todo_list_repository;
 pub mod todo_repository;
+pub mod webhook_repository;
+
+/// Read a unix timestamp column as a `DateTime<Utc>`.
+pub(crate) fn timestamp(row: &Row, idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    DateTime::from_timestamp(row.get(idx)?, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)
+}
+
+/// Read a nullable unix timestamp column.
+pub(crate) fn optional_timestamp(row: &Row, idx: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    match row.get::<_, Option<i64>>(idx)? {
+        Some(_) => Ok(Some(timestamp(row, idx)?)),
+        None => Ok(None),
+    }
+}
+
+/// Add `column` to an existing `table` unless it is already there, so
+/// databases created by older versions pick up new columns.
+///
+/// `definition` is everything after the column name in `ALTER TABLE ... ADD COLUMN`.
+pub(crate) fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        [table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), ())?;
+    }
+    Ok(())
+}
+
+/// Whether `table` exists, for statements that touch tables another
+/// repository may not have created yet.
+pub(crate) fn table_exists(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+}
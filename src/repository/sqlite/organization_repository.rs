@@ -0,0 +1,338 @@
+use rusqlite::{Connection, OptionalExtension, params, Row};
+
+use crate::models::{
+    Membership, Organization, OrganizationDTO, OrganizationStats, OrgRole, TodoItem, TodoItemDTO, ValidationError,
+};
+use crate::repository::entity::Entity;
+use crate::repository::sqlite::timestamp;
+use crate::repository::sqlite::todo_repository::{create_todos_table, todo_from_row, NOT_SNOOZED, TODO_COLUMNS};
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Stores organizations and their memberships, and hands out
+/// [`TenantTodos`] views that only ever see one organization's todos.
+pub struct OrganizationRepository {
+    conn: Connection,
+}
+
+impl Entity for Organization {
+    type Id = i64;
+    type Item = Organization;
+    type ItemDto = OrganizationDTO;
+}
+
+const ORGANIZATION_COLUMNS: &str = "id, name, created_datetime";
+
+/// A condition on a member row of organization `?1` that holds unless it is
+/// the organization's only owner.
+const NOT_LAST_OWNER: &str = "(organization_members.role != 'owner' OR \
+(SELECT COUNT(*) FROM organization_members WHERE organization_id = ?1 AND role = 'owner') > 1)";
+
+fn last_owner() -> RepositoryError {
+    ValidationError::field("role", "the organization's last owner can't leave or be demoted").into()
+}
+
+fn organization_from_row(row: &Row) -> rusqlite::Result<Organization> {
+    Ok(Organization {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        created_datetime: timestamp(row, 2)?,
+    })
+}
+
+const MEMBERSHIP_COLUMNS: &str = "organization_id, user_id, role, joined_datetime";
+
+fn membership_from_row(row: &Row) -> rusqlite::Result<Membership> {
+    let role: String = row.get(2)?;
+    Ok(Membership {
+        organization_id: row.get(0)?,
+        user_id: row.get(1)?,
+        role: OrgRole::parse(&role).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, format!("unknown role {}", role).into())
+        })?,
+        joined_datetime: timestamp(row, 3)?,
+    })
+}
+
+impl OrganizationRepository {
+    /// Generate an instance of the organization repository.
+    /// If no connection string (desired db file name) is provided, returns an in-memory db.
+    pub fn new(connection_string: Option<&str>) -> Result<OrganizationRepository> {
+        let conn = match connection_string {
+            Some(connection_string) => OrganizationRepository::connect_to_db(connection_string)?,
+            None => Connection::open_in_memory()?,
+        };
+        let organization_repo = OrganizationRepository { conn };
+        organization_repo.create_db()?;
+        Ok(organization_repo)
+    }
+
+    fn create_db(&self) -> Result<()> {
+        create_todos_table(&self.conn)?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS organizations (\
+id INTEGER PRIMARY KEY,\
+name TEXT NOT NULL,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+            (),
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS organization_members (\
+organization_id INTEGER NOT NULL,\
+user_id INTEGER NOT NULL,\
+role TEXT NOT NULL,\
+joined_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+PRIMARY KEY (organization_id, user_id)\
+)",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Add a user to an organization, or change the role of an existing member.
+    ///
+    /// Fails with `RepositoryError::Validation` if that would demote the
+    /// organization's last owner.
+    pub fn add_member(&self, organization_id: &i64, user_id: &i64, role: OrgRole) -> Result<usize> {
+        self.select_item_by_id(organization_id)?;
+        let changed = self.conn.execute(
+            &format!(
+                "INSERT INTO organization_members (organization_id, user_id, role) VALUES (?1, ?2, ?3) \
+ON CONFLICT (organization_id, user_id) DO UPDATE SET role = excluded.role WHERE excluded.role = 'owner' OR {}",
+                NOT_LAST_OWNER
+            ),
+            params![organization_id, user_id, role.as_str()],
+        )?;
+        if changed == 0 {
+            return Err(last_owner());
+        }
+        Ok(changed)
+    }
+
+    /// Remove a user from an organization. Their todos stay with the organization.
+    ///
+    /// Fails with `RepositoryError::Validation` if they are its last owner.
+    pub fn remove_member(&self, organization_id: &i64, user_id: &i64) -> Result<usize> {
+        let removed = self.conn.execute(
+            &format!(
+                "DELETE FROM organization_members WHERE organization_id = ?1 AND user_id = ?2 AND {}",
+                NOT_LAST_OWNER
+            ),
+            params![organization_id, user_id],
+        )?;
+        if removed == 0 && self.get_membership(organization_id, user_id)?.is_some() {
+            return Err(last_owner());
+        }
+        Ok(removed)
+    }
+
+    /// A user's membership of an organization, or `None` if they aren't a member.
+    pub fn get_membership(&self, organization_id: &i64, user_id: &i64) -> Result<Option<Membership>> {
+        Ok(self.conn.query_row(
+            &format!(
+                "SELECT {} FROM organization_members WHERE organization_id = ?1 AND user_id = ?2",
+                MEMBERSHIP_COLUMNS
+            ),
+            params![organization_id, user_id],
+            membership_from_row,
+        ).optional()?)
+    }
+
+    /// An organization's members, ordered by user id.
+    pub fn get_members(&self, organization_id: &i64) -> Result<Vec<Membership>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM organization_members WHERE organization_id = ?1 ORDER BY user_id",
+            MEMBERSHIP_COLUMNS
+        ))?;
+        let member_iter = stmt.query_map(params![organization_id], membership_from_row)?;
+        let mut members = Vec::new();
+        for member in member_iter {
+            members.push(member?);
+        }
+        Ok(members)
+    }
+
+    /// The organizations a user belongs to, ordered by id.
+    pub fn get_user_organizations(&self, user_id: &i64) -> Result<Vec<Organization>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM organizations \
+WHERE id IN (SELECT organization_id FROM organization_members WHERE user_id = ?1) ORDER BY id",
+            ORGANIZATION_COLUMNS
+        ))?;
+        let organization_iter = stmt.query_map(params![user_id], organization_from_row)?;
+        let mut organizations = Vec::new();
+        for organization in organization_iter {
+            organizations.push(organization?);
+        }
+        Ok(organizations)
+    }
+
+    /// Member and todo counts for an organization.
+    pub fn get_stats(&self, organization_id: &i64) -> Result<OrganizationStats> {
+        self.select_item_by_id(organization_id)?;
+        Ok(self.conn.query_row(
+            "SELECT \
+(SELECT COUNT(*) FROM organization_members WHERE organization_id = ?1), \
+COUNT(*), \
+COALESCE(SUM(completed), 0) \
+FROM todos WHERE organization_id = ?1",
+            params![organization_id],
+            |row| {
+                let todo_count: i64 = row.get(1)?;
+                let completed_count: i64 = row.get(2)?;
+                Ok(OrganizationStats {
+                    organization_id: *organization_id,
+                    member_count: row.get(0)?,
+                    todo_count,
+                    completed_count,
+                    open_count: todo_count - completed_count,
+                })
+            },
+        )?)
+    }
+
+    /// The todos of one organization. Nothing reached through the returned
+    /// view can read or change another organization's todos.
+    pub fn todos(&self, organization_id: &i64) -> TenantTodos<'_> {
+        TenantTodos { conn: &self.conn, organization_id: *organization_id }
+    }
+}
+
+impl Repository<Connection, Organization, RepositoryError> for OrganizationRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Connection> {
+        Ok(Connection::open(connection_string)?)
+    }
+
+    /// Validate and save a new organization, returning its id.
+    fn save_new_item(&self, organization_dto: &OrganizationDTO) -> Result<i64> {
+        let organization_dto = organization_dto.validate()?;
+        self.conn.execute("INSERT INTO organizations (name) VALUES (?1)", params![organization_dto.name])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<Organization> {
+        Ok(self.conn.query_row(
+            &format!("SELECT {} FROM organizations WHERE id = ?1", ORGANIZATION_COLUMNS),
+            params![id],
+            organization_from_row,
+        )?)
+    }
+
+    fn update_item(&self, id: &i64, organization_dto: &OrganizationDTO) -> Result<usize> {
+        let organization_dto = organization_dto.validate()?;
+        Ok(self.conn.execute(
+            "UPDATE organizations SET name = ?1 WHERE id = ?2",
+            params![organization_dto.name, id],
+        )?)
+    }
+
+    /// Delete an organization along with its memberships and todos.
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM todos WHERE organization_id = ?1", params![id])?;
+        tx.execute("DELETE FROM organization_members WHERE organization_id = ?1", params![id])?;
+        let deleted_count = tx.execute("DELETE FROM organizations WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(deleted_count)
+    }
+}
+
+/// The todos of a single organization, from [`OrganizationRepository::todos`].
+///
+/// Every query is limited to the organization, so ids belonging to another
+/// tenant behave exactly like missing ones, and todos can only be owned by
+/// the organization's members.
+pub struct TenantTodos<'a> {
+    conn: &'a Connection,
+    organization_id: i64,
+}
+
+impl TenantTodos<'_> {
+    pub fn organization_id(&self) -> i64 {
+        self.organization_id
+    }
+
+    /// Save a new todo in the organization, returning its id.
+    ///
+    /// Fails with `RepositoryError::NotFound` if the todo's user is not a member.
+    pub fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        let inserted = self.conn.execute(
+            "INSERT INTO todos (user_id, task, organization_id) SELECT ?1, ?2, ?3 \
+WHERE EXISTS (SELECT 1 FROM organization_members WHERE organization_id = ?3 AND user_id = ?1)",
+            params![todo_dto.user_id, todo_dto.task, self.organization_id],
+        )?;
+        if inserted == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
+        Ok(self.conn.query_row(
+            &format!("SELECT {} FROM todos WHERE id = ?1 AND organization_id = ?2", TODO_COLUMNS),
+            params![id, self.organization_id],
+            todo_from_row,
+        )?)
+    }
+
+    /// Update a todo's task. Its owner stays the same; `todo_dto.user_id` is ignored.
+    pub fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE todos SET version = version + 1, task = ?1 WHERE id = ?2 AND organization_id = ?3",
+            params![todo_dto.task, id, self.organization_id],
+        )?)
+    }
+
+    pub fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM todos WHERE id = ?1 AND organization_id = ?2",
+            params![id, self.organization_id],
+        )?)
+    }
+
+    pub fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE todos SET version = version + 1, completed = 1, completed_datetime = (strftime('%s', 'now')) WHERE id = ?1 AND organization_id = ?2",
+            params![id, self.organization_id],
+        )?)
+    }
+
+    pub fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE todos SET version = version + 1, completed = 0, completed_datetime = NULL WHERE id = ?1 AND organization_id = ?2",
+            params![id, self.organization_id],
+        )?)
+    }
+
+    /// A member's todos within the organization, ordered by id. Snoozed todos
+    /// are left out.
+    pub fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        self.query(
+            &format!(
+                "SELECT {} FROM todos WHERE user_id = ?1 AND organization_id = ?2 AND {} ORDER BY id",
+                TODO_COLUMNS, NOT_SNOOZED
+            ),
+            params![user_id, self.organization_id],
+        )
+    }
+
+    /// Every todo in the organization, ordered by id. Snoozed todos are left out.
+    pub fn get_todos(&self) -> Result<Vec<TodoItem>> {
+        self.query(
+            &format!("SELECT {} FROM todos WHERE organization_id = ?1 AND {} ORDER BY id", TODO_COLUMNS, NOT_SNOOZED),
+            params![self.organization_id],
+        )
+    }
+
+    fn query(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let todo_iter = stmt.query_map(params, todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+}
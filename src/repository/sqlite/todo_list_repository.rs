@@ -0,0 +1,265 @@
+use rusqlite::{Connection, OptionalExtension, params, Row};
+
+use crate::models::{ListMember, ShareLevel, TodoItem, TodoList, TodoListDTO};
+use crate::repository::entity::Entity;
+use crate::repository::sqlite::todo_repository::{create_todos_table, todo_from_row, NOT_SNOOZED, PERSONAL, TODO_COLUMNS};
+use crate::repository::sqlite::{optional_timestamp, table_exists, timestamp};
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Stores todo lists and who they are shared with.
+///
+/// Todos are put in a list with [`TodoRepository::set_todo_list`](crate::repository::sqlite::todo_repository::TodoRepository::set_todo_list),
+/// so open both repositories on the same database.
+pub struct TodoListRepository {
+    conn: Connection,
+}
+
+impl Entity for TodoList {
+    type Id = i64;
+    type Item = TodoList;
+    type ItemDto = TodoListDTO;
+}
+
+const LIST_COLUMNS: &str = "id, owner_id, name, created_datetime";
+
+fn list_from_row(row: &Row) -> rusqlite::Result<TodoList> {
+    Ok(TodoList {
+        id: row.get(0)?,
+        owner_id: row.get(1)?,
+        name: row.get(2)?,
+        created_datetime: timestamp(row, 3)?,
+    })
+}
+
+const MEMBER_COLUMNS: &str = "list_id, user_id, level, invited_by, invited_datetime, accepted_datetime";
+
+fn member_from_row(row: &Row) -> rusqlite::Result<ListMember> {
+    let level: String = row.get(2)?;
+    Ok(ListMember {
+        list_id: row.get(0)?,
+        user_id: row.get(1)?,
+        level: ShareLevel::parse(&level).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, format!("unknown share level {}", level).into())
+        })?,
+        invited_by: row.get(3)?,
+        invited_datetime: timestamp(row, 4)?,
+        accepted_datetime: optional_timestamp(row, 5)?,
+    })
+}
+
+/// The ids of the lists a user owns or has accepted a share of, for use in `IN (...)`.
+const VISIBLE_LIST_IDS: &str = "SELECT id FROM todo_lists WHERE owner_id = ?1 \
+UNION SELECT list_id FROM todo_list_members WHERE user_id = ?1 AND accepted_datetime IS NOT NULL";
+
+impl TodoListRepository {
+    /// Generate an instance of the todo list repository.
+    /// If no connection string (desired db file name) is provided, returns an in-memory db.
+    pub fn new(connection_string: Option<&str>) -> Result<TodoListRepository> {
+        let conn = match connection_string {
+            Some(connection_string) => TodoListRepository::connect_to_db(connection_string)?,
+            None => Connection::open_in_memory()?,
+        };
+        let list_repo = TodoListRepository { conn };
+        list_repo.create_db()?;
+        Ok(list_repo)
+    }
+
+    fn create_db(&self) -> Result<()> {
+        create_todos_table(&self.conn)?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS todo_lists (\
+id INTEGER PRIMARY KEY,\
+owner_id INTEGER NOT NULL,\
+name TEXT NOT NULL,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+            (),
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS todo_list_members (\
+list_id INTEGER NOT NULL,\
+user_id INTEGER NOT NULL,\
+level TEXT NOT NULL,\
+invited_by INTEGER NOT NULL,\
+invited_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+accepted_datetime INTEGER,\
+PRIMARY KEY (list_id, user_id)\
+)",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Every list a user owns or has accepted a share of, ordered by id.
+    pub fn get_user_lists(&self, user_id: &i64) -> Result<Vec<TodoList>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todo_lists WHERE id IN ({}) ORDER BY id",
+            LIST_COLUMNS, VISIBLE_LIST_IDS
+        ))?;
+        let list_iter = stmt.query_map(params![user_id], list_from_row)?;
+        let mut lists = Vec::new();
+        for list in list_iter {
+            lists.push(list?);
+        }
+        Ok(lists)
+    }
+
+    /// How much `user_id` can do with a list: `Owner` for its owner, the
+    /// share level for accepted members, and `None` for everyone else
+    /// (including users who have only been invited).
+    pub fn access_level(&self, list_id: &i64, user_id: &i64) -> Result<Option<ShareLevel>> {
+        let list = self.select_item_by_id(list_id)?;
+        if list.owner_id == *user_id {
+            return Ok(Some(ShareLevel::Owner));
+        }
+        let level = self.conn.query_row(
+            "SELECT level FROM todo_list_members WHERE list_id = ?1 AND user_id = ?2 AND accepted_datetime IS NOT NULL",
+            params![list_id, user_id],
+            |row| row.get::<_, String>(0),
+        ).optional()?;
+        Ok(level.as_deref().and_then(ShareLevel::parse))
+    }
+
+    /// Invite a user to a list at `level`.
+    ///
+    /// Inviting someone who is already a member changes their level without
+    /// asking them to accept again.
+    pub fn invite(&self, list_id: &i64, user_id: &i64, level: ShareLevel, invited_by: &i64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "INSERT INTO todo_list_members (list_id, user_id, level, invited_by) VALUES (?1, ?2, ?3, ?4) \
+ON CONFLICT (list_id, user_id) DO UPDATE SET level = excluded.level, invited_by = excluded.invited_by",
+            params![list_id, user_id, level.as_str(), invited_by],
+        )?)
+    }
+
+    /// Accept a pending invitation, returning the number of invitations accepted.
+    pub fn accept_invitation(&self, list_id: &i64, user_id: &i64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE todo_list_members SET accepted_datetime = strftime('%s', 'now') \
+WHERE list_id = ?1 AND user_id = ?2 AND accepted_datetime IS NULL",
+            params![list_id, user_id],
+        )?)
+    }
+
+    /// Remove a member or decline/withdraw an invitation.
+    pub fn remove_member(&self, list_id: &i64, user_id: &i64) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM todo_list_members WHERE list_id = ?1 AND user_id = ?2",
+            params![list_id, user_id],
+        )?)
+    }
+
+    /// Everyone a list has been shared with, accepted or not, ordered by user id.
+    pub fn get_members(&self, list_id: &i64) -> Result<Vec<ListMember>> {
+        self.query_members(
+            &format!("SELECT {} FROM todo_list_members WHERE list_id = ?1 ORDER BY user_id", MEMBER_COLUMNS),
+            list_id,
+        )
+    }
+
+    /// The invitations a user has not accepted yet, ordered by list id.
+    pub fn get_pending_invitations(&self, user_id: &i64) -> Result<Vec<ListMember>> {
+        self.query_members(
+            &format!(
+                "SELECT {} FROM todo_list_members WHERE user_id = ?1 AND accepted_datetime IS NULL ORDER BY list_id",
+                MEMBER_COLUMNS
+            ),
+            user_id,
+        )
+    }
+
+    /// The todos in a list, ordered by id. Snoozed todos are left out.
+    pub fn get_list_todos(&self, list_id: &i64) -> Result<Vec<TodoItem>> {
+        self.query_todos(
+            &format!("SELECT {} FROM todos WHERE list_id = ?1 AND {} AND {} ORDER BY id", TODO_COLUMNS, PERSONAL, NOT_SNOOZED),
+            list_id,
+        )
+    }
+
+    /// Whether a user with this id exists. Lists can only be shared with
+    /// users in the same database, so without a users table there are none.
+    pub fn user_exists(&self, user_id: &i64) -> Result<bool> {
+        if !table_exists(&self.conn, "users")? {
+            return Ok(false);
+        }
+        Ok(self.conn.query_row("SELECT EXISTS (SELECT 1 FROM users WHERE id = ?1)", params![user_id], |row| row.get(0))?)
+    }
+
+    /// Every todo a user can see: their own, those assigned to them, and those
+    /// in lists they own or have accepted a share of, ordered by id. Snoozed
+    /// todos are left out.
+    pub fn get_visible_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        self.query_todos(
+            &format!(
+                "SELECT {} FROM todos WHERE (user_id = ?1 OR assignee_id = ?1 OR list_id IN ({})) AND {} AND {} ORDER BY id",
+                TODO_COLUMNS, VISIBLE_LIST_IDS, PERSONAL, NOT_SNOOZED
+            ),
+            user_id,
+        )
+    }
+
+    fn query_members(&self, sql: &str, id: &i64) -> Result<Vec<ListMember>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let member_iter = stmt.query_map(params![id], member_from_row)?;
+        let mut members = Vec::new();
+        for member in member_iter {
+            members.push(member?);
+        }
+        Ok(members)
+    }
+
+    fn query_todos(&self, sql: &str, id: &i64) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let todo_iter = stmt.query_map(params![id], todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+}
+
+impl Repository<Connection, TodoList, RepositoryError> for TodoListRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Connection> {
+        Ok(Connection::open(connection_string)?)
+    }
+
+    /// Validate and save a new list, returning its id.
+    fn save_new_item(&self, list_dto: &TodoListDTO) -> Result<i64> {
+        let list_dto = list_dto.validate()?;
+        self.conn.execute(
+            "INSERT INTO todo_lists (owner_id, name) VALUES (?1, ?2)",
+            params![list_dto.owner_id, list_dto.name],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<TodoList> {
+        Ok(self.conn.query_row(
+            &format!("SELECT {} FROM todo_lists WHERE id = ?1", LIST_COLUMNS),
+            params![id],
+            list_from_row,
+        )?)
+    }
+
+    /// Validate and update a list's name and owner.
+    fn update_item(&self, id: &i64, list_dto: &TodoListDTO) -> Result<usize> {
+        let list_dto = list_dto.validate()?;
+        Ok(self.conn.execute(
+            "UPDATE todo_lists SET owner_id = ?1, name = ?2 WHERE id = ?3",
+            params![list_dto.owner_id, list_dto.name, id],
+        )?)
+    }
+
+    /// Delete a list and its shares. Its todos are kept, outside of any list.
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("UPDATE todos SET version = version + 1, list_id = NULL WHERE list_id = ?1", params![id])?;
+        tx.execute("DELETE FROM todo_list_members WHERE list_id = ?1", params![id])?;
+        let deleted = tx.execute("DELETE FROM todo_lists WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+}
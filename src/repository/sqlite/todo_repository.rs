@@ -1,12 +1,193 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+
 use chrono::{DateTime, Utc};
-use rusqlite::{Connection, params, Result};
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter, Result, Row};
 
-use crate::models::{TodoItem, TodoItemDTO};
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptedTodos;
+use crate::models::{
+    Delegation, Excuse, ExcuseCount, Refusal, RefusalCount, RefusalReason, TodoItem, TodoItemDTO, ValidationError,
+};
 use crate::repository::entity::Entity;
-use crate::repository::Repository;
+use crate::repository::events::{EntityType, EventKind, Observers, RepositoryEvent};
+use crate::repository::sqlite::{add_column_if_missing, optional_timestamp, table_exists, timestamp};
+use crate::repository::{Repository, RepositoryError, TodoStore};
+use crate::stats::TodoStats;
+use crate::sync::{create_sync_tables, TodoSync};
+use crate::undo::{create_history_table, TodoHistory};
+
+/// The columns read by [`todo_from_row`], in order.
+pub(crate) const TODO_COLUMNS: &str = "id, user_id, task, completed, created_datetime, completed_datetime, list_id, assignee_id, organization_id, snoozed_until, deferral_count, refused_datetime, refusal_reason, version";
+
+/// A condition matching todos outside any organization. Organizations' todos
+/// are only reachable through [`TenantTodos`](crate::repository::sqlite::organization_repository::TenantTodos).
+pub(crate) const PERSONAL: &str = "organization_id IS NULL";
+
+/// A condition matching todos that aren't snoozed, for leaving snoozed ones out of listings.
+pub(crate) const NOT_SNOOZED: &str = "(snoozed_until IS NULL OR snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER))";
+
+/// Delete a personal todo along with its delegation log, excuses and
+/// refusals, within the caller's transaction. Returns the number of todos deleted.
+pub(crate) fn delete_todo_rows(conn: &Connection, id: &i64) -> Result<usize> {
+    let deleted = conn.execute("DELETE FROM todos WHERE id = ?1 AND organization_id IS NULL", params![id])?;
+    if deleted > 0 {
+        conn.execute("DELETE FROM todo_delegations WHERE todo_id = ?1", params![id])?;
+        conn.execute("DELETE FROM todo_excuses WHERE todo_id = ?1", params![id])?;
+        conn.execute("DELETE FROM todo_refusals WHERE todo_id = ?1", params![id])?;
+    }
+    Ok(deleted)
+}
+
+/// Build a `TodoItem` from a row selected with [`TODO_COLUMNS`].
+pub(crate) fn todo_from_row(row: &Row) -> Result<TodoItem> {
+    let completed_datetime: Option<DateTime<Utc>> = match row.get(5)? {
+        Some(timestamp) => Some(DateTime::from_timestamp(timestamp, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)?),
+        None => None,
+    };
+    Ok(TodoItem {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        task: row.get(2)?,
+        completed: row.get(3)?,
+        created_datetime: DateTime::from_timestamp(row.get(4)?, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)?,
+        completed_datetime,
+        list_id: row.get(6)?,
+        assignee_id: row.get(7)?,
+        organization_id: row.get(8)?,
+        snoozed_until: optional_timestamp(row, 9)?,
+        deferral_count: row.get(10)?,
+        refused_datetime: optional_timestamp(row, 11)?,
+        refusal_reason: optional_refusal_reason(row, 12)?,
+        version: row.get(13)?,
+    })
+}
+
+/// Read a refusal reason column.
+pub(crate) fn refusal_reason(row: &Row, idx: usize) -> Result<RefusalReason> {
+    let reason: String = row.get(idx)?;
+    RefusalReason::parse(&reason).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, format!("unknown refusal reason {}", reason).into())
+    })
+}
+
+/// Read a nullable refusal reason column.
+fn optional_refusal_reason(row: &Row, idx: usize) -> Result<Option<RefusalReason>> {
+    match row.get::<_, Option<String>>(idx)? {
+        Some(_) => Ok(Some(refusal_reason(row, idx)?)),
+        None => Ok(None),
+    }
+}
+
+const DELEGATION_COLUMNS: &str = "id, todo_id, from_user_id, to_user_id, delegated_by, delegated_datetime";
+
+fn delegation_from_row(row: &Row) -> Result<Delegation> {
+    Ok(Delegation {
+        id: row.get(0)?,
+        todo_id: row.get(1)?,
+        from_user_id: row.get(2)?,
+        to_user_id: row.get(3)?,
+        delegated_by: row.get(4)?,
+        delegated_datetime: timestamp(row, 5)?,
+    })
+}
+
+const EXCUSE_COLUMNS: &str = "id, todo_id, user_id, excuse, snoozed_until, created_datetime";
+
+fn excuse_from_row(row: &Row) -> Result<Excuse> {
+    Ok(Excuse {
+        id: row.get(0)?,
+        todo_id: row.get(1)?,
+        user_id: row.get(2)?,
+        excuse: row.get(3)?,
+        snoozed_until: timestamp(row, 4)?,
+        created_datetime: timestamp(row, 5)?,
+    })
+}
+
+const REFUSAL_COLUMNS: &str = "id, todo_id, user_id, reason, refused_datetime, reconsidered_datetime";
+
+fn refusal_from_row(row: &Row) -> Result<Refusal> {
+    Ok(Refusal {
+        id: row.get(0)?,
+        todo_id: row.get(1)?,
+        user_id: row.get(2)?,
+        reason: refusal_reason(row, 3)?,
+        refused_datetime: timestamp(row, 4)?,
+        reconsidered_datetime: optional_timestamp(row, 5)?,
+    })
+}
+
+/// The columns of the `todos` table, for creating it and rebuilding it.
+///
+/// Ids are AUTOINCREMENT so the id of a deleted todo is never handed to a
+/// new one, which undo history and webhook receivers would take for the old.
+const TODOS_SCHEMA: &str = "(\
+id INTEGER PRIMARY KEY AUTOINCREMENT,\
+user_id INTEGER NOT NULL,\
+task TEXT NOT NULL,\
+completed INTEGER NOT NULL DEFAULT 0,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+completed_datetime INTEGER,\
+list_id INTEGER,\
+assignee_id INTEGER,\
+organization_id INTEGER,\
+snoozed_until INTEGER,\
+deferral_count INTEGER NOT NULL DEFAULT 0,\
+refused_datetime INTEGER,\
+refusal_reason TEXT,\
+version INTEGER NOT NULL DEFAULT 1)";
+
+/// Create the `todos` table. Other repositories whose queries join on todos
+/// call this too, so they work against a fresh database.
+pub(crate) fn create_todos_table(conn: &Connection) -> Result<()> {
+    conn.execute(&format!("CREATE TABLE IF NOT EXISTS todos{}", TODOS_SCHEMA), ())?;
+    add_column_if_missing(conn, "todos", "list_id", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "assignee_id", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "organization_id", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "snoozed_until", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "deferral_count", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "todos", "refused_datetime", "INTEGER")?;
+    add_column_if_missing(conn, "todos", "refusal_reason", "TEXT")?;
+    add_column_if_missing(conn, "todos", "version", "INTEGER NOT NULL DEFAULT 1")?;
+    add_autoincrement(conn)
+}
+
+/// Rebuild a `todos` table created before ids were AUTOINCREMENT, keeping
+/// its indexes and triggers. Ids of todos deleted before the rebuild can't be
+/// known, so only those above the current highest id are safe from reuse
+/// afterwards.
+fn add_autoincrement(conn: &Connection) -> Result<()> {
+    let sql: String = conn.query_row(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'todos'",
+        (),
+        |row| row.get(0),
+    )?;
+    if sql.contains("AUTOINCREMENT") {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    let dependents: Vec<String> = tx
+        .prepare("SELECT sql FROM sqlite_master WHERE tbl_name = 'todos' AND type IN ('index', 'trigger') AND sql IS NOT NULL")?
+        .query_map((), |row| row.get(0))?
+        .collect::<Result<_>>()?;
+    tx.execute_batch(&format!(
+        "CREATE TABLE todos_autoincrement{schema};
+INSERT INTO todos_autoincrement ({columns}) SELECT {columns} FROM todos;
+DROP TABLE todos;
+ALTER TABLE todos_autoincrement RENAME TO todos;",
+        schema = TODOS_SCHEMA,
+        columns = TODO_COLUMNS,
+    ))?;
+    for sql in dependents {
+        tx.execute(&sql, ())?;
+    }
+    tx.commit()
+}
 
 pub struct TodoRepository {
     conn: Connection,
+    observers: Observers,
 }
 
 impl Entity for TodoItem {
@@ -16,46 +197,70 @@ impl Entity for TodoItem {
 }
 
 impl TodoRepository {
+    /// Open the todos at `connection_string`, or an in-memory db if there is none.
+    ///
+    /// Fails if the database's tasks are encrypted: those can only be opened
+    /// with their key, through `EncryptedTodos` (behind the `encryption` feature).
     pub fn new(connection_string: Option<&str>) -> Result<TodoRepository> {
+        let todo_repo = TodoRepository::open(connection_string)?;
+        todo_repo.refuse_if_encrypted("need their key")?;
+        Ok(todo_repo)
+    }
+
+    /// Open the todos whether or not their tasks are encrypted.
+    pub(crate) fn open(connection_string: Option<&str>) -> Result<TodoRepository> {
         let conn = match connection_string {
             None => Connection::open_in_memory()?,
             Some(connection_string) => TodoRepository::connect_to_db(connection_string)?,
         };
-        let todo_repo = TodoRepository { conn };
+        let todo_repo = TodoRepository { conn, observers: Observers::default() };
         todo_repo.create_db()?;
         Ok(todo_repo)
     }
 
     fn create_db(&self) -> Result<()> {
+        create_todos_table(&self.conn)?;
         self.conn.execute(
-            "CREATE TABLE todos(\
+            "CREATE TABLE IF NOT EXISTS todo_delegations (\
 id INTEGER PRIMARY KEY,\
+todo_id INTEGER NOT NULL,\
+from_user_id INTEGER,\
+to_user_id INTEGER,\
+delegated_by INTEGER NOT NULL,\
+delegated_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+            (),
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS todo_excuses (\
+id INTEGER PRIMARY KEY,\
+todo_id INTEGER NOT NULL,\
 user_id INTEGER NOT NULL,\
-task TEXT NOT NULL,\
-completed INTEGER NOT NULL DEFAULT 0,\
-created_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
-completed_datetime INTEGER)",
+excuse TEXT NOT NULL,\
+snoozed_until INTEGER NOT NULL,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
             (),
         )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS todo_refusals (\
+id INTEGER PRIMARY KEY,\
+todo_id INTEGER NOT NULL,\
+user_id INTEGER NOT NULL,\
+reason TEXT NOT NULL,\
+refused_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+reconsidered_datetime INTEGER\
+)",
+            (),
+        )?;
+        create_history_table(&self.conn)?;
         Ok(())
     }
 
+    /// A user's todos, leaving out snoozed ones.
     pub fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
-        let mut stmt = self.conn.prepare("SELECT * FROM todos WHERE user_id = ?1")?;
-        let todo_iter = stmt.query_map(params![user_id], |row| {
-            let completed_datetime: Option<DateTime<Utc>> = match row.get(5)? {
-                Some(timestamp) => Some(DateTime::from_timestamp(timestamp, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)?),
-                None => None,
-            };
-            Ok(TodoItem {
-                id: row.get(0)?,
-                user_id: row.get(1)?,
-                task: row.get(2)?,
-                completed: row.get(3)?,
-                created_datetime: DateTime::from_timestamp(row.get(4)?, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)?,
-                completed_datetime,
-            })
-        })?;
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM todos WHERE user_id = ?1 AND {} AND {} ORDER BY id", TODO_COLUMNS, PERSONAL, NOT_SNOOZED))?;
+        let todo_iter = stmt.query_map(params![user_id], todo_from_row)?;
         let mut todos = Vec::new();
         for todo in todo_iter {
             todos.push(todo?);
@@ -63,17 +268,463 @@ completed_datetime INTEGER)",
         Ok(todos)
     }
 
+    /// Get the todos for several users with a single query, grouped by user id.
+    ///
+    /// Every requested user has an entry, even if they have no todos. Snoozed
+    /// todos are left out.
+    pub fn get_todos_for_users(&self, user_ids: &[i64]) -> Result<HashMap<i64, Vec<TodoItem>>> {
+        let mut todos_by_user: HashMap<i64, Vec<TodoItem>> =
+            user_ids.iter().map(|user_id| (*user_id, Vec::new())).collect();
+        if user_ids.is_empty() {
+            return Ok(todos_by_user);
+        }
+        let placeholders = vec!["?"; user_ids.len()].join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todos WHERE user_id IN ({}) AND {} AND {} ORDER BY id",
+            TODO_COLUMNS, placeholders, PERSONAL, NOT_SNOOZED
+        ))?;
+        let todo_iter = stmt.query_map(params_from_iter(user_ids), todo_from_row)?;
+        for todo in todo_iter {
+            let todo = todo?;
+            todos_by_user.entry(todo.user_id).or_default().push(todo);
+        }
+        Ok(todos_by_user)
+    }
+
+    /// Call `callback` after every successful create, update, delete,
+    /// complete and uncomplete of a todo.
+    pub fn subscribe(&self, callback: impl FnMut(&RepositoryEvent) + Send + 'static) {
+        self.observers.subscribe(callback)
+    }
+
+    /// A channel receiving the same events as [`subscribe`](TodoRepository::subscribe) callbacks.
+    pub fn events(&self) -> Receiver<RepositoryEvent> {
+        self.observers.channel()
+    }
+
+    /// Mark a todo as done. Completing a refused todo takes back the refusal,
+    /// like [`reconsider`](TodoRepository::reconsider).
     pub fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.observers.transaction(&self.conn, || {
+            self.conn.execute(
+                "UPDATE todo_refusals SET reconsidered_datetime = strftime('%s', 'now') \
+WHERE todo_id = ?1 AND reconsidered_datetime IS NULL AND todo_id IN (SELECT id FROM todos WHERE id = ?1 AND organization_id IS NULL)",
+                params![id],
+            )?;
+            let updated = self.conn.execute(
+                "UPDATE todos SET version = version + 1, completed = 1, completed_datetime = (strftime('%s', 'now')), \
+refused_datetime = NULL, refusal_reason = NULL WHERE id = ?1 AND organization_id IS NULL",
+                params![id],
+            )?;
+            Ok(self.observers.notify_written(updated, EntityType::Todo, *id, EventKind::Completed))
+        })
+    }
+
+    pub fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        let updated = self.conn.execute(
+            "UPDATE todos SET version = version + 1, completed = 0, completed_datetime = NULL WHERE id = ?1 AND organization_id IS NULL",
+            params![id],
+        )?;
+        Ok(self.observers.notify_written(updated, EntityType::Todo, *id, EventKind::Uncompleted))
+    }
+
+    /// Assign a todo to a user, or unassign it with `None`, recording the
+    /// hand-off in the delegation log.
+    ///
+    /// Returns the number of todos updated; nothing is logged if the todo
+    /// doesn't exist or already has that assignee.
+    pub fn assign_todo_item(&self, id: &i64, assignee_id: Option<i64>, delegated_by: &i64) -> Result<usize> {
+        self.observers.transaction(&self.conn, || {
+            let logged = self.conn.execute(
+                "INSERT INTO todo_delegations (todo_id, from_user_id, to_user_id, delegated_by) \
+SELECT id, assignee_id, ?1, ?2 FROM todos WHERE id = ?3 AND assignee_id IS NOT ?1 AND organization_id IS NULL",
+                params![assignee_id, delegated_by, id],
+            )?;
+            if logged > 0 {
+                self.conn.execute("UPDATE todos SET version = version + 1, assignee_id = ?1 WHERE id = ?2", params![assignee_id, id])?;
+            }
+            Ok(logged)
+        })
+    }
+
+    /// The todos assigned to a user that aren't snoozed, ordered by id.
+    pub fn get_assigned_todos(&self, assignee_id: &i64) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todos WHERE assignee_id = ?1 AND {} AND {} ORDER BY id",
+            TODO_COLUMNS, PERSONAL, NOT_SNOOZED
+        ))?;
+        let todo_iter = stmt.query_map(params![assignee_id], todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+
+    /// Every hand-off of a todo, oldest first.
+    pub fn get_delegations(&self, todo_id: &i64) -> Result<Vec<Delegation>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todo_delegations WHERE todo_id = ?1 ORDER BY id",
+            DELEGATION_COLUMNS
+        ))?;
+        let delegation_iter = stmt.query_map(params![todo_id], delegation_from_row)?;
+        let mut delegations = Vec::new();
+        for delegation in delegation_iter {
+            delegations.push(delegation?);
+        }
+        Ok(delegations)
+    }
+
+    /// Hide a todo until `until`, counting the deferral and logging the excuse
+    /// against the todo's owner.
+    ///
+    /// Snoozing an already snoozed todo moves its wake-up time and counts as
+    /// another deferral. Returns the number of todos snoozed.
+    ///
+    /// Fails with `RepositoryError::Validation` if `until` isn't in the
+    /// future or the excuse is blank.
+    pub fn snooze(&self, id: &i64, until: &DateTime<Utc>, excuse: &str) -> std::result::Result<usize, RepositoryError> {
+        let mut error = ValidationError::default();
+        if *until <= Utc::now() {
+            error.add("until", "must be in the future");
+        }
+        if excuse.trim().is_empty() {
+            error.add("excuse", "must not be empty");
+        }
+        if !error.is_empty() {
+            return Err(error.into());
+        }
+        self.observers.transaction(&self.conn, || {
+            let snoozed = self.conn.execute(
+                "UPDATE todos SET version = version + 1, snoozed_until = ?1, deferral_count = deferral_count + 1 WHERE id = ?2 AND organization_id IS NULL",
+                params![until.timestamp(), id],
+            )?;
+            if snoozed > 0 {
+                self.conn.execute(
+                    "INSERT INTO todo_excuses (todo_id, user_id, excuse, snoozed_until) \
+SELECT id, user_id, ?1, ?2 FROM todos WHERE id = ?3",
+                    params![excuse.trim(), until.timestamp(), id],
+                )?;
+            }
+            Ok(snoozed)
+        })
+    }
+
+    /// Bring a snoozed todo back early. Its deferral count is kept.
+    pub fn unsnooze(&self, id: &i64) -> Result<usize> {
         self.conn.execute(
-            "UPDATE todos SET completed = 1, completed_datetime = (strftime('%s', 'now')) WHERE id = ?1",
+            "UPDATE todos SET version = version + 1, snoozed_until = NULL WHERE id = ?1 AND snoozed_until IS NOT NULL AND organization_id IS NULL",
             params![id],
         )
     }
 
-    pub fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+    /// A user's todos that are snoozed right now, soonest to wake first.
+    pub fn get_snoozed_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todos WHERE user_id = ?1 AND {} AND NOT {} ORDER BY snoozed_until, id",
+            TODO_COLUMNS, PERSONAL, NOT_SNOOZED
+        ))?;
+        let todo_iter = stmt.query_map(params![user_id], todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+
+    /// Up to `limit` of a user's todos that have been snoozed at least once,
+    /// most deferred first, whether or not they are snoozed now.
+    pub fn get_most_deferred(&self, user_id: &i64, limit: u32) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todos WHERE user_id = ?1 AND deferral_count > 0 AND {} ORDER BY deferral_count DESC, id LIMIT ?2",
+            TODO_COLUMNS, PERSONAL
+        ))?;
+        let todo_iter = stmt.query_map(params![user_id, limit], todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+
+    /// Every excuse given for snoozing a todo, oldest first.
+    pub fn get_excuses(&self, todo_id: &i64) -> Result<Vec<Excuse>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todo_excuses WHERE todo_id = ?1 ORDER BY id",
+            EXCUSE_COLUMNS
+        ))?;
+        let excuse_iter = stmt.query_map(params![todo_id], excuse_from_row)?;
+        let mut excuses = Vec::new();
+        for excuse in excuse_iter {
+            excuses.push(excuse?);
+        }
+        Ok(excuses)
+    }
+
+    /// How often a user has given each excuse, most used first. Excuses that
+    /// differ only in case count as the same one, reported as last written.
+    pub fn get_excuse_report(&self, user_id: &i64) -> Result<Vec<ExcuseCount>> {
+        let mut stmt = self.conn.prepare(
+            "WITH ranked AS (\
+SELECT excuse, created_datetime, id, COUNT(*) OVER (PARTITION BY lower(excuse)) AS count, \
+ROW_NUMBER() OVER (PARTITION BY lower(excuse) ORDER BY id DESC) AS recency \
+FROM todo_excuses WHERE user_id = ?1\
+) \
+SELECT excuse, count, created_datetime FROM ranked WHERE recency = 1 ORDER BY count DESC, id DESC",
+        )?;
+        let count_iter = stmt.query_map(params![user_id], |row| {
+            Ok(ExcuseCount { excuse: row.get(0)?, count: row.get(1)?, last_used_datetime: timestamp(row, 2)? })
+        })?;
+        let mut counts = Vec::new();
+        for count in count_iter {
+            counts.push(count?);
+        }
+        Ok(counts)
+    }
+
+    /// Declare that an open todo won't be done, for `reason`.
+    ///
+    /// Unlike deleting, the todo is kept, and unlike completing, it doesn't
+    /// count as done. Completed and already refused todos are left alone.
+    /// Returns the number of todos refused.
+    pub fn refuse(&self, id: &i64, reason: RefusalReason) -> Result<usize> {
+        self.observers.transaction(&self.conn, || {
+            let refused = self.conn.execute(
+                "UPDATE todos SET version = version + 1, refused_datetime = strftime('%s', 'now'), refusal_reason = ?1 \
+WHERE id = ?2 AND completed = 0 AND refused_datetime IS NULL AND organization_id IS NULL",
+                params![reason.as_str(), id],
+            )?;
+            if refused > 0 {
+                self.conn.execute(
+                    "INSERT INTO todo_refusals (todo_id, user_id, reason, refused_datetime) \
+SELECT id, user_id, refusal_reason, refused_datetime FROM todos WHERE id = ?1",
+                    params![id],
+                )?;
+            }
+            Ok(refused)
+        })
+    }
+
+    /// Take back a refusal, making the todo open again. The refusal stays in
+    /// the log, marked as reconsidered.
+    pub fn reconsider(&self, id: &i64) -> Result<usize> {
+        self.observers.transaction(&self.conn, || {
+            let reconsidered = self.conn.execute(
+                "UPDATE todos SET version = version + 1, refused_datetime = NULL, refusal_reason = NULL WHERE id = ?1 AND refused_datetime IS NOT NULL AND organization_id IS NULL",
+                params![id],
+            )?;
+            if reconsidered > 0 {
+                self.conn.execute(
+                    "UPDATE todo_refusals SET reconsidered_datetime = strftime('%s', 'now') \
+WHERE todo_id = ?1 AND reconsidered_datetime IS NULL",
+                    params![id],
+                )?;
+            }
+            Ok(reconsidered)
+        })
+    }
+
+    /// A user's currently refused todos, most recently refused first.
+    pub fn get_refused_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todos WHERE user_id = ?1 AND refused_datetime IS NOT NULL AND {} ORDER BY refused_datetime DESC, id DESC",
+            TODO_COLUMNS, PERSONAL
+        ))?;
+        let todo_iter = stmt.query_map(params![user_id], todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+
+    /// Every time a todo was refused, oldest first.
+    pub fn get_refusals(&self, todo_id: &i64) -> Result<Vec<Refusal>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todo_refusals WHERE todo_id = ?1 ORDER BY id",
+            REFUSAL_COLUMNS
+        ))?;
+        let refusal_iter = stmt.query_map(params![todo_id], refusal_from_row)?;
+        let mut refusals = Vec::new();
+        for refusal in refusal_iter {
+            refusals.push(refusal?);
+        }
+        Ok(refusals)
+    }
+
+    /// How often a user has refused todos for each reason, including
+    /// reconsidered refusals, in [`RefusalReason::ALL`] order.
+    pub fn get_refusal_report(&self, user_id: &i64) -> Result<Vec<RefusalCount>> {
+        let mut report: Vec<RefusalCount> = RefusalReason::ALL
+            .into_iter()
+            .map(|reason| RefusalCount { reason, refused_count: 0, reconsidered_count: 0 })
+            .collect();
+        let mut stmt = self.conn.prepare(
+            "SELECT reason, COUNT(*), COUNT(reconsidered_datetime) FROM todo_refusals WHERE user_id = ?1 GROUP BY reason",
+        )?;
+        let rows = stmt.query_map(params![user_id], |row| {
+            Ok((refusal_reason(row, 0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))
+        })?;
+        for row in rows {
+            let (reason, refused_count, reconsidered_count) = row?;
+            if let Some(count) = report.iter_mut().find(|count| count.reason == reason) {
+                count.refused_count = refused_count;
+                count.reconsidered_count = reconsidered_count;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Update a todo's task, keeping its owner, but only if it is still at
+    /// `expected_version`.
+    ///
+    /// Fails with `RepositoryError::Conflict` if someone else changed it
+    /// first, and `RepositoryError::NotFound` if it doesn't exist.
+    pub fn update_item_if_version(
+        &self,
+        id: &i64,
+        todo_item: &TodoItemDTO,
+        expected_version: i64,
+    ) -> std::result::Result<usize, RepositoryError> {
+        let updated = self.conn.execute(
+            "UPDATE todos SET version = version + 1, task = ?1 WHERE id = ?2 AND version = ?3 AND organization_id IS NULL",
+            params![todo_item.task, id, expected_version],
+        )?;
+        if updated == 0 {
+            let actual = self.conn.query_row("SELECT version FROM todos WHERE id = ?1 AND organization_id IS NULL", params![id], |row| row.get(0))?;
+            return Err(RepositoryError::Conflict { expected: expected_version, actual });
+        }
+        Ok(self.observers.notify_written(updated, EntityType::Todo, *id, EventKind::Updated))
+    }
+
+    /// Statistics over the todos in this repository.
+    pub fn stats(&self) -> TodoStats<'_> {
+        TodoStats::new(&self.conn)
+    }
+
+    /// Undoable writes on behalf of `user_id`. This does no ownership checks
+    /// of its own; callers go through [`crate::auth::UserTodos`].
+    pub(crate) fn history(&self, user_id: i64) -> TodoHistory<'_> {
+        TodoHistory::new(self, &self.conn, user_id)
+    }
+
+    /// Announce a todo write made outside this repository's own methods,
+    /// if `count` rows were written; see [`Observers::notify_written`].
+    pub(crate) fn notify_written(&self, count: usize, id: i64, kind: EventKind) -> usize {
+        self.observers.notify_written(count, EntityType::Todo, id, kind)
+    }
+
+    /// Run `write` in one transaction, or as part of the one already open,
+    /// delivering its events once that commits.
+    pub(crate) fn transaction<T, E: From<rusqlite::Error>>(
+        &self,
+        write: impl FnOnce() -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        self.observers.transaction(&self.conn, write)
+    }
+
+    /// Change tracking and merging of `user_id`'s todos with other
+    /// databases. The first call starts tracking changes to this database's
+    /// todos.
+    ///
+    /// Fails if the database's tasks are encrypted, as other databases would
+    /// only get the encrypted form.
+    pub fn sync(&self, user_id: i64) -> Result<TodoSync<'_>> {
+        self.refuse_if_encrypted("can't be synced")?;
+        create_sync_tables(&self.conn)?;
+        Ok(TodoSync::new(self, &self.conn, user_id))
+    }
+
+    /// Turn this repository into one that encrypts tasks at rest with `key`;
+    /// see [`crate::encryption`]. The first call sets the database up for
+    /// encryption with that key, after which it can only be opened with
+    /// [`EncryptedTodos::open`].
+    #[cfg(feature = "encryption")]
+    pub fn encrypted(self, key: &str) -> std::result::Result<EncryptedTodos, RepositoryError> {
+        EncryptedTodos::new(self, key)
+    }
+
+    /// Whether a key has been set up for encrypting this database's tasks.
+    fn is_encrypted(&self) -> Result<bool> {
+        if !table_exists(&self.conn, "todo_encryption")? {
+            return Ok(false);
+        }
+        self.conn.query_row("SELECT EXISTS (SELECT 1 FROM todo_encryption)", (), |row| row.get(0))
+    }
+
+    /// Fail with `SQLITE_AUTH`, saying the todos `why`, if the database's
+    /// tasks are encrypted.
+    pub(crate) fn refuse_if_encrypted(&self, why: &str) -> Result<()> {
+        if self.is_encrypted()? {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_AUTH),
+                Some(format!("the todos in this database are encrypted and {}", why)),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The connection, for modules that keep their own tables next to the todos.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Every column of a todo as a JSON object, or `None` if it doesn't exist.
+    pub(crate) fn snapshot(&self, id: &i64) -> Result<Option<String>> {
+        let fields: Vec<String> = TODO_COLUMNS.split(", ").map(|column| format!("'{0}', {0}", column)).collect();
+        self.conn
+            .query_row(&format!("SELECT json_object({}) FROM todos WHERE id = ?1", fields.join(", ")), params![id], |row| {
+                row.get(0)
+            })
+            .optional()
+    }
+
+    /// Put a todo back exactly as in a [`snapshot`](TodoRepository::snapshot),
+    /// id and version included, or delete it for `None`, notifying `kind`.
+    pub(crate) fn restore_item(&self, id: &i64, snapshot: Option<&str>, kind: EventKind) -> Result<usize> {
+        let Some(snapshot) = snapshot else {
+            return self.delete_item_by_id(id);
+        };
+        let columns: Vec<&str> = TODO_COLUMNS.split(", ").collect();
+        let values: Vec<String> = columns.iter().map(|column| format!("json_extract(?1, '$.{}')", column)).collect();
+        let assignments: Vec<String> = columns.iter().zip(&values).skip(1).map(|(column, value)| format!("{} = {}", column, value)).collect();
+        let mut restored = self.conn.execute(
+            &format!("UPDATE todos SET {} WHERE id = ?2", assignments.join(", ")),
+            params![snapshot, id],
+        )?;
+        if restored == 0 {
+            restored = self.conn.execute(
+                &format!("INSERT INTO todos ({}) SELECT {}", TODO_COLUMNS, values.join(", ")),
+                params![snapshot],
+            )?;
+        }
+        Ok(self.observers.notify_written(restored, EntityType::Todo, *id, kind))
+    }
+
+    /// The todo in a snapshot from [`snapshot`](TodoRepository::snapshot).
+    pub(crate) fn todo_from_snapshot(&self, snapshot: &str) -> Result<TodoItem> {
+        let values: Vec<String> = TODO_COLUMNS.split(", ").map(|column| format!("json_extract(?1, '$.{}')", column)).collect();
+        self.conn.query_row(&format!("SELECT {}", values.join(", ")), params![snapshot], todo_from_row)
+    }
+
+    /// Save a new todo straight into a list, returning its id. It is written
+    /// in one statement, so it is never left outside the list.
+    pub fn save_new_item_in_list(&self, todo_dto: &TodoItemDTO, list_id: &i64) -> Result<i64> {
         self.conn.execute(
-            "UPDATE todos SET completed = 0, completed_datetime = NULL WHERE id = ?1",
-            params![id],
+            "INSERT INTO todos (user_id, task, list_id) VALUES (?1, ?2, ?3)",
+            params![todo_dto.user_id, todo_dto.task, list_id],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        self.observers.notify(EntityType::Todo, id, EventKind::Created);
+        Ok(id)
+    }
+
+    /// Put a todo in a list, or take it out of its list with `None`.
+    pub fn set_todo_list(&self, id: &i64, list_id: Option<i64>) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE todos SET version = version + 1, list_id = ?1 WHERE id = ?2 AND organization_id IS NULL",
+            params![list_id, id],
         )
     }
 }
@@ -89,42 +740,51 @@ impl Repository<Connection, TodoItem, rusqlite::Error> for TodoRepository {
             "INSERT INTO todos (user_id, task) VALUES (?1, ?2)",
             params![todo_dto.user_id, todo_dto.task],
         )?;
-        Ok(self.conn.last_insert_rowid())
+        let id = self.conn.last_insert_rowid();
+        self.observers.notify(EntityType::Todo, id, EventKind::Created);
+        Ok(id)
     }
 
 
     fn select_item_by_id(&self, id: &i64) -> Result<TodoItem> {
         self.conn.query_row(
-            "Select id, user_id, task, completed, created_datetime, completed_datetime FROM todos where id = ?1",
+            &format!("SELECT {} FROM todos WHERE id = ?1 AND {}", TODO_COLUMNS, PERSONAL),
             params![id],
-            |row| {
-                let completed_datetime: Option<DateTime<Utc>> = match row.get(5)? {
-                    Some(timestamp) => Some(DateTime::from_timestamp(timestamp, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)?),
-                    None => None,
-                };
-                Ok(TodoItem {
-                    id: row.get(0)?,
-                    user_id: row.get(1)?,
-                    task: row.get(2)?,
-                    completed: row.get(3)?,
-                    created_datetime: DateTime::from_timestamp(row.get(4)?, 0).ok_or(rusqlite::Error::QueryReturnedNoRows)?,
-                    completed_datetime,
-                })
-            },
+            todo_from_row,
         ).map_err(|e| e.into())
     }
 
+    /// Update a todo's task. Its owner stays the same; `todo_item.user_id`
+    /// is ignored.
+    ///
+    /// This does no ownership checks of its own; callers acting for a user
+    /// should go through [`crate::auth::UserTodos`].
     fn update_item(&self, id: &i64, todo_item: &TodoItemDTO) -> Result<usize> {
-        self.conn.execute("UPDATE todos SET task = ?1 WHERE id = ?2",
+        let updated = self.conn.execute("UPDATE todos SET version = version + 1, task = ?1 WHERE id = ?2 AND organization_id IS NULL",
             params![todo_item.task, id],
-        )
+        )?;
+        Ok(self.observers.notify_written(updated, EntityType::Todo, *id, EventKind::Updated))
     }
 
+    /// Delete a todo along with its delegation log, excuses and refusals.
     fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
-        self.conn.execute(
-            "DELETE FROM todos WHERE id = ?1",
-            params![id],
-        )
+        self.observers.transaction(&self.conn, || {
+            let deleted = delete_todo_rows(&self.conn, id)?;
+            Ok(self.observers.notify_written(deleted, EntityType::Todo, *id, EventKind::Deleted))
+        })
     }
 }
 
+impl TodoStore<Connection, rusqlite::Error> for TodoRepository {
+    fn get_user_todos(&self, user_id: &i64) -> Result<Vec<TodoItem>> {
+        TodoRepository::get_user_todos(self, user_id)
+    }
+
+    fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        TodoRepository::complete_todo_item(self, id)
+    }
+
+    fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        TodoRepository::uncomplete_todo_item(self, id)
+    }
+}
@@ -1,11 +1,32 @@
-use rusqlite::{Connection, params, Result};
+use std::sync::mpsc::Receiver;
 
-use crate::models::{User, UserDTO};
+use rusqlite::{Connection, OptionalExtension, params, Row};
+
+use crate::models::{normalize_email, Role, User, UserDTO, ValidationError};
 use crate::repository::entity::Entity;
-use crate::repository::Repository;
+use crate::repository::events::{EntityType, EventKind, Observers, RepositoryEvent};
+use crate::repository::error::{duplicate_emails, is_unique_violation};
+use crate::repository::sqlite::{add_column_if_missing, table_exists};
+use crate::repository::{Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// What [`UserRepository::delete_account`] removes along with the user, as
+/// `(table, condition on ?1 = the user id)`, children before their todos.
+const OWNED_ROWS: &[(&str, &str)] = &[
+    ("todo_delegations", "todo_id IN (SELECT id FROM todos WHERE user_id = ?1)"),
+    ("todo_excuses", "todo_id IN (SELECT id FROM todos WHERE user_id = ?1)"),
+    ("todo_refusals", "todo_id IN (SELECT id FROM todos WHERE user_id = ?1)"),
+    ("todo_history", "user_id = ?1"),
+    ("todos", "user_id = ?1"),
+    ("sessions", "user_id = ?1"),
+    ("api_tokens", "user_id = ?1"),
+    ("credentials", "user_id = ?1"),
+];
 
 pub struct UserRepository {
     conn: Connection,
+    observers: Observers,
 }
 
 impl Entity for User {
@@ -14,6 +35,33 @@ impl Entity for User {
     type ItemDto = UserDTO;
 }
 
+/// The columns read by [`user_from_row`], in order.
+const USER_COLUMNS: &str = "id, first_name, last_name, email, role, version";
+
+/// Build a `User` from a row selected with [`USER_COLUMNS`].
+fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    let role: String = row.get(4)?;
+    Ok(User {
+        id: row.get(0)?,
+        first_name: row.get(1)?,
+        last_name: row.get(2)?,
+        email: row.get(3)?,
+        role: Role::parse(&role).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, format!("unknown role {}", role).into())
+        })?,
+        version: row.get(5)?,
+    })
+}
+
+/// Report a duplicate email as a validation error rather than a raw constraint failure.
+fn map_write_error(e: rusqlite::Error) -> RepositoryError {
+    if is_unique_violation(&e) {
+        ValidationError::field("email", "is already in use").into()
+    } else {
+        e.into()
+    }
+}
+
 impl UserRepository {
     /// Generate an instance of the user repository.
     /// If no connection string (desired db file name) is provided, returns an in-memory db.
@@ -22,7 +70,7 @@ impl UserRepository {
             Some(connection_string) => UserRepository::connect_to_db(connection_string)?,
             None => Connection::open_in_memory()?,
         };
-        let user_repo = UserRepository { conn };
+        let user_repo = UserRepository { conn, observers: Observers::default() };
         user_repo.create_db()?;
         Ok(user_repo)
     }
@@ -30,53 +78,203 @@ impl UserRepository {
     /// Create the SQLite database structure for the blog database
     fn create_db(&self) -> Result<()> {
         // create users table
+        // AUTOINCREMENT so the id of a deleted user is never handed to a new
+        // one, who would otherwise inherit anything still pointing at it
         self.conn.execute(
-            "CREATE TABLE users (\
-id INTEGER PRIMARY KEY,\
+            "CREATE TABLE IF NOT EXISTS users (\
+id INTEGER PRIMARY KEY AUTOINCREMENT,\
 first_name TEXT NOT NULL,\
 last_name TEXT NOT NULL,\
-email TEXT NOT NULL\
+email TEXT NOT NULL,\
+role TEXT NOT NULL DEFAULT 'user',\
+version INTEGER NOT NULL DEFAULT 1\
 )",
             (),
         )?;
+        add_column_if_missing(&self.conn, "users", "role", "TEXT NOT NULL DEFAULT 'user'")?;
+        add_column_if_missing(&self.conn, "users", "version", "INTEGER NOT NULL DEFAULT 1")?;
+        self.add_autoincrement()?;
+        // emails are stored normalized, but index the normalized form anyway
+        // so rows written before validation existed are covered too; those
+        // rows may clash, which is reported instead of failing on the index
+        let indexed: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = 'users_email_unique')",
+            (),
+            |row| row.get(0),
+        )?;
+        if !indexed {
+            let mut stmt = self.conn.prepare(
+                "SELECT lower(trim(email)), group_concat(id, ', ') FROM users \
+GROUP BY lower(trim(email)) HAVING count(*) > 1 ORDER BY min(id)",
+            )?;
+            let duplicates = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+            if !duplicates.is_empty() {
+                return Err(duplicate_emails(duplicates));
+            }
+        }
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS users_email_unique ON users (lower(trim(email)))",
+            (),
+        )?;
         Ok(())
     }
+
+    /// Rebuild a `users` table created before ids were AUTOINCREMENT. Ids of
+    /// users deleted before the rebuild can't be known, so only those above
+    /// the current highest id are safe from reuse afterwards.
+    fn add_autoincrement(&self) -> Result<()> {
+        let sql: String = self.conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'users'",
+            (),
+            |row| row.get(0),
+        )?;
+        if sql.contains("AUTOINCREMENT") {
+            return Ok(());
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch(
+            "ALTER TABLE users RENAME TO users_before_autoincrement;
+CREATE TABLE users (\
+id INTEGER PRIMARY KEY AUTOINCREMENT,\
+first_name TEXT NOT NULL,\
+last_name TEXT NOT NULL,\
+email TEXT NOT NULL,\
+role TEXT NOT NULL DEFAULT 'user',\
+version INTEGER NOT NULL DEFAULT 1\
+);
+INSERT INTO users (id, first_name, last_name, email, role, version) \
+SELECT id, first_name, last_name, email, role, version FROM users_before_autoincrement;
+DROP TABLE users_before_autoincrement;",
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Call `callback` after every successful create, update and delete of a user.
+    pub fn subscribe(&self, callback: impl FnMut(&RepositoryEvent) + Send + 'static) {
+        self.observers.subscribe(callback)
+    }
+
+    /// A channel receiving the same events as [`subscribe`](UserRepository::subscribe) callbacks.
+    pub fn events(&self) -> Receiver<RepositoryEvent> {
+        self.observers.channel()
+    }
+
+    /// Get every user, ordered by id.
+    pub fn get_users(&self) -> Result<Vec<User>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM users ORDER BY id", USER_COLUMNS))?;
+        let user_iter = stmt.query_map((), user_from_row)?;
+        let mut users = Vec::new();
+        for user in user_iter {
+            users.push(user?);
+        }
+        Ok(users)
+    }
+
+    /// Find the user with the given email, compared after normalization.
+    ///
+    /// Returns `Ok(None)` if there is no such user.
+    pub fn find_by_email(&self, email: &str) -> Result<Option<User>> {
+        let user = self.conn.query_row(
+            &format!("SELECT {} FROM users WHERE lower(trim(email)) = ?1", USER_COLUMNS),
+            params![normalize_email(email)],
+            user_from_row,
+        ).optional()?;
+        Ok(user)
+    }
+
+    /// Change a user's role, returning the number of rows updated.
+    ///
+    /// Roles are not part of `UserDTO`, so users can't promote themselves
+    /// through an ordinary update.
+    pub fn set_role(&self, id: &i64, role: Role) -> Result<usize> {
+        let updated_count = self.conn.execute(
+            "UPDATE users SET version = version + 1, role = ?1 WHERE id = ?2",
+            params![role.as_str(), id],
+        )?;
+        Ok(self.observers.notify_written(updated_count, EntityType::User, *id, EventKind::Updated))
+    }
+
+    /// Validate and update a user like `update_item`, but only if they are
+    /// still at `expected_version`.
+    ///
+    /// Fails with `RepositoryError::Conflict` if someone else changed them
+    /// first, and `RepositoryError::NotFound` if they don't exist.
+    pub fn update_item_if_version(&self, id: &i64, user: &UserDTO, expected_version: i64) -> Result<usize> {
+        let user = user.validate()?;
+        let updated_count = self.conn.execute(
+            "UPDATE users SET version = version + 1, first_name = ?1, last_name = ?2, email = ?3 WHERE id = ?4 AND version = ?5",
+            params![user.first_name, user.last_name, user.email, id, expected_version],
+        ).map_err(map_write_error)?;
+        if updated_count == 0 {
+            let actual = self.conn.query_row("SELECT version FROM users WHERE id = ?1", params![id], |row| row.get(0))?;
+            return Err(RepositoryError::Conflict { expected: expected_version, actual });
+        }
+        Ok(self.observers.notify_written(updated_count, EntityType::User, *id, EventKind::Updated))
+    }
+
+    /// Delete a user along with their todos, the delegations, excuses and
+    /// refusals logged against those todos, their undo history, and their
+    /// password, sessions and API tokens, all in one transaction. Todos of
+    /// other users assigned to them are unassigned.
+    ///
+    /// Returns the number of users deleted, like `delete_item_by_id`.
+    pub fn delete_account(&self, id: &i64) -> Result<usize> {
+        self.observers.transaction(&self.conn, || {
+            for (table, condition) in OWNED_ROWS {
+                if table_exists(&self.conn, table)? {
+                    self.conn.execute(&format!("DELETE FROM {} WHERE {}", table, condition), params![id])?;
+                }
+            }
+            if table_exists(&self.conn, "todos")? {
+                self.conn.execute(
+                    "UPDATE todos SET version = version + 1, assignee_id = NULL WHERE assignee_id = ?1",
+                    params![id],
+                )?;
+            }
+            let deleted_count = self.conn.execute("DELETE FROM users WHERE id = ?1", params![id])?;
+            Ok(self.observers.notify_written(deleted_count, EntityType::User, *id, EventKind::Deleted))
+        })
+    }
 }
 
 
-impl Repository<Connection, User, rusqlite::Error> for UserRepository {
+impl Repository<Connection, User, RepositoryError> for UserRepository {
     fn connect_to_db(connection_string: &str) -> Result<Connection> {
         let conn: Connection = Connection::open(connection_string)?;
         Ok(conn)
     }
 
+    /// Validate and save a new user, returning its id.
+    ///
+    /// Fails with `RepositoryError::Validation` if the DTO is invalid or the
+    /// email is already used by another user.
     fn save_new_item(&self, user_dto: &UserDTO) -> Result<i64> {
+        let user_dto = user_dto.validate()?;
         self.conn.execute(
             "INSERT INTO users (first_name, last_name, email) VALUES (?1, ?2, ?3)",
             params![user_dto.first_name, user_dto.last_name, user_dto.email],
-        )?;
-        Ok(self.conn.last_insert_rowid())
+        ).map_err(map_write_error)?;
+        let id = self.conn.last_insert_rowid();
+        self.observers.notify(EntityType::User, id, EventKind::Created);
+        Ok(id)
     }
     fn select_item_by_id(&self, id: &i64) -> Result<User> {
         self.conn.query_row(
-            "Select id, first_name, last_name, email FROM users where id = ?1",
+            &format!("SELECT {} FROM users WHERE id = ?1", USER_COLUMNS),
             params![id],
-            |row| {
-                Ok(User {
-                    id: row.get(0)?,
-                    first_name: row.get(1)?,
-                    last_name: row.get(2)?,
-                    email: row.get(3)?,
-                })
-            },
+            user_from_row,
         ).map_err(|e| e.into())
     }
+    /// Validate and update a user, with the same rules as `save_new_item`.
     fn update_item(&self, id: &i64, user: &UserDTO) -> Result<usize> {
+        let user = user.validate()?;
         let updated_count = self.conn.execute(
-            "UPDATE users SET first_name = ?1, last_name = ?2, email = ?3 WHERE id = ?4",
+            "UPDATE users SET version = version + 1, first_name = ?1, last_name = ?2, email = ?3 WHERE id = ?4",
             params![user.first_name, user.last_name, user.email, id],
-        )?;
-        Ok(updated_count)
+        ).map_err(map_write_error)?;
+        Ok(self.observers.notify_written(updated_count, EntityType::User, *id, EventKind::Updated))
     }
 
     /// Delete a user by id
@@ -92,6 +290,6 @@ impl Repository<Connection, User, rusqlite::Error> for UserRepository {
             "DELETE FROM users WHERE id = ?1",
             params![id],
         )?;
-        Ok(deleted_count)
+        Ok(self.observers.notify_written(deleted_count, EntityType::User, *id, EventKind::Deleted))
     }
 }
@@ -0,0 +1,281 @@
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, params, Row};
+
+use crate::auth::generate_token;
+use crate::models::{Webhook, WebhookDTO, WebhookDelivery};
+use crate::repository::entity::Entity;
+use crate::repository::sqlite::todo_repository::create_todos_table;
+use crate::repository::sqlite::{optional_timestamp, timestamp};
+use crate::repository::{EventKind, Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// Stores webhooks and the outbox of deliveries waiting to be sent.
+///
+/// Deliveries are queued by triggers on the `todos` table, so they are
+/// written in the same transaction as the change itself, whichever
+/// repository makes it. Sending them is up to
+/// [`WebhookDispatcher`](crate::webhooks::WebhookDispatcher).
+pub struct WebhookRepository {
+    conn: Connection,
+}
+
+impl Entity for Webhook {
+    type Id = i64;
+    type Item = Webhook;
+    type ItemDto = WebhookDTO;
+}
+
+const WEBHOOK_COLUMNS: &str = "id, user_id, url, events, secret, created_datetime";
+
+fn event_kind(row: &Row, idx: usize) -> rusqlite::Result<EventKind> {
+    let kind: String = row.get(idx)?;
+    EventKind::parse(&kind).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, format!("unknown event {}", kind).into())
+    })
+}
+
+/// Build a `Webhook` from a row selected with [`WEBHOOK_COLUMNS`].
+/// Events are stored space-separated; unknown ones are ignored.
+fn webhook_from_row(row: &Row) -> rusqlite::Result<Webhook> {
+    let events: String = row.get(3)?;
+    Ok(Webhook {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        url: row.get(2)?,
+        events: events.split_whitespace().filter_map(EventKind::parse).collect(),
+        secret: row.get(4)?,
+        created_datetime: timestamp(row, 5)?,
+    })
+}
+
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, payload, attempts, next_attempt_datetime, \
+delivered_datetime, failed_datetime, last_error, created_datetime";
+
+fn delivery_from_row(row: &Row) -> rusqlite::Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        webhook_id: row.get(1)?,
+        event: event_kind(row, 2)?,
+        payload: row.get(3)?,
+        attempts: row.get(4)?,
+        next_attempt_datetime: timestamp(row, 5)?,
+        delivered_datetime: optional_timestamp(row, 6)?,
+        failed_datetime: optional_timestamp(row, 7)?,
+        last_error: row.get(8)?,
+        created_datetime: timestamp(row, 9)?,
+    })
+}
+
+fn join_events(events: &[EventKind]) -> String {
+    events.iter().map(EventKind::as_str).collect::<Vec<_>>().join(" ")
+}
+
+/// A JSON object of the todo in the trigger row `row` (`NEW` or `OLD`).
+fn todo_json(row: &str) -> String {
+    format!(
+        "json_object('id', {row}.id, 'user_id', {row}.user_id, 'task', {row}.task, \
+'completed', json(CASE WHEN {row}.completed THEN 'true' ELSE 'false' END), 'version', {row}.version)"
+    )
+}
+
+/// A trigger queueing a delivery to each of the owner's webhooks that want
+/// the event `kind` (an SQL expression) about the todo in `row`, for the
+/// changes matching `when`.
+fn outbox_trigger(name: &str, timing: &str, when: &str, kind: &str, row: &str) -> String {
+    format!(
+        "CREATE TRIGGER IF NOT EXISTS {name} AFTER {timing} ON todos WHEN {when} BEGIN \
+INSERT INTO webhook_outbox (webhook_id, event, payload) \
+SELECT webhooks.id, change.kind, json_object('event', change.kind, 'todo', {todo}, \
+'timestamp', strftime('%Y-%m-%dT%H:%M:%SZ', 'now')) \
+FROM webhooks, (SELECT {kind} AS kind) AS change \
+WHERE webhooks.user_id = {row}.user_id AND instr(' ' || webhooks.events || ' ', ' ' || change.kind || ' ') > 0; \
+END",
+        todo = todo_json(row),
+    )
+}
+
+impl WebhookRepository {
+    /// Generate an instance of the webhook repository.
+    /// If no connection string (desired db file name) is provided, returns an in-memory db.
+    pub fn new(connection_string: Option<&str>) -> Result<WebhookRepository> {
+        let conn = match connection_string {
+            Some(connection_string) => WebhookRepository::connect_to_db(connection_string)?,
+            None => Connection::open_in_memory()?,
+        };
+        let webhook_repo = WebhookRepository { conn };
+        webhook_repo.create_db()?;
+        Ok(webhook_repo)
+    }
+
+    fn create_db(&self) -> Result<()> {
+        create_todos_table(&self.conn)?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhooks (\
+id INTEGER PRIMARY KEY,\
+user_id INTEGER NOT NULL,\
+url TEXT NOT NULL,\
+events TEXT NOT NULL,\
+secret TEXT NOT NULL,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+            (),
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_outbox (\
+id INTEGER PRIMARY KEY,\
+webhook_id INTEGER NOT NULL,\
+event TEXT NOT NULL,\
+payload TEXT NOT NULL,\
+attempts INTEGER NOT NULL DEFAULT 0,\
+next_attempt_datetime INTEGER DEFAULT (strftime('%s', 'now')),\
+delivered_datetime INTEGER,\
+failed_datetime INTEGER,\
+last_error TEXT,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+            (),
+        )?;
+        self.conn.execute(&outbox_trigger("todos_webhook_insert", "INSERT", "true", "'created'", "NEW"), ())?;
+        // snoozing, assigning and the like aren't webhook events; databases
+        // from before that was checked have a trigger without the condition
+        self.conn.execute("DROP TRIGGER IF EXISTS todos_webhook_update", ())?;
+        self.conn.execute(
+            &outbox_trigger(
+                "todos_webhook_update",
+                "UPDATE",
+                "OLD.task IS NOT NEW.task OR OLD.completed IS NOT NEW.completed OR OLD.user_id IS NOT NEW.user_id",
+                "CASE WHEN NEW.completed AND NOT OLD.completed THEN 'completed' \
+WHEN OLD.completed AND NOT NEW.completed THEN 'uncompleted' ELSE 'updated' END",
+                "NEW",
+            ),
+            (),
+        )?;
+        self.conn.execute(&outbox_trigger("todos_webhook_delete", "DELETE", "true", "'deleted'", "OLD"), ())?;
+        Ok(())
+    }
+
+    /// A user's webhooks, ordered by id.
+    pub fn get_user_webhooks(&self, user_id: &i64) -> Result<Vec<Webhook>> {
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM webhooks WHERE user_id = ?1 ORDER BY id", WEBHOOK_COLUMNS))?;
+        let webhook_iter = stmt.query_map(params![user_id], webhook_from_row)?;
+        let mut webhooks = Vec::new();
+        for webhook in webhook_iter {
+            webhooks.push(webhook?);
+        }
+        Ok(webhooks)
+    }
+
+    /// Every delivery queued for a webhook, oldest first.
+    pub fn get_deliveries(&self, webhook_id: &i64) -> Result<Vec<WebhookDelivery>> {
+        self.query_deliveries(
+            &format!("SELECT {} FROM webhook_outbox WHERE webhook_id = ?1 ORDER BY id", DELIVERY_COLUMNS),
+            params![webhook_id],
+        )
+    }
+
+    /// Up to `limit` deliveries that are neither delivered nor given up on and
+    /// are due by `now`, oldest first.
+    pub fn get_due_deliveries(&self, now: &DateTime<Utc>, limit: u32) -> Result<Vec<WebhookDelivery>> {
+        self.query_deliveries(
+            &format!(
+                "SELECT {} FROM webhook_outbox \
+WHERE delivered_datetime IS NULL AND failed_datetime IS NULL AND next_attempt_datetime <= ?1 ORDER BY id LIMIT ?2",
+                DELIVERY_COLUMNS
+            ),
+            params![now.timestamp(), limit],
+        )
+    }
+
+    /// Take up to `limit` due deliveries to send, oldest first, like
+    /// [`get_due_deliveries`](WebhookRepository::get_due_deliveries).
+    ///
+    /// Their next attempt is pushed back to `now + lease` in the same
+    /// statement, so other dispatchers on the database skip them until they
+    /// are marked delivered or failed, or the lease runs out.
+    pub fn claim_due_deliveries(&self, now: &DateTime<Utc>, lease: Duration, limit: u32) -> Result<Vec<WebhookDelivery>> {
+        let mut deliveries = self.query_deliveries(
+            &format!(
+                "UPDATE webhook_outbox SET next_attempt_datetime = ?3 WHERE id IN (\
+SELECT id FROM webhook_outbox \
+WHERE delivered_datetime IS NULL AND failed_datetime IS NULL AND next_attempt_datetime <= ?1 ORDER BY id LIMIT ?2\
+) RETURNING {}",
+                DELIVERY_COLUMNS
+            ),
+            params![now.timestamp(), limit, (*now + lease).timestamp()],
+        )?;
+        deliveries.sort_by_key(|delivery| delivery.id);
+        Ok(deliveries)
+    }
+
+    /// Record a successful attempt.
+    pub fn mark_delivered(&self, id: &i64, now: &DateTime<Utc>) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE webhook_outbox SET attempts = attempts + 1, delivered_datetime = ?1, last_error = NULL WHERE id = ?2",
+            params![now.timestamp(), id],
+        )?)
+    }
+
+    /// Record a failed attempt, to be retried at `retry_at`, or given up on
+    /// for good with `None`.
+    pub fn mark_failed(&self, id: &i64, error: &str, now: &DateTime<Utc>, retry_at: Option<DateTime<Utc>>) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE webhook_outbox SET attempts = attempts + 1, last_error = ?1, \
+next_attempt_datetime = COALESCE(?2, next_attempt_datetime), failed_datetime = CASE WHEN ?2 IS NULL THEN ?3 END \
+WHERE id = ?4",
+            params![error, retry_at.map(|retry_at| retry_at.timestamp()), now.timestamp(), id],
+        )?)
+    }
+
+    fn query_deliveries(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<WebhookDelivery>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let delivery_iter = stmt.query_map(params, delivery_from_row)?;
+        let mut deliveries = Vec::new();
+        for delivery in delivery_iter {
+            deliveries.push(delivery?);
+        }
+        Ok(deliveries)
+    }
+}
+
+impl Repository<Connection, Webhook, RepositoryError> for WebhookRepository {
+    fn connect_to_db(connection_string: &str) -> Result<Connection> {
+        Ok(Connection::open(connection_string)?)
+    }
+
+    /// Validate and save a new webhook with a fresh signing secret, returning its id.
+    fn save_new_item(&self, webhook_dto: &WebhookDTO) -> Result<i64> {
+        let webhook_dto = webhook_dto.validate()?;
+        self.conn.execute(
+            "INSERT INTO webhooks (user_id, url, events, secret) VALUES (?1, ?2, ?3, ?4)",
+            params![webhook_dto.user_id, webhook_dto.url, join_events(&webhook_dto.events), generate_token()],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn select_item_by_id(&self, id: &i64) -> Result<Webhook> {
+        Ok(self.conn.query_row(
+            &format!("SELECT {} FROM webhooks WHERE id = ?1", WEBHOOK_COLUMNS),
+            params![id],
+            webhook_from_row,
+        )?)
+    }
+
+    /// Validate and update a webhook's URL and events, keeping its secret.
+    fn update_item(&self, id: &i64, webhook_dto: &WebhookDTO) -> Result<usize> {
+        let webhook_dto = webhook_dto.validate()?;
+        Ok(self.conn.execute(
+            "UPDATE webhooks SET user_id = ?1, url = ?2, events = ?3 WHERE id = ?4",
+            params![webhook_dto.user_id, webhook_dto.url, join_events(&webhook_dto.events), id],
+        )?)
+    }
+
+    /// Delete a webhook along with its queued deliveries.
+    fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM webhook_outbox WHERE webhook_id = ?1", params![id])?;
+        let deleted_count = tx.execute("DELETE FROM webhooks WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(deleted_count)
+    }
+}
@@ -0,0 +1,234 @@
+//! Per-user procrastination statistics, computed from the `todos` table.
+//!
+//! Get a [`TodoStats`] from [`TodoRepository::stats`]. Every metric is a
+//! single SQL query with aggregates, so the cost doesn't grow with the number
+//! of rows copied out of the database. Day and week boundaries are in UTC, and
+//! weeks start on Monday.
+//!
+//! [`TodoRepository::stats`]: crate::repository::sqlite::todo_repository::TodoRepository::stats
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rusqlite::{Connection, params, Result};
+
+use crate::models::{RefusalCount, RefusalReason, TodoItem};
+use crate::repository::sqlite::todo_repository::{refusal_reason, todo_from_row, TODO_COLUMNS};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// The unix epoch was a Thursday; shifting by three days makes week numbers
+/// roll over on Mondays.
+const WEEK_OFFSET_DAYS: i64 = 3;
+
+/// How many of a user's todos are done. Refused todos count as neither open
+/// nor completed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompletionSummary {
+    pub open_count: i64,
+    pub completed_count: i64,
+    /// Completed todos as a fraction of open and completed todos, or 0 if
+    /// there are none.
+    pub completion_rate: f64,
+}
+
+/// Runs of consecutive days with at least one completed todo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompletionStreaks {
+    /// The run ending today, or yesterday if nothing has been completed yet today.
+    pub current_days: i64,
+    pub longest_days: i64,
+}
+
+/// Todos created and completed in one week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeeklyCount {
+    /// The Monday the week starts on.
+    pub week_start: NaiveDate,
+    pub created_count: i64,
+    pub completed_count: i64,
+}
+
+/// Todos refused in one week, by reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeeklyRefusals {
+    /// The Monday the week starts on.
+    pub week_start: NaiveDate,
+    /// One entry per reason, in [`RefusalReason::ALL`] order.
+    pub counts: Vec<RefusalCount>,
+}
+
+/// Everything [`TodoStats`] knows about a user, in one struct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserStats {
+    pub user_id: i64,
+    pub completion: CompletionSummary,
+    pub median_completion_time: Option<Duration>,
+    pub streaks: CompletionStreaks,
+}
+
+/// Statistics queries over a todo database, from [`TodoRepository::stats`].
+///
+/// [`TodoRepository::stats`]: crate::repository::sqlite::todo_repository::TodoRepository::stats
+pub struct TodoStats<'a> {
+    conn: &'a Connection,
+}
+
+fn day_of(datetime: DateTime<Utc>) -> i64 {
+    datetime.timestamp().div_euclid(SECONDS_PER_DAY)
+}
+
+fn week_of(datetime: DateTime<Utc>) -> i64 {
+    (day_of(datetime) + WEEK_OFFSET_DAYS).div_euclid(7)
+}
+
+fn week_start(week: i64) -> NaiveDate {
+    DateTime::UNIX_EPOCH.date_naive() + Duration::days(week * 7 - WEEK_OFFSET_DAYS)
+}
+
+impl<'a> TodoStats<'a> {
+    pub(crate) fn new(conn: &'a Connection) -> TodoStats<'a> {
+        TodoStats { conn }
+    }
+
+    /// Open and completed counts and the completion rate.
+    pub fn completion_summary(&self, user_id: &i64) -> Result<CompletionSummary> {
+        let (open_count, completed_count): (i64, i64) = self.conn.query_row(
+            "SELECT COALESCE(SUM(completed = 0 AND refused_datetime IS NULL), 0), COALESCE(SUM(completed = 1), 0) FROM todos WHERE user_id = ?1",
+            params![user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let total = open_count + completed_count;
+        Ok(CompletionSummary {
+            open_count,
+            completed_count,
+            completion_rate: if total == 0 { 0.0 } else { completed_count as f64 / total as f64 },
+        })
+    }
+
+    /// The median time from creating a todo to completing it, or `None` if
+    /// nothing has been completed.
+    pub fn median_completion_time(&self, user_id: &i64) -> Result<Option<Duration>> {
+        // pick the middle one or two durations in order and average them
+        let median: Option<f64> = self.conn.query_row(
+            "WITH durations AS (\
+SELECT completed_datetime - created_datetime AS duration FROM todos \
+WHERE user_id = ?1 AND completed = 1 AND completed_datetime IS NOT NULL\
+) \
+SELECT AVG(duration) FROM (\
+SELECT duration FROM durations ORDER BY duration \
+LIMIT 2 - (SELECT COUNT(*) FROM durations) % 2 \
+OFFSET ((SELECT COUNT(*) FROM durations) - 1) / 2\
+)",
+            params![user_id],
+            |row| row.get(0),
+        )?;
+        Ok(median.map(|seconds| Duration::seconds(seconds.round() as i64)))
+    }
+
+    /// Up to `limit` open todos, oldest first. Refused todos aren't open.
+    pub fn oldest_untouched(&self, user_id: &i64, limit: u32) -> Result<Vec<TodoItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todos WHERE user_id = ?1 AND completed = 0 AND refused_datetime IS NULL ORDER BY created_datetime, id LIMIT ?2",
+            TODO_COLUMNS
+        ))?;
+        let todo_iter = stmt.query_map(params![user_id, limit], todo_from_row)?;
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+
+    /// The current and longest runs of days with a completion, as of `now`.
+    pub fn completion_streaks(&self, user_id: &i64, now: DateTime<Utc>) -> Result<CompletionStreaks> {
+        // consecutive days share the same `day - row_number`, so each group is one run
+        let mut stmt = self.conn.prepare(
+            "WITH days AS (\
+SELECT DISTINCT completed_datetime / ?2 AS day FROM todos \
+WHERE user_id = ?1 AND completed = 1 AND completed_datetime IS NOT NULL\
+), runs AS (\
+SELECT COUNT(*) AS length, MAX(day) AS last_day \
+FROM (SELECT day, day - ROW_NUMBER() OVER (ORDER BY day) AS run FROM days) GROUP BY run\
+) \
+SELECT COALESCE(MAX(length), 0), COALESCE(MAX(CASE WHEN last_day >= ?3 - 1 THEN length END), 0) FROM runs",
+        )?;
+        stmt.query_row(params![user_id, SECONDS_PER_DAY, day_of(now)], |row| {
+            Ok(CompletionStreaks { longest_days: row.get(0)?, current_days: row.get(1)? })
+        })
+    }
+
+    /// Todos created and completed per week, for the `weeks` weeks up to and
+    /// including the one containing `now`, oldest first. Quiet weeks are
+    /// included with zero counts.
+    pub fn weekly_series(&self, user_id: &i64, weeks: u32, now: DateTime<Utc>) -> Result<Vec<WeeklyCount>> {
+        let last_week = week_of(now);
+        let first_week = last_week - i64::from(weeks) + 1;
+        let mut series: Vec<WeeklyCount> = (first_week..=last_week)
+            .map(|week| WeeklyCount { week_start: week_start(week), created_count: 0, completed_count: 0 })
+            .collect();
+        let mut stmt = self.conn.prepare(
+            "WITH events AS (\
+SELECT (created_datetime / ?2 + ?3) / 7 AS week, 1 AS created, 0 AS completed FROM todos WHERE user_id = ?1 \
+UNION ALL \
+SELECT (completed_datetime / ?2 + ?3) / 7, 0, 1 FROM todos \
+WHERE user_id = ?1 AND completed = 1 AND completed_datetime IS NOT NULL\
+) \
+SELECT week, SUM(created), SUM(completed) FROM events WHERE week BETWEEN ?4 AND ?5 GROUP BY week",
+        )?;
+        let rows = stmt.query_map(
+            params![user_id, SECONDS_PER_DAY, WEEK_OFFSET_DAYS, first_week, last_week],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)),
+        )?;
+        for row in rows {
+            let (week, created_count, completed_count) = row?;
+            let entry = &mut series[(week - first_week) as usize];
+            entry.created_count = created_count;
+            entry.completed_count = completed_count;
+        }
+        Ok(series)
+    }
+
+    /// Refusals per week and reason, for the `weeks` weeks up to and including
+    /// the one containing `now`, oldest first. Refusals count in the week they
+    /// were made, even if they were reconsidered later.
+    pub fn weekly_refusals(&self, user_id: &i64, weeks: u32, now: DateTime<Utc>) -> Result<Vec<WeeklyRefusals>> {
+        let last_week = week_of(now);
+        let first_week = last_week - i64::from(weeks) + 1;
+        let mut series: Vec<WeeklyRefusals> = (first_week..=last_week)
+            .map(|week| WeeklyRefusals {
+                week_start: week_start(week),
+                counts: RefusalReason::ALL
+                    .into_iter()
+                    .map(|reason| RefusalCount { reason, refused_count: 0, reconsidered_count: 0 })
+                    .collect(),
+            })
+            .collect();
+        let mut stmt = self.conn.prepare(
+            "SELECT week, reason, COUNT(*), COUNT(reconsidered_datetime) FROM (\
+SELECT (refused_datetime / ?2 + ?3) / 7 AS week, reason, reconsidered_datetime FROM todo_refusals WHERE user_id = ?1\
+) WHERE week BETWEEN ?4 AND ?5 GROUP BY week, reason",
+        )?;
+        let rows = stmt.query_map(
+            params![user_id, SECONDS_PER_DAY, WEEK_OFFSET_DAYS, first_week, last_week],
+            |row| Ok((row.get::<_, i64>(0)?, refusal_reason(row, 1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?)),
+        )?;
+        for row in rows {
+            let (week, reason, refused_count, reconsidered_count) = row?;
+            let counts = &mut series[(week - first_week) as usize].counts;
+            if let Some(count) = counts.iter_mut().find(|count| count.reason == reason) {
+                count.refused_count = refused_count;
+                count.reconsidered_count = reconsidered_count;
+            }
+        }
+        Ok(series)
+    }
+
+    /// The summary, median completion time and streaks together.
+    pub fn user_stats(&self, user_id: &i64, now: DateTime<Utc>) -> Result<UserStats> {
+        Ok(UserStats {
+            user_id: *user_id,
+            completion: self.completion_summary(user_id)?,
+            median_completion_time: self.median_completion_time(user_id)?,
+            streaks: self.completion_streaks(user_id, now)?,
+        })
+    }
+}
@@ -0,0 +1,449 @@
+//! Merging todos between databases, for using one account on several devices
+//! that are often offline.
+//!
+//! Get a [`TodoSync`] for a user from [`TodoRepository::sync`]. From then on,
+//! triggers keep the latest value of each synced field of each todo, stamped
+//! with a hybrid logical clock ([`Hlc`]) and a local sequence number. Syncing
+//! is two calls: [`changes_since`](TodoSync::changes_since) on one database,
+//! with the cursor the other one last got, and
+//! [`apply_changes`](TodoSync::apply_changes) on the other. Do both directions
+//! and the user's todos agree.
+//!
+//! Conflicts are settled per field, the higher clock winning, so the result
+//! doesn't depend on the order changes arrive in:
+//!
+//! - `task` is a plain last-writer-wins field.
+//! - Completion is one field holding the completion time, so the latest
+//!   complete or uncomplete wins and the winner's completion time travels
+//!   with it, rather than being the time the change arrived.
+//! - Deleting a todo is final: once a device has seen the delete, later edits
+//!   from other devices are ignored.
+//!
+//! Todos are matched across databases by a random sync id, since row ids
+//! differ. Ownership stays local too, as user ids differ between databases:
+//! a user's changes only carry their own todos, and todos arriving from
+//! another database belong to whoever they are applied for. Lists,
+//! assignees, snoozes and refusals stay local.
+//!
+//! [`TodoRepository::sync`]: crate::repository::sqlite::todo_repository::TodoRepository::sync
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params, Result, Row};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::repository::sqlite::todo_repository::{delete_todo_rows, TodoRepository};
+use crate::repository::sqlite::{add_column_if_missing, optional_timestamp};
+use crate::repository::EventKind;
+
+/// A hybrid logical clock reading: wall-clock milliseconds, a counter for
+/// writes within one millisecond (or behind a faster remote clock), and the
+/// database it was read on, to break ties.
+///
+/// Readings are ordered by those three, in that order.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Hlc {
+    pub millis: i64,
+    pub counter: i64,
+    pub node_id: String,
+}
+
+impl Hlc {
+    /// Read the `millis-counter-node` form written by `Display`.
+    pub fn parse(hlc: &str) -> Option<Hlc> {
+        let mut parts = hlc.splitn(3, '-');
+        Some(Hlc {
+            millis: parts.next()?.parse().ok()?,
+            counter: parts.next()?.parse().ok()?,
+            node_id: parts.next()?.to_string(),
+        })
+    }
+}
+
+/// Zero-padded so that readings sort the same as text.
+impl fmt::Display for Hlc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:015}-{:010}-{}", self.millis, self.counter, self.node_id)
+    }
+}
+
+/// A synced field of a todo and its new value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TodoField {
+    Task(String),
+    /// When the todo was completed, or `None` if it isn't.
+    Completed(Option<DateTime<Utc>>),
+    Deleted,
+}
+
+impl TodoField {
+    fn name(&self) -> &'static str {
+        match self {
+            TodoField::Task(_) => "task",
+            TodoField::Completed(_) => "completed",
+            TodoField::Deleted => "deleted",
+        }
+    }
+}
+
+/// One field of one todo, as last written on some device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Change {
+    pub sync_id: String,
+    pub field: TodoField,
+    pub hlc: Hlc,
+}
+
+/// The result of [`TodoSync::changes_since`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChangeSet {
+    pub changes: Vec<Change>,
+    /// Pass this to the next `changes_since` to get only what changed after.
+    pub cursor: i64,
+}
+
+/// The current time in unix milliseconds, in SQL.
+const NOW_MILLIS: &str = "CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)";
+
+/// Advance the clock and the sequence for a local write.
+fn tick() -> String {
+    format!(
+        "UPDATE sync_state SET counter = CASE WHEN {now} > millis THEN 0 ELSE counter + 1 END, \
+millis = MAX(millis, {now}), seq = seq + 1;",
+        now = NOW_MILLIS
+    )
+}
+
+/// Record `value` as the latest `field` of the todo whose id is `id`, if
+/// `condition` holds, stamped with the current clock.
+fn track(field: &str, value: &str, id: &str, condition: &str) -> String {
+    format!(
+        "INSERT INTO sync_fields (sync_id, field, value, hlc, seq) \
+SELECT sync_rows.sync_id, '{field}', {value}, printf('%015d-%010d-%s', millis, counter, node_id), seq \
+FROM sync_rows, sync_state WHERE sync_rows.todo_id = {id} AND {condition} \
+ON CONFLICT (sync_id, field) DO UPDATE SET value = excluded.value, hlc = excluded.hlc, seq = excluded.seq;"
+    )
+}
+
+/// The completion field of the todo row `row`: its completion time, or NULL.
+fn completed_value(row: &str) -> String {
+    format!("CASE WHEN {row}.completed THEN COALESCE({row}.completed_datetime, {row}.created_datetime) END")
+}
+
+/// Create the sync tables and triggers, and start tracking todos written
+/// before sync was first set up.
+///
+/// `sync_rows` maps sync ids to local todo ids (NULL once deleted) and
+/// their local owners, `sync_fields` holds the latest value of every field
+/// with its clock and the sequence number it arrived at, and `sync_state` is
+/// the one-row clock. The triggers skip writes made while `applying` is set,
+/// which [`TodoSync::apply_changes`] records itself.
+pub(crate) fn create_sync_tables(conn: &Connection) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sync_state (\
+id INTEGER PRIMARY KEY CHECK (id = 1),\
+node_id TEXT NOT NULL,\
+millis INTEGER NOT NULL DEFAULT 0,\
+counter INTEGER NOT NULL DEFAULT 0,\
+seq INTEGER NOT NULL DEFAULT 0,\
+applying INTEGER NOT NULL DEFAULT 0);
+INSERT OR IGNORE INTO sync_state (id, node_id) VALUES (1, lower(hex(randomblob(8))));
+CREATE TABLE IF NOT EXISTS sync_rows (\
+sync_id TEXT PRIMARY KEY,\
+todo_id INTEGER UNIQUE,\
+user_id INTEGER);
+CREATE TABLE IF NOT EXISTS sync_fields (\
+sync_id TEXT NOT NULL,\
+field TEXT NOT NULL,\
+value,\
+hlc TEXT NOT NULL,\
+seq INTEGER NOT NULL,\
+PRIMARY KEY (sync_id, field));
+CREATE INDEX IF NOT EXISTS sync_fields_seq ON sync_fields (seq);",
+    )?;
+    // owners used to be synced as raw user ids, which mean different users
+    // on different databases
+    add_column_if_missing(&tx, "sync_rows", "user_id", "INTEGER")?;
+    tx.execute_batch(&format!(
+        "UPDATE sync_rows SET user_id = (SELECT user_id FROM todos WHERE id = sync_rows.todo_id) WHERE user_id IS NULL;
+DELETE FROM sync_fields WHERE field = 'user_id';
+DROP TRIGGER IF EXISTS todos_sync_insert;
+DROP TRIGGER IF EXISTS todos_sync_update;
+DROP TRIGGER IF EXISTS todos_sync_delete;
+CREATE TRIGGER todos_sync_insert AFTER INSERT ON todos \
+WHEN (SELECT applying FROM sync_state) = 0 BEGIN \
+INSERT INTO sync_rows (sync_id, todo_id, user_id) VALUES (lower(hex(randomblob(16))), NEW.id, NEW.user_id); \
+{tick} {task} {completed} END;
+CREATE TRIGGER todos_sync_update AFTER UPDATE ON todos \
+WHEN (SELECT applying FROM sync_state) = 0 AND (OLD.user_id IS NOT NEW.user_id OR OLD.task IS NOT NEW.task \
+OR OLD.completed IS NOT NEW.completed OR OLD.completed_datetime IS NOT NEW.completed_datetime) BEGIN \
+UPDATE sync_rows SET user_id = NEW.user_id WHERE todo_id = NEW.id; \
+{tick} {changed_task} {changed_completed} END;
+CREATE TRIGGER todos_sync_delete AFTER DELETE ON todos \
+WHEN (SELECT applying FROM sync_state) = 0 BEGIN \
+{tick} {deleted} UPDATE sync_rows SET todo_id = NULL WHERE todo_id = OLD.id; END;
+INSERT INTO sync_rows (sync_id, todo_id, user_id) SELECT lower(hex(randomblob(16))), id, user_id FROM todos \
+WHERE id NOT IN (SELECT todo_id FROM sync_rows WHERE todo_id IS NOT NULL);",
+        tick = tick(),
+        task = track("task", "NEW.task", "NEW.id", "1"),
+        completed = track("completed", &completed_value("NEW"), "NEW.id", "1"),
+        changed_task = track("task", "NEW.task", "NEW.id", "OLD.task IS NOT NEW.task"),
+        changed_completed = track(
+            "completed",
+            &completed_value("NEW"),
+            "NEW.id",
+            &format!("{} IS NOT {}", completed_value("OLD"), completed_value("NEW")),
+        ),
+        deleted = track("deleted", "1", "OLD.id", "1"),
+    ))?;
+    backfill(&tx)?;
+    tx.commit()
+}
+
+/// Stop tracking changes and forget the sync log, for a database whose tasks
+/// are being encrypted and so can no longer be synced.
+#[cfg(feature = "encryption")]
+pub(crate) fn drop_sync_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS todos_sync_insert;
+DROP TRIGGER IF EXISTS todos_sync_update;
+DROP TRIGGER IF EXISTS todos_sync_delete;
+DROP TABLE IF EXISTS sync_fields;
+DROP TABLE IF EXISTS sync_rows;
+DROP TABLE IF EXISTS sync_state;",
+    )
+}
+
+/// Stamp the fields of todos that have a sync id but no fields yet: those
+/// written before sync was set up. Called within `create_sync_tables`'s
+/// transaction.
+fn backfill(conn: &Connection) -> Result<()> {
+    let missing: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sync_rows WHERE todo_id IS NOT NULL \
+AND sync_id NOT IN (SELECT sync_id FROM sync_fields))",
+        (),
+        |row| row.get(0),
+    )?;
+    if !missing {
+        return Ok(());
+    }
+    conn.execute(&tick(), ())?;
+    for (field, value) in [("task", "todos.task".to_string()), ("completed", completed_value("todos"))] {
+        conn.execute(
+            &format!(
+                "INSERT INTO sync_fields (sync_id, field, value, hlc, seq) \
+SELECT sync_rows.sync_id, '{field}', {value}, printf('%015d-%010d-%s', millis, counter, node_id), seq \
+FROM todos JOIN sync_rows ON sync_rows.todo_id = todos.id, sync_state WHERE true \
+ON CONFLICT (sync_id, field) DO NOTHING"
+            ),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+fn change_from_row(row: &Row) -> Result<Change> {
+    let field: String = row.get(1)?;
+    let field = match field.as_str() {
+        "task" => TodoField::Task(row.get(2)?),
+        "completed" => TodoField::Completed(optional_timestamp(row, 2)?),
+        "deleted" => TodoField::Deleted,
+        _ => {
+            return Err(rusqlite::Error::FromSqlConversionFailure(
+                1,
+                rusqlite::types::Type::Text,
+                format!("unknown field {}", field).into(),
+            ))
+        }
+    };
+    Ok(Change { sync_id: row.get(0)?, field, hlc: hlc(row, 3)? })
+}
+
+fn hlc(row: &Row, idx: usize) -> Result<Hlc> {
+    let hlc: String = row.get(idx)?;
+    Hlc::parse(&hlc).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, format!("bad clock {}", hlc).into())
+    })
+}
+
+/// Everything known about one todo, from its `sync_fields`.
+#[derive(Default)]
+struct SyncedTodo {
+    task: Option<String>,
+    completed: Option<Option<i64>>,
+    deleted: bool,
+}
+
+/// Change tracking and merging for one user's todos, from [`TodoRepository::sync`].
+///
+/// [`TodoRepository::sync`]: crate::repository::sqlite::todo_repository::TodoRepository::sync
+pub struct TodoSync<'a> {
+    repo: &'a TodoRepository,
+    conn: &'a Connection,
+    user_id: i64,
+}
+
+impl<'a> TodoSync<'a> {
+    pub(crate) fn new(repo: &'a TodoRepository, conn: &'a Connection, user_id: i64) -> TodoSync<'a> {
+        TodoSync { repo, conn, user_id }
+    }
+
+    /// The random id this database stamps its clock readings with.
+    pub fn node_id(&self) -> Result<String> {
+        self.conn.query_row("SELECT node_id FROM sync_state", (), |row| row.get(0))
+    }
+
+    /// The latest value of every field of the user's todos changed after
+    /// `cursor`, in the order they changed here. Start with a cursor of 0 to
+    /// get everything.
+    pub fn changes_since(&self, cursor: i64) -> Result<ChangeSet> {
+        self.repo.refuse_if_encrypted("can't be synced")?;
+        let mut stmt = self.conn.prepare(
+            "SELECT sync_fields.sync_id, field, value, hlc, seq FROM sync_fields \
+JOIN sync_rows ON sync_rows.sync_id = sync_fields.sync_id \
+WHERE seq > ?1 AND sync_rows.user_id = ?2 ORDER BY seq, sync_fields.sync_id, field",
+        )?;
+        let mut rows = stmt.query(params![cursor, self.user_id])?;
+        let mut changes = Vec::new();
+        let mut last = cursor;
+        while let Some(row) = rows.next()? {
+            changes.push(change_from_row(row)?);
+            last = row.get(4)?;
+        }
+        Ok(ChangeSet { changes, cursor: last })
+    }
+
+    /// Merge changes from another database into the user's todos, returning
+    /// how many of them won over what was here. Applying the same changes
+    /// twice does nothing, and changes to other users' todos are ignored.
+    ///
+    /// The todos written are announced to the repository's subscribers once
+    /// the whole merge has committed.
+    pub fn apply_changes(&self, changes: &[Change]) -> Result<usize> {
+        self.repo.refuse_if_encrypted("can't be synced")?;
+        self.repo.transaction(|| {
+            self.conn.execute("UPDATE sync_state SET applying = 1", ())?;
+            let mut touched = BTreeSet::new();
+            let mut won = 0;
+            for change in changes {
+                let owner: Option<Option<i64>> = self
+                    .conn
+                    .query_row("SELECT user_id FROM sync_rows WHERE sync_id = ?1", params![change.sync_id], |row| row.get(0))
+                    .optional()?;
+                if owner.is_some_and(|owner| owner != Some(self.user_id)) {
+                    continue;
+                }
+                self.receive(&change.hlc)?;
+                let current: Option<Hlc> = self
+                    .conn
+                    .query_row(
+                        "SELECT hlc FROM sync_fields WHERE sync_id = ?1 AND field = ?2",
+                        params![change.sync_id, change.field.name()],
+                        |row| hlc(row, 0),
+                    )
+                    .optional()?;
+                if current.is_some_and(|current| current >= change.hlc) {
+                    continue;
+                }
+                let value = match &change.field {
+                    TodoField::Task(task) => Some(rusqlite::types::Value::Text(task.clone())),
+                    TodoField::Completed(completed) => completed.map(|completed| rusqlite::types::Value::Integer(completed.timestamp())),
+                    TodoField::Deleted => Some(rusqlite::types::Value::Integer(1)),
+                };
+                self.conn.execute("UPDATE sync_state SET seq = seq + 1", ())?;
+                self.conn.execute(
+                    "INSERT INTO sync_fields (sync_id, field, value, hlc, seq) \
+SELECT ?1, ?2, ?3, ?4, seq FROM sync_state WHERE true \
+ON CONFLICT (sync_id, field) DO UPDATE SET value = excluded.value, hlc = excluded.hlc, seq = excluded.seq",
+                    params![change.sync_id, change.field.name(), value, change.hlc.to_string()],
+                )?;
+                self.conn.execute(
+                    "INSERT OR IGNORE INTO sync_rows (sync_id, user_id) VALUES (?1, ?2)",
+                    params![change.sync_id, self.user_id],
+                )?;
+                touched.insert(change.sync_id.as_str());
+                won += 1;
+            }
+            for sync_id in &touched {
+                self.reconcile(sync_id)?;
+            }
+            self.conn.execute("UPDATE sync_state SET applying = 0", ())?;
+            Ok(won)
+        })
+    }
+
+    /// Move the clock past a remote reading, so later local writes win over it.
+    fn receive(&self, remote: &Hlc) -> Result<()> {
+        self.conn.execute(
+            "UPDATE sync_state SET counter = CASE WHEN ?1 > millis THEN ?2 WHEN ?1 = millis THEN MAX(counter, ?2) ELSE counter END, \
+millis = MAX(millis, ?1)",
+            params![remote.millis, remote.counter],
+        )?;
+        Ok(())
+    }
+
+    /// Bring the local todo in line with its synced fields: create it for
+    /// the user once its task is known, update it, or delete it.
+    fn reconcile(&self, sync_id: &str) -> Result<()> {
+        let mut synced = SyncedTodo::default();
+        let mut stmt = self.conn.prepare("SELECT field, value FROM sync_fields WHERE sync_id = ?1")?;
+        let mut rows = stmt.query(params![sync_id])?;
+        while let Some(row) = rows.next()? {
+            match row.get::<_, String>(0)?.as_str() {
+                "task" => synced.task = row.get(1)?,
+                "completed" => synced.completed = Some(row.get(1)?),
+                "deleted" => synced.deleted = true,
+                _ => {}
+            }
+        }
+        let todo_id: Option<i64> =
+            self.conn.query_row("SELECT todo_id FROM sync_rows WHERE sync_id = ?1", params![sync_id], |row| row.get(0))?;
+        let completed = synced.completed.flatten();
+        match (todo_id, synced) {
+            (Some(todo_id), SyncedTodo { deleted: true, .. }) => {
+                let deleted = delete_todo_rows(self.conn, &todo_id)?;
+                self.conn.execute("UPDATE sync_rows SET todo_id = NULL WHERE sync_id = ?1", params![sync_id])?;
+                self.repo.notify_written(deleted, todo_id, EventKind::Deleted);
+            }
+            (_, SyncedTodo { deleted: true, .. }) => {}
+            (Some(todo_id), synced) => {
+                let was_completed: bool =
+                    self.conn.query_row("SELECT completed FROM todos WHERE id = ?1", params![todo_id], |row| row.get(0))?;
+                let updated = self.conn.execute(
+                    "UPDATE todos SET version = version + 1, task = COALESCE(?1, task), \
+completed = CASE WHEN ?2 THEN ?3 IS NOT NULL ELSE completed END, \
+completed_datetime = CASE WHEN ?2 THEN ?3 ELSE completed_datetime END \
+WHERE id = ?4 AND (task IS NOT COALESCE(?1, task) OR (?2 AND completed_datetime IS NOT ?3))",
+                    params![synced.task, synced.completed.is_some(), completed, todo_id],
+                )?;
+                let kind = match (synced.completed.is_some(), was_completed, completed.is_some()) {
+                    (true, false, true) => EventKind::Completed,
+                    (true, true, false) => EventKind::Uncompleted,
+                    _ => EventKind::Updated,
+                };
+                self.repo.notify_written(updated, todo_id, kind);
+            }
+            (None, SyncedTodo { task: Some(task), .. }) => {
+                self.conn.execute(
+                    "INSERT INTO todos (user_id, task, completed, completed_datetime) VALUES (?1, ?2, ?3 IS NOT NULL, ?3)",
+                    params![self.user_id, task, completed],
+                )?;
+                let todo_id = self.conn.last_insert_rowid();
+                self.conn.execute("UPDATE sync_rows SET todo_id = ?1 WHERE sync_id = ?2", params![todo_id, sync_id])?;
+                self.repo.notify_written(1, todo_id, EventKind::Created);
+            }
+            // not enough of it has arrived yet
+            (None, _) => {}
+        }
+        Ok(())
+    }
+}
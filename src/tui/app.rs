@@ -0,0 +1,265 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use rusqlite::Result;
+
+use crate::models::{TodoItem, TodoItemDTO};
+use crate::repository::Repository;
+use crate::repository::sqlite::todo_repository::TodoRepository;
+
+/// Which todos are shown based on their completion state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusFilter {
+    All,
+    Open,
+    Completed,
+}
+
+impl StatusFilter {
+    /// Cycle All -> Open -> Completed -> All.
+    pub fn next(self) -> StatusFilter {
+        match self {
+            StatusFilter::All => StatusFilter::Open,
+            StatusFilter::Open => StatusFilter::Completed,
+            StatusFilter::Completed => StatusFilter::All,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusFilter::All => "all",
+            StatusFilter::Open => "open",
+            StatusFilter::Completed => "completed",
+        }
+    }
+
+    fn matches(self, todo: &TodoItem) -> bool {
+        match self {
+            StatusFilter::All => true,
+            StatusFilter::Open => !todo.completed,
+            StatusFilter::Completed => todo.completed,
+        }
+    }
+}
+
+/// What keystrokes are currently being applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Navigating the list.
+    Normal,
+    /// Typing the task for a new todo.
+    Adding,
+    /// Rewriting the task of the selected todo.
+    Editing,
+    /// Typing the text filter.
+    Filtering,
+}
+
+/// State of the terminal UI for a single user's todos.
+///
+/// All writes go straight through the wrapped `TodoRepository`, after which
+/// the list is reloaded so the screen always reflects what is in the database.
+pub struct App {
+    repo: TodoRepository,
+    user_id: i64,
+    todos: Vec<TodoItem>,
+    pub selected: usize,
+    pub mode: Mode,
+    pub input: String,
+    pub filter: String,
+    pub status_filter: StatusFilter,
+    pub message: Option<String>,
+    pub should_quit: bool,
+}
+
+impl App {
+    /// Create the app for `user_id` and load their todos.
+    pub fn new(repo: TodoRepository, user_id: i64) -> Result<App> {
+        let mut app = App {
+            repo,
+            user_id,
+            todos: Vec::new(),
+            selected: 0,
+            mode: Mode::Normal,
+            input: String::new(),
+            filter: String::new(),
+            status_filter: StatusFilter::All,
+            message: None,
+            should_quit: false,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    pub fn user_id(&self) -> i64 {
+        self.user_id
+    }
+
+    pub fn repository(&self) -> &TodoRepository {
+        &self.repo
+    }
+
+    /// Re-read the user's todos from the repository.
+    pub fn reload(&mut self) -> Result<()> {
+        self.todos = self.repo.get_user_todos(&self.user_id)?;
+        self.clamp_selection();
+        Ok(())
+    }
+
+    /// The todos that pass both the status filter and the text filter, in list order.
+    pub fn visible_todos(&self) -> Vec<&TodoItem> {
+        let needle = self.filter.to_lowercase();
+        self.todos
+            .iter()
+            .filter(|todo| self.status_filter.matches(todo))
+            .filter(|todo| needle.is_empty() || todo.task.to_lowercase().contains(&needle))
+            .collect()
+    }
+
+    pub fn selected_todo(&self) -> Option<&TodoItem> {
+        self.visible_todos().get(self.selected).copied()
+    }
+
+    /// Apply a single key press.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.should_quit = true;
+            return Ok(());
+        }
+        match self.mode {
+            Mode::Normal => self.handle_normal_key(key),
+            Mode::Adding | Mode::Editing => self.handle_input_key(key),
+            Mode::Filtering => {
+                self.handle_filter_key(key);
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent) -> Result<()> {
+        self.message = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+            KeyCode::Char('j') | KeyCode::Down => self.select_next(),
+            KeyCode::Char('k') | KeyCode::Up => self.select_previous(),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => {
+                self.selected = self.visible_todos().len().saturating_sub(1)
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => self.toggle_selected()?,
+            KeyCode::Char('d') => self.delete_selected()?,
+            KeyCode::Char('e') => {
+                if let Some(task) = self.selected_todo().map(|todo| todo.task.clone()) {
+                    self.input = task;
+                    self.mode = Mode::Editing;
+                }
+            }
+            KeyCode::Char('a') => {
+                self.input.clear();
+                self.mode = Mode::Adding;
+            }
+            KeyCode::Char('/') => self.mode = Mode::Filtering,
+            KeyCode::Tab => {
+                self.status_filter = self.status_filter.next();
+                self.clamp_selection();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_input_key(&mut self, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Esc => {
+                self.input.clear();
+                self.mode = Mode::Normal;
+            }
+            KeyCode::Enter => {
+                let task = self.input.trim().to_string();
+                if task.is_empty() {
+                    self.message = Some("A task can't be empty, no matter how little you plan to do it".to_string());
+                } else {
+                    self.commit_input(task)?;
+                }
+                self.input.clear();
+                self.mode = Mode::Normal;
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_filter_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.mode = Mode::Normal;
+            }
+            KeyCode::Enter => self.mode = Mode::Normal,
+            KeyCode::Backspace => {
+                self.filter.pop();
+            }
+            KeyCode::Char(c) => self.filter.push(c),
+            _ => {}
+        }
+        self.clamp_selection();
+    }
+
+    fn commit_input(&mut self, task: String) -> Result<()> {
+        let dto = TodoItemDTO { user_id: self.user_id, task };
+        match self.mode {
+            Mode::Adding => {
+                self.repo.save_new_item(&dto)?;
+                self.reload()?;
+                self.selected = self.visible_todos().len().saturating_sub(1);
+            }
+            Mode::Editing => {
+                if let Some(id) = self.selected_todo().map(|todo| todo.id) {
+                    self.repo.update_item(&id, &dto)?;
+                    self.reload()?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn toggle_selected(&mut self) -> Result<()> {
+        if let Some((id, completed)) = self.selected_todo().map(|todo| (todo.id, todo.completed)) {
+            if completed {
+                self.repo.uncomplete_todo_item(&id)?;
+            } else {
+                self.repo.complete_todo_item(&id)?;
+            }
+            self.reload()?;
+        }
+        Ok(())
+    }
+
+    fn delete_selected(&mut self) -> Result<()> {
+        if let Some(id) = self.selected_todo().map(|todo| todo.id) {
+            self.repo.delete_item_by_id(&id)?;
+            self.reload()?;
+        }
+        Ok(())
+    }
+
+    fn select_next(&mut self) {
+        if self.selected + 1 < self.visible_todos().len() {
+            self.selected += 1;
+        }
+    }
+
+    fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.visible_todos().len();
+        if self.selected >= len {
+            self.selected = len.saturating_sub(1);
+        }
+    }
+}
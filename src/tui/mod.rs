@@ -0,0 +1,36 @@
+//! Full-screen terminal UI for browsing and editing a user's todos.
+//!
+//! The UI is split into [`App`], which owns the state and applies key presses,
+//! and [`draw`], which renders that state. Because [`run`] is generic over the
+//! ratatui backend, the whole thing can be driven headlessly with a
+//! `TestBackend` that renders into a buffer.
+
+use std::io;
+
+use ratatui::backend::Backend;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use ratatui::Terminal;
+
+pub use app::{App, Mode, StatusFilter};
+pub use ui::draw;
+
+mod app;
+mod ui;
+
+/// Draw and handle crossterm key events until the user quits.
+///
+/// Repository errors are shown on the status line rather than ending the session.
+pub fn run<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
+    while !app.should_quit {
+        terminal.draw(|frame| draw(frame, app))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Err(e) = app.handle_key(key) {
+                app.message = Some(e.to_string());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+use crate::tui::app::{App, Mode};
+
+/// Render the whole screen: the todo list, the input line and a help/status line.
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [list_area, input_area, help_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let items: Vec<ListItem> = app
+        .visible_todos()
+        .into_iter()
+        .map(|todo| {
+            let checkbox = if todo.completed { "[x] " } else { "[ ] " };
+            let style = if todo.completed {
+                Style::default().add_modifier(Modifier::CROSSED_OUT)
+            } else {
+                Style::default()
+            };
+            ListItem::new(Line::from(vec![Span::raw(checkbox), Span::styled(todo.task.clone(), style)]))
+        })
+        .collect();
+
+    let mut title = format!(" Things user {} won't do ({}) ", app.user_id(), app.status_filter.label());
+    if !app.filter.is_empty() {
+        title.push_str(&format!("/{}/ ", app.filter));
+    }
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state = ListState::default();
+    if !app.visible_todos().is_empty() {
+        state.select(Some(app.selected));
+    }
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let (input_title, input_text) = match app.mode {
+        Mode::Adding => (" New task ", app.input.as_str()),
+        Mode::Editing => (" Edit task ", app.input.as_str()),
+        Mode::Filtering => (" Filter ", app.filter.as_str()),
+        Mode::Normal => ("", ""),
+    };
+    let input = Paragraph::new(input_text).block(Block::default().borders(Borders::ALL).title(input_title));
+    frame.render_widget(input, input_area);
+    if app.mode != Mode::Normal {
+        let x = input_area.x + 1 + input_text.chars().count() as u16;
+        frame.set_cursor_position((x.min(input_area.right().saturating_sub(2)), input_area.y + 1));
+    }
+
+    let help = match (&app.message, app.mode) {
+        (Some(message), _) => message.as_str(),
+        (None, Mode::Normal) => "j/k move  space toggle  a add  e edit  d delete  / filter  tab status  q quit",
+        (None, _) => "enter save  esc cancel",
+    };
+    frame.render_widget(Paragraph::new(help), help_area);
+}
@@ -0,0 +1,301 @@
+//! Per-user undo and redo of todo writes, persisted in the database.
+//!
+//! Writes made through [`UserTodos`] go through a [`TodoHistory`] for the
+//! principal, which logs each one in the `todo_history` table with snapshots
+//! of the todo before and after, so [`undo`](TodoHistory::undo) and
+//! [`redo`](TodoHistory::redo) work the same after a restart. Making a new
+//! write drops anything that could be redone.
+//!
+//! Undo and redo put the whole todo back as it was, version included, so a
+//! client that read it at that version is up to date again. They refuse to
+//! overwrite changes made outside the history: if the todo isn't at the
+//! version the write left it at, they fail with [`RepositoryError::Conflict`]
+//! (with `expected` 0 when the todo should not exist) or, if it is gone or
+//! is no longer the same todo, [`RepositoryError::NotFound`]. Such a write
+//! can be [`discard`](TodoHistory::discard)ed to get at the ones before it.
+//!
+//! [`UserTodos`]: crate::auth::UserTodos
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params, Row};
+
+use crate::models::{TodoItem, TodoItemDTO};
+use crate::repository::sqlite::todo_repository::TodoRepository;
+use crate::repository::sqlite::timestamp;
+use crate::repository::{EventKind, Repository, RepositoryError};
+
+type Result<T> = std::result::Result<T, RepositoryError>;
+
+/// How many writes each user can undo; older ones are forgotten.
+pub const MAX_HISTORY: u32 = 100;
+
+/// One logged write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub user_id: i64,
+    pub todo_id: i64,
+    pub operation: EventKind,
+    /// Whether the write is currently undone, and so can be redone.
+    pub undone: bool,
+    pub created_datetime: DateTime<Utc>,
+}
+
+const ENTRY_COLUMNS: &str = "id, user_id, todo_id, operation, undone, created_datetime";
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    let operation: String = row.get(3)?;
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        todo_id: row.get(2)?,
+        operation: EventKind::parse(&operation).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, format!("unknown operation {}", operation).into())
+        })?,
+        undone: row.get(4)?,
+        created_datetime: timestamp(row, 5)?,
+    })
+}
+
+/// Create the `todo_history` table; called by [`TodoRepository::new`].
+///
+/// `before_state` and `after_state` are JSON snapshots of the todo from
+/// [`TodoRepository::snapshot`], NULL when it didn't exist.
+pub(crate) fn create_history_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS todo_history (\
+id INTEGER PRIMARY KEY,\
+user_id INTEGER NOT NULL,\
+todo_id INTEGER NOT NULL,\
+operation TEXT NOT NULL,\
+before_state TEXT,\
+after_state TEXT,\
+undone INTEGER NOT NULL DEFAULT 0,\
+created_datetime INTEGER DEFAULT (strftime('%s', 'now'))\
+)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// A logged write along with what undoing and redoing it needs.
+struct Step {
+    entry: HistoryEntry,
+    before_state: Option<String>,
+    after_state: Option<String>,
+}
+
+/// The event for putting back what `operation` changed.
+fn inverse(operation: EventKind) -> EventKind {
+    match operation {
+        EventKind::Created => EventKind::Deleted,
+        EventKind::Updated => EventKind::Updated,
+        EventKind::Deleted => EventKind::Created,
+        EventKind::Completed => EventKind::Uncompleted,
+        EventKind::Uncompleted => EventKind::Completed,
+    }
+}
+
+/// Undoable todo writes for one user. Only undo and redo check access, with
+/// the check they are given, so it is only handed out to
+/// [`UserTodos`](crate::auth::UserTodos).
+pub struct TodoHistory<'a> {
+    repo: &'a TodoRepository,
+    conn: &'a Connection,
+    user_id: i64,
+}
+
+impl<'a> TodoHistory<'a> {
+    pub(crate) fn new(repo: &'a TodoRepository, conn: &'a Connection, user_id: i64) -> TodoHistory<'a> {
+        TodoHistory { repo, conn, user_id }
+    }
+
+    pub fn save_new_item(&self, todo_dto: &TodoItemDTO) -> Result<i64> {
+        self.repo.transaction(|| {
+            let id = self.repo.save_new_item(todo_dto)?;
+            self.record(id, EventKind::Created, None)?;
+            Ok(id)
+        })
+    }
+
+    pub fn update_item(&self, id: &i64, todo_dto: &TodoItemDTO) -> Result<usize> {
+        self.logged(id, EventKind::Updated, || Ok(self.repo.update_item(id, todo_dto)?))
+    }
+
+    /// Like [`update_item`](TodoHistory::update_item), failing with
+    /// [`RepositoryError::Conflict`] if the todo is no longer at `expected_version`.
+    pub fn update_item_if_version(&self, id: &i64, todo_dto: &TodoItemDTO, expected_version: i64) -> Result<usize> {
+        self.logged(id, EventKind::Updated, || self.repo.update_item_if_version(id, todo_dto, expected_version))
+    }
+
+    pub fn delete_item_by_id(&self, id: &i64) -> Result<usize> {
+        self.logged(id, EventKind::Deleted, || Ok(self.repo.delete_item_by_id(id)?))
+    }
+
+    pub fn complete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.logged(id, EventKind::Completed, || Ok(self.repo.complete_todo_item(id)?))
+    }
+
+    pub fn uncomplete_todo_item(&self, id: &i64) -> Result<usize> {
+        self.logged(id, EventKind::Uncompleted, || Ok(self.repo.uncomplete_todo_item(id)?))
+    }
+
+    /// Revert the user's latest write that isn't undone yet, returning it,
+    /// or `None` if there is nothing to undo.
+    ///
+    /// `authorize` is given the write and the todo as it is now, or as it
+    /// was if undoing brings it back, and can refuse with an error.
+    ///
+    /// Undoing a delete brings the todo back with the same id, but not its
+    /// delegation log, excuses or refusals.
+    pub fn undo<E>(
+        &self,
+        authorize: impl FnOnce(&HistoryEntry, &TodoItem) -> std::result::Result<(), E>,
+    ) -> std::result::Result<Option<HistoryEntry>, E>
+    where
+        E: From<RepositoryError> + From<rusqlite::Error>,
+    {
+        self.repo.transaction(|| {
+            let Some(step) = self.step("undone = 0 ORDER BY id DESC")? else {
+                return Ok(None);
+            };
+            self.check_state(&step.entry.todo_id, step.after_state.as_deref())?;
+            authorize(&step.entry, &self.todo(&step)?)?;
+            let operation = inverse(step.entry.operation);
+            self.repo.restore_item(&step.entry.todo_id, step.before_state.as_deref(), operation)?;
+            Ok(self.mark(step.entry, true)?)
+        })
+    }
+
+    /// Repeat the user's most recently undone write, returning it, or `None`
+    /// if there is nothing to redo. `authorize` is as for [`undo`](TodoHistory::undo).
+    pub fn redo<E>(
+        &self,
+        authorize: impl FnOnce(&HistoryEntry, &TodoItem) -> std::result::Result<(), E>,
+    ) -> std::result::Result<Option<HistoryEntry>, E>
+    where
+        E: From<RepositoryError> + From<rusqlite::Error>,
+    {
+        self.repo.transaction(|| {
+            let Some(step) = self.step("undone = 1 ORDER BY id")? else {
+                return Ok(None);
+            };
+            self.check_state(&step.entry.todo_id, step.before_state.as_deref())?;
+            authorize(&step.entry, &self.todo(&step)?)?;
+            self.repo.restore_item(&step.entry.todo_id, step.after_state.as_deref(), step.entry.operation)?;
+            Ok(self.mark(step.entry, false)?)
+        })
+    }
+
+    /// Forget the write [`undo`](TodoHistory::undo) would revert next,
+    /// returning it, or `None` if there is nothing to undo. For writes that
+    /// can no longer be undone, which would otherwise block the older ones.
+    pub fn discard(&self) -> Result<Option<HistoryEntry>> {
+        let Some(step) = self.step("undone = 0 ORDER BY id DESC")? else {
+            return Ok(None);
+        };
+        self.conn.execute("DELETE FROM todo_history WHERE id = ?1", params![step.entry.id])?;
+        Ok(Some(step.entry))
+    }
+
+    /// The user's logged writes, newest first.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM todo_history WHERE user_id = ?1 ORDER BY id DESC",
+            ENTRY_COLUMNS
+        ))?;
+        let entry_iter = stmt.query_map(params![self.user_id], entry_from_row)?;
+        let mut entries = Vec::new();
+        for entry in entry_iter {
+            entries.push(entry?);
+        }
+        Ok(entries)
+    }
+
+    /// Run `write` on todo `id` and log it if it changed anything, all in
+    /// one transaction.
+    fn logged(&self, id: &i64, operation: EventKind, write: impl FnOnce() -> Result<usize>) -> Result<usize> {
+        self.repo.transaction(|| {
+            let before_state = self.repo.snapshot(id)?;
+            let written = write()?;
+            if written > 0 {
+                self.record(*id, operation, before_state)?;
+            }
+            Ok(written)
+        })
+    }
+
+    /// Log a write to `todo_id`, which was in `before_state` beforehand,
+    /// dropping whatever could be redone and anything past [`MAX_HISTORY`].
+    /// Called within the write's transaction.
+    fn record(&self, todo_id: i64, operation: EventKind, before_state: Option<String>) -> Result<()> {
+        let after_state = self.repo.snapshot(&todo_id)?;
+        self.conn.execute("DELETE FROM todo_history WHERE user_id = ?1 AND undone = 1", params![self.user_id])?;
+        self.conn.execute(
+            "INSERT INTO todo_history (user_id, todo_id, operation, before_state, after_state) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![self.user_id, todo_id, operation.as_str(), before_state, after_state],
+        )?;
+        self.conn.execute(
+            "DELETE FROM todo_history WHERE user_id = ?1 AND id NOT IN \
+(SELECT id FROM todo_history WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![self.user_id, MAX_HISTORY],
+        )?;
+        Ok(())
+    }
+
+    /// The first of the user's entries matching `condition`, which includes the ordering.
+    fn step(&self, condition: &str) -> Result<Option<Step>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {}, before_state, after_state FROM todo_history WHERE user_id = ?1 AND {} LIMIT 1",
+                    ENTRY_COLUMNS, condition
+                ),
+                params![self.user_id],
+                |row| {
+                    Ok(Step {
+                        entry: entry_from_row(row)?,
+                        before_state: row.get(6)?,
+                        after_state: row.get(7)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// The todo a step is about: as it was after the write if it existed
+    /// then, or else as it was before.
+    fn todo(&self, step: &Step) -> Result<TodoItem> {
+        let snapshot = step.after_state.as_deref().or(step.before_state.as_deref()).ok_or(RepositoryError::NotFound)?;
+        Ok(self.repo.todo_from_snapshot(snapshot)?)
+    }
+
+    /// Fail unless the todo is the one in the snapshot `expected`, at its
+    /// version, or is missing for `None`. A todo with the same id but another
+    /// owner or creation time is a different todo, and counts as missing.
+    fn check_state(&self, todo_id: &i64, expected: Option<&str>) -> Result<()> {
+        let actual: Option<(i64, bool, Option<i64>)> = self
+            .conn
+            .query_row(
+                "SELECT version, user_id IS json_extract(?2, '$.user_id') \
+AND created_datetime IS json_extract(?2, '$.created_datetime'), json_extract(?2, '$.version') FROM todos WHERE id = ?1",
+                params![todo_id, expected],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        match (expected, actual) {
+            (Some(_), None) | (Some(_), Some((_, false, _))) => Err(RepositoryError::NotFound),
+            (Some(_), Some((actual, true, Some(expected)))) if expected != actual => {
+                Err(RepositoryError::Conflict { expected, actual })
+            }
+            (None, Some((actual, _, _))) => Err(RepositoryError::Conflict { expected: 0, actual }),
+            _ => Ok(()),
+        }
+    }
+
+    fn mark(&self, entry: HistoryEntry, undone: bool) -> Result<Option<HistoryEntry>> {
+        self.conn.execute("UPDATE todo_history SET undone = ?1 WHERE id = ?2", params![undone, entry.id])?;
+        Ok(Some(HistoryEntry { undone, ..entry }))
+    }
+}
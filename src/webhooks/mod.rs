@@ -0,0 +1,144 @@
+//! Sending queued webhook deliveries, with retries and exponential backoff.
+//!
+//! Todo writes queue a delivery per interested webhook in the database (see
+//! [`WebhookRepository`]); a [`WebhookDispatcher`] sends the ones that are
+//! due. Each is a `POST` of the JSON payload with these headers:
+//!
+//! - `X-ToDont-Event`: the event, e.g. `uncompleted`
+//! - `X-ToDont-Delivery`: the delivery id, the same on every retry
+//! - `X-ToDont-Signature`: `sha256=` and the hex HMAC-SHA256 of the body,
+//!   keyed with the webhook's secret (see [`sign`])
+//!
+//! Any 2xx response counts as delivered. Everything else is retried until
+//! the [`RetryPolicy`] runs out of attempts.
+
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::auth::to_hex;
+use crate::models::WebhookDelivery;
+use crate::repository::sqlite::webhook_repository::WebhookRepository;
+use crate::repository::{Repository, RepositoryError};
+
+/// How often, and how far apart, failed deliveries are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first, before giving up.
+    pub max_attempts: i64,
+    /// The wait after the first failure; it doubles after each further one.
+    pub initial_delay: Duration,
+    /// The longest wait between attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 8, initial_delay: Duration::seconds(30), max_delay: Duration::hours(1) }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before the next attempt, after `failures` failed attempts,
+    /// or `None` once there are no attempts left.
+    pub fn next_delay(&self, failures: i64) -> Option<Duration> {
+        if failures >= self.max_attempts {
+            return None;
+        }
+        let doublings = u32::try_from(failures.saturating_sub(1)).unwrap_or(u32::MAX).min(30);
+        Some(self.initial_delay.checked_mul(2_i32.pow(doublings)).map_or(self.max_delay, |delay| delay.min(self.max_delay)))
+    }
+}
+
+/// What one call to [`WebhookDispatcher::dispatch_due`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DispatchReport {
+    pub delivered: usize,
+    /// Failed, but will be tried again.
+    pub retrying: usize,
+    /// Failed for the last time.
+    pub failed: usize,
+}
+
+/// The `X-ToDont-Signature` header value for `body`.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", to_hex(&mac.finalize().into_bytes()))
+}
+
+/// How long sending one delivery may take.
+const SEND_TIMEOUT_SECONDS: u32 = 10;
+
+/// Sends the due deliveries from a [`WebhookRepository`].
+///
+/// It keeps no state of its own, so call [`dispatch_due`](WebhookDispatcher::dispatch_due)
+/// on whatever schedule suits; anything not delivered stays in the outbox.
+/// Several dispatchers can share a database: each claims its batch before
+/// sending it.
+pub struct WebhookDispatcher<'a> {
+    repo: &'a WebhookRepository,
+    agent: ureq::Agent,
+    policy: RetryPolicy,
+    batch_size: u32,
+}
+
+impl<'a> WebhookDispatcher<'a> {
+    pub fn new(repo: &'a WebhookRepository) -> WebhookDispatcher<'a> {
+        let agent = ureq::AgentBuilder::new().timeout(StdDuration::from_secs(SEND_TIMEOUT_SECONDS.into())).redirects(0).build();
+        WebhookDispatcher { repo, agent, policy: RetryPolicy::default(), batch_size: 100 }
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> WebhookDispatcher<'a> {
+        self.policy = policy;
+        self
+    }
+
+    /// Try every delivery due by `now`, up to one batch, recording how each went.
+    ///
+    /// The batch is claimed for long enough to send all of it; if this
+    /// dispatcher stops partway, the rest are due again after that.
+    pub fn dispatch_due(&self, now: DateTime<Utc>) -> Result<DispatchReport, RepositoryError> {
+        let mut report = DispatchReport::default();
+        let lease = Duration::seconds(i64::from(SEND_TIMEOUT_SECONDS) * i64::from(self.batch_size));
+        for delivery in self.repo.claim_due_deliveries(&now, lease, self.batch_size)? {
+            match self.send(&delivery) {
+                Ok(()) => {
+                    self.repo.mark_delivered(&delivery.id, &now)?;
+                    report.delivered += 1;
+                }
+                Err(error) => match self.policy.next_delay(delivery.attempts + 1) {
+                    Some(delay) => {
+                        self.repo.mark_failed(&delivery.id, &error, &now, Some(now + delay))?;
+                        report.retrying += 1;
+                    }
+                    None => {
+                        self.repo.mark_failed(&delivery.id, &error, &now, None)?;
+                        report.failed += 1;
+                    }
+                },
+            }
+        }
+        Ok(report)
+    }
+
+    fn send(&self, delivery: &WebhookDelivery) -> Result<(), String> {
+        let webhook = self.repo.select_item_by_id(&delivery.webhook_id).map_err(|e| e.to_string())?;
+        let response = self
+            .agent
+            .post(&webhook.url)
+            .set("Content-Type", "application/json")
+            .set("X-ToDont-Event", delivery.event.as_str())
+            .set("X-ToDont-Delivery", &delivery.id.to_string())
+            .set("X-ToDont-Signature", &sign(&webhook.secret, &delivery.payload))
+            .send_string(&delivery.payload);
+        match response {
+            Ok(response) if (200..300).contains(&response.status()) => Ok(()),
+            Ok(response) => Err(format!("HTTP {}", response.status())),
+            Err(ureq::Error::Status(status, _)) => Err(format!("HTTP {}", status)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,466 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use chrono::{Duration, Utc};
+
+    use to_dont::auth::{AuthError, AuthService};
+    use to_dont::models::{OrgRole, RefusalReason, Role, Scope, ShareLevel, UserDTO};
+    use to_dont::repository::{Repository, RepositoryError};
+    use to_dont::repository::sqlite::organization_repository::OrganizationRepository;
+    use to_dont::repository::sqlite::todo_list_repository::TodoListRepository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+
+    fn taylor() -> UserDTO {
+        UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_register_and_authenticate() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+
+        // register a user with a password
+        let user_id = auth.register(&taylor(), "correct horse")?;
+
+        // the right password yields a session for that user
+        let session = auth.authenticate("TLowery@fakemail.com", "correct horse")?;
+        assert_eq!(session.principal.user_id, user_id);
+
+        // and the token resolves back to the same principal
+        assert_eq!(auth.verify_session(&session.token)?, session.principal);
+
+        // wrong passwords and unknown emails are both just invalid credentials
+        assert!(matches!(auth.authenticate("tlowery@fakemail.com", "wrong horse"), Err(AuthError::InvalidCredentials)));
+        assert!(matches!(auth.authenticate("nobody@fakemail.com", "correct horse"), Err(AuthError::InvalidCredentials)));
+
+        // users created without a password can't log in
+        let other_user = UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() };
+        auth.users().save_new_item(&other_user)?;
+        assert!(matches!(auth.authenticate("2hott2tott@fakemail.com", ""), Err(AuthError::InvalidCredentials)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_validates() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+
+        // short passwords are rejected, and no user is created
+        match auth.register(&taylor(), "short") {
+            Err(AuthError::Repository(RepositoryError::Validation(e))) => assert!(e.has_field("password")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        assert!(auth.users().find_by_email("tlowery@fakemail.com")?.is_none());
+
+        // duplicate emails are rejected as usual
+        auth.register(&taylor(), "correct horse")?;
+        assert!(matches!(
+            auth.register(&taylor(), "correct horse"),
+            Err(AuthError::Repository(RepositoryError::Validation(_)))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sessions_expire_and_can_be_revoked() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        auth.register(&taylor(), "correct horse")?;
+
+        // revoking a session invalidates only that token
+        let session = auth.authenticate("tlowery@fakemail.com", "correct horse")?;
+        let other_session = auth.authenticate("tlowery@fakemail.com", "correct horse")?;
+        auth.revoke_session(&session.token)?;
+        assert!(matches!(auth.verify_session(&session.token), Err(AuthError::InvalidToken)));
+        auth.verify_session(&other_session.token)?;
+
+        // changing the password logs out everywhere, and the old password stops working
+        auth.set_password(&session.principal.user_id, "battery staple")?;
+        assert!(matches!(auth.verify_session(&other_session.token), Err(AuthError::InvalidToken)));
+        assert!(matches!(auth.authenticate("tlowery@fakemail.com", "correct horse"), Err(AuthError::InvalidCredentials)));
+        auth.authenticate("tlowery@fakemail.com", "battery staple")?;
+
+        // sessions past their expiry are rejected
+        let auth = auth.with_session_ttl(Duration::seconds(-1));
+        let expired = auth.authenticate("tlowery@fakemail.com", "battery staple")?;
+        assert!(matches!(auth.verify_session(&expired.token), Err(AuthError::InvalidToken)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deleted_accounts_lose_their_sessions_and_tokens() -> Result<(), Box<dyn Error>> {
+        // users and sessions share a database, in memory as on disk
+        let auth = AuthService::new(None)?;
+        auth.register(&UserDTO { email: "first@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let user_id = auth.register(&taylor(), "correct horse")?;
+        let session = auth.authenticate("tlowery@fakemail.com", "correct horse")?;
+        let token = auth.mint_api_token(&user_id, "script", &[Scope::ReadTodos])?.token;
+
+        auth.users().delete_account(&user_id)?;
+        assert!(matches!(auth.verify_session(&session.token), Err(AuthError::InvalidToken)));
+        assert!(matches!(auth.verify_api_token(&token, Scope::ReadTodos), Err(AuthError::InvalidToken)));
+
+        // whoever signs up next gets a new id, not the deleted one
+        let next_id = auth.register(&UserDTO { email: "next@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        assert!(next_id > user_id);
+        assert!(matches!(auth.verify_session(&session.token), Err(AuthError::InvalidToken)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_first_admin() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        auth.register(&UserDTO { email: "first@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+
+        // the first admin can log in as one
+        let admin_id = auth.create_first_admin(&taylor(), "correct horse")?;
+        let session = auth.authenticate("tlowery@fakemail.com", "correct horse")?;
+        assert_eq!((session.principal.user_id, session.principal.role), (admin_id, Role::Admin));
+
+        // after that, admins are made by other admins
+        let other = UserDTO { email: "other@fakemail.com".to_string(), ..taylor() };
+        assert!(matches!(auth.create_first_admin(&other, "correct horse"), Err(AuthError::Forbidden)));
+        assert!(auth.users().find_by_email("other@fakemail.com")?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_principal_only_sees_own_todos() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let todo_repo = TodoRepository::new(None)?;
+        auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+
+        // todos are created for the principal
+        let todo_id = taylor.todos(&todo_repo).create("Water the plants")?;
+        assert_eq!(taylor.todos(&todo_repo).get(&todo_id)?.user_id, taylor.user_id);
+        assert_eq!(taylor.todos(&todo_repo).list()?.len(), 1);
+
+        // and are off limits to everyone else
+        assert!(tater.todos(&todo_repo).list()?.is_empty());
+        assert!(matches!(tater.todos(&todo_repo).list_for(&taylor.user_id), Err(AuthError::Forbidden)));
+        assert!(matches!(tater.todos(&todo_repo).get(&todo_id), Err(AuthError::Forbidden)));
+        assert!(matches!(tater.todos(&todo_repo).complete(&todo_id), Err(AuthError::Forbidden)));
+        assert!(matches!(tater.todos(&todo_repo).delete(&todo_id), Err(AuthError::Forbidden)));
+        assert!(matches!(tater.todos(&todo_repo).create_for(&taylor.user_id, "Nap"), Err(AuthError::Forbidden)));
+
+        // missing ids are still just not found
+        assert!(matches!(tater.todos(&todo_repo).get(&42), Err(AuthError::Repository(RepositoryError::NotFound))));
+
+        // while the owner can work with them
+        taylor.todos(&todo_repo).complete(&todo_id)?;
+        assert!(taylor.todos(&todo_repo).get(&todo_id)?.completed);
+        taylor.todos(&todo_repo).update(&todo_id, "Water the plants again")?;
+        assert_eq!(taylor.todos(&todo_repo).delete(&todo_id)?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_api_tokens_are_scoped_and_revocable() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let user_id = auth.register(&taylor(), "correct horse")?;
+
+        // mint a token that can only read todos
+        let minted = auth.mint_api_token(&user_id, "ci", &[Scope::ReadTodos])?;
+        assert!(minted.api_token.last_used_datetime.is_none());
+
+        // it verifies for that scope only, and records when it was used
+        let principal = auth.verify_api_token(&minted.token, Scope::ReadTodos)?;
+        assert_eq!(principal.user_id, user_id);
+        assert!(matches!(
+            auth.verify_api_token(&minted.token, Scope::WriteTodos),
+            Err(AuthError::MissingScope(Scope::WriteTodos))
+        ));
+        assert!(auth.list_api_tokens(&user_id)?[0].last_used_datetime.is_some());
+
+        // bearer verification accepts both sessions and API tokens
+        let session = auth.authenticate("tlowery@fakemail.com", "correct horse")?;
+        assert!(auth.verify_bearer(&session.token)?.has_scope(Scope::ManageUsers));
+        assert!(!auth.verify_bearer(&minted.token)?.has_scope(Scope::WriteTodos));
+
+        // tokens need a name and at least one scope, and a real user
+        assert!(matches!(auth.mint_api_token(&user_id, " ", &[]), Err(AuthError::Repository(RepositoryError::Validation(_)))));
+        assert!(matches!(auth.mint_api_token(&42, "ci", &Scope::ALL), Err(AuthError::Repository(RepositoryError::NotFound))));
+
+        // revoked tokens stop working, and can't be revoked twice
+        auth.revoke_api_token(&user_id, &minted.api_token.id)?;
+        assert!(matches!(auth.verify_bearer(&minted.token), Err(AuthError::InvalidToken)));
+        assert!(auth.revoke_api_token(&user_id, &minted.api_token.id).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_admins_can_access_every_todo() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let todo_repo = TodoRepository::new(None)?;
+        let admin_id = auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+        let todo_id = tater.todos(&todo_repo).create("Nap")?;
+
+        // everyone starts as a regular user, who can't hand out roles
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        assert_eq!(taylor.role, Role::User);
+        assert!(matches!(taylor.todos(&todo_repo).get(&todo_id), Err(AuthError::Forbidden)));
+        assert!(matches!(auth.set_role(&taylor, &admin_id, Role::Admin), Err(AuthError::Forbidden)));
+
+        // once promoted, new sessions carry the admin role
+        auth.users().set_role(&admin_id, Role::Admin)?;
+        let admin = auth.verify_session(&auth.authenticate("tlowery@fakemail.com", "correct horse")?.token)?;
+        assert!(admin.is_admin());
+
+        // and can work with other users' todos, which keep their owner
+        assert_eq!(admin.todos(&todo_repo).list_for(&tater.user_id)?.len(), 1);
+        admin.todos(&todo_repo).update(&todo_id, "Nap longer")?;
+        assert_eq!(tater.todos(&todo_repo).get(&todo_id)?.user_id, tater.user_id);
+        let created_id = admin.todos(&todo_repo).create_for(&tater.user_id, "Nap again")?;
+        assert_eq!(tater.todos(&todo_repo).list()?.len(), 2);
+        admin.todos(&todo_repo).delete(&created_id)?;
+
+        // admins can grant roles, but an API token still needs the right scope
+        auth.set_role(&admin, &tater.user_id, Role::Admin)?;
+        let minted = auth.mint_api_token(&admin_id, "ci", &[Scope::ReadTodos])?;
+        let bot = auth.verify_bearer(&minted.token)?;
+        assert!(bot.is_admin());
+        assert!(matches!(bot.todos(&todo_repo).complete(&todo_id), Err(AuthError::MissingScope(Scope::WriteTodos))));
+        assert!(matches!(auth.set_role(&bot, &tater.user_id, Role::User), Err(AuthError::MissingScope(Scope::ManageUsers))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assigned_todos() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let todo_repo = TodoRepository::new(None)?;
+        auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        auth.register(&UserDTO { email: "someone@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+        let someone = auth.authenticate("someone@fakemail.com", "correct horse")?.principal;
+
+        // taylor delegates a chore to tater, who can then see and work on it
+        let todo_id = taylor.todos(&todo_repo).create("Clean the gutters")?;
+        assert!(matches!(someone.todos(&todo_repo).assign(&todo_id, Some(someone.user_id)), Err(AuthError::Forbidden)));
+        taylor.todos(&todo_repo).assign(&todo_id, Some(tater.user_id))?;
+        assert_eq!(tater.todos(&todo_repo).assigned_to_me()?.len(), 1);
+        assert_eq!(tater.todos(&todo_repo).visible()?.len(), 1);
+        tater.todos(&todo_repo).complete(&todo_id)?;
+        tater.todos(&todo_repo).uncomplete(&todo_id)?;
+
+        // but can't otherwise change it
+        let tater_todos = tater.todos(&todo_repo);
+        assert!(matches!(tater_todos.update(&todo_id, "Clean the gutters, eventually"), Err(AuthError::Forbidden)));
+        assert!(matches!(tater_todos.snooze(&todo_id, &(Utc::now() + Duration::days(1)), "rain"), Err(AuthError::Forbidden)));
+        assert!(matches!(tater_todos.delete(&todo_id), Err(AuthError::Forbidden)));
+
+        // tater passes it on, and loses access to it
+        tater.todos(&todo_repo).assign(&todo_id, Some(someone.user_id))?;
+        assert!(tater.todos(&todo_repo).assigned_to_me()?.is_empty());
+        assert!(matches!(tater.todos(&todo_repo).get(&todo_id), Err(AuthError::Forbidden)));
+
+        // the log tells the whole story
+        let delegations = someone.todos(&todo_repo).delegations(&todo_id)?;
+        assert_eq!(delegations.len(), 2);
+        assert_eq!(delegations[1].delegated_by, tater.user_id);
+        assert_eq!(delegations[1].to_user_id, Some(someone.user_id));
+
+        // the creator can still take it back
+        taylor.todos(&todo_repo).assign(&todo_id, None)?;
+        assert!(someone.todos(&todo_repo).assigned_to_me()?.is_empty());
+        assert_eq!(taylor.todos(&todo_repo).get(&todo_id)?.assignee_id, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snoozing_todos() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let todo_repo = TodoRepository::new(None)?;
+        auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+        let next_week = Utc::now() + Duration::weeks(1);
+
+        // only someone who may change a todo can put it off
+        let todo_id = taylor.todos(&todo_repo).create("Renew passport")?;
+        assert!(matches!(tater.todos(&todo_repo).snooze(&todo_id, &next_week, "Not mine"), Err(AuthError::Forbidden)));
+        taylor.todos(&todo_repo).snooze(&todo_id, &next_week, "Not travelling anyway")?;
+        assert!(taylor.todos(&todo_repo).list()?.is_empty());
+        assert_eq!(taylor.todos(&todo_repo).snoozed()?.len(), 1);
+
+        // and everyone keeps their own excuses
+        assert_eq!(taylor.todos(&todo_repo).most_deferred(5)?[0].deferral_count, 1);
+        assert_eq!(taylor.todos(&todo_repo).excuse_report()?[0].excuse, "Not travelling anyway");
+        assert!(tater.todos(&todo_repo).excuse_report()?.is_empty());
+        assert!(matches!(tater.todos(&todo_repo).excuses(&todo_id), Err(AuthError::Forbidden)));
+
+        taylor.todos(&todo_repo).unsnooze(&todo_id)?;
+        assert_eq!(taylor.todos(&todo_repo).list()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refusing_todos() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let todo_repo = TodoRepository::new(None)?;
+        auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+
+        // nobody gets to refuse someone else's todo
+        let todo_id = taylor.todos(&todo_repo).create("Fold the laundry")?;
+        assert!(matches!(tater.todos(&todo_repo).refuse(&todo_id, RefusalReason::NotMyJob), Err(AuthError::Forbidden)));
+        taylor.todos(&todo_repo).refuse(&todo_id, RefusalReason::TooBoring)?;
+        assert_eq!(taylor.todos(&todo_repo).refused()?.len(), 1);
+        assert!(matches!(tater.todos(&todo_repo).reconsider(&todo_id), Err(AuthError::Forbidden)));
+
+        taylor.todos(&todo_repo).reconsider(&todo_id)?;
+        assert!(taylor.todos(&todo_repo).refused()?.is_empty());
+        assert_eq!(taylor.todos(&todo_repo).refusals(&todo_id)?.len(), 1);
+        assert_eq!(taylor.todos(&todo_repo).refusal_report()?[1].refused_count, 1);
+        assert_eq!(tater.todos(&todo_repo).refusal_report()?[1].refused_count, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_lists() -> Result<(), Box<dyn Error>> {
+        let conn_string = "file:auth_shared_lists?mode=memory&cache=shared";
+        let auth = AuthService::new(Some(conn_string))?;
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let list_repo = TodoListRepository::new(Some(conn_string))?;
+        auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+
+        // taylor makes a list with a todo, and invites tater to view it
+        let list_id = taylor.lists(&list_repo, &todo_repo).create("Chores")?;
+        let todo_id = taylor.lists(&list_repo, &todo_repo).add_todo(&list_id, "Mop")?;
+        taylor.lists(&list_repo, &todo_repo).share(&list_id, &tater.user_id, ShareLevel::Viewer)?;
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.list_id, Some(list_id));
+
+        // only existing users can be invited
+        match taylor.lists(&list_repo, &todo_repo).share(&list_id, &42, ShareLevel::Viewer) {
+            Err(AuthError::Repository(RepositoryError::Validation(e))) => assert!(e.has_field("user_id")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        // until tater accepts, the list is off limits
+        let tater_lists = tater.lists(&list_repo, &todo_repo);
+        assert!(matches!(tater_lists.todos(&list_id), Err(AuthError::Forbidden)));
+        assert_eq!(tater_lists.invitations()?.len(), 1);
+        tater_lists.accept(&list_id)?;
+
+        // then tater can read, but not change, the list and its todos
+        assert_eq!(tater_lists.todos(&list_id)?.len(), 1);
+        assert_eq!(tater_lists.visible_todos()?.len(), 1);
+        let tater_todos = tater.todos(&todo_repo).with_lists(&list_repo);
+        assert_eq!(tater_todos.get(&todo_id)?.task, "Mop");
+        assert!(matches!(tater_todos.complete(&todo_id), Err(AuthError::Forbidden)));
+        assert!(matches!(tater_lists.add_todo(&list_id, "Nap"), Err(AuthError::Forbidden)));
+        assert!(matches!(tater_lists.share(&list_id, &tater.user_id, ShareLevel::Owner), Err(AuthError::Forbidden)));
+
+        // editors can change todos and add their own to the list
+        taylor.lists(&list_repo, &todo_repo).share(&list_id, &tater.user_id, ShareLevel::Editor)?;
+        tater_todos.complete(&todo_id)?;
+        let nap_id = tater.todos(&todo_repo).create("Nap")?;
+        tater_lists.move_todo(&nap_id, Some(list_id))?;
+        assert_eq!(taylor.lists(&list_repo, &todo_repo).todos(&list_id)?.len(), 2);
+
+        // snoozed todos are hidden from the list like everywhere else
+        tater_todos.snooze(&nap_id, &(Utc::now() + Duration::hours(1)), "too tired")?;
+        assert_eq!(taylor.lists(&list_repo, &todo_repo).todos(&list_id)?.len(), 1);
+        tater_todos.unsnooze(&nap_id)?;
+        assert!(matches!(tater_lists.delete(&list_id), Err(AuthError::Forbidden)));
+
+        // after leaving, tater only sees their own todo
+        tater_lists.decline(&list_id)?;
+        assert!(matches!(tater_todos.get(&todo_id), Err(AuthError::Forbidden)));
+        assert_eq!(tater_lists.visible_todos()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_organization_roles() -> Result<(), Box<dyn Error>> {
+        let auth = AuthService::new(None)?;
+        let organization_repo = OrganizationRepository::new(None)?;
+        auth.register(&taylor(), "correct horse")?;
+        auth.register(&UserDTO { email: "2hott2tott@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        auth.register(&UserDTO { email: "someone@fakemail.com".to_string(), ..taylor() }, "correct horse")?;
+        let taylor = auth.authenticate("tlowery@fakemail.com", "correct horse")?.principal;
+        let tater = auth.authenticate("2hott2tott@fakemail.com", "correct horse")?.principal;
+        let someone = auth.authenticate("someone@fakemail.com", "correct horse")?.principal;
+
+        // taylor founds an organization and owns it
+        let org_id = taylor.organizations(&organization_repo).create("Acme")?;
+        assert_eq!(organization_repo.get_membership(&org_id, &taylor.user_id)?.unwrap().role, OrgRole::Owner);
+
+        // outsiders can't see into it
+        assert!(matches!(someone.organizations(&organization_repo).stats(&org_id), Err(AuthError::Forbidden)));
+        assert!(someone.organizations(&organization_repo).todos(&org_id).is_err());
+
+        // an admin can add members but not make owners
+        taylor.organizations(&organization_repo).add_member(&org_id, &tater.user_id, OrgRole::Admin)?;
+        let tater_orgs = tater.organizations(&organization_repo);
+        tater_orgs.add_member(&org_id, &someone.user_id, OrgRole::Member)?;
+        assert!(matches!(tater_orgs.add_member(&org_id, &someone.user_id, OrgRole::Owner), Err(AuthError::Forbidden)));
+        assert!(matches!(tater_orgs.remove_member(&org_id, &taylor.user_id), Err(AuthError::Forbidden)));
+        assert!(matches!(tater_orgs.delete(&org_id), Err(AuthError::Forbidden)));
+
+        // nor demote or remove owners, and the last owner can't step down
+        assert!(matches!(tater_orgs.add_member(&org_id, &taylor.user_id, OrgRole::Member), Err(AuthError::Forbidden)));
+        let taylor_orgs = taylor.organizations(&organization_repo);
+        for result in [taylor_orgs.add_member(&org_id, &taylor.user_id, OrgRole::Admin), taylor_orgs.remove_member(&org_id, &taylor.user_id)] {
+            assert!(matches!(result, Err(AuthError::Repository(RepositoryError::Validation(_)))));
+        }
+
+        // members work with the organization's todos and see its statistics
+        let someone_orgs = someone.organizations(&organization_repo);
+        let anvils = someone_orgs.todos(&org_id)?.create("Anvils")?;
+        assert_eq!(someone_orgs.stats(&org_id)?.member_count, 3);
+        assert_eq!(someone_orgs.stats(&org_id)?.open_count, 1);
+
+        // members change only their own todos, while admins manage everyone's
+        let taters = tater_orgs.todos(&org_id)?.create("Rockets")?;
+        let someone_todos = someone_orgs.todos(&org_id)?;
+        assert_eq!(someone_todos.list()?.len(), 2);
+        assert!(matches!(someone_todos.update(&taters, "Mine now"), Err(AuthError::Forbidden)));
+        assert!(matches!(someone_todos.complete(&taters), Err(AuthError::Forbidden)));
+        assert!(matches!(someone_todos.delete(&taters), Err(AuthError::Forbidden)));
+        assert!(matches!(someone_todos.create_for(&tater.user_id, "Nap"), Err(AuthError::Forbidden)));
+        someone_todos.update(&anvils, "More anvils")?;
+        tater_orgs.todos(&org_id)?.complete(&anvils)?;
+        assert_eq!(someone_todos.get(&anvils)?.user_id, someone.user_id);
+
+        // read-only tokens can only read
+        let token = auth.mint_api_token(&someone.user_id, "reader", &[Scope::ReadTodos])?.token;
+        let reader = auth.verify_api_token(&token, Scope::ReadTodos)?;
+        let reader_todos = reader.organizations(&organization_repo).todos(&org_id)?;
+        assert_eq!(reader_todos.list()?.len(), 2);
+        assert!(matches!(reader_todos.update(&anvils, "Fewer anvils"), Err(AuthError::MissingScope(Scope::WriteTodos))));
+        assert!(matches!(someone_orgs.add_member(&org_id, &someone.user_id, OrgRole::Admin), Err(AuthError::Forbidden)));
+
+        // anyone can leave
+        someone_orgs.remove_member(&org_id, &someone.user_id)?;
+        assert!(someone_orgs.list()?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use rusqlite::Connection;
+    use to_dont::models::{TodoItem, TodoItemDTO, UserDTO};
+    use to_dont::repository::event_sourced::todo_repository::EventSourcedTodoRepository;
+    use to_dont::repository::event_sourced::user_repository::EventSourcedUserRepository;
+    use to_dont::repository::event_sourced::{Event, TodoEvent};
+    use to_dont::repository::{Repository, TodoStore};
+
+    /// A database file in the temp directory, with any leftover from an
+    /// earlier run removed.
+    fn test_db(name: &str) -> Result<PathBuf, std::io::Error> {
+        let path = std::env::temp_dir().join(format!("to_dont_{}_{}.db3", std::process::id(), name));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(path)
+    }
+
+    fn tasks(todos: Vec<TodoItem>) -> Vec<(String, bool)> {
+        todos.into_iter().map(|todo| (todo.task, todo.completed)).collect()
+    }
+
+    /// Leave a gap between events, so the instants in between are distinct.
+    fn tick() {
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_time_travel() -> Result<(), Box<dyn Error>> {
+        let repo = EventSourcedTodoRepository::new(":memory:")?;
+        let before = Utc::now();
+        tick();
+        let id = repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        tick();
+        let saved = Utc::now();
+        tick();
+        repo.complete_todo_item(&id)?;
+        let other = repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Walk the dog".to_string() })?;
+        tick();
+        let completed = Utc::now();
+        tick();
+        repo.delete_item_by_id(&id)?;
+        repo.update_item(&other, &TodoItemDTO { user_id: 1, task: "Walk the cat".to_string() })?;
+
+        assert!(repo.get_user_todos_at(&1, &before)?.is_empty());
+        assert_eq!(tasks(repo.get_user_todos_at(&1, &saved)?), vec![("Learn Rust".to_string(), false)]);
+        assert_eq!(
+            tasks(repo.get_user_todos_at(&1, &completed)?),
+            vec![("Learn Rust".to_string(), true), ("Walk the dog".to_string(), false)]
+        );
+        assert_eq!(tasks(repo.get_user_todos_at(&1, &Utc::now())?), vec![("Walk the cat".to_string(), false)]);
+        assert_eq!(tasks(repo.get_user_todos_at(&1, &Utc::now())?), tasks(repo.get_user_todos(&1)?));
+
+        // the deleted todo's events are all still there
+        let events: Vec<Event> = repo.history(&id)?.into_iter().map(|logged| logged.event).collect();
+        assert_eq!(
+            events,
+            vec![
+                Event::Todo(id, TodoEvent::Created { user_id: 1, task: "Learn Rust".to_string() }),
+                Event::Todo(id, TodoEvent::Completed),
+                Event::Todo(id, TodoEvent::Deleted),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots_give_the_same_state() -> Result<(), Box<dyn Error>> {
+        let path = test_db("event_snapshots")?;
+        let path_str = path.to_str().unwrap();
+        let (first, snapshot_taken) = {
+            let repo = EventSourcedTodoRepository::new(path_str)?;
+            let first = repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+            repo.complete_todo_item(&first)?;
+            repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Walk the dog".to_string() })?;
+            assert_eq!(repo.snapshot()?, 3);
+            tick();
+            let snapshot_taken = Utc::now();
+            tick();
+            repo.uncomplete_todo_item(&first)?;
+            repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Read a book".to_string() })?;
+            (first, snapshot_taken)
+        };
+
+        let conn = Connection::open(&path)?;
+        let snapshot_todos: i64 = conn.query_row("SELECT COUNT(*) FROM event_snapshot_todos", [], |row| row.get(0))?;
+        assert_eq!(snapshot_todos, 2);
+
+        // reopening loads the snapshot and replays the two events after it
+        let repo = EventSourcedTodoRepository::new(path_str)?;
+        let todo = repo.select_item_by_id(&first)?;
+        assert_eq!((todo.completed, todo.version), (false, 3));
+        assert_eq!(
+            tasks(repo.get_user_todos(&1)?),
+            vec![("Learn Rust".to_string(), false), ("Walk the dog".to_string(), false), ("Read a book".to_string(), false)]
+        );
+        assert_eq!(
+            tasks(repo.get_user_todos_at(&1, &snapshot_taken)?),
+            vec![("Learn Rust".to_string(), true), ("Walk the dog".to_string(), false)]
+        );
+
+        drop(repo);
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_deleted_ids_are_not_reused() -> Result<(), Box<dyn Error>> {
+        let path = test_db("event_deleted_ids")?;
+        let path_str = path.to_str().unwrap();
+        let last = {
+            let repo = EventSourcedTodoRepository::new(path_str)?;
+            repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+            let last = repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Walk the dog".to_string() })?;
+            repo.delete_item_by_id(&last)?;
+            assert_eq!(repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Read a book".to_string() })?, last + 1);
+            repo.delete_item_by_id(&(last + 1))?;
+            repo.snapshot()?;
+            last + 1
+        };
+
+        // the snapshot no longer holds the deleted todo, but keeps its id taken
+        let conn = Connection::open(&path)?;
+        let last_todo_id: i64 = conn.query_row("SELECT last_todo_id FROM event_snapshots", [], |row| row.get(0))?;
+        assert_eq!(last_todo_id, last);
+        // snapshots from before the ids were saved get them from the log
+        conn.execute("UPDATE event_snapshots SET last_todo_id = NULL, last_user_id = NULL", [])?;
+        let repo = EventSourcedTodoRepository::new(path_str)?;
+        assert_eq!(repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Water the plants".to_string() })?, last + 1);
+
+        drop((conn, repo));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_repositories_share_a_log() -> Result<(), Box<dyn Error>> {
+        let path = test_db("event_shared")?;
+        let path_str = path.to_str().unwrap();
+        let users = EventSourcedUserRepository::new(path_str)?;
+        let todos = EventSourcedTodoRepository::new(path_str)?;
+        let other_todos = EventSourcedTodoRepository::new(path_str)?;
+
+        let user_id = users.save_new_item(&UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        })?;
+        let id = todos.save_new_item(&TodoItemDTO { user_id, task: "Learn Rust".to_string() })?;
+
+        // each sees the others' writes, and ids don't collide
+        other_todos.complete_todo_item(&id)?;
+        assert!(todos.select_item_by_id(&id)?.completed);
+        let next = other_todos.save_new_item(&TodoItemDTO { user_id, task: "Walk the dog".to_string() })?;
+        assert_eq!(next, id + 1);
+        assert_eq!(todos.get_user_todos(&user_id)?.len(), 2);
+        assert_eq!(users.history(&user_id)?.len(), 1);
+
+        drop((users, todos, other_todos));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
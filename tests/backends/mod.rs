@@ -0,0 +1,56 @@
+mod event_sourced_tests;
+mod todo_store_tests;
+mod user_store_tests;
+
+#[cfg(feature = "flatfile")]
+use std::path::{Path, PathBuf};
+
+/// A path in the temp directory for one test's data file, with any
+/// leftovers from an earlier run removed.
+#[cfg(feature = "flatfile")]
+fn test_file(name: &str) -> Result<PathBuf, std::io::Error> {
+    let path = std::env::temp_dir().join(format!("to_dont_{}_{}", std::process::id(), name));
+    remove(&path)?;
+    Ok(path)
+}
+
+/// Remove a data file and its lock file.
+#[cfg(feature = "flatfile")]
+fn remove(path: &Path) -> Result<(), std::io::Error> {
+    for file in [path.to_path_buf(), PathBuf::from(format!("{}.lock", path.display()))] {
+        if file.exists() {
+            std::fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
+
+/// The test PostgreSQL server, `TO_DONT_POSTGRES_URL` if set, in
+/// `key=value` form.
+#[cfg(feature = "postgres")]
+fn postgres_url() -> String {
+    std::env::var("TO_DONT_POSTGRES_URL").unwrap_or_else(|_| "host=localhost user=postgres".to_string())
+}
+
+/// A connection string for a fresh schema of its own for one test, or `None`
+/// if there is no PostgreSQL server to connect to, in which case the test
+/// should be skipped.
+#[cfg(feature = "postgres")]
+fn postgres_schema(name: &str) -> Option<String> {
+    let Ok(mut client) = postgres::Client::connect(&postgres_url(), postgres::NoTls) else {
+        eprintln!("skipping {}: no PostgreSQL server at {}", name, postgres_url());
+        return None;
+    };
+    let schema = format!("to_dont_{}_{}", name, std::process::id());
+    client
+        .batch_execute(&format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}", schema))
+        .expect("creating the test schema");
+    Some(format!("{} options='-c search_path={}'", postgres_url(), schema))
+}
+
+/// Drop the schema made by [`postgres_schema`].
+#[cfg(feature = "postgres")]
+fn drop_postgres_schema(name: &str) -> Result<(), postgres::Error> {
+    let mut client = postgres::Client::connect(&postgres_url(), postgres::NoTls)?;
+    client.batch_execute(&format!("DROP SCHEMA to_dont_{}_{} CASCADE", name, std::process::id()))
+}
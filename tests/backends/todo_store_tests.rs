@@ -0,0 +1,283 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fmt::Debug;
+
+    use to_dont::models::TodoItemDTO;
+    use to_dont::repository::event_sourced::todo_repository::EventSourcedTodoRepository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::{RepositoryError, TodoStore};
+
+    /// The behaviour every `TodoStore` backend shares, checked on an empty store.
+    fn check_todo_store<C, Err, S>(store: &S) -> Result<(), Box<dyn Error>>
+    where
+        Err: Into<RepositoryError> + Debug + Error + 'static,
+        S: TodoStore<C, Err>,
+    {
+        // saving and reading back
+        let id = store.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        let todo = store.select_item_by_id(&id)?;
+        assert_eq!((todo.id, todo.user_id, todo.task.as_str()), (id, 1, "Learn Rust"));
+        assert!(!todo.completed);
+        assert_eq!(todo.completed_datetime, None);
+        assert_eq!(todo.version, 1);
+        assert!(matches!(store.select_item_by_id(&(id + 100)).map_err(Into::into), Err(RepositoryError::NotFound)));
+
+        // ids are unique across users, and each user sees their own in id order
+        let other = store.save_new_item(&TodoItemDTO { user_id: 2, task: "Read a book".to_string() })?;
+        let second = store.save_new_item(&TodoItemDTO { user_id: 1, task: "Walk the dog".to_string() })?;
+        assert!(id != other && other != second && id != second);
+        let tasks: Vec<String> = store.get_user_todos(&1)?.into_iter().map(|todo| todo.task).collect();
+        assert_eq!(tasks, vec!["Learn Rust", "Walk the dog"]);
+        assert!(store.get_user_todos(&3)?.is_empty());
+
+        // updating bumps the version, and keeps the owner
+        assert_eq!(store.update_item(&id, &TodoItemDTO { user_id: 1, task: "Learn more Rust".to_string() })?, 1);
+        let todo = store.select_item_by_id(&id)?;
+        assert_eq!((todo.task.as_str(), todo.version), ("Learn more Rust", 2));
+        assert_eq!(store.update_item(&second, &TodoItemDTO { user_id: 2, task: "Walk the dog".to_string() })?, 1);
+        assert_eq!(store.get_user_todos(&1)?.len(), 2);
+        assert_eq!(store.get_user_todos(&2)?.iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![other]);
+        assert_eq!(store.update_item(&(id + 100), &TodoItemDTO { user_id: 1, task: "Nothing".to_string() })?, 0);
+
+        // completing and uncompleting
+        assert_eq!(store.complete_todo_item(&id)?, 1);
+        let todo = store.select_item_by_id(&id)?;
+        assert!(todo.completed);
+        assert!(todo.completed_datetime.is_some_and(|completed| completed >= todo.created_datetime));
+        assert_eq!(todo.version, 3);
+        assert_eq!(store.uncomplete_todo_item(&id)?, 1);
+        let todo = store.select_item_by_id(&id)?;
+        assert!(!todo.completed);
+        assert_eq!(todo.completed_datetime, None);
+        assert_eq!(store.complete_todo_item(&(id + 100))?, 0);
+
+        // deleting leaves the others alone
+        assert_eq!(store.delete_item_by_id(&id)?, 1);
+        assert_eq!(store.delete_item_by_id(&id)?, 0);
+        assert!(store.select_item_by_id(&id).is_err());
+        assert_eq!(store.get_user_todos(&1)?.iter().map(|todo| todo.id).collect::<Vec<_>>(), vec![second]);
+        assert_eq!(store.select_item_by_id(&other)?.task, "Read a book");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_todo_store() -> Result<(), Box<dyn Error>> {
+        check_todo_store(&TodoRepository::new(None)?)
+    }
+
+    #[test]
+    fn test_event_sourced_todo_store() -> Result<(), Box<dyn Error>> {
+        check_todo_store(&EventSourcedTodoRepository::new(":memory:")?)
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_todo_store() -> Result<(), Box<dyn Error>> {
+        let repo = TodoRepository::new(None)?;
+        check_todo_store(&repo.encrypted("correct horse battery staple")?)
+    }
+
+    #[cfg(feature = "git")]
+    mod git {
+        use std::error::Error;
+        use std::fs;
+        use std::path::PathBuf;
+
+        use to_dont::models::TodoItemDTO;
+        use to_dont::repository::git::todo_repository::GitTodoRepository;
+        use to_dont::repository::{Repository, TodoStore};
+
+        use super::check_todo_store;
+
+        /// A fresh directory for one test's repository.
+        fn test_dir(name: &str) -> Result<PathBuf, std::io::Error> {
+            let dir = std::env::temp_dir().join(format!("to_dont_{}_{}", name, std::process::id()));
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+            Ok(dir)
+        }
+
+        fn git_log(repo: &GitTodoRepository) -> Result<Vec<String>, std::io::Error> {
+            let output = std::process::Command::new("git").arg("-C").arg(repo.path()).args(["log", "--format=%s"]).output()?;
+            Ok(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+        }
+
+        #[test]
+        fn test_git_todo_store() -> Result<(), Box<dyn Error>> {
+            let dir = test_dir("git_store")?;
+            check_todo_store(&GitTodoRepository::new(dir.to_str().unwrap())?)?;
+            fs::remove_dir_all(&dir)?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_every_write_is_a_commit() -> Result<(), Box<dyn Error>> {
+            let dir = test_dir("git_commits")?;
+            let id = {
+                let repo = GitTodoRepository::new(dir.to_str().unwrap())?;
+                let id = repo.save_new_item(&TodoItemDTO { user_id: 7, task: "Learn Rust".to_string() })?;
+                repo.complete_todo_item(&id)?;
+                assert_eq!(git_log(&repo)?, vec![format!("Complete todo {}", id), format!("Add todo {}: Learn Rust", id)]);
+
+                // the file is plain TOML, one table per todo
+                let contents = fs::read_to_string(dir.join("todos/7.toml"))?;
+                assert!(contents.contains("[[todo]]"));
+                assert!(contents.contains("task = \"Learn Rust\""));
+                id
+            };
+
+            // reopening picks up where it left off
+            let repo = GitTodoRepository::new(dir.to_str().unwrap())?;
+            assert!(repo.select_item_by_id(&id)?.completed);
+            repo.delete_item_by_id(&id)?;
+            assert!(!dir.join("todos/7.toml").exists());
+            assert_eq!(git_log(&repo)?.len(), 3);
+
+            fs::remove_dir_all(&dir)?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_failed_commits_leave_the_files_alone() -> Result<(), Box<dyn Error>> {
+            let dir = test_dir("git_failed_commit")?;
+            let repo = GitTodoRepository::new(dir.to_str().unwrap())?;
+            let id = repo.save_new_item(&TodoItemDTO { user_id: 7, task: "Learn Rust".to_string() })?;
+            let contents = fs::read_to_string(dir.join("todos/7.toml"))?;
+
+            // signing with a program that always fails makes every commit fail
+            let git = |args: &[&str]| std::process::Command::new("git").arg("-C").arg(&dir).args(args).output();
+            git(&["config", "commit.gpgSign", "true"])?;
+            git(&["config", "gpg.program", "false"])?;
+            assert!(repo.complete_todo_item(&id).is_err());
+            assert!(repo.save_new_item(&TodoItemDTO { user_id: 8, task: "Walk the dog".to_string() }).is_err());
+            assert!(repo.delete_item_by_id(&id).is_err());
+            assert_eq!(fs::read_to_string(dir.join("todos/7.toml"))?, contents);
+            assert!(!dir.join("todos/8.toml").exists());
+            assert!(git(&["status", "--porcelain"])?.stdout.is_empty());
+
+            git(&["config", "commit.gpgSign", "false"])?;
+            assert!(!repo.select_item_by_id(&id)?.completed);
+            assert_eq!(repo.save_new_item(&TodoItemDTO { user_id: 8, task: "Walk the dog".to_string() })?, id + 1);
+            assert_eq!(git_log(&repo)?.len(), 2);
+
+            fs::remove_dir_all(&dir)?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    mod postgres {
+        use std::error::Error;
+
+        use to_dont::models::TodoItemDTO;
+        use to_dont::repository::postgres::todo_repository::PostgresTodoRepository;
+        use to_dont::repository::{Repository, TodoStore};
+
+        use super::check_todo_store;
+        use crate::backends::{drop_postgres_schema, postgres_schema};
+
+        #[test]
+        fn test_postgres_todo_store() -> Result<(), Box<dyn Error>> {
+            let Some(connection_string) = postgres_schema("todo_store") else {
+                return Ok(());
+            };
+            check_todo_store(&PostgresTodoRepository::new(&connection_string)?)?;
+            drop_postgres_schema("todo_store")?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_postgres_schema_is_upgraded() -> Result<(), Box<dyn Error>> {
+            let Some(connection_string) = postgres_schema("todo_upgrade") else {
+                return Ok(());
+            };
+            // a table from before todos had versions or could be snoozed
+            let mut client = PostgresTodoRepository::connect_to_db(&connection_string)?;
+            client.batch_execute(
+                "CREATE TABLE todos (id BIGINT PRIMARY KEY, user_id BIGINT NOT NULL, task TEXT NOT NULL, \
+completed BOOLEAN NOT NULL DEFAULT FALSE, created_datetime TIMESTAMPTZ NOT NULL DEFAULT date_trunc('second', now()), \
+completed_datetime TIMESTAMPTZ);
+INSERT INTO todos (id, user_id, task) VALUES (1, 1, 'Learn Rust');",
+            )?;
+
+            let repo = PostgresTodoRepository::new(&connection_string)?;
+            let todo = repo.select_item_by_id(&1)?;
+            assert_eq!((todo.version, todo.snoozed_until), (1, None));
+            let id = repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Walk the dog".to_string() })?;
+            assert_eq!(id, 2);
+            repo.complete_todo_item(&id)?;
+            assert_eq!(repo.get_user_todos(&1)?.iter().filter(|todo| todo.completed).count(), 1);
+            // ids now come from a sequence, so a deleted one isn't given out again
+            repo.delete_item_by_id(&id)?;
+            let repo = PostgresTodoRepository::new(&connection_string)?;
+            assert_eq!(repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Feed the cat".to_string() })?, 3);
+
+            drop((client, repo));
+            drop_postgres_schema("todo_upgrade")?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "flatfile")]
+    mod flatfile {
+        use std::error::Error;
+        use std::fs;
+        use std::thread;
+
+        use to_dont::models::TodoItemDTO;
+        use to_dont::repository::file::todo_repository::FileTodoRepository;
+        use to_dont::repository::{Repository, TodoStore};
+
+        use super::check_todo_store;
+        use crate::backends::{remove, test_file};
+
+        #[test]
+        fn test_json_todo_store() -> Result<(), Box<dyn Error>> {
+            let path = test_file("todos.json")?;
+            check_todo_store(&FileTodoRepository::new(path.to_str().unwrap())?)?;
+            assert!(fs::read_to_string(&path)?.trim_start().starts_with('{'));
+            remove(&path)?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_toml_todo_store() -> Result<(), Box<dyn Error>> {
+            let path = test_file("todos.toml")?;
+            check_todo_store(&FileTodoRepository::new(path.to_str().unwrap())?)?;
+            assert!(fs::read_to_string(&path)?.contains("[[todos]]"));
+            remove(&path)?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_concurrent_writers_share_the_file() -> Result<(), Box<dyn Error>> {
+            let path = test_file("concurrent.json")?;
+            let writers: Vec<_> = (1..=4)
+                .map(|user_id| {
+                    let path = path.clone();
+                    thread::spawn(move || -> Result<Vec<i64>, to_dont::repository::RepositoryError> {
+                        // each writer opens the file itself, as separate processes would
+                        let repo = FileTodoRepository::new(path.to_str().unwrap())?;
+                        (0..10).map(|i| repo.save_new_item(&TodoItemDTO { user_id, task: format!("Task {}", i) })).collect()
+                    })
+                })
+                .collect();
+            let mut ids = Vec::new();
+            for writer in writers {
+                ids.extend(writer.join().unwrap()?);
+            }
+            ids.sort();
+            assert_eq!(ids, (1..=40).collect::<Vec<i64>>());
+
+            let repo = FileTodoRepository::new(path.to_str().unwrap())?;
+            for user_id in 1..=4 {
+                assert_eq!(repo.get_user_todos(&user_id)?.len(), 10);
+            }
+            remove(&path)?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use to_dont::models::{User, UserDTO, ValidationError};
+    use to_dont::repository::event_sourced::user_repository::EventSourcedUserRepository;
+    use to_dont::repository::sqlite::user_repository::UserRepository;
+    use to_dont::repository::{Repository, RepositoryError};
+
+    fn user_dto(first_name: &str, email: &str) -> UserDTO {
+        UserDTO { first_name: first_name.to_string(), last_name: "Lowery".to_string(), email: email.to_string() }
+    }
+
+    /// The behaviour every user repository shares, checked on an empty store.
+    fn check_user_repository<C, R: Repository<C, User, RepositoryError>>(repo: &R) -> Result<(), Box<dyn Error>> {
+        // users are validated and normalized on the way in
+        let id = repo.save_new_item(&user_dto(" Taylor ", " TLowery@FakeMail.com"))?;
+        let user = repo.select_item_by_id(&id)?;
+        assert_eq!((user.first_name.as_str(), user.email.as_str(), user.version), ("Taylor", "tlowery@fakemail.com", 1));
+        assert!(matches!(repo.save_new_item(&user_dto("", "not an email")), Err(RepositoryError::Validation(_))));
+        assert!(matches!(repo.select_item_by_id(&(id + 100)), Err(RepositoryError::NotFound)));
+
+        // emails are unique, however they're written
+        let Err(RepositoryError::Validation(e)) = repo.save_new_item(&user_dto("Tater", "tlowery@FAKEMAIL.com")) else {
+            panic!("a duplicate email was accepted");
+        };
+        assert_eq!(e, ValidationError::field("email", "is already in use"));
+        let other = repo.save_new_item(&user_dto("Tater", "tot@fakemail.com"))?;
+        assert_eq!(other, id + 1);
+        assert!(matches!(repo.update_item(&other, &user_dto("Tater", "tlowery@fakemail.com")), Err(RepositoryError::Validation(_))));
+
+        // updating bumps the version; keeping your own email is fine
+        assert_eq!(repo.update_item(&id, &user_dto("Tay", "tlowery@fakemail.com"))?, 1);
+        let user = repo.select_item_by_id(&id)?;
+        assert_eq!((user.first_name.as_str(), user.version), ("Tay", 2));
+        assert_eq!(repo.update_item(&(id + 100), &user_dto("Nobody", "nobody@fakemail.com"))?, 0);
+
+        // deleting, after which the email is free again; sessions and tokens
+        // name users by id, so a deleted user's id is never given out again
+        assert_eq!(repo.delete_item_by_id(&other)?, 1);
+        assert_eq!(repo.delete_item_by_id(&other)?, 0);
+        assert!(repo.save_new_item(&user_dto("Tater", "tot@fakemail.com"))? > other);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_user_repository() -> Result<(), Box<dyn Error>> {
+        check_user_repository(&UserRepository::new(None)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_sourced_user_repository() -> Result<(), Box<dyn Error>> {
+        check_user_repository(&EventSourcedUserRepository::new(":memory:")?)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_postgres_user_repository() -> Result<(), Box<dyn Error>> {
+        use to_dont::repository::postgres::user_repository::PostgresUserRepository;
+
+        use crate::backends::{drop_postgres_schema, postgres_schema};
+
+        let Some(connection_string) = postgres_schema("user_store") else {
+            return Ok(());
+        };
+        let repo = PostgresUserRepository::new(&connection_string)?;
+        check_user_repository(&repo)?;
+        assert_eq!(repo.get_users()?.len(), 2);
+        assert_eq!(repo.find_by_email("TOT@fakemail.com ")?.map(|user| user.first_name), Some("Tater".to_string()));
+        drop(repo);
+        drop_postgres_schema("user_store")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_postgres_duplicate_emails_are_reported() -> Result<(), Box<dyn Error>> {
+        use to_dont::repository::postgres::user_repository::PostgresUserRepository;
+        use to_dont::repository::RepositoryError;
+
+        use crate::backends::{drop_postgres_schema, postgres_schema};
+
+        let Some(connection_string) = postgres_schema("user_duplicates") else {
+            return Ok(());
+        };
+        let mut client = postgres::Client::connect(&connection_string, postgres::NoTls)?;
+        client.batch_execute(
+            "CREATE TABLE users (id BIGINT PRIMARY KEY, first_name TEXT NOT NULL, last_name TEXT NOT NULL, email TEXT NOT NULL);
+INSERT INTO users VALUES (1, 'Taylor', 'Lowery', 'tlowery@fakemail.com'), (2, 'Other', 'Taylor', 'TLowery@fakemail.com ');",
+        )?;
+        match PostgresUserRepository::new(&connection_string) {
+            Err(RepositoryError::Validation(e)) => {
+                assert!(e.errors[0].message.contains("tlowery@fakemail.com is used by users 1, 2"));
+            }
+            other => panic!("expected a validation error, got {:?}", other.map(|_| ())),
+        }
+        client.batch_execute("UPDATE users SET email = 'other@fakemail.com' WHERE id = 2")?;
+        let repo = PostgresUserRepository::new(&connection_string)?;
+        assert_eq!(repo.get_users()?.len(), 2);
+        assert_eq!(repo.save_new_item(&user_dto("Third", "third@fakemail.com"))?, 3);
+        drop(repo);
+        drop(client);
+        drop_postgres_schema("user_duplicates")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "flatfile")]
+    mod flatfile {
+        use std::error::Error;
+
+        use to_dont::models::TodoItemDTO;
+        use to_dont::repository::file::todo_repository::FileTodoRepository;
+        use to_dont::repository::file::user_repository::FileUserRepository;
+        use to_dont::repository::{Repository, TodoStore};
+
+        use super::{check_user_repository, user_dto};
+        use crate::backends::{remove, test_file};
+
+        #[test]
+        fn test_file_user_repository() -> Result<(), Box<dyn Error>> {
+            let path = test_file("users.toml")?;
+            check_user_repository(&FileUserRepository::new(path.to_str().unwrap())?)?;
+            remove(&path)?;
+            Ok(())
+        }
+
+        #[test]
+        fn test_users_and_todos_share_a_file() -> Result<(), Box<dyn Error>> {
+            let path = test_file("shared.json")?;
+            let users = FileUserRepository::new(path.to_str().unwrap())?;
+            let todos = FileTodoRepository::new(path.to_str().unwrap())?;
+            let user_id = users.save_new_item(&user_dto("Taylor", "tlowery@fakemail.com"))?;
+            todos.save_new_item(&TodoItemDTO { user_id, task: "Learn Rust".to_string() })?;
+            users.update_item(&user_id, &user_dto("Tay", "tlowery@fakemail.com"))?;
+
+            // neither write lost the other's data
+            assert_eq!(todos.get_user_todos(&user_id)?.len(), 1);
+            assert_eq!(users.get_users()?.len(), 1);
+            assert_eq!(users.find_by_email(" TLOWERY@fakemail.com")?.map(|user| user.first_name), Some("Tay".to_string()));
+            remove(&path)?;
+            Ok(())
+        }
+    }
+}
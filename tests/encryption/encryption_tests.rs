@@ -0,0 +1,159 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use rusqlite::Connection;
+    use to_dont::auth::{Credential, Principal};
+    use to_dont::encryption::EncryptedTodos;
+    use to_dont::models::{Role, TodoItemDTO};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::{Repository, RepositoryError, TodoStore};
+
+    fn memory_db(name: &str) -> String {
+        format!("file:{}?mode=memory&cache=shared", name)
+    }
+
+    /// A repository plus a second connection to the same in-memory database,
+    /// for looking at what is actually stored.
+    fn open(name: &str) -> Result<(TodoRepository, Connection), rusqlite::Error> {
+        Ok((TodoRepository::new(Some(&memory_db(name)))?, Connection::open(memory_db(name))?))
+    }
+
+    fn stored_tasks(conn: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+        conn.prepare("SELECT task FROM todos ORDER BY id")?.query_map((), |row| row.get(0))?.collect()
+    }
+
+    fn test_db(name: &str) -> Result<PathBuf, std::io::Error> {
+        let path = std::env::temp_dir().join(format!("to_dont_{}_{}.db3", std::process::id(), name));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(path)
+    }
+
+    #[test]
+    fn test_tasks_are_encrypted_at_rest() -> Result<(), Box<dyn Error>> {
+        let (repo, conn) = open("encryption_at_rest")?;
+        // a todo from before encryption was turned on, with undo history
+        let principal = Principal { user_id: 1, role: Role::User, credential: Credential::Session { id: 1 } };
+        let old = principal.todos(&repo).create("Call the dentist")?;
+        principal.todos(&repo).update(&old, "Cancel the dentist")?;
+
+        let todos = repo.encrypted("correct horse battery staple")?;
+        let new = todos.save_new_item(&TodoItemDTO { user_id: 1, task: "Hide the evidence".to_string() })?;
+        let tasks: Vec<String> = todos.get_user_todos(&1)?.into_iter().map(|todo| todo.task).collect();
+        assert_eq!(tasks, vec!["Cancel the dentist", "Hide the evidence"]);
+
+        // none of it is readable in the database, history included
+        for task in stored_tasks(&conn)? {
+            assert!(task.starts_with("enc1:") && !task.contains("dentist") && !task.contains("evidence"));
+        }
+        let history: String = conn.query_row("SELECT group_concat(before_state || after_state) FROM todo_history", (), |row| row.get(0))?;
+        assert!(!history.contains("dentist"));
+
+        // the plain repository no longer opens it, and plain tasks are refused
+        assert!(TodoRepository::new(Some(&memory_db("encryption_at_rest"))).is_err());
+        assert!(conn.execute("INSERT INTO todos (user_id, task) VALUES (1, 'Call the dentist')", ()).is_err());
+        assert!(conn.execute("UPDATE todos SET task = 'Call the dentist' WHERE id = ?1", [old]).is_err());
+        assert_eq!(todos.select_item_by_id(&old)?.task, "Cancel the dentist");
+
+        // the same task encrypts differently every time
+        let again = todos.save_new_item(&TodoItemDTO { user_id: 1, task: "Hide the evidence".to_string() })?;
+        let stored = stored_tasks(&conn)?;
+        assert_ne!(stored[(new - 1) as usize], stored[(again - 1) as usize]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_key_is_reported() -> Result<(), Box<dyn Error>> {
+        let path = test_db("encryption_keys")?;
+        let path_str = path.to_str().unwrap();
+        {
+            let repo = TodoRepository::new(Some(path_str))?;
+            repo.encrypted("open sesame")?.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        }
+
+        assert!(TodoRepository::new(Some(path_str)).is_err());
+        assert!(matches!(EncryptedTodos::open(Some(path_str), "open barley"), Err(RepositoryError::WrongKey)));
+        assert!(matches!(EncryptedTodos::open(Some(path_str), ""), Err(RepositoryError::Validation(_))));
+        assert_eq!(EncryptedTodos::open(Some(path_str), "open sesame")?.get_user_todos(&1)?[0].task, "Learn Rust");
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_rotation() -> Result<(), Box<dyn Error>> {
+        let (repo, conn) = open("encryption_rotation")?;
+        let mut todos = repo.encrypted("old key")?;
+        let id = todos.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        let before = stored_tasks(&conn)?;
+
+        todos.rotate_key("new key")?;
+        assert_ne!(stored_tasks(&conn)?, before);
+        assert_eq!(todos.select_item_by_id(&id)?.task, "Learn Rust");
+        // rotating isn't an edit
+        assert_eq!(todos.select_item_by_id(&id)?.version, 1);
+
+        let conn_string = memory_db("encryption_rotation");
+        assert!(matches!(EncryptedTodos::open(Some(&conn_string), "old key"), Err(RepositoryError::WrongKey)));
+        assert_eq!(EncryptedTodos::open(Some(&conn_string), "new key")?.select_item_by_id(&id)?.task, "Learn Rust");
+        Ok(())
+    }
+
+    #[cfg(feature = "webhooks")]
+    #[test]
+    fn test_encrypting_scrubs_webhooks_quietly() -> Result<(), Box<dyn Error>> {
+        use to_dont::models::WebhookDTO;
+        use to_dont::repository::EventKind;
+        use to_dont::repository::sqlite::webhook_repository::WebhookRepository;
+
+        let (repo, _conn) = open("encryption_side_tables")?;
+        let webhooks = WebhookRepository::new(Some(&memory_db("encryption_side_tables")))?;
+        let webhook_id = webhooks.save_new_item(&WebhookDTO {
+            user_id: 1,
+            url: "https://example.com/hook".to_string(),
+            events: vec![EventKind::Created, EventKind::Updated],
+        })?;
+        repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Call the dentist".to_string() })?;
+        let queued = webhooks.get_deliveries(&webhook_id)?.len();
+
+        let mut todos = repo.encrypted("open sesame")?;
+        todos.rotate_key("open barley")?;
+
+        // nothing was queued, and nothing is left in plain text
+        let deliveries = webhooks.get_deliveries(&webhook_id)?;
+        assert_eq!(deliveries.len(), queued);
+        assert!(deliveries.iter().all(|delivery| !delivery.payload.contains("dentist")));
+
+        // writes through the key still reach webhooks
+        todos.save_new_item(&TodoItemDTO { user_id: 1, task: "Hide the evidence".to_string() })?;
+        assert_eq!(webhooks.get_deliveries(&webhook_id)?.len(), queued + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_databases_dont_sync() -> Result<(), Box<dyn Error>> {
+        let (repo, conn) = open("encryption_sync")?;
+        // a connection that was syncing before the database was encrypted
+        let other = TodoRepository::new(Some(&memory_db("encryption_sync")))?;
+        let sync = other.sync(1)?;
+        repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Call the dentist".to_string() })?;
+        let changes = sync.changes_since(0)?.changes;
+        assert_eq!(changes.len(), 2);
+
+        let todos = repo.encrypted("open sesame")?;
+        todos.save_new_item(&TodoItemDTO { user_id: 1, task: "Hide the evidence".to_string() })?;
+
+        // the sync log is gone, and nothing hands out or takes in changes
+        let logged: bool = conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name LIKE 'sync%')", (), |row| row.get(0))?;
+        assert!(!logged);
+        assert!(sync.changes_since(0).is_err());
+        assert!(sync.apply_changes(&changes).is_err());
+        assert!(other.sync(1).is_err());
+        assert_eq!(todos.get_user_todos(&1)?.len(), 2);
+        Ok(())
+    }
+}
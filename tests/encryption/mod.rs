@@ -0,0 +1 @@
+mod encryption_tests;
@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use async_graphql::dataloader::{DataLoader, Loader};
+    use async_graphql::Request;
+    use serde_json::json;
+
+    use to_dont::auth::{Credential, Principal};
+    use to_dont::graphql::{build_schema, TodoLoader, TodoSchema};
+    use to_dont::models::{Role, Scope, TodoItem, TodoItemDTO, UserDTO};
+    use to_dont::repository::Repository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::sqlite::user_repository::UserRepository;
+
+    /// Build a schema with three users who each have `todos_per_user` todos.
+    fn seeded_schema(todos_per_user: usize) -> TodoSchema {
+        let user_repo = UserRepository::new(None).unwrap();
+        let todo_repo = TodoRepository::new(None).unwrap();
+        for name in ["Taylor", "Tater", "Tot"] {
+            let user_id = user_repo
+                .save_new_item(&UserDTO {
+                    first_name: name.to_string(),
+                    last_name: "Lowery".to_string(),
+                    email: format!("{}@fakemail.com", name.to_lowercase()),
+                })
+                .unwrap();
+            for n in 0..todos_per_user {
+                todo_repo
+                    .save_new_item(&TodoItemDTO { user_id, task: format!("{} chore {}", name, n) })
+                    .unwrap();
+            }
+        }
+        build_schema(user_repo, todo_repo)
+    }
+
+    /// A principal signed in with a session, which has every scope.
+    fn principal(user_id: i64, role: Role) -> Principal {
+        Principal { user_id, role, credential: Credential::Session { id: 1 } }
+    }
+
+    /// Run a query as an admin, failing on any errors.
+    async fn execute(schema: &TodoSchema, query: &str) -> serde_json::Value {
+        execute_as(schema, principal(1, Role::Admin), query).await
+    }
+
+    async fn execute_as(schema: &TodoSchema, principal: Principal, query: &str) -> serde_json::Value {
+        let response = schema.execute(Request::new(query).data(principal)).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    /// The error messages from running a query as `principal`, if any.
+    async fn errors_as(schema: &TodoSchema, principal: Option<Principal>, query: &str) -> Vec<String> {
+        let mut request = Request::new(query);
+        if let Some(principal) = principal {
+            request = request.data(principal);
+        }
+        schema.execute(request).await.errors.into_iter().map(|error| error.message).collect()
+    }
+
+    #[tokio::test]
+    async fn test_user_todos_connection_pages() {
+        let schema = seeded_schema(3);
+
+        // first page of two
+        let data = execute(
+            &schema,
+            "{ user(id: 1) { firstName todos(first: 2) { edges { cursor node { task } } pageInfo { hasNextPage endCursor } } } }",
+        )
+        .await;
+        let todos = &data["user"]["todos"];
+        assert_eq!(data["user"]["firstName"], "Taylor");
+        assert_eq!(todos["edges"].as_array().unwrap().len(), 2);
+        assert_eq!(todos["edges"][0]["node"]["task"], "Taylor chore 0");
+        assert_eq!(todos["pageInfo"]["hasNextPage"], true);
+
+        // the rest after the end cursor
+        let after = todos["pageInfo"]["endCursor"].as_str().unwrap();
+        let data = execute(
+            &schema,
+            &format!("{{ user(id: 1) {{ todos(first: 2, after: \"{}\") {{ edges {{ node {{ task }} }} pageInfo {{ hasNextPage hasPreviousPage }} }} }} }}", after),
+        )
+        .await;
+        let todos = &data["user"]["todos"];
+        assert_eq!(todos["edges"].as_array().unwrap().len(), 1);
+        assert_eq!(todos["edges"][0]["node"]["task"], "Taylor chore 2");
+        assert_eq!(todos["pageInfo"]["hasNextPage"], false);
+        assert_eq!(todos["pageInfo"]["hasPreviousPage"], true);
+    }
+
+    #[tokio::test]
+    async fn test_todos_filter() {
+        let schema = seeded_schema(3);
+        execute(&schema, "mutation { completeTodo(id: 2) { completed } }").await;
+
+        let data = execute(
+            &schema,
+            "{ user(id: 1) { done: todos(filter: { completed: true }) { edges { node { id } } } \
+             searched: todos(filter: { search: \"CHORE 1\" }) { edges { node { task } } } } }",
+        )
+        .await;
+        assert_eq!(data["user"]["done"]["edges"], json!([{ "node": { "id": 2 } }]));
+        assert_eq!(data["user"]["searched"]["edges"], json!([{ "node": { "task": "Taylor chore 1" } }]));
+    }
+
+    #[tokio::test]
+    async fn test_mutations() {
+        let schema = seeded_schema(0);
+
+        let data = execute(&schema, "mutation { createTodo(userId: 2, task: \"Fix the fence\") { id userId completed } }").await;
+        assert_eq!(data["createTodo"], json!({ "id": 1, "userId": 2, "completed": false }));
+
+        let data = execute(&schema, "mutation { updateTodo(id: 1, task: \"Admire the fence\") { task userId } }").await;
+        assert_eq!(data["updateTodo"], json!({ "task": "Admire the fence", "userId": 2 }));
+
+        let data = execute(&schema, "mutation { completeTodo(id: 1) { completed completedDatetime } }").await;
+        assert_eq!(data["completeTodo"]["completed"], true);
+        assert!(data["completeTodo"]["completedDatetime"].is_string());
+
+        let data = execute(&schema, "mutation { uncompleteTodo(id: 1) { completed completedDatetime } }").await;
+        assert_eq!(data["uncompleteTodo"], json!({ "completed": false, "completedDatetime": null }));
+
+        let data = execute(&schema, "mutation { deleteTodo(id: 1) }").await;
+        assert_eq!(data["deleteTodo"], true);
+        let data = execute(&schema, "{ todo(id: 1) { id } }").await;
+        assert_eq!(data["todo"], json!(null));
+
+        // missing ids and empty tasks are errors
+        let admin = || Some(principal(1, Role::Admin));
+        assert_eq!(errors_as(&schema, admin(), "mutation { completeTodo(id: 1) { id } }").await, ["todo 1 not found"]);
+        assert!(!errors_as(&schema, admin(), "mutation { createTodo(userId: 2, task: \" \") { id } }").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_requests_act_as_their_principal() {
+        let schema = seeded_schema(1);
+        let tater = || Some(principal(2, Role::User));
+
+        // nothing is served without a principal
+        assert_eq!(errors_as(&schema, None, "{ todo(id: 1) { id } }").await, ["not authenticated"]);
+
+        // a user sees only themselves and their own todos
+        let data = execute_as(&schema, principal(2, Role::User), "{ users { id todos { edges { node { id } } } } todo(id: 2) { task } }").await;
+        assert_eq!(data["users"], json!([{ "id": 2, "todos": { "edges": [{ "node": { "id": 2 } }] } }]));
+        assert_eq!(data["todo"]["task"], "Tater chore 0");
+        assert!(!errors_as(&schema, tater(), "{ user(id: 1) { id } }").await.is_empty());
+        assert!(!errors_as(&schema, tater(), "{ todo(id: 1) { id } }").await.is_empty());
+
+        // and can't change anyone else's
+        for mutation in [
+            "mutation { createTodo(userId: 1, task: \"Nap\") { id } }",
+            "mutation { updateTodo(id: 1, task: \"Nap\") { id } }",
+            "mutation { completeTodo(id: 1) { id } }",
+            "mutation { uncompleteTodo(id: 1) { id } }",
+            "mutation { deleteTodo(id: 1) }",
+        ] {
+            assert!(!errors_as(&schema, tater(), mutation).await.is_empty(), "{}", mutation);
+        }
+
+        // a read-only API token can read but not write
+        let reader = Principal { credential: Credential::ApiToken { id: 1, scopes: vec![Scope::ReadTodos] }, ..principal(2, Role::User) };
+        assert_eq!(execute_as(&schema, reader.clone(), "{ todo(id: 2) { id } }").await["todo"]["id"], 2);
+        assert!(!errors_as(&schema, Some(reader), "mutation { completeTodo(id: 2) { id } }").await.is_empty());
+    }
+
+    /// Counts the batches passed on to a `TodoLoader`.
+    struct CountingLoader {
+        inner: TodoLoader,
+        batches: AtomicUsize,
+    }
+
+    impl Loader<i64> for CountingLoader {
+        type Value = Vec<TodoItem>;
+        type Error = String;
+
+        async fn load(&self, user_ids: &[i64]) -> Result<HashMap<i64, Vec<TodoItem>>, String> {
+            self.batches.fetch_add(1, Ordering::SeqCst);
+            self.inner.load(user_ids).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_todos_for_many_users_are_batched() {
+        let schema = seeded_schema(2);
+
+        let data = execute(&schema, "{ users { email todos { edges { node { task } } } } }").await;
+        let users = data["users"].as_array().unwrap();
+        assert_eq!(users.len(), 3);
+        for user in users {
+            assert_eq!(user["todos"]["edges"].as_array().unwrap().len(), 2);
+        }
+
+        // concurrent lookups through the loader become a single query
+        let todo_repo = TodoRepository::new(None).unwrap();
+        for user_id in 1..=3 {
+            todo_repo.save_new_item(&TodoItemDTO { user_id, task: "Clean the gutters".to_string() }).unwrap();
+        }
+        let inner = TodoLoader::new(Arc::new(Mutex::new(todo_repo)));
+        let loader = DataLoader::new(CountingLoader { inner, batches: AtomicUsize::new(0) }, tokio::spawn);
+        let (first, second, third) = tokio::join!(loader.load_one(1), loader.load_one(2), loader.load_one(3));
+        for todos in [first, second, third] {
+            assert_eq!(todos.unwrap().unwrap().len(), 1);
+        }
+        assert_eq!(loader.loader().batches.load(Ordering::SeqCst), 1);
+    }
+}
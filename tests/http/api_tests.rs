@@ -0,0 +1,446 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use to_dont::http::{self, ApiState};
+    use to_dont::models::Role;
+    use to_dont::repository::sqlite::user_repository::UserRepository;
+
+    fn app() -> Router {
+        http::router(Arc::new(ApiState::open(None).expect("in-memory database")))
+    }
+
+    /// A fresh shared in-memory database, for tests that also reach it directly.
+    fn database() -> String {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        format!("file:http_api_{}?mode=memory&cache=shared", COUNT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn app_on(database: &str) -> Router {
+        http::router(Arc::new(ApiState::open(Some(database)).expect("in-memory database")))
+    }
+
+    /// Send a request to the router and return the status and the JSON body (or `Null` if empty).
+    async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        send_as(app, None, method, uri, body).await
+    }
+
+    /// Like `send`, with an optional bearer token.
+    async fn send_as(app: &Router, token: Option<&str>, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header("content-type", "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        let value = if bytes.is_empty() { Value::Null } else { serde_json::from_slice(&bytes).unwrap() };
+        (status, value)
+    }
+
+    async fn create_user(app: &Router, admin: Option<&str>, email: &str) -> i64 {
+        let (status, user) = send_as(
+            app,
+            admin,
+            Method::POST,
+            "/users",
+            Some(json!({ "first_name": "Taylor", "last_name": "Lowery", "email": email })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        user["id"].as_i64().unwrap()
+    }
+
+    /// Register a user with a password and log them in, returning their id and session token.
+    async fn login(app: &Router, email: &str) -> (i64, String) {
+        let (status, user) = send(
+            app,
+            Method::POST,
+            "/auth/register",
+            Some(json!({ "first_name": "Taylor", "last_name": "Lowery", "email": email, "password": "correct horse" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, session) = send(
+            app,
+            Method::POST,
+            "/auth/login",
+            Some(json!({ "email": email, "password": "correct horse" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(session["user_id"], user["id"]);
+        (user["id"].as_i64().unwrap(), session["token"].as_str().unwrap().to_string())
+    }
+
+    /// Like `login`, for a user who is then made an admin directly in `database`.
+    async fn login_admin(app: &Router, database: &str, email: &str) -> (i64, String) {
+        let (user_id, token) = login(app, email).await;
+        UserRepository::new(Some(database)).unwrap().set_role(&user_id, Role::Admin).unwrap();
+        (user_id, token)
+    }
+
+    #[tokio::test]
+    async fn test_user_crud() {
+        let database = database();
+        let app = app_on(&database);
+        let (user_id, token) = login(&app, "tlowery@fakemail.com").await;
+        let token = Some(token.as_str());
+        let (_, admin) = login_admin(&app, &database, "admin@fakemail.com").await;
+        let admin = Some(admin.as_str());
+        create_user(&app, admin, "someone@fakemail.com").await;
+
+        // the user can fetch themselves, but only admins list or create users
+        let (status, user) = send_as(&app, token, Method::GET, &format!("/users/{}", user_id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["email"], "tlowery@fakemail.com");
+        let (status, _) = send(&app, Method::GET, &format!("/users/{}", user_id), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_as(&app, token, Method::GET, "/users", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let user = json!({ "first_name": "Tater", "last_name": "Tot", "email": "tot@fakemail.com" });
+        let (status, _) = send_as(&app, token, Method::POST, "/users", Some(user)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (_, users) = send_as(&app, admin, Method::GET, "/users", None).await;
+        assert_eq!(users.as_array().unwrap().len(), 3);
+
+        // update the user, which only they can do
+        let (status, user) = send_as(
+            &app,
+            token,
+            Method::PUT,
+            &format!("/users/{}", user_id),
+            Some(json!({ "first_name": "Tater", "last_name": "Tot", "email": "2hott2tott@fakemail.com" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["first_name"], "Tater");
+
+        let (status, _) = send(&app, Method::DELETE, &format!("/users/{}", user_id), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_as(&app, token, Method::DELETE, &format!("/users/{}", user_id + 1), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // delete the user, after which it is gone
+        let (status, _) = send_as(&app, token, Method::DELETE, &format!("/users/{}", user_id), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = send_as(&app, admin, Method::GET, &format!("/users/{}", user_id), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_todo_crud_and_completion() {
+        let app = app();
+        let (user_id, token) = login(&app, "tlowery@fakemail.com").await;
+        let token = Some(token.as_str());
+        let todos_uri = format!("/users/{}/todos", user_id);
+
+        // create a todo
+        let (status, todo) = send_as(&app, token, Method::POST, &todos_uri, Some(json!({ "task": "Clean the gutters" }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(todo["user_id"], user_id);
+        assert_eq!(todo["completed"], false);
+        let todo_id = todo["id"].as_i64().unwrap();
+        let todo_uri = format!("{}/{}", todos_uri, todo_id);
+
+        // update it
+        let (status, todo) = send_as(&app, token, Method::PUT, &todo_uri, Some(json!({ "task": "Clean the gutters, eventually" }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(todo["task"], "Clean the gutters, eventually");
+
+        // complete and uncomplete it
+        let (status, todo) = send_as(&app, token, Method::POST, &format!("/todos/{}/complete", todo_id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(todo["completed"], true);
+        assert!(todo["completed_datetime"].is_string());
+        let (_, todo) = send_as(&app, token, Method::POST, &format!("/todos/{}/uncomplete", todo_id), None).await;
+        assert_eq!(todo["completed"], false);
+        assert!(todo["completed_datetime"].is_null());
+
+        // it shows up in the user's list until deleted
+        let (_, todos) = send_as(&app, token, Method::GET, &todos_uri, None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        let (status, _) = send_as(&app, token, Method::DELETE, &todo_uri, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, todos) = send_as(&app, token, Method::GET, &todos_uri, None).await;
+        assert!(todos.as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_missing_ids_are_not_found() {
+        let database = database();
+        let app = app_on(&database);
+        let (user_id, token) = login_admin(&app, &database, "tlowery@fakemail.com").await;
+        let token = Some(token.as_str());
+
+        let (status, _) = send_as(&app, token, Method::GET, "/users/42", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send_as(&app, token, Method::GET, &format!("/users/{}/todos/42", user_id), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send_as(&app, token, Method::POST, "/todos/42/complete", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = send_as(&app, token, Method::DELETE, &format!("/users/{}/todos/42", user_id), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_todos_require_a_session() {
+        let app = app();
+        let (user_id, token) = login(&app, "tlowery@fakemail.com").await;
+        let todos_uri = format!("/users/{}/todos", user_id);
+        let (_, todo) = send_as(&app, Some(&token), Method::POST, &todos_uri, Some(json!({ "task": "Nap" }))).await;
+
+        // no token, or a made-up one, is unauthorized
+        let (status, _) = send(&app, Method::GET, &todos_uri, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_as(&app, Some("made-up"), Method::GET, &todos_uri, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // a wrong password doesn't get a token
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/auth/login",
+            Some(json!({ "email": "tlowery@fakemail.com", "password": "wrong horse" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // another user can't list these todos, or reach them by id
+        let (other_id, other_token) = login(&app, "2hott2tott@fakemail.com").await;
+        let other_token = Some(other_token.as_str());
+        let (status, _) = send_as(&app, other_token, Method::GET, &todos_uri, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_as(&app, other_token, Method::POST, &format!("/todos/{}/complete", todo["id"]), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // nor by claiming it is theirs
+        let (status, _) = send_as(&app, other_token, Method::GET, &format!("/users/{}/todos/{}", other_id, todo["id"]), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // after logging out the token stops working
+        let (status, _) = send_as(&app, Some(&token), Method::POST, "/auth/logout", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send_as(&app, Some(&token), Method::GET, &todos_uri, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_api_tokens_are_scoped() {
+        let app = app();
+        let (user_id, session) = login(&app, "tlowery@fakemail.com").await;
+        let session = Some(session.as_str());
+        let todos_uri = format!("/users/{}/todos", user_id);
+        send_as(&app, session, Method::POST, &todos_uri, Some(json!({ "task": "Nap" }))).await;
+
+        // mint a read-only token; the secret is only returned this once
+        let (status, minted) = send_as(&app, session, Method::POST, "/auth/tokens", Some(json!({ "name": "ci", "scopes": ["todos:read"] }))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(minted["scopes"], json!(["todos:read"]));
+        let token = minted["token"].as_str().unwrap().to_string();
+        let token = Some(token.as_str());
+        let (_, tokens) = send_as(&app, session, Method::GET, "/auth/tokens", None).await;
+        assert_eq!(tokens.as_array().unwrap().len(), 1);
+        assert!(tokens[0]["token"].is_null());
+
+        // it can read todos, but not write them or manage tokens
+        let (status, todos) = send_as(&app, token, Method::GET, &todos_uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        let (status, _) = send_as(&app, token, Method::POST, &todos_uri, Some(json!({ "task": "Nap again" }))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send_as(&app, token, Method::GET, "/auth/tokens", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // unknown scopes are rejected
+        let (status, _) = send_as(&app, session, Method::POST, "/auth/tokens", Some(json!({ "name": "ci", "scopes": ["todos:everything"] }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // once revoked it stops working
+        let path = format!("/auth/tokens/{}", minted["id"]);
+        let (status, _) = send_as(&app, session, Method::DELETE, &path, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send_as(&app, token, Method::GET, &todos_uri, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send_as(&app, session, Method::DELETE, &path, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_todo_assignment() {
+        let app = app();
+        let (user_id, token) = login(&app, "tlowery@fakemail.com").await;
+        let (other_id, other_token) = login(&app, "2hott2tott@fakemail.com").await;
+        let (token, other_token) = (Some(token.as_str()), Some(other_token.as_str()));
+        let (_, todo) = send_as(&app, token, Method::POST, &format!("/users/{}/todos", user_id), Some(json!({ "task": "Nap" }))).await;
+        let assign_uri = format!("/todos/{}/assign", todo["id"]);
+
+        // assign it to the other user, who then sees it
+        let (status, assigned) = send_as(&app, token, Method::POST, &assign_uri, Some(json!({ "assignee_id": other_id }))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(assigned["assignee_id"], other_id);
+        let (_, todos) = send_as(&app, other_token, Method::GET, "/todos/assigned", None).await;
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+
+        // unknown assignees are not found
+        let (status, _) = send_as(&app, token, Method::POST, &assign_uri, Some(json!({ "assignee_id": 42 }))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // strangers are refused before the assignee is looked up, so they can't probe for users
+        let (_, stranger_token) = login(&app, "someone@fakemail.com").await;
+        for assignee_id in [json!(42), json!(user_id)] {
+            let body = Some(json!({ "assignee_id": assignee_id }));
+            let (status, _) = send_as(&app, Some(&stranger_token), Method::POST, &assign_uri, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+
+        // the assignee hands it back, and the log shows both steps
+        let (_, unassigned) = send_as(&app, other_token, Method::POST, &assign_uri, Some(json!({ "assignee_id": null }))).await;
+        assert!(unassigned["assignee_id"].is_null());
+        let (status, delegations) = send_as(&app, token, Method::GET, &format!("/todos/{}/delegations", todo["id"]), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(delegations.as_array().unwrap().len(), 2);
+        assert_eq!(delegations[1]["delegated_by"], other_id);
+    }
+
+    #[tokio::test]
+    async fn test_if_match_detects_conflicts() {
+        let app = app();
+        let (user_id, token) = login(&app, "tlowery@fakemail.com").await;
+        let (_, todo) = send_as(&app, Some(&token), Method::POST, &format!("/users/{}/todos", user_id), Some(json!({ "task": "Nap" }))).await;
+        assert_eq!(todo["version"], 1);
+        let todo_uri = format!("/users/{}/todos/{}", user_id, todo["id"]);
+        let put_if_match = |version: &'static str, task: &'static str| {
+            Request::builder()
+                .method(Method::PUT)
+                .uri(&todo_uri)
+                .header("authorization", format!("Bearer {}", token))
+                .header("content-type", "application/json")
+                .header("if-match", version)
+                .body(Body::from(json!({ "task": task }).to_string()))
+                .unwrap()
+        };
+
+        // the version read first is current, so the update goes through
+        let response = app.clone().oneshot(put_if_match("\"1\"", "Long nap")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // a second update from the same stale read is refused
+        let response = app.clone().oneshot(put_if_match("1", "Short nap")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let (_, todo) = send_as(&app, Some(&token), Method::GET, &todo_uri, None).await;
+        assert_eq!((todo["task"].as_str(), todo["version"].as_i64()), (Some("Long nap"), Some(2)));
+
+        // and nonsense versions are rejected
+        let response = app.clone().oneshot(put_if_match("*", "Short nap")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input_is_unprocessable() {
+        let database = database();
+        let app = app_on(&database);
+        let (user_id, token) = login_admin(&app, &database, "tlowery@fakemail.com").await;
+
+        let (status, body) = send_as(&app, Some(&token), Method::POST, &format!("/users/{}/todos", user_id), Some(json!({ "task": "  " }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].is_string());
+
+        let (status, _) = send_as(
+            &app,
+            Some(&token),
+            Method::POST,
+            "/users",
+            Some(json!({ "first_name": "", "last_name": "Lowery", "email": "tlowery@fakemail.com" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, _) = send_as(
+            &app,
+            Some(&token),
+            Method::PUT,
+            &format!("/users/{}", user_id),
+            Some(json!({ "first_name": "Taylor", "last_name": "Lowery", "email": "not an email" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        // so is an email that is already taken
+        let (status, body) = send_as(
+            &app,
+            Some(&token),
+            Method::POST,
+            "/users",
+            Some(json!({ "first_name": "Tater", "last_name": "Tot", "email": "TLOWERY@fakemail.com" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].as_str().unwrap().contains("email"));
+
+        // and a password that is too short
+        let (status, body) = send(
+            &app,
+            Method::POST,
+            "/auth/register",
+            Some(json!({ "first_name": "Tater", "last_name": "Tot", "email": "2hott2tott@fakemail.com", "password": "short" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].as_str().unwrap().contains("password"));
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_covers_routes() {
+        let app = app();
+        let (status, document) = send(&app, Method::GET, "/openapi.json", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(document["openapi"], "3.0.3");
+        for path in ["/auth/register", "/auth/login", "/auth/logout", "/auth/tokens", "/auth/tokens/{id}", "/users", "/users/{id}", "/users/{id}/todos", "/users/{id}/todos/{todo_id}", "/todos/{id}/complete", "/todos/{id}/uncomplete", "/todos/{id}/assign", "/todos/{id}/delegations", "/todos/assigned"] {
+            assert!(document["paths"][path].is_object(), "missing {}", path);
+        }
+        assert!(document["paths"]["/todos/{id}/complete"]["post"]["security"].is_array());
+    }
+
+    #[cfg(feature = "graphql")]
+    #[tokio::test]
+    async fn test_graphql_route() {
+        use to_dont::graphql::build_schema;
+        use to_dont::repository::sqlite::todo_repository::TodoRepository;
+
+        let database = database();
+        let state = Arc::new(ApiState::open(Some(&database)).unwrap());
+        let schema = build_schema(UserRepository::new(Some(&database)).unwrap(), TodoRepository::new(Some(&database)).unwrap());
+        let app = http::router(state.clone()).merge(http::graphql_router(state, schema));
+        let (user_id, token) = login(&app, "tlowery@fakemail.com").await;
+        let query = |user_id: i64| json!({ "query": format!("mutation {{ createTodo(userId: {}, task: \"Nap\") {{ id task }} }}", user_id) });
+
+        // the bearer token is required
+        let (status, _) = send(&app, Method::POST, "/graphql", Some(query(user_id))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = send_as(&app, Some(&token), Method::POST, "/graphql", Some(query(user_id))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["createTodo"], json!({ "id": 1, "task": "Nap" }));
+
+        // and it acts as its user, who can't create todos for others
+        let (_, body) = send_as(&app, Some(&token), Method::POST, "/graphql", Some(query(user_id + 1))).await;
+        assert!(body["errors"].is_array());
+    }
+}
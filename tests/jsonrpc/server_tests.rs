@@ -0,0 +1,189 @@
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::{json, Value};
+
+    use to_dont::auth::AuthService;
+    use to_dont::jsonrpc::protocol::{FORBIDDEN, INVALID_PARAMS, METHOD_NOT_FOUND, NOT_FOUND, PARSE_ERROR, UNAUTHORIZED};
+    use to_dont::jsonrpc::{Server, TODOS_CHANGED};
+    use to_dont::models::{Role, UserDTO};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::sqlite::user_repository::UserRepository;
+
+    /// A fresh shared in-memory database for one test.
+    fn database() -> String {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        format!("file:jsonrpc_server_{}?mode=memory&cache=shared", COUNT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Register a user with a password on `database`, returning their session token.
+    /// The database only lives while a connection to it is open, so open the
+    /// server first.
+    fn sign_up(database: &str, email: &str, role: Role) -> String {
+        let auth = AuthService::new(Some(database)).unwrap();
+        let user = UserDTO { first_name: "Taylor".to_string(), last_name: "Lowery".to_string(), email: email.to_string() };
+        let user_id = auth.register(&user, "correct horse").unwrap();
+        UserRepository::new(Some(database)).unwrap().set_role(&user_id, role).unwrap();
+        auth.authenticate(email, "correct horse").unwrap().token
+    }
+
+    fn unauthenticated_server(database: &str) -> Server {
+        Server::new(AuthService::new(Some(database)).unwrap(), TodoRepository::new(Some(database)).unwrap())
+    }
+
+    /// A server authenticated as an admin, who is user 1.
+    fn server() -> Server {
+        let database = database();
+        let mut server = unauthenticated_server(&database);
+        let token = sign_up(&database, "admin@fakemail.com", Role::Admin);
+        assert_eq!(call(&mut server, 0, "auth.authenticate", json!({ "token": token }))[0]["result"]["user_id"], 1);
+        server
+    }
+
+    fn call(server: &mut Server, id: i64, method: &str, params: Value) -> Vec<Value> {
+        server.handle_line(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string())
+    }
+
+    #[test]
+    fn test_serve_over_streams() {
+        let mut server = server();
+        let input = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "users.save_new_item",
+                    "params": { "first_name": "Taylor", "last_name": "Lowery", "email": "tlowery@fakemail.com" } }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "users.select_item_by_id", "params": { "id": 2 } }),
+        ]
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+        let mut output = Vec::new();
+        server.serve(Cursor::new(input), &mut output).unwrap();
+
+        // one response line per request, in order
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], json!({ "jsonrpc": "2.0", "id": 1, "result": 2 }));
+        assert_eq!(lines[1]["result"]["email"], "tlowery@fakemail.com");
+    }
+
+    #[test]
+    fn test_todo_writes_push_notifications() {
+        let mut server = server();
+
+        // creating a todo responds with the id, then notifies
+        let messages = call(&mut server, 1, "todos.save_new_item", json!({ "user_id": 1, "task": "Sort the mail" }));
+        assert_eq!(messages[0]["result"], 1);
+        assert_eq!(messages[1], json!({ "jsonrpc": "2.0", "method": TODOS_CHANGED, "params": { "id": 1, "kind": "created" } }));
+
+        let messages = call(&mut server, 2, "todos.complete_todo_item", json!({ "id": 1 }));
+        assert_eq!(messages[1]["params"]["kind"], "completed");
+
+        let messages = call(&mut server, 3, "todos.get_user_todos", json!({ "user_id": 1 }));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["result"][0]["completed"], true);
+
+        let messages = call(&mut server, 4, "todos.uncomplete_todo_item", json!({ "id": 1 }));
+        assert_eq!(messages[1]["params"]["kind"], "uncompleted");
+
+        let messages = call(&mut server, 5, "todos.update_item", json!({ "id": 1, "item": { "user_id": 1, "task": "Sort the mail later" } }));
+        assert_eq!(messages[1]["params"]["kind"], "updated");
+
+        let messages = call(&mut server, 6, "todos.delete_item_by_id", json!({ "id": 1 }));
+        assert_eq!(messages[1]["params"]["kind"], "deleted");
+
+        // writes to missing todos fail, and don't notify
+        let messages = call(&mut server, 7, "todos.delete_item_by_id", json!({ "id": 1 }));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["error"]["code"], NOT_FOUND);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = server();
+
+        assert_eq!(server.handle_line("{not json")[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(call(&mut server, 1, "todos.procrastinate", json!({}))[0]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(call(&mut server, 2, "todos.select_item_by_id", json!({ "todo": 1 }))[0]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call(&mut server, 3, "todos.select_item_by_id", json!({ "id": 1 }))[0]["error"]["code"], NOT_FOUND);
+        assert_eq!(call(&mut server, 4, "todos.save_new_item", json!({ "user_id": 1, "task": "" }))[0]["error"]["code"], INVALID_PARAMS);
+
+        // user validation errors carry the failing fields as data
+        let messages = call(&mut server, 5, "users.save_new_item", json!({ "first_name": "Taylor", "last_name": "Lowery", "email": "nope" }));
+        assert_eq!(messages[0]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(messages[0]["error"]["data"][0]["field"], "email");
+    }
+
+    #[test]
+    fn test_batches_and_client_notifications() {
+        let mut server = server();
+
+        // a client notification (no id) is executed but gets no response
+        let messages = server.handle_line(
+            &json!({ "jsonrpc": "2.0", "method": "todos.save_new_item", "params": { "user_id": 1, "task": "Nap" } }).to_string(),
+        );
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["method"], TODOS_CHANGED);
+
+        // a batch gets an array of responses, followed by the notifications
+        let messages = server.handle_line(
+            &json!([
+                { "jsonrpc": "2.0", "id": 1, "method": "todos.complete_todo_item", "params": { "id": 1 } },
+                { "jsonrpc": "2.0", "id": 2, "method": "todos.select_item_by_id", "params": { "id": 1 } },
+            ])
+            .to_string(),
+        );
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0][0]["result"], 1);
+        assert_eq!(messages[0][1]["result"]["completed"], true);
+        assert_eq!(messages[1]["params"]["kind"], "completed");
+
+        // a null id is still a request, answered with a null id
+        let messages = server.handle_line(
+            &json!({ "jsonrpc": "2.0", "id": null, "method": "todos.select_item_by_id", "params": { "id": 1 } }).to_string(),
+        );
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["id"], Value::Null);
+        assert_eq!(messages[0]["result"]["task"], "Nap");
+    }
+
+    #[test]
+    fn test_calls_act_as_the_authenticated_principal() {
+        let database = database();
+        let mut server = unauthenticated_server(&database);
+        let owner = sign_up(&database, "tlowery@fakemail.com", Role::User);
+        let other = sign_up(&database, "2hott2tott@fakemail.com", Role::User);
+
+        // nothing works before authenticating, or with a made-up token
+        assert_eq!(call(&mut server, 1, "todos.get_user_todos", json!({ "user_id": 1 }))[0]["error"]["code"], UNAUTHORIZED);
+        assert_eq!(call(&mut server, 2, "auth.authenticate", json!({ "token": "made-up" }))[0]["error"]["code"], UNAUTHORIZED);
+
+        // the owner creates a todo
+        call(&mut server, 3, "auth.authenticate", json!({ "token": owner }));
+        assert_eq!(call(&mut server, 4, "todos.save_new_item", json!({ "user_id": 1, "task": "Nap" }))[0]["result"], 1);
+
+        // another user can't reach it, create todos for the owner, or manage users
+        call(&mut server, 5, "auth.authenticate", json!({ "token": other }));
+        for (method, params) in [
+            ("todos.select_item_by_id", json!({ "id": 1 })),
+            ("todos.get_user_todos", json!({ "user_id": 1 })),
+            ("todos.complete_todo_item", json!({ "id": 1 })),
+            ("todos.update_item", json!({ "id": 1, "item": { "user_id": 2, "task": "Mine now" } })),
+            ("todos.delete_item_by_id", json!({ "id": 1 })),
+            ("todos.save_new_item", json!({ "user_id": 1, "task": "Nap more" })),
+            ("users.select_item_by_id", json!({ "id": 1 })),
+            ("users.delete_item_by_id", json!({ "id": 1 })),
+            ("users.save_new_item", json!({ "first_name": "Tot", "last_name": "Lowery", "email": "tot@fakemail.com" })),
+        ] {
+            let messages = call(&mut server, 6, method, params);
+            assert_eq!(messages[0]["error"]["code"], FORBIDDEN, "{}", method);
+            assert_eq!(messages.len(), 1);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use to_dont::maintenance::{Maintenance, TableRowCount};
+    use to_dont::models::{TodoItemDTO, UserDTO};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::sqlite::user_repository::UserRepository;
+    use to_dont::repository::Repository;
+
+    /// A database file in the temp directory, with any leftover from an
+    /// earlier run removed.
+    fn test_db(name: &str) -> Result<PathBuf, std::io::Error> {
+        let path = std::env::temp_dir().join(format!("to_dont_{}_{}.db3", std::process::id(), name));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(path)
+    }
+
+    fn save_todos(repo: &TodoRepository, count: usize) -> Result<(), rusqlite::Error> {
+        for i in 0..count {
+            repo.save_new_item(&TodoItemDTO { user_id: 1, task: format!("Task {}", i) })?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_and_restore_a_live_database() -> Result<(), Box<dyn std::error::Error>> {
+        let path = test_db("maintenance_live")?;
+        let backup_path = test_db("maintenance_backup")?;
+        let repo = TodoRepository::new(Some(path.to_str().unwrap()))?;
+        save_todos(&repo, 3)?;
+
+        let mut maintenance = Maintenance::open(path.to_str().unwrap())?;
+        maintenance.backup_to(&backup_path)?;
+        let copy = TodoRepository::new(Some(backup_path.to_str().unwrap()))?;
+        assert_eq!(copy.get_user_todos(&1)?.len(), 3);
+        drop(copy);
+
+        // later changes are undone by restoring, even for connections already open
+        save_todos(&repo, 2)?;
+        repo.delete_item_by_id(&1)?;
+        maintenance.restore_from(&backup_path)?;
+        let tasks: Vec<String> = repo.get_user_todos(&1)?.into_iter().map(|todo| todo.task).collect();
+        assert_eq!(tasks, vec!["Task 0", "Task 1", "Task 2"]);
+
+        // a missing backup is refused rather than restored as an empty database
+        let missing = test_db("maintenance_missing")?;
+        assert!(maintenance.restore_from(&missing).is_err());
+        assert!(!missing.exists());
+        assert_eq!(repo.get_user_todos(&1)?.len(), 3);
+
+        drop((repo, maintenance));
+        fs::remove_file(&path)?;
+        fs::remove_file(&backup_path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_a_corrupt_backup_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+        let path = test_db("maintenance_target")?;
+        let corrupt = test_db("maintenance_corrupt")?;
+        fs::write(&corrupt, "not a database")?;
+        let repo = TodoRepository::new(Some(path.to_str().unwrap()))?;
+        save_todos(&repo, 1)?;
+
+        let mut maintenance = Maintenance::open(path.to_str().unwrap())?;
+        assert!(maintenance.restore_from(&corrupt).is_err());
+        assert_eq!(repo.get_user_todos(&1)?.len(), 1);
+
+        drop((repo, maintenance));
+        fs::remove_file(&path)?;
+        fs::remove_file(&corrupt)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum_analyze_and_reports() -> Result<(), Box<dyn std::error::Error>> {
+        let path = test_db("maintenance_reports")?;
+        let todos = TodoRepository::new(Some(path.to_str().unwrap()))?;
+        let users = UserRepository::new(Some(path.to_str().unwrap()))?;
+        users.save_new_item(&UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        })?;
+        for i in 0..200 {
+            todos.save_new_item(&TodoItemDTO { user_id: 1, task: format!("Task {} {}", i, "x".repeat(200)) })?;
+        }
+
+        let maintenance = Maintenance::open(path.to_str().unwrap())?;
+        let counts = maintenance.row_counts()?;
+        assert!(counts.contains(&TableRowCount { table: "todos".to_string(), rows: 200 }));
+        assert!(counts.contains(&TableRowCount { table: "users".to_string(), rows: 1 }));
+        assert!(counts.windows(2).all(|pair| pair[0].table < pair[1].table));
+
+        // deleting leaves free pages behind until a vacuum
+        for id in 1..=200 {
+            todos.delete_item_by_id(&id)?;
+        }
+        let before = maintenance.size()?;
+        assert!(before.free_pages > 0);
+        assert_eq!(before.bytes(), fs::metadata(&path)?.len() as i64);
+        maintenance.vacuum()?;
+        let after = maintenance.size()?;
+        assert_eq!(after.free_pages, 0);
+        assert!(after.bytes() < before.bytes());
+
+        maintenance.analyze()?;
+        assert!(maintenance.row_counts()?.iter().all(|count| !count.table.starts_with("sqlite_")));
+        assert!(maintenance.integrity_check()?.is_empty());
+
+        drop((todos, users, maintenance));
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -1 +1,19 @@
-mod sqlite;
\ No newline at end of file
+mod auth;
+mod backends;
+mod maintenance;
+mod sqlite;
+mod stats;
+mod sync;
+mod undo;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "jsonrpc")]
+mod jsonrpc;
+#[cfg(feature = "webhooks")]
+mod webhooks;
+#[cfg(feature = "encryption")]
+mod encryption;
@@ -1,2 +1,5 @@
+mod organization_repo_tests;
 mod user_repo_tests;
-mod todo_repo_tests;
\ No newline at end of file
+mod todo_list_repo_tests;
+mod todo_repo_tests;
+mod webhook_repo_tests;
@@ -0,0 +1,105 @@
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use to_dont::models::{OrgRole, OrganizationDTO, TodoItemDTO};
+    use to_dont::repository::sqlite::organization_repository::OrganizationRepository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::{Repository, RepositoryError};
+
+    #[test]
+    fn test_memberships() -> Result<(), RepositoryError> {
+        let organization_repo = OrganizationRepository::new(None)?;
+        let org_id = organization_repo.save_new_item(&OrganizationDTO { name: " Acme ".to_string() })?;
+        assert_eq!(organization_repo.select_item_by_id(&org_id)?.name, "Acme");
+
+        // add two members, then promote one
+        organization_repo.add_member(&org_id, &1, OrgRole::Owner)?;
+        organization_repo.add_member(&org_id, &2, OrgRole::Member)?;
+        organization_repo.add_member(&org_id, &2, OrgRole::Admin)?;
+        let members = organization_repo.get_members(&org_id)?;
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[1].role, OrgRole::Admin);
+        assert_eq!(organization_repo.get_user_organizations(&2)?.len(), 1);
+
+        // removing a member takes them out of the organization
+        assert_eq!(organization_repo.remove_member(&org_id, &2)?, 1);
+        assert!(organization_repo.get_membership(&org_id, &2)?.is_none());
+        assert!(organization_repo.get_user_organizations(&2)?.is_empty());
+
+        // the last owner can't leave or be demoted, but can once there is another
+        let last_owner = |result| matches!(result, Err(RepositoryError::Validation(e)) if e.has_field("role"));
+        assert!(last_owner(organization_repo.remove_member(&org_id, &1)));
+        assert!(last_owner(organization_repo.add_member(&org_id, &1, OrgRole::Admin)));
+        organization_repo.add_member(&org_id, &3, OrgRole::Owner)?;
+        organization_repo.add_member(&org_id, &1, OrgRole::Admin)?;
+        assert!(last_owner(organization_repo.remove_member(&org_id, &3)));
+        assert_eq!(organization_repo.remove_member(&org_id, &1)?, 1);
+
+        // organizations that don't exist can't gain members
+        assert!(matches!(organization_repo.add_member(&42, &1, OrgRole::Member), Err(RepositoryError::NotFound)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tenant_todos_never_cross_organizations() -> Result<(), RepositoryError> {
+        let conn_string = "file:organization_tenants?mode=memory&cache=shared";
+        let organization_repo = OrganizationRepository::new(Some(conn_string))?;
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let acme = organization_repo.save_new_item(&OrganizationDTO { name: "Acme".to_string() })?;
+        let globex = organization_repo.save_new_item(&OrganizationDTO { name: "Globex".to_string() })?;
+        organization_repo.add_member(&acme, &1, OrgRole::Owner)?;
+        organization_repo.add_member(&globex, &2, OrgRole::Owner)?;
+
+        // each organization gets a todo, and there is a personal one outside both
+        let acme_todo = organization_repo.todos(&acme).save_new_item(&TodoItemDTO { user_id: 1, task: "Anvils".to_string() })?;
+        let globex_todo = organization_repo.todos(&globex).save_new_item(&TodoItemDTO { user_id: 2, task: "Domination".to_string() })?;
+        todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Diary".to_string() })?;
+        assert_eq!(organization_repo.todos(&acme).select_item_by_id(&acme_todo)?.organization_id, Some(acme));
+
+        // non-members can't own todos in an organization
+        let result = organization_repo.todos(&acme).save_new_item(&TodoItemDTO { user_id: 2, task: "Espionage".to_string() });
+        assert!(matches!(result, Err(RepositoryError::NotFound)));
+        assert_eq!(organization_repo.todos(&acme).update_item(&acme_todo, &TodoItemDTO { user_id: 2, task: "Anvils".to_string() })?, 1);
+        assert_eq!(organization_repo.todos(&acme).select_item_by_id(&acme_todo)?.user_id, 1);
+
+        // the personal todo api can't reach organizations' todos
+        assert!(todo_repo.select_item_by_id(&acme_todo).is_err());
+        assert_eq!(todo_repo.get_user_todos(&1).map_err(RepositoryError::from)?.len(), 1);
+        assert_eq!(todo_repo.complete_todo_item(&acme_todo).map_err(RepositoryError::from)?, 0);
+        assert_eq!(todo_repo.update_item(&acme_todo, &TodoItemDTO { user_id: 1, task: "Mine".to_string() }).map_err(RepositoryError::from)?, 0);
+        assert_eq!(todo_repo.delete_item_by_id(&acme_todo).map_err(RepositoryError::from)?, 0);
+
+        // the other organization's todos look like they don't exist
+        let acme_todos = organization_repo.todos(&acme);
+        assert_eq!(acme_todos.get_todos()?.len(), 1);
+        assert!(acme_todos.get_user_todos(&2)?.is_empty());
+        assert!(matches!(acme_todos.select_item_by_id(&globex_todo), Err(RepositoryError::NotFound)));
+        assert_eq!(acme_todos.complete_todo_item(&globex_todo)?, 0);
+        assert_eq!(acme_todos.delete_item_by_id(&globex_todo)?, 0);
+        assert_eq!(organization_repo.todos(&globex).get_todos()?.len(), 1);
+
+        // snoozed todos are hidden from the organization's lists
+        let conn = rusqlite::Connection::open(conn_string)?;
+        let until = (Utc::now() + Duration::hours(1)).timestamp();
+        conn.execute("UPDATE todos SET snoozed_until = ?1 WHERE id = ?2", rusqlite::params![until, globex_todo])?;
+        assert!(organization_repo.todos(&globex).get_todos()?.is_empty());
+        assert!(organization_repo.todos(&globex).get_user_todos(&2)?.is_empty());
+        conn.execute("UPDATE todos SET snoozed_until = NULL WHERE id = ?1", rusqlite::params![globex_todo])?;
+
+        // statistics are per organization
+        acme_todos.complete_todo_item(&acme_todo)?;
+        let stats = organization_repo.get_stats(&acme)?;
+        assert_eq!((stats.member_count, stats.todo_count, stats.completed_count, stats.open_count), (1, 1, 1, 0));
+
+        // deleting an organization deletes its todos, and only its todos
+        organization_repo.delete_item_by_id(&acme)?;
+        assert!(todo_repo.select_item_by_id(&acme_todo).is_err());
+        assert_eq!(todo_repo.get_user_todos(&1).map_err(RepositoryError::from)?.len(), 1);
+        assert_eq!(organization_repo.get_stats(&globex)?.todo_count, 1);
+
+        Ok(())
+    }
+}
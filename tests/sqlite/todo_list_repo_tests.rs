@@ -0,0 +1,75 @@
+
+#[cfg(test)]
+mod tests {
+    use to_dont::models::{ShareLevel, TodoItemDTO, TodoListDTO};
+    use to_dont::repository::sqlite::todo_list_repository::TodoListRepository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::{Repository, RepositoryError};
+
+    #[test]
+    fn test_todo_list_crud() -> Result<(), RepositoryError> {
+        let list_repo = TodoListRepository::new(None)?;
+
+        // create a list, trimmed like other names
+        let list_id = list_repo.save_new_item(&TodoListDTO { owner_id: 1, name: " Chores ".to_string() })?;
+        let list = list_repo.select_item_by_id(&list_id)?;
+        assert_eq!(list.owner_id, 1);
+        assert_eq!(list.name, "Chores");
+
+        // blank names are rejected
+        let result = list_repo.save_new_item(&TodoListDTO { owner_id: 1, name: " ".to_string() });
+        assert!(matches!(result, Err(RepositoryError::Validation(_))));
+
+        // rename it, then delete it
+        list_repo.update_item(&list_id, &TodoListDTO { owner_id: 1, name: "Errands".to_string() })?;
+        assert_eq!(list_repo.select_item_by_id(&list_id)?.name, "Errands");
+        assert_eq!(list_repo.delete_item_by_id(&list_id)?, 1);
+        assert!(matches!(list_repo.select_item_by_id(&list_id), Err(RepositoryError::NotFound)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharing_and_visible_todos() -> Result<(), RepositoryError> {
+        // both repositories share one in-memory database
+        let conn_string = "file:todo_list_sharing?mode=memory&cache=shared";
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let list_repo = TodoListRepository::new(Some(conn_string))?;
+
+        // user 1 has a list with one todo in it and one todo outside of it
+        let list_id = list_repo.save_new_item(&TodoListDTO { owner_id: 1, name: "Chores".to_string() })?;
+        let listed_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Mop".to_string() })?;
+        todo_repo.set_todo_list(&listed_id, Some(list_id))?;
+        todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Diary".to_string() })?;
+        let own_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 2, task: "Nap".to_string() })?;
+        assert_eq!(todo_repo.select_item_by_id(&listed_id)?.list_id, Some(list_id));
+
+        // an invitation grants nothing until it is accepted
+        list_repo.invite(&list_id, &2, ShareLevel::Editor, &1)?;
+        assert_eq!(list_repo.access_level(&list_id, &1)?, Some(ShareLevel::Owner));
+        assert_eq!(list_repo.access_level(&list_id, &2)?, None);
+        assert_eq!(list_repo.get_pending_invitations(&2)?.len(), 1);
+        assert_eq!(list_repo.get_visible_todos(&2)?.len(), 1);
+
+        // once accepted, user 2 sees the list and its todos, but not user 1's other todo
+        assert_eq!(list_repo.accept_invitation(&list_id, &2)?, 1);
+        assert_eq!(list_repo.accept_invitation(&list_id, &2)?, 0);
+        assert_eq!(list_repo.access_level(&list_id, &2)?, Some(ShareLevel::Editor));
+        assert!(list_repo.get_pending_invitations(&2)?.is_empty());
+        assert_eq!(list_repo.get_user_lists(&2)?.len(), 1);
+        let visible: Vec<i64> = list_repo.get_visible_todos(&2)?.iter().map(|todo| todo.id).collect();
+        assert_eq!(visible, vec![listed_id, own_id]);
+
+        // re-inviting changes the level without another acceptance
+        list_repo.invite(&list_id, &2, ShareLevel::Viewer, &1)?;
+        assert_eq!(list_repo.access_level(&list_id, &2)?, Some(ShareLevel::Viewer));
+        assert!(list_repo.get_members(&list_id)?[0].is_accepted());
+
+        // deleting the list removes the share but keeps the todo
+        list_repo.delete_item_by_id(&list_id)?;
+        assert!(list_repo.get_user_lists(&2)?.is_empty());
+        assert_eq!(todo_repo.select_item_by_id(&listed_id)?.list_id, None);
+
+        Ok(())
+    }
+}
@@ -4,9 +4,12 @@ mod tests {
     use std::error::Error;
     use std::fs;
     use std::path::Path;
+    use std::sync::{Arc, Mutex};
 
-    use to_dont::models::todo::{TodoItem, TodoItemDTO};
-    use to_dont::repository::Repository;
+    use chrono::{Duration, Utc};
+
+    use to_dont::models::todo::{RefusalReason, TodoItem, TodoItemDTO};
+    use to_dont::repository::{EntityType, EventKind, Repository, RepositoryError};
     use to_dont::repository::sqlite::todo_repository;
 
     #[test]
@@ -66,6 +69,13 @@ mod tests {
         assert_eq!(todo_item.user_id, update_dto.user_id);
         assert_eq!(todo_item.task, update_dto.task);
 
+        // the owner in the dto can't hand the todo to someone else
+        todo_repo.update_item(&todo_id, &TodoItemDTO { user_id: 2, task: "Handed off".to_string() })?;
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.user_id, 1);
+        let handed_off = TodoItemDTO { user_id: 2, task: "Handed off".to_string() };
+        assert_eq!(todo_repo.update_item_if_version(&todo_id, &handed_off, 3).ok(), Some(1));
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.user_id, 1);
+
         Ok(())
     }
 
@@ -214,6 +224,251 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_get_todos_for_users() -> Result<(), rusqlite::Error> {
+        let todo_repo = todo_repository::TodoRepository::new(None)?;
+
+        // two todos for user 1, one for user 2 and one for user 3
+        for (user_id, task) in [(1, "Test todo item"), (2, "Test todo item"), (1, "Test todo item 2"), (3, "Test todo item")] {
+            todo_repo.save_new_item(&TodoItemDTO { user_id, task: task.to_string() })?;
+        }
+
+        // fetch users 1, 2 and 4 at once
+        let todos_by_user = todo_repo.get_todos_for_users(&[1, 2, 4])?;
+
+        // every requested user has an entry, and user 3 was not fetched
+        assert_eq!(todos_by_user.len(), 3);
+        assert_eq!(todos_by_user[&1].len(), 2);
+        assert_eq!(todos_by_user[&1][1].task, "Test todo item 2");
+        assert_eq!(todos_by_user[&2].len(), 1);
+        assert!(todos_by_user[&4].is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assign_todo_item() -> Result<(), rusqlite::Error> {
+        let todo_repo = todo_repository::TodoRepository::new(None)?;
+        let todo_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Take out the trash".to_string() })?;
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.assignee_id, None);
+
+        // user 1 passes it to user 2, who passes it to user 3
+        assert_eq!(todo_repo.assign_todo_item(&todo_id, Some(2), &1)?, 1);
+        assert_eq!(todo_repo.assign_todo_item(&todo_id, Some(3), &2)?, 1);
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.assignee_id, Some(3));
+        assert_eq!(todo_repo.get_assigned_todos(&3)?.len(), 1);
+        assert!(todo_repo.get_assigned_todos(&2)?.is_empty());
+
+        // assigning to the current assignee changes nothing
+        assert_eq!(todo_repo.assign_todo_item(&todo_id, Some(3), &1)?, 0);
+
+        // then it is unassigned
+        todo_repo.assign_todo_item(&todo_id, None, &3)?;
+        assert!(todo_repo.get_assigned_todos(&3)?.is_empty());
+
+        // every hand-off was logged, in order
+        let chain: Vec<(Option<i64>, Option<i64>, i64)> = todo_repo
+            .get_delegations(&todo_id)?
+            .iter()
+            .map(|delegation| (delegation.from_user_id, delegation.to_user_id, delegation.delegated_by))
+            .collect();
+        assert_eq!(chain, vec![(None, Some(2), 1), (Some(2), Some(3), 2), (Some(3), None, 3)]);
+
+        // missing todos aren't assigned or logged
+        assert_eq!(todo_repo.assign_todo_item(&42, Some(2), &1)?, 0);
+        assert!(todo_repo.get_delegations(&42)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snooze_todo_item() -> Result<(), RepositoryError> {
+        let todo_repo = todo_repository::TodoRepository::new(None)?;
+        let taxes = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Do taxes".to_string() })?;
+        let dentist = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Call the dentist".to_string() })?;
+        let tomorrow = Utc::now() + Duration::days(1);
+
+        // snoozed todos drop out of the user's list until they wake up
+        assert_eq!(todo_repo.snooze(&taxes, &tomorrow, "Too tired")?, 1);
+        todo_repo.snooze(&taxes, &(tomorrow + Duration::days(1)), "  too tired ")?;
+        todo_repo.snooze(&dentist, &tomorrow, "Mercury is in retrograde")?;
+        assert!(todo_repo.get_user_todos(&1)?.is_empty());
+        let snoozed: Vec<i64> = todo_repo.get_snoozed_todos(&1)?.iter().map(|todo| todo.id).collect();
+        assert_eq!(snoozed, vec![dentist, taxes]);
+
+        let todo = todo_repo.select_item_by_id(&taxes)?;
+        assert_eq!(todo.deferral_count, 2);
+        assert_eq!(todo.snoozed_until.map(|until| until.timestamp()), Some((tomorrow + Duration::days(1)).timestamp()));
+
+        // wake-up times in the past and blank excuses are rejected, without counting
+        for (until, excuse, field) in [(Utc::now() - Duration::minutes(1), "Forgot", "until"), (tomorrow, " ", "excuse")] {
+            match todo_repo.snooze(&dentist, &until, excuse) {
+                Err(RepositoryError::Validation(e)) => assert!(e.has_field(field)),
+                other => panic!("expected a validation error, got {:?}", other),
+            }
+        }
+        assert_eq!(todo_repo.select_item_by_id(&dentist)?.deferral_count, 1);
+
+        // unsnoozing brings them back but keeps the count
+        todo_repo.snooze(&dentist, &tomorrow, "Forgot")?;
+        todo_repo.unsnooze(&dentist)?;
+        todo_repo.unsnooze(&taxes)?;
+        assert_eq!(todo_repo.get_user_todos(&1)?.len(), 2);
+        assert!(todo_repo.get_snoozed_todos(&1)?.is_empty());
+        assert_eq!(todo_repo.select_item_by_id(&taxes)?.deferral_count, 2);
+
+        // most deferred first, ties by id
+        let most_deferred: Vec<(i64, i64)> =
+            todo_repo.get_most_deferred(&1, 10)?.iter().map(|todo| (todo.id, todo.deferral_count)).collect();
+        assert_eq!(most_deferred, vec![(taxes, 2), (dentist, 2)]);
+
+        // excuses are logged per todo and tallied per user, ignoring case
+        let excuses: Vec<String> = todo_repo.get_excuses(&taxes)?.into_iter().map(|excuse| excuse.excuse).collect();
+        assert_eq!(excuses, vec!["Too tired", "too tired"]);
+        let report: Vec<(String, i64)> =
+            todo_repo.get_excuse_report(&1)?.into_iter().map(|count| (count.excuse, count.count)).collect();
+        assert_eq!(
+            report,
+            vec![("too tired".to_string(), 2), ("Forgot".to_string(), 1), ("Mercury is in retrograde".to_string(), 1)]
+        );
+        assert!(todo_repo.get_excuse_report(&2)?.is_empty());
+
+        // missing todos can't be snoozed
+        assert_eq!(todo_repo.snooze(&42, &tomorrow, "Doesn't exist")?, 0);
+        assert_eq!(todo_repo.get_excuse_report(&1)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuse_and_reconsider_todo_item() -> Result<(), rusqlite::Error> {
+        let todo_repo = todo_repository::TodoRepository::new(None)?;
+        let gym = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Go to the gym".to_string() })?;
+        let dishes = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Do the dishes".to_string() })?;
+        let done = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Eat cake".to_string() })?;
+        todo_repo.complete_todo_item(&done)?;
+
+        // refusing keeps the todo, open and not done
+        assert_eq!(todo_repo.refuse(&gym, RefusalReason::TooBoring)?, 1);
+        let todo = todo_repo.select_item_by_id(&gym)?;
+        assert!(!todo.completed);
+        assert!(todo.refused_datetime.is_some());
+        assert_eq!(todo.refusal_reason, Some(RefusalReason::TooBoring));
+        assert_eq!(todo_repo.get_user_todos(&1)?.len(), 3);
+
+        // already refused, completed and missing todos can't be refused
+        assert_eq!(todo_repo.refuse(&gym, RefusalReason::Never)?, 0);
+        assert_eq!(todo_repo.refuse(&done, RefusalReason::Never)?, 0);
+        assert_eq!(todo_repo.refuse(&42, RefusalReason::Never)?, 0);
+
+        // reconsidering reopens it, and it can be refused again
+        todo_repo.refuse(&dishes, RefusalReason::NotMyJob)?;
+        assert_eq!(todo_repo.get_refused_todos(&1)?.len(), 2);
+        assert_eq!(todo_repo.reconsider(&gym)?, 1);
+        assert_eq!(todo_repo.reconsider(&gym)?, 0);
+        let todo = todo_repo.select_item_by_id(&gym)?;
+        assert_eq!((todo.refused_datetime, todo.refusal_reason), (None, None));
+        todo_repo.refuse(&gym, RefusalReason::Later)?;
+
+        let refusals = todo_repo.get_refusals(&gym)?;
+        assert_eq!(refusals.len(), 2);
+        assert_eq!(refusals[0].reason, RefusalReason::TooBoring);
+        assert!(refusals[0].reconsidered_datetime.is_some());
+        assert_eq!(refusals[1].reconsidered_datetime, None);
+
+        // every reason is reported, in order
+        let report: Vec<(RefusalReason, i64, i64)> = todo_repo
+            .get_refusal_report(&1)?
+            .iter()
+            .map(|count| (count.reason, count.refused_count, count.reconsidered_count))
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                (RefusalReason::NotMyJob, 1, 0),
+                (RefusalReason::TooBoring, 1, 1),
+                (RefusalReason::Later, 1, 0),
+                (RefusalReason::Never, 0, 0),
+            ]
+        );
+
+        // completing a refused todo takes the refusal back
+        assert_eq!(todo_repo.complete_todo_item(&gym)?, 1);
+        let todo = todo_repo.select_item_by_id(&gym)?;
+        assert!(todo.completed);
+        assert_eq!((todo.refused_datetime, todo.refusal_reason), (None, None));
+        assert!(todo_repo.get_refusals(&gym)?[1].reconsidered_datetime.is_some());
+        assert_eq!(todo_repo.get_refused_todos(&1)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_todo_item_if_version() -> Result<(), RepositoryError> {
+        let todo_repo = todo_repository::TodoRepository::new(None)?;
+        let todo_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Water the plants".to_string() })?;
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.version, 1);
+
+        // every write bumps the version
+        todo_repo.complete_todo_item(&todo_id)?;
+        todo_repo.uncomplete_todo_item(&todo_id)?;
+        assert_eq!(todo_repo.select_item_by_id(&todo_id)?.version, 3);
+
+        // two clients read version 3; the first to write wins
+        let first = TodoItemDTO { user_id: 1, task: "Water the plants today".to_string() };
+        let second = TodoItemDTO { user_id: 1, task: "Buy plastic plants".to_string() };
+        assert_eq!(todo_repo.update_item_if_version(&todo_id, &first, 3)?, 1);
+        assert!(matches!(
+            todo_repo.update_item_if_version(&todo_id, &second, 3),
+            Err(RepositoryError::Conflict { expected: 3, actual: 4 })
+        ));
+        let todo = todo_repo.select_item_by_id(&todo_id)?;
+        assert_eq!((todo.task.as_str(), todo.version), ("Water the plants today", 4));
+
+        assert!(matches!(todo_repo.update_item_if_version(&42, &second, 1), Err(RepositoryError::NotFound)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_events() -> Result<(), RepositoryError> {
+        let todo_repo = todo_repository::TodoRepository::new(None)?;
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let callback_seen = seen.clone();
+        todo_repo.subscribe(move |event| callback_seen.lock().unwrap().push((event.id, event.kind)));
+        let events = todo_repo.events();
+
+        let todo_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Floss".to_string() })?;
+        todo_repo.update_item(&todo_id, &TodoItemDTO { user_id: 1, task: "Floss daily".to_string() })?;
+        todo_repo.complete_todo_item(&todo_id)?;
+        todo_repo.uncomplete_todo_item(&todo_id)?;
+        todo_repo.delete_item_by_id(&todo_id)?;
+
+        // writes that touch nothing, and failed ones, aren't announced
+        todo_repo.complete_todo_item(&todo_id)?;
+        todo_repo.delete_item_by_id(&todo_id)?;
+        assert!(todo_repo.update_item_if_version(&todo_id, &TodoItemDTO { user_id: 1, task: "Floss".to_string() }, 1).is_err());
+
+        let expected = vec![
+            (todo_id, EventKind::Created),
+            (todo_id, EventKind::Updated),
+            (todo_id, EventKind::Completed),
+            (todo_id, EventKind::Uncompleted),
+            (todo_id, EventKind::Deleted),
+        ];
+        assert_eq!(*seen.lock().unwrap(), expected);
+        let received: Vec<_> = events.try_iter().collect();
+        assert!(received.iter().all(|event| event.entity == EntityType::Todo));
+        assert_eq!(received.iter().map(|event| (event.id, event.kind)).collect::<Vec<_>>(), expected);
+
+        // a dropped receiver doesn't stop the others
+        drop(events);
+        todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Floss again".to_string() })?;
+        assert_eq!(seen.lock().unwrap().len(), 6);
+
+        Ok(())
+    }
+
     #[test]
     fn test_create_db_from_todo_repo() -> Result<(), Box<dyn Error>> {
 
@@ -247,4 +502,35 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_ids_are_not_reused_after_upgrade() -> Result<(), rusqlite::Error> {
+        // a database whose ids were plain rowids, with a trigger on todos
+        let conn_string = "file:todo_autoincrement?mode=memory&cache=shared";
+        let conn = rusqlite::Connection::open(conn_string)?;
+        conn.execute_batch(
+            "CREATE TABLE todos (id INTEGER PRIMARY KEY, user_id INTEGER NOT NULL, task TEXT NOT NULL, completed INTEGER NOT NULL DEFAULT 0, \
+created_datetime INTEGER DEFAULT (strftime('%s', 'now')), completed_datetime INTEGER);
+CREATE TABLE deleted_tasks (task TEXT);
+CREATE TRIGGER todos_deleted AFTER DELETE ON todos BEGIN INSERT INTO deleted_tasks VALUES (OLD.task); END;
+INSERT INTO todos (user_id, task) VALUES (1, 'Learn Rust'), (1, 'Walk the dog');",
+        )?;
+
+        // upgrading keeps the todos and the trigger
+        let todo_repo = todo_repository::TodoRepository::new(Some(conn_string))?;
+        assert_eq!(todo_repo.select_item_by_id(&2)?.task, "Walk the dog");
+        assert_eq!(todo_repo.delete_item_by_id(&2)?, 1);
+        let deleted: String = conn.query_row("SELECT group_concat(task) FROM deleted_tasks", (), |row| row.get(0))?;
+        assert_eq!(deleted, "Walk the dog");
+
+        // and a deleted todo's id isn't given to the next one
+        let todo_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Read a book".to_string() })?;
+        assert_eq!(todo_id, 3);
+
+        // opening it again doesn't rebuild the table
+        todo_repository::TodoRepository::new(Some(conn_string))?;
+        assert_eq!(todo_repo.get_user_todos(&1)?.len(), 2);
+
+        Ok(())
+    }
+}
@@ -5,12 +5,13 @@ mod tests {
     use std::fs;
     use std::path::Path;
 
-    use to_dont::models::{User, UserDTO};
-    use to_dont::repository::Repository;
+    use to_dont::models::{Role, TodoItemDTO, User, UserDTO};
+    use to_dont::repository::{EntityType, EventKind, Repository, RepositoryError};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
     use to_dont::repository::sqlite::user_repository;
 
     #[test]
-    fn test_new_user() -> Result<(), rusqlite::Error> {
+    fn test_new_user() -> Result<(), RepositoryError> {
         let user_repo = user_repository::UserRepository::new(None)?;
 
         // create a user
@@ -35,7 +36,7 @@ mod tests {
     }
 
     #[test]
-    fn test_update_user() -> Result<(), rusqlite::Error> {
+    fn test_update_user() -> Result<(), RepositoryError> {
         let user_repo = user_repository::UserRepository::new(None)?;
 
         // create a user
@@ -76,7 +77,7 @@ mod tests {
     }
 
     #[test]
-    fn test_delete_user() -> Result<(), rusqlite::Error> {
+    fn test_delete_user() -> Result<(), RepositoryError> {
         let user_repo = user_repository::UserRepository::new(None)?;
 
         // create a user
@@ -129,6 +130,328 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_get_users() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+
+        // no users yet
+        assert!(user_repo.get_users()?.is_empty());
+
+        // create two users
+        let user_id = user_repo.save_new_item(&UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        })?;
+        let user_id2 = user_repo.save_new_item(&UserDTO {
+            first_name: "Tater".to_string(),
+            last_name: "Tot".to_string(),
+            email: "2hott2tott@fakemail.com".to_string(),
+        })?;
+
+        // both come back, in id order
+        let users = user_repo.get_users()?;
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].id, user_id);
+        assert_eq!(users[1].id, user_id2);
+
+        Ok(())
+    }
+
+
+    #[test]
+    fn test_user_is_validated_and_normalized() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+
+        // blank names and a malformed email are all reported together
+        let invalid_user = UserDTO {
+            first_name: " ".to_string(),
+            last_name: "".to_string(),
+            email: "tlowery at fakemail".to_string(),
+        };
+        match user_repo.save_new_item(&invalid_user) {
+            Err(RepositoryError::Validation(e)) => {
+                assert_eq!(e.errors.len(), 3);
+                assert!(e.has_field("first_name"));
+                assert!(e.has_field("last_name"));
+                assert!(e.has_field("email"));
+            }
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        // an empty email is rejected too
+        let no_email = UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "".to_string(),
+        };
+        assert!(matches!(user_repo.save_new_item(&no_email), Err(RepositoryError::Validation(_))));
+
+        // a valid user is stored trimmed, with the email lower-cased
+        let user_id = user_repo.save_new_item(&UserDTO {
+            first_name: " Taylor ".to_string(),
+            last_name: "Lowery".to_string(),
+            email: " TLowery@FakeMail.com ".to_string(),
+        })?;
+        let user: User = user_repo.select_item_by_id(&user_id)?;
+        assert_eq!(user.first_name, "Taylor");
+        assert_eq!(user.email, "tlowery@fakemail.com");
+
+        // updates are validated as well
+        let result = user_repo.update_item(&user_id, &UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "@fakemail.com".to_string(),
+        });
+        assert!(matches!(result, Err(RepositoryError::Validation(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_email_must_be_unique() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+
+        let user_id = user_repo.save_new_item(&UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        })?;
+        let other_user_id = user_repo.save_new_item(&UserDTO {
+            first_name: "Tater".to_string(),
+            last_name: "Tot".to_string(),
+            email: "2hott2tott@fakemail.com".to_string(),
+        })?;
+
+        // the same email in a different case is a duplicate
+        let duplicate = UserDTO {
+            first_name: "Other".to_string(),
+            last_name: "Taylor".to_string(),
+            email: "TLOWERY@fakemail.com".to_string(),
+        };
+        match user_repo.save_new_item(&duplicate) {
+            Err(RepositoryError::Validation(e)) => assert!(e.has_field("email")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        // so is updating another user to it
+        assert!(matches!(user_repo.update_item(&other_user_id, &duplicate), Err(RepositoryError::Validation(_))));
+
+        // but a user can keep their own email
+        user_repo.update_item(&user_id, &duplicate)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_emails_from_before_validation_are_reported() -> Result<(), RepositoryError> {
+        // a database from before emails were normalized and indexed
+        let conn_string = "file:user_duplicate_emails?mode=memory&cache=shared";
+        let conn = rusqlite::Connection::open(conn_string)?;
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, first_name TEXT NOT NULL, last_name TEXT NOT NULL, email TEXT NOT NULL);
+INSERT INTO users (first_name, last_name, email) VALUES ('Taylor', 'Lowery', 'tlowery@fakemail.com');
+INSERT INTO users (first_name, last_name, email) VALUES ('Tater', 'Tot', '2hott2tott@fakemail.com');
+INSERT INTO users (first_name, last_name, email) VALUES ('Other', 'Taylor', ' TLowery@fakemail.com');",
+        )?;
+
+        // the clash is reported with the ids involved, and nothing is indexed
+        match user_repository::UserRepository::new(Some(conn_string)) {
+            Err(RepositoryError::Validation(e)) => {
+                assert!(e.has_field("email"));
+                assert!(e.errors[0].message.contains("tlowery@fakemail.com is used by users 1, 3"));
+            }
+            other => panic!("expected a validation error, got {:?}", other.map(|_| ())),
+        }
+
+        // once it is fixed by hand, the database opens
+        conn.execute("UPDATE users SET email = 'other@fakemail.com' WHERE id = 3", ())?;
+        let user_repo = user_repository::UserRepository::new(Some(conn_string))?;
+        assert_eq!(user_repo.get_users()?.len(), 3);
+
+        Ok(())
+    }
+
+
+    #[test]
+    fn test_ids_are_not_reused_after_upgrade() -> Result<(), RepositoryError> {
+        // a database whose ids were plain rowids
+        let conn_string = "file:user_autoincrement?mode=memory&cache=shared";
+        let conn = rusqlite::Connection::open(conn_string)?;
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, first_name TEXT NOT NULL, last_name TEXT NOT NULL, email TEXT NOT NULL);
+INSERT INTO users (first_name, last_name, email) VALUES ('Taylor', 'Lowery', 'tlowery@fakemail.com');
+INSERT INTO users (first_name, last_name, email) VALUES ('Tater', 'Tot', '2hott2tott@fakemail.com');",
+        )?;
+
+        // upgrading keeps the users
+        let user_repo = user_repository::UserRepository::new(Some(conn_string))?;
+        assert_eq!(user_repo.select_item_by_id(&2)?.email, "2hott2tott@fakemail.com");
+
+        // and a deleted user's id isn't given to the next one
+        assert_eq!(user_repo.delete_item_by_id(&2)?, 1);
+        let user_id = user_repo.save_new_item(&UserDTO {
+            first_name: "Tater".to_string(),
+            last_name: "Tot".to_string(),
+            email: "tot@fakemail.com".to_string(),
+        })?;
+        assert_eq!(user_id, 3);
+
+        // opening it again doesn't rebuild the table
+        user_repository::UserRepository::new(Some(conn_string))?;
+        assert_eq!(user_repo.get_users()?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_by_email() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+
+        let user_id = user_repo.save_new_item(&UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        })?;
+
+        // lookups are normalized the same way as saves
+        let user = user_repo.find_by_email("  TLowery@fakemail.com")?.expect("user should be found");
+        assert_eq!(user.id, user_id);
+
+        // unknown emails are not an error
+        assert!(user_repo.find_by_email("nobody@fakemail.com")?.is_none());
+
+        // and a missing id is reported as not found
+        assert!(matches!(user_repo.select_item_by_id(&42), Err(RepositoryError::NotFound)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_role() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+
+        // new users are regular users
+        let user_id = user_repo.save_new_item(&UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        })?;
+        assert_eq!(user_repo.select_item_by_id(&user_id)?.role, Role::User);
+
+        // promoting sticks, and ordinary updates leave the role alone
+        assert_eq!(user_repo.set_role(&user_id, Role::Admin)?, 1);
+        user_repo.update_item(&user_id, &UserDTO {
+            first_name: "Tater".to_string(),
+            last_name: "Tot".to_string(),
+            email: "2hott2tott@fakemail.com".to_string(),
+        })?;
+        assert_eq!(user_repo.select_item_by_id(&user_id)?.role, Role::Admin);
+
+        // unknown users aren't updated
+        assert_eq!(user_repo.set_role(&42, Role::Admin)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_events() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+        let events = user_repo.events();
+        let taylor = UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        };
+
+        let user_id = user_repo.save_new_item(&taylor)?;
+        user_repo.set_role(&user_id, Role::Admin)?;
+        user_repo.delete_item_by_id(&user_id)?;
+        // rejected writes aren't announced
+        assert!(user_repo.save_new_item(&UserDTO { email: "nope".to_string(), ..taylor }).is_err());
+
+        let received: Vec<(EntityType, i64, EventKind)> =
+            events.try_iter().map(|event| (event.entity, event.id, event.kind)).collect();
+        assert_eq!(
+            received,
+            vec![
+                (EntityType::User, user_id, EventKind::Created),
+                (EntityType::User, user_id, EventKind::Updated),
+                (EntityType::User, user_id, EventKind::Deleted),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_user_if_version() -> Result<(), RepositoryError> {
+        let user_repo = user_repository::UserRepository::new(None)?;
+        let taylor = UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: "tlowery@fakemail.com".to_string(),
+        };
+        let user_id = user_repo.save_new_item(&taylor)?;
+        assert_eq!(user_repo.select_item_by_id(&user_id)?.version, 1);
+
+        // every write bumps the version
+        user_repo.set_role(&user_id, Role::Admin)?;
+        assert_eq!(user_repo.select_item_by_id(&user_id)?.version, 2);
+
+        // a stale version is a conflict and changes nothing
+        let tater = UserDTO { first_name: "Tater".to_string(), ..taylor };
+        assert!(matches!(
+            user_repo.update_item_if_version(&user_id, &tater, 1),
+            Err(RepositoryError::Conflict { expected: 1, actual: 2 })
+        ));
+        assert_eq!(user_repo.select_item_by_id(&user_id)?.first_name, "Taylor");
+
+        // the current version goes through
+        assert_eq!(user_repo.update_item_if_version(&user_id, &tater, 2)?, 1);
+        let user = user_repo.select_item_by_id(&user_id)?;
+        assert_eq!((user.first_name.as_str(), user.version), ("Tater", 3));
+
+        assert!(matches!(user_repo.update_item_if_version(&42, &tater, 1), Err(RepositoryError::NotFound)));
+
+        Ok(())
+    }
+
+
+    #[test]
+    fn test_delete_user_with_todos() -> Result<(), RepositoryError> {
+        let conn_string = "file:user_delete_account?mode=memory&cache=shared";
+        let user_repo = user_repository::UserRepository::new(Some(conn_string))?;
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let user = |email: &str| UserDTO {
+            first_name: "Taylor".to_string(),
+            last_name: "Lowery".to_string(),
+            email: email.to_string(),
+        };
+        let taylor = user_repo.save_new_item(&user("tlowery@fakemail.com"))?;
+        let other = user_repo.save_new_item(&user("someone@fakemail.com"))?;
+        let own = todo_repo.save_new_item(&TodoItemDTO { user_id: taylor, task: "Clean the gutters".to_string() })?;
+        todo_repo.assign_todo_item(&own, Some(other), &taylor)?;
+        let others = todo_repo.save_new_item(&TodoItemDTO { user_id: other, task: "Walk the dog".to_string() })?;
+        todo_repo.assign_todo_item(&others, Some(taylor), &other)?;
+
+        // the user goes, and their todos with them
+        assert_eq!(user_repo.delete_account(&taylor)?, 1);
+        assert!(matches!(user_repo.select_item_by_id(&taylor), Err(RepositoryError::NotFound)));
+        assert!(todo_repo.get_user_todos(&taylor)?.is_empty());
+        assert!(todo_repo.select_item_by_id(&own).is_err());
+        assert!(todo_repo.get_delegations(&own)?.is_empty());
+
+        // other users' todos stay, but are no longer assigned to them
+        assert_eq!(todo_repo.select_item_by_id(&others)?.assignee_id, None);
+
+        // deleting a missing user deletes nothing
+        assert_eq!(user_repo.delete_account(&taylor)?, 0);
+
+        Ok(())
+    }
+
+
     #[test]
     fn check_db_created_from_user_repo() -> Result<(), Box<dyn Error>> {
 
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    use to_dont::models::{TodoItemDTO, WebhookDTO};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::sqlite::webhook_repository::WebhookRepository;
+    use to_dont::repository::{EventKind, Repository, RepositoryError};
+
+    #[test]
+    fn test_webhooks_are_validated() -> Result<(), RepositoryError> {
+        let webhook_repo = WebhookRepository::new(None)?;
+        let invalid = WebhookDTO { user_id: 1, url: "ftp://example.com".to_string(), events: Vec::new() };
+        match webhook_repo.save_new_item(&invalid) {
+            Err(RepositoryError::Validation(e)) => assert!(e.has_field("url") && e.has_field("events")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+
+        // URLs are trimmed, events deduplicated, and each webhook gets its own secret
+        let webhook_dto = WebhookDTO {
+            user_id: 1,
+            url: " https://example.com/hook ".to_string(),
+            events: vec![EventKind::Completed, EventKind::Completed, EventKind::Deleted],
+        };
+        let first = webhook_repo.select_item_by_id(&webhook_repo.save_new_item(&webhook_dto)?)?;
+        let second = webhook_repo.select_item_by_id(&webhook_repo.save_new_item(&webhook_dto)?)?;
+        assert_eq!(first.url, "https://example.com/hook");
+        assert_eq!(first.events, vec![EventKind::Completed, EventKind::Deleted]);
+        assert_ne!(first.secret, second.secret);
+        assert_eq!(webhook_repo.get_user_webhooks(&1)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_todo_writes_fill_the_outbox() -> Result<(), Box<dyn std::error::Error>> {
+        let conn_string = "file:webhook_outbox?mode=memory&cache=shared";
+        let webhook_repo = WebhookRepository::new(Some(conn_string))?;
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let shame = webhook_repo.save_new_item(&WebhookDTO {
+            user_id: 1,
+            url: "https://chat.example.com/shame".to_string(),
+            events: vec![EventKind::Uncompleted],
+        })?;
+        let everything = webhook_repo.save_new_item(&WebhookDTO {
+            user_id: 1,
+            url: "https://example.com/all".to_string(),
+            events: EventKind::ALL.to_vec(),
+        })?;
+
+        let todo_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Go for a run".to_string() })?;
+        todo_repo.complete_todo_item(&todo_id)?;
+        todo_repo.uncomplete_todo_item(&todo_id)?;
+        // changes other than the task, completion and owner aren't events
+        todo_repo.snooze(&todo_id, &(Utc::now() + Duration::days(1)), "It's raining")?;
+        todo_repo.assign_todo_item(&todo_id, Some(2), &1)?;
+        todo_repo.delete_item_by_id(&todo_id)?;
+        // other users' todos go to other users' webhooks
+        todo_repo.save_new_item(&TodoItemDTO { user_id: 2, task: "Someone else's".to_string() })?;
+
+        let events: Vec<EventKind> = webhook_repo.get_deliveries(&everything)?.iter().map(|delivery| delivery.event).collect();
+        assert_eq!(events, vec![EventKind::Created, EventKind::Completed, EventKind::Uncompleted, EventKind::Deleted]);
+
+        // the payload is a snapshot of the todo when it happened
+        let deliveries = webhook_repo.get_deliveries(&shame)?;
+        assert_eq!(deliveries.len(), 1);
+        let payload: Value = serde_json::from_str(&deliveries[0].payload)?;
+        assert_eq!(payload["event"], "uncompleted");
+        assert_eq!(payload["todo"]["id"], todo_id);
+        assert_eq!(payload["todo"]["task"], "Go for a run");
+        assert_eq!(payload["todo"]["completed"], false);
+
+        // failures push the next attempt back; giving up takes it out of the queue
+        let now = Utc::now();
+        assert_eq!(webhook_repo.get_due_deliveries(&now, 10)?.len(), 5);
+        let id = deliveries[0].id;
+        webhook_repo.mark_failed(&id, "HTTP 500", &now, Some(now + Duration::minutes(1)))?;
+        assert_eq!(webhook_repo.get_due_deliveries(&now, 10)?.len(), 4);
+        assert_eq!(webhook_repo.get_due_deliveries(&(now + Duration::minutes(1)), 10)?.len(), 5);
+        webhook_repo.mark_failed(&id, "HTTP 500", &now, None)?;
+        let delivery = &webhook_repo.get_deliveries(&shame)?[0];
+        assert_eq!((delivery.attempts, delivery.last_error.as_deref()), (2, Some("HTTP 500")));
+        assert!(delivery.failed_datetime.is_some());
+        assert_eq!(webhook_repo.get_due_deliveries(&(now + Duration::hours(1)), 10)?.len(), 4);
+
+        // claimed deliveries aren't handed out again until the lease runs out
+        assert_eq!(webhook_repo.claim_due_deliveries(&now, Duration::minutes(5), 3)?.len(), 3);
+        let claimed = webhook_repo.claim_due_deliveries(&now, Duration::minutes(5), 10)?;
+        assert_eq!(claimed.len(), 1);
+        assert!(webhook_repo.claim_due_deliveries(&now, Duration::minutes(5), 10)?.is_empty());
+        webhook_repo.mark_failed(&claimed[0].id, "HTTP 500", &now, Some(now + Duration::minutes(1)))?;
+        assert_eq!(webhook_repo.get_due_deliveries(&(now + Duration::minutes(1)), 10)?.len(), 1);
+        assert_eq!(webhook_repo.get_due_deliveries(&(now + Duration::minutes(5)), 10)?.len(), 4);
+
+        // deleting a webhook drops what it had queued
+        webhook_repo.delete_item_by_id(&everything)?;
+        assert!(webhook_repo.get_deliveries(&everything)?.is_empty());
+        assert!(webhook_repo.get_due_deliveries(&now, 10)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1 @@
+mod stats_tests;
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+    use rusqlite::{params, Connection};
+
+    use to_dont::models::{RefusalReason, TodoItemDTO};
+    use to_dont::repository::Repository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+
+    const DAY: i64 = 86_400;
+
+    /// A repository plus a second connection to the same in-memory database,
+    /// for backdating todos.
+    fn open(name: &str) -> Result<(TodoRepository, Connection), rusqlite::Error> {
+        let conn_string = format!("file:{}?mode=memory&cache=shared", name);
+        Ok((TodoRepository::new(Some(&conn_string))?, Connection::open(&conn_string)?))
+    }
+
+    /// Save a todo created at `created` and, if given, completed at `completed`.
+    fn todo_at(
+        repo: &TodoRepository,
+        conn: &Connection,
+        user_id: i64,
+        created: DateTime<Utc>,
+        completed: Option<DateTime<Utc>>,
+    ) -> Result<i64, rusqlite::Error> {
+        let id = repo.save_new_item(&TodoItemDTO { user_id, task: "Something".to_string() })?;
+        conn.execute(
+            "UPDATE todos SET created_datetime = ?1, completed = ?2, completed_datetime = ?3 WHERE id = ?4",
+            params![created.timestamp(), completed.is_some(), completed.map(|c| c.timestamp()), id],
+        )?;
+        Ok(id)
+    }
+
+    #[test]
+    fn test_summary_median_and_oldest() -> Result<(), rusqlite::Error> {
+        let (repo, conn) = open("stats_summary")?;
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+
+        // no todos yet: nothing to divide by, nothing completed
+        let summary = repo.stats().completion_summary(&1)?;
+        assert_eq!((summary.open_count, summary.completed_count, summary.completion_rate), (0, 0, 0.0));
+        assert_eq!(repo.stats().median_completion_time(&1)?, None);
+
+        // three completed after 1, 2 and 10 days; one still open; one for someone else
+        todo_at(&repo, &conn, 1, start, Some(start + Duration::days(1)))?;
+        todo_at(&repo, &conn, 1, start, Some(start + Duration::days(10)))?;
+        todo_at(&repo, &conn, 1, start, Some(start + Duration::days(2)))?;
+        let oldest = todo_at(&repo, &conn, 1, start - Duration::days(30), None)?;
+        todo_at(&repo, &conn, 2, start - Duration::days(60), None)?;
+
+        let summary = repo.stats().completion_summary(&1)?;
+        assert_eq!((summary.open_count, summary.completed_count), (1, 3));
+        assert_eq!(summary.completion_rate, 0.75);
+        assert_eq!(repo.stats().median_completion_time(&1)?, Some(Duration::days(2)));
+
+        // with an even number of completions the median is the middle two averaged
+        todo_at(&repo, &conn, 1, start, Some(start + Duration::days(4)))?;
+        assert_eq!(repo.stats().median_completion_time(&1)?, Some(Duration::days(3)));
+
+        // the oldest open todo comes first, and other users' todos never appear
+        let newer = todo_at(&repo, &conn, 1, start, None)?;
+        let untouched: Vec<i64> = repo.stats().oldest_untouched(&1, 5)?.iter().map(|todo| todo.id).collect();
+        assert_eq!(untouched, vec![oldest, newer]);
+        assert_eq!(repo.stats().oldest_untouched(&1, 1)?.len(), 1);
+
+        // refused todos are neither open nor completed
+        repo.refuse(&oldest, RefusalReason::Never)?;
+        let untouched: Vec<i64> = repo.stats().oldest_untouched(&1, 5)?.iter().map(|todo| todo.id).collect();
+        assert_eq!(untouched, vec![newer]);
+        let summary = repo.stats().completion_summary(&1)?;
+        assert_eq!((summary.open_count, summary.completed_count), (1, 4));
+        assert_eq!(summary.completion_rate, 0.8);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaks() -> Result<(), rusqlite::Error> {
+        let (repo, conn) = open("stats_streaks")?;
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 18, 0, 0).unwrap();
+        assert_eq!(repo.stats().completion_streaks(&1, now)?.longest_days, 0);
+
+        // a four day run long ago, then a two day run ending yesterday (twice on one day)
+        for days_ago in [20, 19, 18, 17, 2, 1, 1] {
+            let completed = now - Duration::days(days_ago);
+            todo_at(&repo, &conn, 1, completed - Duration::hours(1), Some(completed))?;
+        }
+        let streaks = repo.stats().completion_streaks(&1, now)?;
+        assert_eq!((streaks.current_days, streaks.longest_days), (2, 4));
+
+        // the run is broken once a whole day passes without completing anything
+        let streaks = repo.stats().completion_streaks(&1, now + Duration::days(2))?;
+        assert_eq!((streaks.current_days, streaks.longest_days), (0, 4));
+
+        // and the summary bundles everything together
+        let stats = repo.stats().user_stats(&1, now)?;
+        assert_eq!(stats.completion.completed_count, 7);
+        assert_eq!(stats.median_completion_time, Some(Duration::hours(1)));
+        assert_eq!(stats.streaks.current_days, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_weekly_series() -> Result<(), rusqlite::Error> {
+        let (repo, conn) = open("stats_weekly")?;
+        // a Wednesday
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 12, 0, 0).unwrap();
+
+        // created last Sunday and completed this Monday; created two weeks ago; too old to show
+        todo_at(&repo, &conn, 1, now - Duration::days(3), Some(now - Duration::days(2)))?;
+        todo_at(&repo, &conn, 1, now - Duration::days(14), None)?;
+        todo_at(&repo, &conn, 1, now - Duration::days(40), None)?;
+        conn.execute("UPDATE todos SET created_datetime = created_datetime - ?1 WHERE id = 3", params![DAY])?;
+
+        let series = repo.stats().weekly_series(&1, 3, now)?;
+        let weeks: Vec<(NaiveDate, i64, i64)> =
+            series.iter().map(|week| (week.week_start, week.created_count, week.completed_count)).collect();
+        assert_eq!(
+            weeks,
+            vec![
+                (NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(), 1, 0),
+                (NaiveDate::from_ymd_opt(2024, 3, 11).unwrap(), 1, 0),
+                (NaiveDate::from_ymd_opt(2024, 3, 18).unwrap(), 0, 1),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_weekly_refusals() -> Result<(), rusqlite::Error> {
+        let (repo, conn) = open("stats_refusals")?;
+        // a Wednesday
+        let now = Utc.with_ymd_and_hms(2024, 3, 20, 12, 0, 0).unwrap();
+
+        // refused last week, refused and reconsidered this week
+        for (reason, days_ago) in [(RefusalReason::Never, 7), (RefusalReason::Never, 1), (RefusalReason::Later, 0)] {
+            let id = todo_at(&repo, &conn, 1, now - Duration::days(30), None)?;
+            repo.refuse(&id, reason)?;
+            conn.execute(
+                "UPDATE todo_refusals SET refused_datetime = ?1 WHERE todo_id = ?2",
+                params![(now - Duration::days(days_ago)).timestamp(), id],
+            )?;
+        }
+        repo.reconsider(&3)?;
+
+        let series = repo.stats().weekly_refusals(&1, 2, now)?;
+        let counts = |week: usize| -> Vec<(RefusalReason, i64, i64)> {
+            series[week].counts.iter().map(|count| (count.reason, count.refused_count, count.reconsidered_count)).collect()
+        };
+        assert_eq!(series[0].week_start, NaiveDate::from_ymd_opt(2024, 3, 11).unwrap());
+        assert_eq!(
+            counts(0),
+            vec![(RefusalReason::NotMyJob, 0, 0), (RefusalReason::TooBoring, 0, 0), (RefusalReason::Later, 0, 0), (RefusalReason::Never, 1, 0)]
+        );
+        assert_eq!(
+            counts(1),
+            vec![(RefusalReason::NotMyJob, 0, 0), (RefusalReason::TooBoring, 0, 0), (RefusalReason::Later, 1, 1), (RefusalReason::Never, 1, 0)]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::thread;
+    use std::time::Duration;
+
+    use to_dont::models::{TodoItem, TodoItemDTO};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::{EventKind, Repository};
+    use to_dont::sync::{Hlc, TodoField};
+
+    /// One database, and how far it has got through the other one's changes.
+    struct Device {
+        repo: TodoRepository,
+        cursor: i64,
+    }
+
+    impl Device {
+        fn new() -> Result<Device, rusqlite::Error> {
+            let repo = TodoRepository::new(None)?;
+            repo.sync(1)?;
+            Ok(Device { repo, cursor: 0 })
+        }
+
+        /// The user's todos as (task, completed), in task order, to compare
+        /// devices whose row ids differ.
+        fn todos(&self, user_id: i64) -> Result<Vec<(String, bool)>, rusqlite::Error> {
+            let mut todos: Vec<(String, bool)> =
+                self.repo.get_user_todos(&user_id)?.into_iter().map(|todo| (todo.task, todo.completed)).collect();
+            todos.sort();
+            Ok(todos)
+        }
+
+        fn find(&self, user_id: i64, task: &str) -> Result<TodoItem, rusqlite::Error> {
+            let todos = self.repo.get_user_todos(&user_id)?;
+            todos.into_iter().find(|todo| todo.task == task).ok_or(rusqlite::Error::QueryReturnedNoRows)
+        }
+    }
+
+    /// Send `from`'s new changes to `to`, returning how many won there.
+    fn push(from: &Device, to: &mut Device) -> Result<usize, rusqlite::Error> {
+        let change_set = from.repo.sync(1)?.changes_since(to.cursor)?;
+        to.cursor = change_set.cursor;
+        to.repo.sync(1)?.apply_changes(&change_set.changes)
+    }
+
+    /// Sync both ways, as two laptops coming back online would.
+    fn sync(a: &mut Device, b: &mut Device) -> Result<(), rusqlite::Error> {
+        push(a, b)?;
+        push(b, a)?;
+        Ok(())
+    }
+
+    /// Let the wall clock move on, so the next write is clearly later.
+    fn later() {
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_hlc_orders_like_its_text() {
+        let earlier = Hlc { millis: 900, counter: 12, node_id: "b".to_string() };
+        let later = Hlc { millis: 1000, counter: 3, node_id: "a".to_string() };
+        assert!(earlier < later);
+        assert!(earlier.to_string() < later.to_string());
+        assert_eq!(Hlc::parse(&later.to_string()), Some(later));
+        assert_eq!(Hlc::parse("not a clock"), None);
+    }
+
+    #[test]
+    fn test_changes_since_cursor() -> Result<(), Box<dyn Error>> {
+        let device = Device::new()?;
+        let id = device.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        let sync = device.repo.sync(1)?;
+
+        let first = sync.changes_since(0)?;
+        let fields: Vec<TodoField> = first.changes.iter().map(|change| change.field.clone()).collect();
+        assert_eq!(fields.len(), 2);
+        assert!(fields.contains(&TodoField::Task("Learn Rust".to_string())));
+        assert!(fields.contains(&TodoField::Completed(None)));
+
+        // only the completion has changed since
+        device.repo.complete_todo_item(&id)?;
+        let second = sync.changes_since(first.cursor)?;
+        let completed_datetime = device.repo.select_item_by_id(&id)?.completed_datetime;
+        assert_eq!(second.changes.len(), 1);
+        assert_eq!(second.changes[0].field, TodoField::Completed(completed_datetime));
+        assert!(second.changes[0].hlc > first.changes[0].hlc);
+        assert!(sync.changes_since(second.cursor)?.changes.is_empty());
+
+        // other columns aren't synced
+        device.repo.set_todo_list(&id, Some(3))?;
+        assert!(sync.changes_since(second.cursor)?.changes.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_two_devices_converge() -> Result<(), Box<dyn Error>> {
+        let mut laptop = Device::new()?;
+        let mut desktop = Device::new()?;
+
+        // todos written before sync was set up are sent too
+        let fresh = TodoRepository::new(None)?;
+        fresh.save_new_item(&TodoItemDTO { user_id: 1, task: "Water the plants".to_string() })?;
+        let change_set = fresh.sync(1)?.changes_since(0)?;
+        assert_eq!(laptop.repo.sync(1)?.apply_changes(&change_set.changes)?, 2);
+
+        laptop.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        desktop.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Read a book".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+        let expected = vec![
+            ("Learn Rust".to_string(), false),
+            ("Read a book".to_string(), false),
+            ("Water the plants".to_string(), false),
+        ];
+        assert_eq!(laptop.todos(1)?, expected);
+        assert_eq!(desktop.todos(1)?, expected);
+
+        // syncing again changes nothing
+        assert_eq!(push(&laptop, &mut desktop)?, 0);
+        let all = laptop.repo.sync(1)?.changes_since(0)?;
+        assert_eq!(desktop.repo.sync(1)?.apply_changes(&all.changes)?, 0);
+
+        // edits to different fields of one todo both survive
+        let on_laptop = laptop.find(1, "Learn Rust")?;
+        let on_desktop = desktop.find(1, "Learn Rust")?;
+        laptop.repo.update_item(&on_laptop.id, &TodoItemDTO { user_id: 1, task: "Learn more Rust".to_string() })?;
+        desktop.repo.complete_todo_item(&on_desktop.id)?;
+        sync(&mut laptop, &mut desktop)?;
+        let on_laptop = laptop.repo.select_item_by_id(&on_laptop.id)?;
+        let on_desktop = desktop.repo.select_item_by_id(&on_desktop.id)?;
+        assert_eq!(on_laptop.task, "Learn more Rust");
+        assert_eq!(on_desktop.task, "Learn more Rust");
+        assert!(on_laptop.completed);
+        assert_eq!(on_laptop.completed_datetime, on_desktop.completed_datetime);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_later_writes_win() -> Result<(), Box<dyn Error>> {
+        let mut laptop = Device::new()?;
+        let mut desktop = Device::new()?;
+        laptop.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+        let on_laptop = laptop.find(1, "Learn Rust")?.id;
+        let on_desktop = desktop.find(1, "Learn Rust")?.id;
+
+        // both rename it offline; the desktop does so last
+        laptop.repo.update_item(&on_laptop, &TodoItemDTO { user_id: 1, task: "Learn Go".to_string() })?;
+        later();
+        desktop.repo.update_item(&on_desktop, &TodoItemDTO { user_id: 1, task: "Learn Zig".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+        assert_eq!(laptop.todos(1)?, vec![("Learn Zig".to_string(), false)]);
+        assert_eq!(desktop.todos(1)?, vec![("Learn Zig".to_string(), false)]);
+
+        // the laptop completes it, the desktop un-completes it later
+        laptop.repo.complete_todo_item(&on_laptop)?;
+        push(&laptop, &mut desktop)?;
+        later();
+        desktop.repo.uncomplete_todo_item(&on_desktop)?;
+        laptop.repo.update_item(&on_laptop, &TodoItemDTO { user_id: 1, task: "Learn Zig well".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+        assert_eq!(laptop.todos(1)?, vec![("Learn Zig well".to_string(), false)]);
+        assert_eq!(desktop.todos(1)?, vec![("Learn Zig well".to_string(), false)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ownership_stays_local() -> Result<(), Box<dyn Error>> {
+        let laptop = Device::new()?;
+        let desktop = Device::new()?;
+        laptop.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        laptop.repo.save_new_item(&TodoItemDTO { user_id: 2, task: "Someone else's".to_string() })?;
+
+        // the same account is user 7 on the desktop; only its own todos travel
+        let events = desktop.repo.events();
+        let change_set = laptop.repo.sync(1)?.changes_since(0)?;
+        assert_eq!(change_set.changes.len(), 2);
+        desktop.repo.sync(7)?.apply_changes(&change_set.changes)?;
+        assert_eq!(desktop.todos(7)?, vec![("Learn Rust".to_string(), false)]);
+        assert!(desktop.todos(1)?.is_empty() && desktop.todos(2)?.is_empty());
+        let on_desktop = desktop.find(7, "Learn Rust")?.id;
+        let received: Vec<(i64, EventKind)> = events.try_iter().map(|event| (event.id, event.kind)).collect();
+        assert_eq!(received, vec![(on_desktop, EventKind::Created)]);
+
+        // and come back to their owner here
+        desktop.repo.complete_todo_item(&on_desktop)?;
+        let change_set = desktop.repo.sync(7)?.changes_since(0)?;
+        laptop.repo.sync(1)?.apply_changes(&change_set.changes)?;
+        assert_eq!(laptop.todos(1)?, vec![("Learn Rust".to_string(), true)]);
+
+        // changes can't reach another user's todos
+        let theirs = laptop.repo.sync(2)?.changes_since(0)?;
+        assert_eq!(desktop.repo.sync(7)?.apply_changes(&theirs.changes)?, 2);
+        assert_eq!(laptop.repo.sync(1)?.apply_changes(&theirs.changes)?, 0);
+        assert_eq!(laptop.todos(1)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deletes_are_final() -> Result<(), Box<dyn Error>> {
+        let mut laptop = Device::new()?;
+        let mut desktop = Device::new()?;
+        laptop.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+
+        // the laptop deletes it while the desktop, offline, edits it afterwards
+        laptop.repo.delete_item_by_id(&laptop.find(1, "Learn Rust")?.id)?;
+        later();
+        let on_desktop = desktop.find(1, "Learn Rust")?.id;
+        desktop.repo.update_item(&on_desktop, &TodoItemDTO { user_id: 1, task: "Learn more Rust".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+        assert!(laptop.todos(1)?.is_empty());
+        assert!(desktop.todos(1)?.is_empty());
+
+        // new todos that reuse the row id are separate todos
+        laptop.repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Read a book".to_string() })?;
+        sync(&mut laptop, &mut desktop)?;
+        assert_eq!(desktop.todos(1)?, vec![("Read a book".to_string(), false)]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use ratatui::backend::TestBackend;
+    use ratatui::crossterm::event::{KeyCode, KeyEvent};
+    use ratatui::Terminal;
+
+    use to_dont::models::TodoItemDTO;
+    use to_dont::repository::Repository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::tui::{self, App, Mode, StatusFilter};
+
+    /// Build an app for user 1 with two todos, plus one todo belonging to user 2.
+    fn seeded_app() -> Result<App, rusqlite::Error> {
+        let todo_repo = TodoRepository::new(None)?;
+        for (user_id, task) in [(1, "Do the dishes"), (1, "File taxes"), (2, "Someone else's chore")] {
+            todo_repo.save_new_item(&TodoItemDTO { user_id, task: task.to_string() })?;
+        }
+        App::new(todo_repo, 1)
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Result<(), rusqlite::Error> {
+        app.handle_key(KeyEvent::from(code))
+    }
+
+    fn type_text(app: &mut App, text: &str) -> Result<(), rusqlite::Error> {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c))?;
+        }
+        Ok(())
+    }
+
+    fn render(app: &App) -> Result<String, Box<dyn Error>> {
+        let mut terminal = Terminal::new(TestBackend::new(60, 12))?;
+        terminal.draw(|frame| tui::draw(frame, app))?;
+        let buffer = terminal.backend().buffer();
+        let mut screen = String::new();
+        for y in 0..buffer.area.height {
+            for x in 0..buffer.area.width {
+                screen.push_str(buffer[(x, y)].symbol());
+            }
+            screen.push('\n');
+        }
+        Ok(screen)
+    }
+
+    #[test]
+    fn test_lists_only_the_users_todos() -> Result<(), Box<dyn Error>> {
+        let app = seeded_app()?;
+
+        // only user 1's todos are loaded
+        assert_eq!(app.visible_todos().len(), 2);
+
+        // and they are what ends up on screen
+        let screen = render(&app)?;
+        assert!(screen.contains("[ ] Do the dishes"));
+        assert!(screen.contains("[ ] File taxes"));
+        assert!(!screen.contains("Someone else's chore"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_navigate_and_toggle_completion() -> Result<(), rusqlite::Error> {
+        let mut app = seeded_app()?;
+
+        // move to the second todo and complete it
+        press(&mut app, KeyCode::Down)?;
+        let todo_id = app.selected_todo().unwrap().id;
+        press(&mut app, KeyCode::Char(' '))?;
+        assert!(app.repository().select_item_by_id(&todo_id)?.completed);
+
+        // toggling again un-completes it
+        press(&mut app, KeyCode::Char(' '))?;
+        assert!(!app.repository().select_item_by_id(&todo_id)?.completed);
+
+        // navigation stops at the ends of the list
+        press(&mut app, KeyCode::Down)?;
+        assert_eq!(app.selected, 1);
+        press(&mut app, KeyCode::Up)?;
+        press(&mut app, KeyCode::Up)?;
+        assert_eq!(app.selected, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_inline_edit_and_add() -> Result<(), rusqlite::Error> {
+        let mut app = seeded_app()?;
+        let todo_id = app.selected_todo().unwrap().id;
+
+        // edit starts with the current task text
+        press(&mut app, KeyCode::Char('e'))?;
+        assert_eq!(app.mode, Mode::Editing);
+        assert_eq!(app.input, "Do the dishes");
+
+        // replace the last word and save
+        for _ in 0.."dishes".len() {
+            press(&mut app, KeyCode::Backspace)?;
+        }
+        type_text(&mut app, "laundry")?;
+        press(&mut app, KeyCode::Enter)?;
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.repository().select_item_by_id(&todo_id)?.task, "Do the laundry");
+
+        // escape throws an edit away
+        press(&mut app, KeyCode::Char('e'))?;
+        type_text(&mut app, " later")?;
+        press(&mut app, KeyCode::Esc)?;
+        assert_eq!(app.repository().select_item_by_id(&todo_id)?.task, "Do the laundry");
+
+        // add a new todo, which becomes the selection
+        press(&mut app, KeyCode::Char('a'))?;
+        type_text(&mut app, "Call mom")?;
+        press(&mut app, KeyCode::Enter)?;
+        assert_eq!(app.repository().get_user_todos(&1)?.len(), 3);
+        assert_eq!(app.selected_todo().unwrap().task, "Call mom");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filtering() -> Result<(), Box<dyn Error>> {
+        let mut app = seeded_app()?;
+
+        // text filter is case-insensitive
+        press(&mut app, KeyCode::Char('/'))?;
+        type_text(&mut app, "TAX")?;
+        press(&mut app, KeyCode::Enter)?;
+        assert_eq!(app.visible_todos().len(), 1);
+        assert_eq!(app.selected_todo().unwrap().task, "File taxes");
+        assert!(!render(&app)?.contains("Do the dishes"));
+
+        // complete it, then cycle the status filter to open only
+        press(&mut app, KeyCode::Char(' '))?;
+        press(&mut app, KeyCode::Tab)?;
+        assert_eq!(app.status_filter, StatusFilter::Open);
+        assert!(app.visible_todos().is_empty());
+        assert!(app.selected_todo().is_none());
+
+        // clearing the text filter shows the remaining open todo
+        press(&mut app, KeyCode::Char('/'))?;
+        press(&mut app, KeyCode::Esc)?;
+        assert_eq!(app.visible_todos().len(), 1);
+        assert_eq!(app.selected_todo().unwrap().task, "Do the dishes");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+    use std::path::Path;
+
+    use rusqlite::{params, Connection};
+
+    use to_dont::auth::{AuthError, Credential, Principal};
+    use to_dont::models::{Role, ShareLevel, TodoItemDTO, TodoListDTO};
+    use to_dont::repository::sqlite::todo_list_repository::TodoListRepository;
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::{EventKind, Repository, RepositoryError};
+
+    fn user(user_id: i64) -> Principal {
+        Principal { user_id, role: Role::User, credential: Credential::Session { id: user_id } }
+    }
+
+    #[test]
+    fn test_undo_and_redo_each_operation() -> Result<(), Box<dyn Error>> {
+        let repo = TodoRepository::new(None)?;
+        let principal = user(1);
+        let history = principal.todos(&repo);
+        assert_eq!(history.undo()?, None);
+
+        let id = history.create("Learn Rust")?;
+        history.update(&id, "Learn more Rust")?;
+        history.complete(&id)?;
+        history.delete(&id)?;
+
+        // each undo reverts the latest remaining write
+        assert_eq!(history.undo()?.map(|entry| entry.operation), Some(EventKind::Deleted));
+        let todo = repo.select_item_by_id(&id)?;
+        assert!(todo.completed);
+        assert_eq!(history.undo()?.map(|entry| entry.operation), Some(EventKind::Completed));
+        assert!(!repo.select_item_by_id(&id)?.completed);
+        assert_eq!(history.undo()?.map(|entry| entry.operation), Some(EventKind::Updated));
+        let todo = repo.select_item_by_id(&id)?;
+        assert_eq!((todo.task.as_str(), todo.version), ("Learn Rust", 1));
+        assert_eq!(history.undo()?.map(|entry| entry.operation), Some(EventKind::Created));
+        assert!(repo.get_user_todos(&1)?.is_empty());
+        assert_eq!(history.undo()?, None);
+
+        // and redo plays them forward again, in order, keeping the id
+        assert_eq!(history.redo()?.map(|entry| entry.operation), Some(EventKind::Created));
+        assert_eq!(repo.select_item_by_id(&id)?.task, "Learn Rust");
+        history.redo()?;
+        assert_eq!(repo.select_item_by_id(&id)?.task, "Learn more Rust");
+        history.redo()?;
+        assert!(repo.select_item_by_id(&id)?.completed);
+        history.redo()?;
+        assert!(repo.get_user_todos(&1)?.is_empty());
+        assert_eq!(history.redo()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_writes_drop_redo_and_histories_are_per_user() -> Result<(), Box<dyn Error>> {
+        let repo = TodoRepository::new(None)?;
+        let (alice, bob) = (user(1), user(2));
+        let (alice, bob) = (alice.todos(&repo), bob.todos(&repo));
+
+        let walk = alice.create("Walk the dog")?;
+        let read = bob.create("Read a book")?;
+        alice.complete(&walk)?;
+
+        // Bob's undo leaves Alice's writes alone
+        assert_eq!(bob.undo()?.map(|entry| entry.todo_id), Some(read));
+        assert!(repo.select_item_by_id(&walk)?.completed);
+        assert_eq!(bob.undo()?, None);
+
+        alice.undo()?;
+        alice.update(&walk, "Walk the cat")?;
+        assert_eq!(alice.redo()?, None);
+        let operations: Vec<EventKind> = alice.history_entries()?.into_iter().map(|entry| entry.operation).collect();
+        assert_eq!(operations, vec![EventKind::Updated, EventKind::Created]);
+
+        // writes that fail aren't logged, and neither are conflicting ones
+        assert!(matches!(alice.delete(&1234), Err(AuthError::Repository(RepositoryError::NotFound))));
+        assert!(matches!(
+            alice.update_if_version(&walk, "Walk the fish", 1),
+            Err(AuthError::Repository(RepositoryError::Conflict { .. }))
+        ));
+        assert_eq!(alice.history_entries()?.len(), 2);
+
+        // nobody can undo their way into someone else's todos
+        assert!(matches!(bob.update(&walk, "Walk Bob's dog"), Err(AuthError::Forbidden)));
+        assert_eq!(bob.undo()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_refuses_to_overwrite_other_changes() -> Result<(), Box<dyn Error>> {
+        let repo = TodoRepository::new(None)?;
+        let principal = user(1);
+        let history = principal.todos(&repo);
+        let id = history.create("Learn Rust")?;
+        history.update(&id, "Learn Go")?;
+
+        // a write outside the history moves the todo on
+        repo.update_item(&id, &TodoItemDTO { user_id: 1, task: "Learn Zig".to_string() })?;
+        assert!(matches!(history.undo(), Err(AuthError::Repository(RepositoryError::Conflict { expected: 2, actual: 3 }))));
+        assert_eq!(repo.select_item_by_id(&id)?.task, "Learn Zig");
+
+        repo.delete_item_by_id(&id)?;
+        assert!(matches!(history.undo(), Err(AuthError::Repository(RepositoryError::NotFound))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_writes_can_be_discarded() -> Result<(), Box<dyn Error>> {
+        let repo = TodoRepository::new(None)?;
+        let principal = user(1);
+        let history = principal.todos(&repo);
+        let walk = history.create("Walk the dog")?;
+        let read = history.create("Read a book")?;
+        history.complete(&walk)?;
+        history.update(&read, "Read two books")?;
+
+        // the newest write's todo moves on, so undo is stuck on it
+        repo.update_item(&read, &TodoItemDTO { user_id: 1, task: "Read no books".to_string() })?;
+        assert!(matches!(history.undo(), Err(AuthError::Repository(RepositoryError::Conflict { .. }))));
+
+        // until it is discarded, and the writes before it can be undone again
+        assert_eq!(history.discard_undo()?.map(|entry| (entry.todo_id, entry.operation)), Some((read, EventKind::Updated)));
+        assert_eq!(history.undo()?.map(|entry| (entry.todo_id, entry.operation)), Some((walk, EventKind::Completed)));
+        assert!(!repo.select_item_by_id(&walk)?.completed);
+        assert_eq!(repo.select_item_by_id(&read)?.task, "Read no books");
+        assert_eq!(history.history_entries()?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_leaves_other_users_todos_alone() -> Result<(), Box<dyn Error>> {
+        let conn_string = "file:undo_other_todos?mode=memory&cache=shared";
+        let repo = TodoRepository::new(Some(conn_string))?;
+        let conn = Connection::open(conn_string)?;
+        let (bob, stranger) = (user(1), user(2));
+        let (bob, stranger) = (bob.todos(&repo), stranger.todos(&repo));
+
+        // someone else deletes Bob's newest todo, and its id isn't handed out again
+        let id = bob.create("Learn Rust")?;
+        repo.delete_item_by_id(&id)?;
+        let other = stranger.create("Read a book")?;
+        assert_ne!(other, id);
+        assert!(matches!(bob.undo(), Err(AuthError::Repository(RepositoryError::NotFound))));
+
+        // even a todo put back under the old id, at the same version, is not Bob's to undo
+        conn.execute("INSERT INTO todos (id, user_id, task) VALUES (?1, 2, 'Walk the dog')", params![id])?;
+        assert!(matches!(bob.undo(), Err(AuthError::Repository(RepositoryError::NotFound))));
+        assert_eq!(repo.select_item_by_id(&id)?.task, "Walk the dog");
+        assert_eq!(repo.get_user_todos(&2)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_needs_access_to_the_todo() -> Result<(), Box<dyn Error>> {
+        let conn_string = "file:undo_access?mode=memory&cache=shared";
+        let repo = TodoRepository::new(Some(conn_string))?;
+        let lists = TodoListRepository::new(Some(conn_string))?;
+        let (alice, bob) = (user(1), user(2));
+        let bob_todos = bob.todos(&repo).with_lists(&lists);
+
+        // Alice shares a list with Bob, who edits one todo and deletes another
+        let list_id = lists.save_new_item(&TodoListDTO { owner_id: 1, name: "Chores".to_string() })?;
+        lists.invite(&list_id, &2, ShareLevel::Editor, &1)?;
+        lists.accept_invitation(&list_id, &2)?;
+        let walk = alice.todos(&repo).create("Walk the dog")?;
+        let read = alice.todos(&repo).create("Read a book")?;
+        repo.set_todo_list(&walk, Some(list_id))?;
+        repo.set_todo_list(&read, Some(list_id))?;
+        bob_todos.update(&walk, "Walk the cat")?;
+        bob_todos.delete(&read)?;
+        bob_todos.undo()?;
+        bob_todos.delete(&read)?;
+
+        // once he is removed from the list, he can't undo or redo either
+        lists.remove_member(&list_id, &2)?;
+        assert!(matches!(bob_todos.undo(), Err(AuthError::Forbidden)));
+        assert!(repo.select_item_by_id(&read).is_err());
+        lists.invite(&list_id, &2, ShareLevel::Editor, &1)?;
+        lists.accept_invitation(&list_id, &2)?;
+        assert_eq!(bob_todos.undo()?.map(|entry| entry.todo_id), Some(read));
+        lists.remove_member(&list_id, &2)?;
+        assert!(matches!(bob_todos.redo(), Err(AuthError::Forbidden)));
+        assert_eq!(repo.select_item_by_id(&read)?.task, "Read a book");
+        assert!(matches!(bob_todos.undo(), Err(AuthError::Forbidden)));
+        assert_eq!(repo.select_item_by_id(&walk)?.task, "Walk the cat");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_writes_and_undos_are_all_or_nothing() -> Result<(), Box<dyn Error>> {
+        let conn_string = "file:undo_all_or_nothing?mode=memory&cache=shared";
+        let repo = TodoRepository::new(Some(conn_string))?;
+        let conn = Connection::open(conn_string)?;
+        let principal = user(1);
+        let history = principal.todos(&repo);
+        let id = history.create("Learn Haskell")?;
+        let events = repo.events();
+
+        // a write that can't be logged doesn't happen, and isn't announced
+        conn.execute_batch("CREATE TRIGGER no_logging BEFORE INSERT ON todo_history BEGIN SELECT RAISE(ABORT, 'full'); END")?;
+        assert!(history.complete(&id).is_err());
+        assert!(!repo.select_item_by_id(&id)?.completed);
+
+        // neither does an undo that can't be marked as undone
+        conn.execute_batch(
+            "DROP TRIGGER no_logging; \
+CREATE TRIGGER no_marking BEFORE UPDATE ON todo_history BEGIN SELECT RAISE(ABORT, 'stuck'); END",
+        )?;
+        history.complete(&id)?;
+        assert!(history.undo().is_err());
+        assert!(repo.select_item_by_id(&id)?.completed);
+
+        let kinds: Vec<EventKind> = events.try_iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![EventKind::Completed]);
+        assert_eq!(repo.get_user_todos(&1)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_survives_a_restart() -> Result<(), Box<dyn Error>> {
+        let test_conn_string: &str = "./todo_history_test_db.db3";
+        if Path::new(test_conn_string).exists() {
+            fs::remove_file(test_conn_string)?;
+        }
+
+        let id = {
+            let repo = TodoRepository::new(Some(test_conn_string))?;
+            let principal = user(1);
+            let history = principal.todos(&repo);
+            let id = history.create("Pay the bills")?;
+            // give the deleted row more than a task to restore
+            let conn = Connection::open(test_conn_string)?;
+            conn.execute("UPDATE todos SET deferral_count = 3, list_id = 7 WHERE id = ?1", params![id])?;
+            history.delete(&id)?;
+            id
+        };
+
+        {
+            let repo = TodoRepository::new(Some(test_conn_string))?;
+            let principal = user(1);
+            assert_eq!(principal.todos(&repo).undo()?.map(|entry| entry.operation), Some(EventKind::Deleted));
+            let todo = repo.select_item_by_id(&id)?;
+            assert_eq!(todo.task, "Pay the bills");
+            assert_eq!(todo.deferral_count, 3);
+            assert_eq!(todo.list_id, Some(7));
+        }
+
+        {
+            let repo = TodoRepository::new(Some(test_conn_string))?;
+            assert!(user(1).todos(&repo).redo()?.is_some());
+            assert!(repo.get_user_todos(&1)?.is_empty());
+        }
+
+        fs::remove_file(test_conn_string)?;
+        Ok(())
+    }
+}
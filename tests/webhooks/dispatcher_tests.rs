@@ -0,0 +1,142 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    use to_dont::models::{TodoItemDTO, WebhookDTO};
+    use to_dont::repository::sqlite::todo_repository::TodoRepository;
+    use to_dont::repository::sqlite::webhook_repository::WebhookRepository;
+    use to_dont::repository::{EventKind, Repository};
+    use to_dont::webhooks::{sign, DispatchReport, RetryPolicy, WebhookDispatcher};
+
+    /// A request received by the stand-in: lower-cased headers and the body.
+    struct Received {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// A local HTTP server answering one request per status in `statuses`,
+    /// in order. Returns its URL and the requests it received.
+    fn stand_in(statuses: Vec<u16>) -> (String, Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+                    }
+                }
+                let length = headers.get("content-length").map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(stream, "HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+                sender.send(Received { headers, body: String::from_utf8(body).unwrap() }).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn test_retry_policy_backs_off() {
+        let policy = RetryPolicy { max_attempts: 5, initial_delay: Duration::seconds(10), max_delay: Duration::seconds(60) };
+        let delays: Vec<Option<Duration>> = (1..=5).map(|failures| policy.next_delay(failures)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::seconds(10)),
+                Some(Duration::seconds(20)),
+                Some(Duration::seconds(40)),
+                Some(Duration::seconds(60)),
+                None,
+            ]
+        );
+
+        // long waits are capped rather than overflowing
+        let policy = RetryPolicy { max_attempts: 50, initial_delay: Duration::weeks(1_000_000), max_delay: Duration::days(1) };
+        assert_eq!(policy.next_delay(40), Some(Duration::days(1)));
+    }
+
+    #[test]
+    fn test_deliveries_are_signed_and_retried() -> Result<(), Box<dyn std::error::Error>> {
+        let conn_string = "file:webhook_dispatch?mode=memory&cache=shared";
+        let webhook_repo = WebhookRepository::new(Some(conn_string))?;
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let (url, received) = stand_in(vec![500, 204]);
+        let webhook_id = webhook_repo.save_new_item(&WebhookDTO { user_id: 1, url, events: vec![EventKind::Uncompleted] })?;
+        let policy = RetryPolicy { max_attempts: 3, initial_delay: Duration::seconds(30), max_delay: Duration::hours(1) };
+        let dispatcher = WebhookDispatcher::new(&webhook_repo).with_policy(policy);
+
+        // only the un-completion is interesting to this webhook
+        let todo_id = todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Meditate".to_string() })?;
+        todo_repo.complete_todo_item(&todo_id)?;
+        todo_repo.uncomplete_todo_item(&todo_id)?;
+
+        // the first attempt fails and is put off
+        let now = Utc::now();
+        assert_eq!(dispatcher.dispatch_due(now)?, DispatchReport { delivered: 0, retrying: 1, failed: 0 });
+        let first = received.recv()?;
+        assert_eq!(dispatcher.dispatch_due(now + Duration::seconds(29))?, DispatchReport::default());
+
+        // the retry succeeds, with the same delivery id and body
+        assert_eq!(dispatcher.dispatch_due(now + Duration::seconds(30))?, DispatchReport { delivered: 1, retrying: 0, failed: 0 });
+        let second = received.recv()?;
+        assert_eq!(first.body, second.body);
+        assert_eq!(first.headers["x-todont-delivery"], second.headers["x-todont-delivery"]);
+
+        let secret = webhook_repo.select_item_by_id(&webhook_id)?.secret;
+        assert_eq!(second.headers["x-todont-signature"], sign(&secret, &second.body));
+        assert_eq!(second.headers["x-todont-event"], "uncompleted");
+        assert_eq!(second.headers["content-type"], "application/json");
+        let payload: Value = serde_json::from_str(&second.body)?;
+        assert_eq!(payload["todo"]["task"], "Meditate");
+
+        let delivery = &webhook_repo.get_deliveries(&webhook_id)?[0];
+        assert_eq!(delivery.attempts, 2);
+        assert!(delivery.delivered_datetime.is_some());
+        assert_eq!(delivery.last_error, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deliveries_give_up_eventually() -> Result<(), Box<dyn std::error::Error>> {
+        let conn_string = "file:webhook_give_up?mode=memory&cache=shared";
+        let webhook_repo = WebhookRepository::new(Some(conn_string))?;
+        let todo_repo = TodoRepository::new(Some(conn_string))?;
+        let (url, received) = stand_in(vec![503, 410]);
+        let webhook_id = webhook_repo.save_new_item(&WebhookDTO { user_id: 1, url, events: vec![EventKind::Created] })?;
+        let policy = RetryPolicy { max_attempts: 2, initial_delay: Duration::seconds(1), max_delay: Duration::seconds(1) };
+        let dispatcher = WebhookDispatcher::new(&webhook_repo).with_policy(policy);
+        todo_repo.save_new_item(&TodoItemDTO { user_id: 1, task: "Learn Rust".to_string() })?;
+
+        let now = Utc::now();
+        assert_eq!(dispatcher.dispatch_due(now)?.retrying, 1);
+        assert_eq!(dispatcher.dispatch_due(now + Duration::seconds(1))?.failed, 1);
+        assert_eq!(received.iter().take(2).count(), 2);
+
+        // nothing is left to send
+        assert_eq!(dispatcher.dispatch_due(now + Duration::days(1))?, DispatchReport::default());
+        let delivery = &webhook_repo.get_deliveries(&webhook_id)?[0];
+        assert_eq!(delivery.last_error.as_deref(), Some("HTTP 410"));
+        assert!(delivery.failed_datetime.is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1 @@
+mod dispatcher_tests;